@@ -0,0 +1,94 @@
+//! Example plugin for the `plugin` transformation: adds a straight line
+//! `slope * x` to the intensities of every frame.
+//!
+//! Build it with `cargo build --example tilt_plugin` and copy the library
+//! from `target/debug/examples` into the plugin directory, i.e. the
+//! `plugins` directory next to the executable or `RUSTMAN_PLUGINS`. It is
+//! then run with `rustman plugin tilt slope=0.5`.
+
+use std::ffi::{c_char, CStr, CString};
+
+/// Interface version of the program this plugin was written for.
+const ABI_VERSION: u32 = 1;
+
+const DESCRIPTION: &CStr = c"name: tilt
+about: Add a straight line through the origin to every frame.
+parameters:
+  - name: slope
+    type: number
+    default: 0.0
+    help: Slope of the line, in intensity per unit of the x-axis.
+";
+
+/// Frames exchanged with the program, as `PluginData` in
+/// `src/transformations/plugin.rs`.
+#[repr(C)]
+pub struct PluginData {
+    pub data: *mut f64,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+#[no_mangle]
+pub extern "C" fn rustman_plugin_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn rustman_plugin_describe() -> *const c_char {
+    DESCRIPTION.as_ptr()
+}
+
+/// # Safety
+///
+/// `parameters` must be a YAML mapping as C string and `input` must hold
+/// `rows` x `cols` values, as passed by the program.
+#[no_mangle]
+pub unsafe extern "C" fn rustman_plugin_transform(
+    parameters: *const c_char,
+    input: *const PluginData,
+    output: *mut PluginData,
+) -> *mut c_char {
+    let parameters = CStr::from_ptr(parameters).to_string_lossy();
+    let slope = serde_yaml::from_str::<serde_yaml::Mapping>(&parameters)
+        .ok()
+        .and_then(|parameters| parameters.get("slope").and_then(|slope| slope.as_f64()));
+    let Some(slope) = slope else {
+        return error("parameter slope is missing");
+    };
+    let input = &*input;
+    let mut values = std::slice::from_raw_parts(input.data, input.rows * input.cols).to_vec();
+    for row in values.chunks_exact_mut(input.cols.max(1)) {
+        for pair in row.chunks_exact_mut(2) {
+            pair[1] += slope * pair[0];
+        }
+    }
+    *output = PluginData {
+        data: Box::into_raw(values.into_boxed_slice()).cast(),
+        rows: input.rows,
+        cols: input.cols,
+    };
+    std::ptr::null_mut()
+}
+
+/// # Safety
+///
+/// `output` and `error` must be null or handed out by
+/// `rustman_plugin_transform`, each is freed once.
+#[no_mangle]
+pub unsafe extern "C" fn rustman_plugin_free(output: *mut PluginData, error: *mut c_char) {
+    if let Some(output) = output.as_mut() {
+        if !output.data.is_null() {
+            let values = std::ptr::slice_from_raw_parts_mut(output.data, output.rows * output.cols);
+            drop(Box::from_raw(values));
+            output.data = std::ptr::null_mut();
+        }
+    }
+    if !error.is_null() {
+        drop(CString::from_raw(error));
+    }
+}
+
+fn error(message: &str) -> *mut c_char {
+    CString::new(message).unwrap_or_default().into_raw()
+}
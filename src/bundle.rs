@@ -0,0 +1,109 @@
+use crate::common::Dataset;
+use crate::output::FloatFormat;
+use anyhow::{Context, Result};
+use std::io::{Seek, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+/// Write a complete record of a pipeline run as ZIP archive, e.g. to attach
+/// it to an entry of an electronic lab notebook:
+///
+/// - `input/<name>`: the raw input file
+/// - `pipeline.yaml`: arguments of the run and parameters of all steps
+/// - `steps/<nn>_<name>.csv`: the dataset after each step
+/// - `result.csv`: the dataset after the last step
+pub fn write_bundle(
+    writer: impl Write + Seek,
+    input_path: &Path,
+    steps: &[(String, &Dataset)],
+    result: &Dataset,
+    float_format: FloatFormat,
+) -> Result<()> {
+    let mut zip = ZipWriter::new(writer);
+    let options = FileOptions::default();
+    let input = std::fs::read(input_path)
+        .with_context(|| format!("Unable to read input file {:?}", input_path))?;
+    let name = input_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "input".to_owned());
+    zip.start_file(format!("input/{}", name), options)?;
+    zip.write_all(&input)?;
+    zip.start_file("pipeline.yaml", options)?;
+    zip.write_all(result.metadata.as_bytes())?;
+    for (i, (name, dataset)) in steps.iter().enumerate() {
+        zip.start_file(format!("steps/{:02}_{}.csv", i + 1, name), options)?;
+        dataset.write(&mut zip, float_format)?;
+    }
+    zip.start_file("result.csv", options)?;
+    result.write(&mut zip, float_format)?;
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_bundle;
+    use crate::common::Dataset;
+    use crate::output::FloatFormat;
+    use std::io::{Cursor, Read};
+    use std::path::Path;
+
+    #[test]
+    fn test_write_bundle() {
+        let input = Path::new("test/test_frames_simple.csv");
+        let first = Dataset::new_test_dummy();
+        let mut result = Dataset::new_test_dummy();
+        result.metadata = "transformation: MedianTransform\n---\n".to_owned();
+        let mut buffer = Cursor::new(vec![]);
+        write_bundle(
+            &mut buffer,
+            input,
+            &[
+                ("OffsetTransform".to_owned(), &first),
+                ("MedianTransform".to_owned(), &result),
+            ],
+            &result,
+            FloatFormat::default(),
+        )
+        .unwrap();
+        let mut archive = zip::ZipArchive::new(buffer).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "input/test_frames_simple.csv",
+                "pipeline.yaml",
+                "result.csv",
+                "steps/01_OffsetTransform.csv",
+                "steps/02_MedianTransform.csv",
+            ]
+        );
+        let mut raw = vec![];
+        archive
+            .by_name("input/test_frames_simple.csv")
+            .unwrap()
+            .read_to_end(&mut raw)
+            .unwrap();
+        assert_eq!(raw, std::fs::read(input).unwrap());
+        let mut pipeline = String::new();
+        archive
+            .by_name("pipeline.yaml")
+            .unwrap()
+            .read_to_string(&mut pipeline)
+            .unwrap();
+        assert_eq!(pipeline, result.metadata);
+        // a missing input is an error, not an incomplete record
+        let mut buffer = Cursor::new(vec![]);
+        assert!(write_bundle(
+            &mut buffer,
+            Path::new("test/missing.csv"),
+            &[],
+            &result,
+            FloatFormat::default()
+        )
+        .is_err());
+    }
+}
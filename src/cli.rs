@@ -1,23 +1,29 @@
-use crate::common::{Dataset, Pipeline};
+use crate::common::{is_stdin_path, CsvFormat, Dataset, Locale, Pipeline};
+use crate::config::{ConfigCommand, UserConfig};
+use crate::diagram::DiagramFormat;
+use crate::metadata::Header;
+use crate::output::{json_document, json_lines, FloatFormat, Layout, Notation, OutputFormat};
+use crate::pipeline_diff::DiffPipelineCommand;
 use crate::plot::PlotTransform;
-use crate::transformations::calibration::CalibrationTransform;
-use crate::transformations::{
-    align::AlignTransform, append::AppendTransform, average::AverageTransform,
-    count_conversion::CountConversionTransform, despike::DespikeTransform,
-    draw_baseline::DrawBaselineTransform, finning::FinningTransform, integrate::IntegrateTransform,
-    mask_pixels::MaskTransform, normalize::NormalizeTransform, offset::OffsetTransform,
-    reshape::ReshapeTransform, select::SelectTransform, shift::RamanShiftTransform,
-    subtract::SubtractTransform,
-};
+use crate::registry::{transformer_by_name, TRANSFORMERS};
+use crate::replay::ReplayCommand;
+use crate::rmbin::write_rmbin;
+use crate::streaming::stream_spe;
+use crate::transformations::condition::ConditionalTransform;
+use crate::transformations::{InstrumentProfile, PipelineContext};
 use anyhow::{anyhow, Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser, Subcommand};
 use serde::{Deserialize, Serialize};
-use std::io::BufWriter;
+use serde_yaml::Value;
+use std::io::{BufWriter, Write};
 
 #[derive(Parser, Serialize, Deserialize, Debug)]
 #[clap(name = "Raman CLI Tools")]
 pub struct Cli {
-    #[clap(parse(from_os_str))]
+    #[clap(
+        parse(from_os_str),
+        help = "Input file, read from STDIN if it is \"-\" or omitted while data is piped in."
+    )]
     pub filepath: Option<std::path::PathBuf>,
     #[clap(
         short,
@@ -26,79 +32,216 @@ pub struct Cli {
         default_value = "#"
     )]
     pub comment: char,
-    #[clap(short, long, help = "the delimiting character", default_value = ",")]
+    #[clap(
+        short,
+        long,
+        help = "the delimiting character, runs of blanks in .txt files unless another than ',' is given",
+        default_value = ","
+    )]
     pub delimiter: char,
+    #[clap(
+        long,
+        action,
+        help = "The first row of the input holds column names, the names of the y-columns become the labels of the frames."
+    )]
+    #[serde(default)]
+    pub header: bool,
+    #[clap(
+        long,
+        default_value = "en",
+        help = "Number format of the input: en (decimal point) or de (decimal comma, fields separated by ';' unless another delimiter than ',' is given)."
+    )]
+    #[serde(default)]
+    pub locale: Locale,
+    #[clap(
+        long,
+        help = "Seed for transformations drawing random numbers (the initial means of kmeans), chosen at random if omitted."
+    )]
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[clap(
+        long,
+        parse(from_os_str),
+        help = "YAML file with properties of the instrument (gain, read_noise), used where a transformation does not set them, or the name of a built-in profile (ideal)."
+    )]
+    #[serde(default)]
+    pub instrument: Option<std::path::PathBuf>,
+    #[clap(
+        long,
+        help = "Free text written to the metadata in the header of the output, e.g. the sample ID and observations during the measurement."
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[clap(
+        long,
+        action,
+        help = "Only check the parameters of the pipeline against the input data, report all problems found."
+    )]
+    #[serde(skip)]
+    pub validate: bool,
+    #[clap(
+        long,
+        action,
+        help = "Read the frames of an SPE file one at a time and keep only the result in memory, for files larger than the memory. The pipeline must end in integrate, kinetics or peak-pick, the steps before must transform each frame on its own (e.g. offset, shift, normalize)."
+    )]
+    #[serde(skip)]
+    pub stream: bool,
+    #[clap(
+        long,
+        help = "Print a diagram of the pipeline (dot or mermaid) instead of running it, e.g. for the documentation of the data processing."
+    )]
+    #[serde(skip)]
+    pub diagram: Option<DiagramFormat>,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Print nothing but the output data and errors, e.g. no warnings."
+    )]
+    #[serde(skip)]
+    pub quiet: bool,
+    #[clap(
+        short = 'F',
+        long,
+        default_value = "csv",
+        help = "Format of the output data: csv (pipeline in the comment header), json-lines (one JSON object per line for each step with its reports, each row of results like integrals, or each frame), json (a single JSON document with the metadata, warnings and the results or frames) or rmbin (exact binary data for another rustman invocation reading from \"-\", detected automatically)."
+    )]
+    #[serde(skip)]
+    pub output_format: OutputFormat,
+    #[clap(
+        long,
+        action,
+        help = "Write a single JSON document instead of CSV, e.g. the windows and values of integrate or peak-pick (same as --output-format json)."
+    )]
+    #[serde(skip)]
+    pub json: bool,
+    #[clap(
+        long,
+        default_value = "%.6e",
+        help = "Number format of the CSV output: %.<digits>e (scientific), %.<digits>f (fixed) or shortest (shortest text that reads back as the same value)."
+    )]
+    #[serde(skip)]
+    pub float_format: FloatFormat,
+    #[clap(
+        long,
+        default_value = "pairs",
+        help = "Columns of the CSV output: pairs (x and y of each frame) or shared-x (one x-column for all frames, if they share their x-axis, about half the size)."
+    )]
+    #[serde(skip)]
+    pub layout: Layout,
+    #[clap(
+        long,
+        help = "Number of digits of the CSV output, replaces the one of --float-format."
+    )]
+    #[serde(skip)]
+    pub precision: Option<usize>,
+    #[clap(
+        long,
+        help = "Notation of the CSV output: fixed or scientific, replaces the one of --float-format."
+    )]
+    #[serde(skip)]
+    pub format: Option<Notation>,
+    #[clap(
+        long,
+        action,
+        help = "Check whether a newer version was released (needs network access, nothing is installed)."
+    )]
+    #[serde(skip)]
+    pub check_update: bool,
     #[clap(subcommand)]
     #[serde(skip_serializing)]
     pub command: Option<Commands>,
 }
 
+/// Commands of the program other than the transformers, which are added to
+/// the help from `crate::registry::TRANSFORMERS`.
 #[derive(Subcommand, Deserialize, Debug)]
 pub enum Commands {
-    // REGISTER: new transformers must be entered here.
-    /// Align frames.
-    Align(AlignTransform),
-    /// Append a dataset from a further input file.
-    Append(AppendTransform),
-    /// Average intensity.
-    Average(AverageTransform),
-    /// Draw and subtract a spline baseline (from all frames).
-    Baseline(DrawBaselineTransform),
-    /// Apply a linear calibration to the wavelength axis.
-    Calibration(CalibrationTransform),
-    /// Convert from counts to photoelectrons per second.
-    CountConverion(CountConversionTransform),
-    /// Apply laplace edge-detection despike algorithm.
-    Despike(DespikeTransform),
-    /// Apply finning despike algorithm.
-    Finning(FinningTransform),
-    /// Integrate frames in given interval(s).
-    Integrate(IntegrateTransform),
-    /// Manually mask data points by pixel and frame number
-    Mask(MaskTransform),
-    /// Normalize frames.
-    Normalize(NormalizeTransform),
-    /// Add offset to value columns.
-    Offset(OffsetTransform),
     /// Plot the dataset.
     Plot(PlotTransform),
-    /// Reshape dataset into different form.
-    Reshape(ReshapeTransform),
-    /// Calculate Raman shift.
-    Shift(RamanShiftTransform),
-    // Subtract frame from other frames.
-    Subtract(SubtractTransform),
-    /// Select frames.
-    Select(SelectTransform),
+    /// Apply the following command only if the dataset fulfils a condition.
+    When(ConditionalTransform),
     /// Run default transformers
     Default,
+    /// Print the processing history recorded in the header of the input file.
+    History,
+    /// Run the pipeline recorded in the header of the input file again on the raw input of that run.
+    Replay(ReplayCommand),
+    /// Print the differences of the pipelines in the headers of two processed files.
+    DiffPipeline(DiffPipelineCommand),
+    /// Print a JSON Schema of the pipeline YAML, for editors to validate and complete pipeline files.
+    Schema,
     /// Run in GUI mode.
-    GUI,
+    GUI {
+        /// "reload" to load the pipeline from the header of the input file
+        #[clap(possible_values = ["reload"])]
+        reload: Option<String>,
+    },
+    /// Run in the terminal, e.g. over SSH on a computer without display.
+    Tui,
+    /// Show or change the defaults in the config file (e.g. the laser wavelength).
+    Config(ConfigCommand),
 }
 
-const COMMANDS: [&str; 19] = [
-    // REGISTER: new transformers must get entry here.
-    "align",
-    "append",
-    "average",
-    "baseline",
-    "calibration",
-    "count-conversion",
+const PROGRAM_COMMANDS: [&str; 10] = [
+    "config",
     "default",
-    "despike",
-    "finning",
+    "diff-pipeline",
     "gui",
-    "integrate",
-    "mask",
-    "normalize",
-    "offset",
+    "history",
     "plot",
-    "reshape",
-    "select",
-    "shift",
-    "subtract",
+    "replay",
+    "schema",
+    "tui",
+    "when",
 ];
 
+/// true if the argument starts a new command, a transformer or a command of
+/// the program
+fn is_command(arg: &str) -> bool {
+    PROGRAM_COMMANDS.contains(&arg) || transformer_by_name(arg).is_some()
+}
+
+/// Commands of the program as on the command line, to parse a single command.
+#[derive(Parser)]
+#[clap(no_binary_name(true))]
+pub(crate) struct CommandLine {
+    #[clap(subcommand)]
+    pub command: Commands,
+}
+
+/// The first command given that runs the program in another mode than
+/// running the pipeline (e.g. `gui` or `schema`), as parsed by clap. Exits
+/// on invalid arguments of the command.
+fn mode_command(commands: &[Vec<String>]) -> Option<Commands> {
+    commands
+        .iter()
+        .filter(|args| {
+            args.first()
+                .is_some_and(|name| PROGRAM_COMMANDS.contains(&name.as_str()))
+        })
+        .map(|args| {
+            CommandLine::try_parse_from(args)
+                .unwrap_or_else(|e| e.exit())
+                .command
+        })
+        .find(|command| {
+            !matches!(
+                command,
+                Commands::Plot(_) | Commands::When(_) | Commands::Default
+            )
+        })
+}
+
+/// Command line of the program, with a subcommand for every transformer.
+fn command_line() -> clap::Command<'static> {
+    TRANSFORMERS
+        .iter()
+        .fold(Cli::command(), |command, transformer| {
+            command.subcommand((transformer.command)())
+        })
+}
+
 pub struct Preprocessor {
     pub args: Cli,
     pub subcommand_args: Option<Vec<Vec<String>>>,
@@ -112,51 +255,122 @@ impl Preprocessor {
         // clap by going through the args passed from the command line one by
         // one and splitting into a new sublist if a subcommand name is found
         let args_raw: Vec<String> = std::env::args().collect();
-        let gui_mode = args_raw.iter().any(|arg| arg == "gui");
-        let reload_pipeline = args_raw.iter().any(|arg| arg == "reload");
-        // sort arguments by command
-        let mut args_sorted_by_command: Vec<Vec<String>> = vec![vec![]];
-        for arg in args_raw {
-            if COMMANDS.contains(&arg.as_str()) {
-                args_sorted_by_command.push(vec![arg]);
-            } else {
-                // we can unwrap because the vector is guaranteed to have a single element
-                args_sorted_by_command.last_mut().unwrap().push(arg);
-            }
-        }
+        let args_sorted_by_command = split_commands(args_raw);
         // the first subset of arguments are always for the preprocessor
         let (preprocessor_args, subcommand_args) = match args_sorted_by_command.split_first() {
             None => (args_sorted_by_command.first().unwrap(), None),
             Some((p, s)) => (p, Some(s.to_vec())),
         };
-        let preprocessor_args = Cli::parse_from(preprocessor_args);
+        let matches = command_line().get_matches_from(preprocessor_args);
+        let mut preprocessor_args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        preprocessor_args.command = mode_command(subcommand_args.as_deref().unwrap_or_default());
+        let (gui_mode, reload_pipeline) = match &preprocessor_args.command {
+            Some(Commands::GUI { reload }) => (true, reload.is_some()),
+            _ => (false, false),
+        };
+        // defaults of the config file replace the built-in ones, but not
+        // the arguments given
+        let config = UserConfig::global();
+        let is_default = |id: &str| matches.value_source(id) == Some(ValueSource::DefaultValue);
+        if let (true, Some(delimiter)) = (is_default("delimiter"), config.delimiter) {
+            preprocessor_args.delimiter = delimiter;
+        }
+        if let (true, Some(comment)) = (is_default("comment"), config.comment) {
+            preprocessor_args.comment = comment;
+        }
         let mut prp = Preprocessor {
             args: preprocessor_args,
             subcommand_args,
             gui_mode,
             reload_pipeline,
         };
-        if prp.args.filepath.is_some() {
+        if prp
+            .args
+            .filepath
+            .as_ref()
+            .is_some_and(|path| !is_stdin_path(path))
+        {
             prp.args.filepath = Some(prp.args.filepath.unwrap().canonicalize().unwrap());
         }
+        prp.args.float_format = prp
+            .args
+            .float_format
+            .with(prp.args.precision, prp.args.format);
+        if prp.args.json {
+            prp.args.output_format = OutputFormat::Json;
+        }
+        // the seed is recorded in the metadata, so a random one can be reused
+        // to reproduce the run
+        if prp.args.seed.is_none() {
+            prp.args.seed = Some(random_seed());
+        }
         prp
     }
 
     pub fn get_input_data(&mut self) -> Result<Dataset> {
-        let mut dataset = if self
+        let format = CsvFormat {
+            comment: self.args.comment,
+            delimiter: self.args.delimiter,
+            header: self.args.header,
+            locale: self.args.locale,
+        };
+        let mut dataset = Dataset::from_file(&self.args.filepath, &format)?;
+        dataset.metadata = self.metadata()?;
+        Ok(dataset)
+    }
+
+    /// Metadata of the preprocessor, the first document of the header.
+    fn metadata(&self) -> Result<String> {
+        Ok("preprocessor: arguments\n".to_owned()
+            + serde_yaml::to_string(&self.args)?.as_str()
+            + "---\n")
+    }
+
+    /// Replace the metadata of the preprocessor at the start of the metadata
+    /// of the dataset by the current one, e.g. after the notes were edited in
+    /// the GUI.
+    pub fn refresh_metadata(&self, dataset: &mut Dataset) -> Result<()> {
+        let metadata = self.metadata()?;
+        let rest = match dataset.metadata.split_once("---\n") {
+            Some((first, rest)) if first.starts_with("preprocessor: arguments\n") => rest,
+            _ => dataset.metadata.as_str(),
+        };
+        dataset.metadata = metadata + rest;
+        Ok(())
+    }
+
+    /// Run the pipeline on the frames of the input file one at a time, see
+    /// `crate::streaming::stream_spe`.
+    pub fn stream_input_data(
+        &self,
+        pipeline: &mut Pipeline,
+        context: &PipelineContext,
+    ) -> Result<Dataset> {
+        let filepath = self
             .args
             .filepath
             .as_ref()
-            .is_some_and(|path| path.extension().unwrap_or_default() == "spe")
-        {
-            Dataset::from_spe(&self.args.filepath.as_ref().unwrap())
-                .map_err(|e| anyhow!("Could not read SPE file: {e}"))?
-        } else {
-            Dataset::from_csv(&self.args.filepath, self.args.comment, self.args.delimiter)?
+            .filter(|path| path.extension().unwrap_or_default() == "spe")
+            .ok_or_else(|| anyhow!("only the frames of SPE files can be streamed"))?;
+        stream_spe(filepath, pipeline, context, self.metadata()?)
+    }
+
+    pub fn get_context(&self) -> Result<PipelineContext> {
+        let instrument = match &self.args.instrument {
+            Some(path) => InstrumentProfile::from_file(path)?,
+            None => InstrumentProfile::default(),
         };
-        dataset.metadata =
-            "preprocessor: arguments\n".to_owned() + &serde_yaml::to_string(&self.args)? + "---\n";
-        Ok(dataset)
+        let context = PipelineContext {
+            input_path: self.args.filepath.clone(),
+            instrument,
+            seed: self.args.seed.unwrap_or_default(),
+            dry_run: self.args.validate,
+            ..Default::default()
+        };
+        for warning in UserConfig::take_warnings() {
+            context.diagnostics.warn(warning);
+        }
+        Ok(context)
     }
 
     pub fn get_pipeline(&self) -> Pipeline {
@@ -165,27 +379,46 @@ impl Preprocessor {
     pub fn get_gui_pipeline(&self) -> Vec<Box<dyn crate::gui::TransformerGUI>> {
         vec![]
     }
-    pub fn print_dataset(&self, dataset: &Dataset) -> Result<()> {
-        let buf = BufWriter::new(std::io::stdout());
-        dataset.write(buf)?;
+    /// Write the dataset to stdout, the warnings are part of the output only
+    /// in the JSON format.
+    pub fn print_dataset(
+        &self,
+        dataset: &Dataset,
+        pipeline: &Pipeline,
+        warnings: &[String],
+    ) -> Result<()> {
+        let mut buf = BufWriter::new(std::io::stdout());
+        match self.args.output_format {
+            OutputFormat::Csv => match self.args.layout {
+                Layout::SharedX if dataset.shared_x_axis().is_some() => {
+                    dataset.write_shared_x(buf, self.args.float_format)?
+                }
+                Layout::SharedX => {
+                    if !self.args.quiet {
+                        eprintln!("the frames do not share their x-axis, writing x- and y-columns of each frame");
+                    }
+                    dataset.write(buf, self.args.float_format)?
+                }
+                Layout::Pairs => dataset.write(buf, self.args.float_format)?,
+            },
+            OutputFormat::JsonLines => buf.write_all(json_lines(dataset, pipeline)?.as_bytes())?,
+            OutputFormat::Json => {
+                buf.write_all(json_document(dataset, pipeline, warnings)?.as_bytes())?
+            }
+            OutputFormat::Rmbin => write_rmbin(dataset, buf)?,
+        }
         Ok(())
     }
 
     pub fn from_yaml_header(yaml_header: &str, gui_mode: bool) -> Result<Self> {
-        let preprocessor_yaml = if let Some(yaml) = yaml_header
-            .split("---")
-            .map(|segment| segment.replace("# ", "").trim().to_string())
-            .find(|segment| segment.contains("preprocessor: arguments"))
-        {
-            yaml
-        } else {
-            return Err(anyhow!(format!(
+        let preprocessor = Header::parse(yaml_header)?.preprocessor.ok_or_else(|| {
+            anyhow!(
                 "Unable to parse preprocessor from YAML header,\
-                \nmissing 'preprocessor: arguments' segment"
-            )));
-        };
-        let args = serde_yaml::from_str::<Cli>(&preprocessor_yaml)
-            .with_context(|| format!("Offending YAML input:\n{}", preprocessor_yaml))?;
+                \nmissing 'preprocessor: arguments' document"
+            )
+        })?;
+        let args = serde_yaml::from_value::<Cli>(Value::Mapping(preprocessor.clone()))
+            .with_context(|| format!("Offending YAML input:\n{:?}", preprocessor))?;
         Ok(Self {
             args,
             subcommand_args: None,
@@ -194,3 +427,88 @@ impl Preprocessor {
         })
     }
 }
+
+/// Sort arguments by command, a new sublist starts at every command name.
+/// The first sublist holds the arguments before the first command. The value
+/// of an option is never taken for a command, e.g. in `--note history`.
+pub(crate) fn split_commands(args: impl IntoIterator<Item = String>) -> Vec<Vec<String>> {
+    let root = command_line();
+    let mut command = &root;
+    let mut is_value = false;
+    let mut args_sorted_by_command: Vec<Vec<String>> = vec![vec![]];
+    for arg in args {
+        if !is_value && is_command(&arg) {
+            command = root.find_subcommand(&arg).unwrap_or(&root);
+            is_value = false;
+            args_sorted_by_command.push(vec![arg]);
+        } else {
+            is_value = !is_value && takes_value(command, &arg);
+            // we can unwrap because the vector is guaranteed to have a single element
+            args_sorted_by_command.last_mut().unwrap().push(arg);
+        }
+    }
+    args_sorted_by_command
+}
+
+/// true if `arg` is an option of `command` whose value is the next argument
+fn takes_value(command: &clap::Command, arg: &str) -> bool {
+    let option = if let Some(long) = arg.strip_prefix("--") {
+        command
+            .get_arguments()
+            .find(|option| option.get_long() == Some(long))
+    } else if let Some(short) = arg.strip_prefix('-') {
+        let mut chars = short.chars();
+        match (chars.next(), chars.next()) {
+            (Some(short), None) => command
+                .get_arguments()
+                .find(|option| option.get_short() == Some(short)),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    option.is_some_and(|option| option.is_takes_value_set())
+}
+
+/// Seed derived from the current time, for runs without `--seed`.
+fn random_seed() -> u64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_secs() ^ u64::from(now.subsec_nanos()) << 32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mode_command, split_commands, Commands};
+
+    #[test]
+    fn test_split_commands() {
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let commands = split_commands(args("rustman in.csv --note history offset 1 gui reload"));
+        assert_eq!(
+            commands,
+            vec![
+                args("rustman in.csv --note history"),
+                args("offset 1"),
+                args("gui reload"),
+            ]
+        );
+        assert!(matches!(
+            mode_command(&commands[1..]),
+            Some(Commands::GUI { reload: Some(_) })
+        ));
+        let commands = split_commands(args("rustman -d ; offset 1 -t 2 schema"));
+        assert_eq!(
+            commands,
+            vec![args("rustman -d ;"), args("offset 1 -t 2"), args("schema")]
+        );
+        let commands = split_commands(args("rustman --note schema offset 1"));
+        assert_eq!(commands.len(), 2);
+        assert!(mode_command(&commands[1..]).is_none());
+        assert!(matches!(
+            mode_command(&[args("plot"), args("history")]),
+            Some(Commands::History)
+        ));
+    }
+}
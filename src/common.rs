@@ -1,27 +1,36 @@
+use crate::config::UserConfig;
 use crate::gui::TransformerGUI;
+use crate::metadata::{document_yaml, Header, HeaderInfo, Provenance, ProvenanceLog, StepRecord};
+use crate::output::FloatFormat;
+use crate::registry::{transformer_by_name, transformer_by_tag};
+use crate::rmbin::{is_rmbin, read_rmbin};
 use crate::spe_rs::SpeData;
-use crate::transformations::calibration::CalibrationTransform;
-use crate::transformations::offset::OffsetIOBuffers;
 use crate::transformations::{
-    align::AlignTransform, append::AppendTransform, average::AverageTransform,
-    baseline::BaselineTransform, count_conversion::CountConversionTransform,
-    despike::DespikeTransform, finning::FinningTransform, integrate::IntegrateTransform,
-    mask_pixels::MaskTransform, normalize::NormalizeTransform, offset::OffsetTransform,
-    reshape::ReshapeTransform, select::SelectTransform, shift::RamanShiftTransform,
-    subtract::SubtractTransform,
+    average::AverageTransform,
+    condition::ConditionalTransform,
+    count_conversion::CountConversionTransform,
+    finning::FinningTransform,
+    offset::{OffsetIOBuffers, OffsetTransform, QuantileMethod},
+    repeat::RepeatTransform,
+    reshape::ReshapeTransform,
+    shift::RamanShiftTransform,
+    PipelineContext,
 };
+use crate::utils::linear_resample_array;
 use anyhow::{anyhow, Context, Result};
 use clap::Parser;
 use csv::ReaderBuilder;
 use egui_plot::PlotPoints;
-use ndarray::{array, Array2, ArrayBase, Axis, Ix1, ViewRepr};
-use ndarray_csv::Array2Reader;
+use ndarray::{array, s, Array1, Array2, ArrayBase, ArrayView1, ArrayView2, Axis, Ix1, ViewRepr};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::Display;
 use std::fs::File;
-use std::io::{BufReader, Read, Write};
+use std::io::{BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
@@ -81,39 +90,183 @@ where
     }
 }
 
+/// Compression of a data file, given by the extension of its name, e.g.
+/// `frames.csv.gz` or `frames.csv.zst`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+    /// extension of compressed files, including the dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+/// Format of an input file, given by the extension of its name without the
+/// extension of a compression, e.g. `frames.txt.gz` is a text file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    Csv,
+    Spe,
+    /// text export with fields separated by blanks
+    Txt,
+}
+
+impl InputFormat {
+    pub fn of(path: &Path) -> Self {
+        let path = match Compression::of(path) {
+            Compression::None => path.to_owned(),
+            _ => path.with_extension(""),
+        };
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("spe") => InputFormat::Spe,
+            Some(ext) if ext.eq_ignore_ascii_case("txt") => InputFormat::Txt,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+/// Number format of CSV input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// decimal point, e.g. 1.5
+    #[default]
+    En,
+    /// decimal comma, e.g. 1,5, with fields separated by ';'
+    De,
+}
+
+impl FromStr for Locale {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "en" => Ok(Locale::En),
+            "de" => Ok(Locale::De),
+            _ => Err(anyhow!("unknown locale \"{}\", use en or de", s)),
+        }
+    }
+}
+
+/// How the text of CSV input is read.
+#[derive(Debug, Clone, Copy)]
+pub struct CsvFormat {
+    pub comment: char,
+    pub delimiter: char,
+    /// the first row holds the names of the columns instead of values
+    pub header: bool,
+    pub locale: Locale,
+}
+
+impl CsvFormat {
+    pub fn new(comment: char, delimiter: char) -> Self {
+        Self {
+            comment,
+            delimiter,
+            header: false,
+            locale: Locale::En,
+        }
+    }
+}
+
+/// `text` with each run of spaces and tabs outside of comments replaced by a
+/// single space, leading and trailing blanks removed.
+fn collapse_blanks(text: &str, comment: char) -> String {
+    text.lines()
+        .map(|line| match line.starts_with(comment) {
+            true => format!("{line}\n"),
+            false => line.split_whitespace().collect::<Vec<_>>().join(" ") + "\n",
+        })
+        .collect()
+}
+
+/// Value of a CSV field, empty fields are missing values (NaN).
+fn parse_csv_value(field: &str, locale: Locale) -> Option<f64> {
+    if field.is_empty() {
+        return Some(f64::NAN);
+    }
+    match locale {
+        Locale::En => field.parse().ok(),
+        Locale::De => field.replace(',', ".").parse().ok(),
+    }
+}
+
+/// Reader of the file, decompressing it if its name says it is compressed.
+pub fn open_data_file(path: &Path) -> Result<Box<dyn Read>> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(match Compression::of(path) {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::with_buffer(file)?),
+    })
+}
+
 pub fn input_data_to_string(filepath: &Option<std::path::PathBuf>) -> Result<String> {
-    let mut input_string = String::new();
+    String::from_utf8(input_data_to_bytes(filepath)?)
+        .with_context(|| "Input data is not valid UTF-8 text.".to_string())
+}
+
+/// true if the file path is "-", which stands for stdin
+pub fn is_stdin_path(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Directory holding the settings of the tools, e.g. ~/.config/rustman on
+/// Linux, None if the home directory is unknown.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    base.map(|dir| dir.join("rustman"))
+}
+
+/// Read the raw input data from the file, or from stdin if the file is "-"
+/// or not given. Stdin is read until its end, e.g. the output of another
+/// rustman invocation that may take a while; without file, a terminal is
+/// not waited for and gives empty input.
+pub fn input_data_to_bytes(filepath: &Option<std::path::PathBuf>) -> Result<Vec<u8>> {
+    let mut input_bytes = vec![];
     match filepath {
-        Some(fp) => {
-            File::open(fp)?.read_to_string(&mut input_string)?;
-        }
-        None => {
-            let (tx, rx) = std::sync::mpsc::channel::<String>();
-            // Try read from stdin in background thread. This
-            // considered as timed-out if nothing is returned within
-            // 100 ms.
-            std::thread::spawn(move || {
-                let mut input_string = String::new();
-                match BufReader::new(std::io::stdin()).read_to_string(&mut input_string) {
-                    Ok(_) => {
-                        tx.send(input_string)
-                            .unwrap_or_else(|e| eprintln!("ERROR: {e}"));
-                    }
-                    Err(_) => {
-                        eprintln!("WARNING: could not read data from STDIN, proceeding with empty input data.");
-                        tx.send(String::new())
-                            .unwrap_or_else(|e| eprintln!("ERROR: {e}"));
-                    }
-                }
-            });
-            // wait for 100 ms, hopefully by then all data was read from stdin
-            std::thread::sleep(std::time::Duration::from_millis(100));
-            if let Ok(s) = rx.try_recv() {
-                input_string = s
-            }
+        Some(fp) if !is_stdin_path(fp) => {
+            open_data_file(fp)?
+                .read_to_end(&mut input_bytes)
+                .with_context(|| format!("Unable to read {:?}", fp))?;
+        }
+        // without input file, only data piped in is read, so the program
+        // does not wait for input typed in the terminal (e.g. the GUI
+        // started without file)
+        None if std::io::stdin().is_terminal() => {}
+        // read up to the end, however long the other side of a pipe takes
+        _ => {
+            std::io::stdin()
+                .lock()
+                .read_to_end(&mut input_bytes)
+                .with_context(|| "Unable to read from STDIN".to_string())?;
         }
     };
-    Ok(input_string)
+    Ok(input_bytes)
 }
 
 #[derive(Clone, Default, Debug)]
@@ -121,6 +274,118 @@ pub struct Dataset {
     pub data: Array2<f64>,
     pub metadata: String,
     pub previous_comments: String,
+    /// per-frame metadata, may be empty if nothing is known about the frames
+    pub frame_metadata: Vec<FrameMetadata>,
+    /// unit of the x-axes of all frames
+    pub x_unit: AxisUnit,
+    /// further quantities of the frames by name (e.g. the subtracted
+    /// baseline), one column per frame on the rows of `data`
+    pub channels: Channels,
+    /// the steps applied to the dataset, written to the header
+    pub provenance_log: Vec<StepRecord>,
+}
+
+/// Values of the channels of a dataset by name, see `Dataset::channels`.
+pub type Channels = BTreeMap<String, Array2<f64>>;
+
+/// Unit of the x-axis, used to catch transformations that would be applied
+/// to the wrong kind of axis (e.g. calculating the Raman shift twice).
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub enum AxisUnit {
+    /// nothing is known about the x-axis, e.g. plain CSV input
+    #[default]
+    Unknown,
+    /// wavelength in nm
+    Wavelength,
+    /// Raman shift in cm⁻¹
+    RamanShift,
+}
+
+impl Display for AxisUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AxisUnit::Unknown => write!(f, "unknown"),
+            AxisUnit::Wavelength => write!(f, "nm"),
+            AxisUnit::RamanShift => write!(f, "cm-1"),
+        }
+    }
+}
+
+impl AxisUnit {
+    /// symbol shown next to x-values in the GUI, empty if unknown
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            AxisUnit::Unknown => "",
+            AxisUnit::Wavelength => "nm",
+            AxisUnit::RamanShift => "cm⁻¹",
+        }
+    }
+}
+
+impl FromStr for AxisUnit {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unknown" => Ok(AxisUnit::Unknown),
+            "nm" => Ok(AxisUnit::Wavelength),
+            "cm-1" => Ok(AxisUnit::RamanShift),
+            _ => Err(anyhow!("unknown axis unit \"{}\"", s)),
+        }
+    }
+}
+
+/// What is known about a dataset without looking at its values, used to check
+/// the parameters of a pipeline before running it. `None` means unknown, e.g.
+/// after appending data from a file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DatasetShape {
+    pub rows: Option<usize>,
+    pub frames: Option<usize>,
+    /// smallest and largest x-value of all frames
+    pub x_range: Option<(f64, f64)>,
+    pub x_unit: AxisUnit,
+}
+
+impl DatasetShape {
+    /// problem description if `frame` (counting from 1) is not in the dataset
+    pub fn check_frame(&self, frame: usize) -> Option<String> {
+        if frame == 0 {
+            return Some("frame count starts at 1, frame \"0\" is invalid".to_owned());
+        }
+        match self.frames {
+            Some(frames) if frame > frames => Some(format!(
+                "frame number {} is out of bounds, largest valid frame number = {}",
+                frame, frames
+            )),
+            _ => None,
+        }
+    }
+    /// problem description if `x` is outside of the x-range of the dataset
+    pub fn check_x(&self, x: f64) -> Option<String> {
+        match self.x_range {
+            Some((min, max)) if x < min || x > max => Some(format!(
+                "x-value {} is outside of the x-range of the data ({} to {})",
+                x, min, max
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Information attached to a single frame, travels with the frame through the pipeline.
+#[derive(Clone, Default, Debug, PartialEq)]
+pub struct FrameMetadata {
+    /// exposure time in seconds
+    pub exposure: Option<f64>,
+    /// gain of the detector in electrons per count
+    pub gain: Option<f64>,
+    /// start of the exposure in seconds since the start of the experiment
+    pub time: Option<f64>,
+    /// name of the frame, e.g. from the column names of the input
+    pub label: Option<String>,
+    /// quality of the frame between 0 and 1, e.g. from the outlier
+    /// detection, frames with a lower weight count less in averages and fits
+    pub weight: Option<f64>,
 }
 
 impl Dataset {
@@ -170,46 +435,172 @@ impl Dataset {
         }
         refs.into_iter()
     }
+    /// Read a dataset from a CSV file, or from stdin if no file is given.
+    /// Input in the binary rmbin format (see `crate::rmbin`) is detected by
+    /// its first bytes and read as well.
     pub fn from_csv(
         filepath: &Option<std::path::PathBuf>,
         comment: char,
         delimiter: char,
     ) -> Result<Self> {
-        let input_string = input_data_to_string(filepath)?;
-        let mut previous_comments: String = input_string
-            .lines()
-            .filter(|line| line.starts_with(comment))
-            .map(|line| format!("{}\n", line))
-            .collect();
+        Self::from_csv_with(filepath, &CsvFormat::new(comment, delimiter))
+    }
+    /// Read the file with the reader for its format: SPE files are read as
+    /// such, text files (TXT) as CSV separated by blanks, unless a delimiter
+    /// other than the default comma is given, everything else as CSV.
+    pub fn from_file(filepath: &Option<std::path::PathBuf>, format: &CsvFormat) -> Result<Self> {
+        let input_format = filepath
+            .as_deref()
+            .map_or(InputFormat::Csv, InputFormat::of);
+        match (input_format, filepath) {
+            (InputFormat::Spe, Some(path)) => {
+                Dataset::from_spe(path).map_err(|e| anyhow!("Could not read SPE file: {e}"))
+            }
+            (InputFormat::Txt, _) if format.delimiter == ',' => Self::from_csv_with(
+                filepath,
+                &CsvFormat {
+                    delimiter: ' ',
+                    ..*format
+                },
+            ),
+            _ => Self::from_csv_with(filepath, format),
+        }
+    }
+    /// Like `from_csv`, for CSV files with a header row or decimal commas.
+    /// Rows with missing fields at the end are padded with NaN.
+    pub fn from_csv_with(
+        filepath: &Option<std::path::PathBuf>,
+        format: &CsvFormat,
+    ) -> Result<Self> {
+        let comment = format.comment;
+        let input_bytes = input_data_to_bytes(filepath)?;
         let filepath_msg = match filepath {
-            None => "comments from input:\n".to_string(),
-            Some(fp) => format!(
+            Some(fp) if !is_stdin_path(fp) => format!(
                 "comments from input file {}:\n",
                 fp.canonicalize()?.display()
             ),
+            _ => "comments from input:\n".to_string(),
         };
+        if is_rmbin(&input_bytes) {
+            let mut dataset = read_rmbin(&input_bytes)?;
+            dataset.previous_comments = filepath_msg + dataset.previous_comments.as_str();
+            return Ok(dataset);
+        }
+        let input_string = String::from_utf8(input_bytes)
+            .with_context(|| "Input data is not valid UTF-8 text.".to_string())?;
+        let mut previous_comments: String = input_string
+            .lines()
+            .filter(|line| line.starts_with(comment))
+            .map(|line| format!("{}\n", line))
+            .collect();
         if !previous_comments.is_empty() {
-            previous_comments = filepath_msg + &previous_comments;
+            previous_comments = filepath_msg + previous_comments.as_str();
         }
 
+        // a comma can not separate fields and decimals at the same time
+        let delimiter = match format.locale {
+            Locale::De if format.delimiter == ',' => ';',
+            _ => format.delimiter,
+        };
+        // columns separated by blanks are usually aligned with runs of
+        // spaces or tabs, each run separates two fields
+        let input_string = match delimiter {
+            ' ' => collapse_blanks(&input_string, comment),
+            _ => input_string,
+        };
         let mut csv_reader_config = ReaderBuilder::new();
         csv_reader_config
             .has_headers(false)
+            .flexible(true)
             .comment(Some(comment as u8))
             .delimiter(delimiter as u8)
             .trim(csv::Trim::All);
 
         let mut csv_reader = csv_reader_config.from_reader(input_string.as_bytes());
-        let data = csv_reader.deserialize_array2_dynamic()?;
+        let mut records = csv_reader.records();
+        let column_names: Vec<String> = match format.header {
+            true => records
+                .next()
+                .transpose()?
+                .map(|names| names.iter().map(str::to_owned).collect())
+                .unwrap_or_default(),
+            false => vec![],
+        };
+        let mut rows: Vec<Vec<f64>> = vec![];
+        for (i, record) in records.enumerate() {
+            // rows counted without comments, but with the header
+            let row_no = i + 1 + format.header as usize;
+            let row = record?
+                .iter()
+                .map(|field| {
+                    parse_csv_value(field, format.locale).ok_or_else(|| {
+                        anyhow!(
+                            "invalid number \"{}\" in row {} of the input, use --header if it holds column names",
+                            field,
+                            row_no
+                        )
+                    })
+                })
+                .collect::<Result<Vec<f64>>>()?;
+            rows.push(row);
+        }
+        let ncols = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let data = Array2::from_shape_fn((rows.len(), ncols), |(i, j)| {
+            rows[i].get(j).copied().unwrap_or(f64::NAN)
+        });
+        // the first column is the x-axis of all frames in files written with
+        // --layout shared-x, only the marker of this file counts, not the
+        // ones of the files it was made from
+        let shared_x_marker = format!("{} layout: {}", comment, SHARED_X_LAYOUT);
+        let shared_x = ncols > 0 && previous_comments.lines().any(|l| l == shared_x_marker);
+        let channel_names = Header::parse(&previous_comments)
+            .ok()
+            .and_then(|header| header.info)
+            .map(|info| info.channels)
+            .unwrap_or_default();
+        let (data, channels) = split_channel_columns(data, &channel_names, shared_x)?;
+        // if the comments name a single exposure time (e.g. CSV converted
+        // from an SPE file), it applies to all frames
+        let exposure = value_from_comments(&previous_comments, "exposure time");
+        let gain = value_from_comments(&previous_comments, "gain");
+        let n_frames = data.ncols() / 2;
+        // frame times only fit if the frames were not reshaped in the meantime
+        let times = values_from_comments(&previous_comments, "frame times")
+            .filter(|times| times.len() == n_frames);
+        let frame_metadata = (0..n_frames)
+            .map(|i| FrameMetadata {
+                exposure,
+                gain,
+                time: times.as_ref().map(|times| times[i]),
+                // name of the y-column
+                label: column_names
+                    .get(match shared_x {
+                        true => 1 + i * (1 + channels.len()),
+                        false => 1 + i * (2 + channels.len()),
+                    })
+                    .filter(|name| !name.is_empty())
+                    .cloned(),
+                weight: None,
+            })
+            .collect();
+        let x_unit = axis_unit_from_comments(&previous_comments);
         Ok(Dataset {
             data,
             metadata: String::new(),
             previous_comments,
+            frame_metadata,
+            x_unit,
+            channels,
+            provenance_log: vec![],
         })
     }
     pub fn from_spe(filepath: &std::path::Path) -> Result<Self, Box<dyn Error>> {
         let spe = SpeData::from_path(filepath)?;
         let previous_comments = spe.get_meta_data_string()?;
+        let regions = spe.get_region_count();
+        if regions > 1 {
+            return Self::from_spe_regions(&spe, previous_comments);
+        }
 
         let frames = spe.get_frames();
         let wavelength = spe.get_wavelength();
@@ -222,49 +613,190 @@ impl Dataset {
             }
         });
 
+        let time_stamps = spe.get_time_stamps();
+        let frame_metadata = (0..frames.len())
+            .map(|i| FrameMetadata {
+                exposure: Some(spe.get_exposure()),
+                // the SPE file only records the analog gain setting, not the
+                // electrons per count, which are given by the user
+                gain: None,
+                time: time_stamps.map(|times| times[i]),
+                label: None,
+                weight: None,
+            })
+            .collect();
+
         Ok(Dataset {
             data,
             metadata: String::new(),
             previous_comments,
+            frame_metadata,
+            x_unit: AxisUnit::Wavelength,
+            channels: Default::default(),
+            provenance_log: vec![],
         })
     }
-    /// Write floats in 2D array to stdout in CSV format
-    pub fn write(&self, mut buf: impl Write) -> Result<()> {
-        // write program version and commit SHA to output buffer
-        let mut version = env!("CARGO_PKG_VERSION").to_string();
-        if let Some(sha) = option_env!("PROJECT_VERSION") {
-            version += format!(" (git commit {})", sha).as_str()
-        };
-        let app_info_string = format!("# Raman CLI Tools version {}.\n# ---\n", version);
-        buf.write(app_info_string.as_bytes())
-            .with_context(|| "Unable to write to buffer.".to_string())?;
-
-        // write metadata to stdout buffer
-        let metadata: String = self
-            .metadata
-            .lines()
-            .map(|line| format!("# {}\n", line))
+    /// Dataset of an SPE file with several regions of interest, each frame
+    /// of the file becomes one frame per region, labeled with the region.
+    fn from_spe_regions(spe: &SpeData, previous_comments: String) -> Result<Self, Box<dyn Error>> {
+        let regions = (0..spe.get_region_count())
+            .map(|r| spe.get_region(r).ok_or("region of interest not found"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let n_frames = spe.get_frames().len();
+        let columns: Vec<(Vec<f64>, Vec<f64>)> = (0..n_frames)
+            .flat_map(|i| {
+                regions.iter().map(move |(wavelength, frames)| {
+                    let counts = frames[i].iter().map(|c| *c as f64).collect();
+                    (wavelength.to_vec(), counts)
+                })
+            })
             .collect();
-        buf.write(metadata.as_bytes())
-            .with_context(|| "Unable to write to buffer.".to_string())?;
-        let prev_comments: String = self
-            .previous_comments
-            .lines()
-            .map(|line| format!("# {}\n", line))
+        let time_stamps = spe.get_time_stamps();
+        let frame_metadata = (0..n_frames)
+            .flat_map(|i| {
+                (0..regions.len()).map(move |r| FrameMetadata {
+                    exposure: Some(spe.get_exposure()),
+                    gain: None,
+                    time: time_stamps.map(|times| times[i]),
+                    label: Some(format!("frame {} ROI {}", i + 1, r + 1)),
+                    weight: None,
+                })
+            })
             .collect();
-        buf.write(prev_comments.as_bytes())
+        Ok(Dataset {
+            data: array_from_frames(&columns),
+            metadata: String::new(),
+            previous_comments,
+            frame_metadata,
+            x_unit: AxisUnit::Wavelength,
+            channels: Default::default(),
+            provenance_log: vec![],
+        })
+    }
+    /// Write floats in 2D array to stdout in CSV format
+    ///
+    /// The comment header always holds the program version, the metadata of
+    /// the pipeline (preprocessor first, then the steps in the order they
+    /// ran) and the comments of the input, in this order. The columns follow
+    /// the frames (x, y of the first frame, x, y of the second, ...). The
+    /// channels, listed in the header info, follow the y-column of each frame
+    /// in the order of their names (e.g. x, y, y_baseline, y_sigma).
+    pub fn write(&self, buf: impl Write, float_format: FloatFormat) -> Result<()> {
+        let header = self.comment_header();
+        self.write_columns(buf, &header, &self.written_columns(false), float_format)
+    }
+    fn write_columns(
+        &self,
+        mut buf: impl Write,
+        header: &str,
+        columns: &[ArrayView1<f64>],
+        float_format: FloatFormat,
+    ) -> Result<()> {
+        buf.write(header.as_bytes())
             .with_context(|| "Unable to write to buffer.".to_string())?;
         // write numeric data to stdout buffer
         let mut wrt = csv::WriterBuilder::new().delimiter(b',').from_writer(buf);
-        for row in self.data.outer_iter() {
-            let record = row.map(std::string::ToString::to_string);
+        for row in 0..self.data.nrows() {
+            let record: Vec<String> = columns
+                .iter()
+                .map(|column| float_format.format(column[row]))
+                .collect();
             wrt.write_record(record.iter())
-                .with_context(|| format!("Unable to write record '{}' to buffer.", record))?;
+                .with_context(|| format!("Unable to write record {} to buffer.", row + 1))?;
         }
         wrt.flush()
             .with_context(|| String::from("Unable to write dataset to buffer."))?;
         Ok(())
     }
+    /// Columns of the written file: the x-column (only the first one if
+    /// `shared_x`), the y-column and the channel columns of each frame.
+    fn written_columns(&self, shared_x: bool) -> Vec<ArrayView1<'_, f64>> {
+        let mut columns = vec![];
+        if shared_x {
+            columns.push(self.data.column(0));
+        }
+        for frame in 0..self.data.ncols() / 2 {
+            if !shared_x {
+                columns.push(self.data.column(2 * frame));
+            }
+            columns.push(self.data.column(2 * frame + 1));
+            columns.extend(self.channels.values().map(|values| values.column(frame)));
+        }
+        columns
+    }
+    /// The x-axis of all frames, if they share one.
+    pub fn shared_x_axis(&self) -> Option<ArrayView1<'_, f64>> {
+        if self.data.ncols() < 2 {
+            return None;
+        }
+        let x = self.data.slice(s![.., 0]);
+        let same = |a: &f64, b: &f64| a == b || (a.is_nan() && b.is_nan());
+        self.data
+            .axis_iter(Axis(1))
+            .step_by(2)
+            .all(|other| other.iter().zip(x.iter()).all(|(a, b)| same(a, b)))
+            .then_some(x)
+    }
+    /// Write the dataset in CSV format like `write`, but with a single x-axis
+    /// column followed by the y-values of each frame, which takes about half
+    /// the space. Fails if the frames do not share their x-axis.
+    pub fn write_shared_x(&self, buf: impl Write, float_format: FloatFormat) -> Result<()> {
+        if self.shared_x_axis().is_none() {
+            return Err(anyhow!("the frames do not share their x-axis"));
+        }
+        let header = self.comment_header_with(Some(SHARED_X_LAYOUT));
+        self.write_columns(buf, &header, &self.written_columns(true), float_format)
+    }
+    /// Comment header of the written dataset: a YAML stream of the header
+    /// info (program version and schema of the header), the metadata of the
+    /// pipeline, the provenance log of its steps and the comments of the
+    /// input, each line starting with "# ".
+    pub fn comment_header(&self) -> String {
+        self.comment_header_with(None)
+    }
+    fn comment_header_with(&self, layout: Option<&str>) -> String {
+        // serializing these plain structs does not fail
+        let mut info = HeaderInfo::new(layout);
+        info.channels = self.channels.keys().cloned().collect();
+        let mut yaml = document_yaml(&info).unwrap_or_default();
+        yaml += &self.metadata;
+        if !self.provenance_log.is_empty() {
+            yaml += &document_yaml(&ProvenanceLog::new(&self.provenance_log)).unwrap_or_default();
+        }
+        if !self.previous_comments.is_empty() {
+            yaml += &serde_yaml::to_string(&Provenance::new(&self.previous_comments))
+                .unwrap_or_default();
+        }
+        yaml.lines().map(|line| format!("# {}\n", line)).collect()
+    }
+    /// Write the dataset as CSV file (see `write`), compressed if the file
+    /// name ends with `.gz` or `.zst`.
+    pub fn write_file(&self, path: &Path, float_format: FloatFormat) -> Result<()> {
+        let file = BufWriter::new(
+            File::create(path).with_context(|| format!("Unable to create {:?}", path))?,
+        );
+        let mut file = match Compression::of(path) {
+            Compression::None => {
+                let mut file = file;
+                self.write(&mut file, float_format)?;
+                file
+            }
+            Compression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                self.write(&mut encoder, float_format)?;
+                encoder.finish()?
+            }
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(file, 0)?;
+                self.write(&mut encoder, float_format)?;
+                encoder.finish()?
+            }
+        };
+        file.flush()
+            .with_context(|| format!("Unable to write {:?}", path))?;
+        Ok(())
+    }
     /// test that a frame index is in bounds, return error otherwise
     pub fn verify_one_frame_in_bounds(&self, frame_no: usize) -> Result<()> {
         if frame_no == 0 {
@@ -298,12 +830,173 @@ impl Dataset {
         }
         Ok(())
     }
+    /// return the metadata belonging to the frames returned by `select_frames`
+    ///
+    /// an empty vector is returned if the frame metadata does not match the data
+    pub fn select_frame_metadata(&self, frames: &[usize], invert: bool) -> Vec<FrameMetadata> {
+        if self.frame_metadata.len() != self.data.ncols() / 2 {
+            return vec![];
+        }
+        self.frame_metadata
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| invert ^ frames.contains(&(n + 1)))
+            .map(|(_, meta)| meta.clone())
+            .collect()
+    }
+    /// copy of the dataset holding only every `row_step`-th row of every `frame_step`-th frame
+    pub fn decimated(&self, row_step: usize, frame_step: usize) -> Dataset {
+        let n_frames = self.data.ncols() / 2;
+        let frames: Vec<usize> = (0..n_frames).step_by(frame_step).collect();
+        let columns: Vec<usize> = frames.iter().flat_map(|i| [2 * i, 2 * i + 1]).collect();
+        let data = self
+            .data
+            .slice(s![..;row_step as isize, ..])
+            .select(Axis(1), &columns);
+        let frame_numbers: Vec<usize> = frames.iter().map(|i| i + 1).collect();
+        Dataset {
+            data,
+            metadata: self.metadata.clone(),
+            previous_comments: self.previous_comments.clone(),
+            frame_metadata: self.select_frame_metadata(&frame_numbers, false),
+            x_unit: self.x_unit,
+            channels: self
+                .channels
+                .iter()
+                .map(|(name, values)| {
+                    let values = values
+                        .slice(s![..;row_step as isize, ..])
+                        .select(Axis(1), &frames);
+                    (name.clone(), values)
+                })
+                .collect(),
+            provenance_log: self.provenance_log.clone(),
+        }
+    }
+    /// Set the values of a channel, one column per frame on the rows of
+    /// `data`.
+    pub fn set_channel(&mut self, name: &str, values: Array2<f64>) -> Result<()> {
+        let shape = (self.data.nrows(), self.data.ncols() / 2);
+        if values.dim() != shape {
+            return Err(anyhow!(
+                "channel \"{}\" has {} rows and {} frames, the data {} rows and {} frames",
+                name,
+                values.nrows(),
+                values.ncols(),
+                shape.0,
+                shape.1
+            ));
+        }
+        self.channels.insert(name.to_owned(), values);
+        Ok(())
+    }
+    /// Remove the channels that do not fit the data anymore, e.g. after
+    /// frames were averaged or rows cropped, returns their names.
+    pub fn drop_mismatched_channels(&mut self) -> Vec<String> {
+        let shape = (self.data.nrows(), self.data.ncols() / 2);
+        let mismatched: Vec<String> = self
+            .channels
+            .iter()
+            .filter(|(_, values)| values.dim() != shape)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in mismatched.iter() {
+            self.channels.remove(name);
+        }
+        mismatched
+    }
+    /// exposure times of all frames, None if not known for every frame
+    pub fn exposures(&self) -> Option<Vec<f64>> {
+        if self.frame_metadata.len() != self.data.ncols() / 2 {
+            return None;
+        }
+        self.frame_metadata
+            .iter()
+            .map(|meta| meta.exposure)
+            .collect()
+    }
+    /// times of all frames (start of exposure), None if not known for every frame
+    pub fn times(&self) -> Option<Vec<f64>> {
+        if self.frame_metadata.len() != self.data.ncols() / 2 {
+            return None;
+        }
+        self.frame_metadata.iter().map(|meta| meta.time).collect()
+    }
+    /// quality weights of all frames (1 for frames without weight), None if no
+    /// frame has a weight
+    pub fn weights(&self) -> Option<Vec<f64>> {
+        if self.frame_metadata.len() != self.data.ncols() / 2
+            || self.frame_metadata.iter().all(|meta| meta.weight.is_none())
+        {
+            return None;
+        }
+        Some(
+            self.frame_metadata
+                .iter()
+                .map(|meta| meta.weight.unwrap_or(1.0))
+                .collect(),
+        )
+    }
+    /// mean time of all frames, e.g. the time of their average
+    pub fn mean_time(&self) -> Option<f64> {
+        let times = self.times().filter(|times| !times.is_empty())?;
+        Some(times.iter().sum::<f64>() / times.len() as f64)
+    }
+    /// exposure time shared by all frames, None if unknown or not the same for all frames
+    pub fn shared_exposure(&self) -> Option<f64> {
+        self.shared_value(|meta| meta.exposure)
+    }
+    /// detector gain shared by all frames, None if unknown or not the same for all frames
+    pub fn shared_gain(&self) -> Option<f64> {
+        self.shared_value(|meta| meta.gain)
+    }
+    fn shared_value(&self, value: fn(&FrameMetadata) -> Option<f64>) -> Option<f64> {
+        if self.frame_metadata.len() != self.data.ncols() / 2 {
+            return None;
+        }
+        let first = value(self.frame_metadata.first()?)?;
+        self.frame_metadata
+            .iter()
+            .all(|meta| value(meta) == Some(first))
+            .then_some(first)
+    }
+    /// metadata of a single frame combining all frames, e.g. their average:
+    /// exposure and gain are only well defined if all frames share them, its
+    /// time is the mean time of the frames
+    pub fn combined_frame_metadata(&self) -> FrameMetadata {
+        FrameMetadata {
+            exposure: self.shared_exposure(),
+            gain: self.shared_gain(),
+            time: self.mean_time(),
+            label: None,
+            weight: None,
+        }
+    }
+    pub fn shape(&self) -> DatasetShape {
+        let x_range = self
+            .data
+            .slice(s![.., 0..;2])
+            .iter()
+            .filter(|x| x.is_finite())
+            .fold(None, |range, x| match range {
+                None => Some((*x, *x)),
+                Some((min, max)) => Some((f64::min(min, *x), f64::max(max, *x))),
+            });
+        DatasetShape {
+            rows: Some(self.data.nrows()),
+            frames: Some(self.data.ncols() / 2),
+            x_range,
+            x_unit: self.x_unit,
+        }
+    }
     /// create small dataset for testing purposes
     #[allow(dead_code)]
     pub fn new_test_dummy() -> Self {
         Dataset {
             metadata: "".to_string(),
             previous_comments: "".to_string(),
+            frame_metadata: vec![FrameMetadata::default(); 4],
+            x_unit: AxisUnit::Unknown,
             data: array![
                 [11., 12., 13., 14., 15., 16., 17., 18.],
                 [21., 22., 23., 24., 25., 26., 27., 28.],
@@ -314,7 +1007,111 @@ impl Dataset {
                 [71., 72., 73., 74., 75., 76., 77., 78.],
                 [81., 82., 83., 84., 85., 86., 87., 88.],
             ],
+            channels: Default::default(),
+            provenance_log: vec![],
+        }
+    }
+    /// create small dataset for testing purposes, all frames on the x-axis of
+    /// the first frame
+    #[allow(dead_code)]
+    pub fn new_test_dummy_shared_x() -> Self {
+        let mut dataset = Dataset::new_test_dummy();
+        let x = dataset.data.column(0).to_owned();
+        for mut xs in dataset.data.axis_iter_mut(Axis(1)).step_by(2) {
+            xs.assign(&x);
+        }
+        dataset
+    }
+    /// return a copy of the dataset holding only the data points in the x-range [`left`, `right`]
+    ///
+    /// If `n_points` is given, all frames are resampled on a common grid of
+    /// `n_points` evenly spaced points, otherwise frames holding less
+    /// points than others are padded with NaN.
+    pub fn crop_x_range(&self, left: f64, right: f64, n_points: Option<usize>) -> Result<Dataset> {
+        let (left, right) = if left < right {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        let frames: Vec<(Vec<f64>, Vec<f64>)> = match n_points {
+            Some(n) if n > 1 => {
+                let grid = Array1::linspace(left, right, n);
+                self.data
+                    .axis_iter(Axis(1))
+                    .step_by(2)
+                    .zip(self.data.axis_iter(Axis(1)).skip(1).step_by(2))
+                    .map(|(xs, ys)| {
+                        let ys = linear_resample_array(&xs, &ys, &grid);
+                        (grid.to_vec(), ys.to_vec())
+                    })
+                    .collect()
+            }
+            Some(_) => return Err(anyhow!("resampling requires at least 2 points")),
+            None => self
+                .data
+                .axis_iter(Axis(1))
+                .step_by(2)
+                .zip(self.data.axis_iter(Axis(1)).skip(1).step_by(2))
+                .map(|(xs, ys)| {
+                    xs.iter()
+                        .zip(ys.iter())
+                        .filter(|(x, _)| **x >= left && **x <= right)
+                        .map(|(x, y)| (*x, *y))
+                        .unzip()
+                })
+                .collect(),
+        };
+        if frames.iter().all(|(xs, _)| xs.is_empty()) {
+            return Err(anyhow!("no data points in x-range {} to {}", left, right));
+        }
+        let data = array_from_frames(&frames);
+        let mut metadata = self.metadata.clone();
+        metadata += &format!("export: visible region\nleft: {}\nright: {}\n", left, right);
+        if let Some(n) = n_points {
+            metadata += &format!("points: {}\n", n);
+        }
+        metadata += "---\n";
+        Ok(Dataset {
+            data,
+            metadata,
+            previous_comments: self.previous_comments.clone(),
+            frame_metadata: self.frame_metadata.clone(),
+            x_unit: self.x_unit,
+            channels: Default::default(),
+            provenance_log: vec![],
+        })
+    }
+    /// remove the data points in the x-range [`left`, `right`] from all frames
+    ///
+    /// Frames left with less points than others are padded with NaN.
+    pub fn remove_x_range(&mut self, left: f64, right: f64) -> Result<()> {
+        let (left, right) = if left < right {
+            (left, right)
+        } else {
+            (right, left)
+        };
+        let frames: Vec<(Vec<f64>, Vec<f64>)> = self
+            .data
+            .axis_iter(Axis(1))
+            .step_by(2)
+            .zip(self.data.axis_iter(Axis(1)).skip(1).step_by(2))
+            .map(|(xs, ys)| {
+                xs.iter()
+                    .zip(ys.iter())
+                    .filter(|(x, _)| **x < left || **x > right)
+                    .map(|(x, y)| (*x, *y))
+                    .unzip()
+            })
+            .collect();
+        if frames.iter().all(|(xs, _)| xs.is_empty()) {
+            return Err(anyhow!(
+                "no data points left after removing x-range {} to {}",
+                left,
+                right
+            ));
         }
+        self.data = array_from_frames(&frames);
+        Ok(())
     }
     /// build vector of PlotPoints from 2D array
     pub fn to_plot_points(&self) -> Vec<PlotPoints> {
@@ -325,160 +1122,301 @@ impl Dataset {
             .map(|(xs, ys)| xs.iter().zip(ys).map(|(x, y)| [*x, *y]).collect())
             .collect()
     }
+    /// PlotPoints of each channel, on the x-axes of the frames
+    pub fn channel_plot_points(&self) -> Vec<(String, Vec<PlotPoints>)> {
+        self.channels
+            .iter()
+            .map(|(name, values)| {
+                let points = self
+                    .data
+                    .axis_iter(Axis(1))
+                    .step_by(2)
+                    .zip(values.axis_iter(Axis(1)))
+                    .map(|(xs, ys)| xs.iter().zip(ys).map(|(x, y)| [*x, *y]).collect())
+                    .collect();
+                (name.clone(), points)
+            })
+            .collect()
+    }
+}
+
+/// Find a value in comments like "exposure time = 1.5" (`name` being
+/// "exposure time"), if exactly one distinct value is given.
+pub(crate) fn value_from_comments(comments: &str, name: &str) -> Option<f64> {
+    let re = Regex::new(&format!(r"{} = ([0-9.eE+-]+)", regex::escape(name))).unwrap();
+    let mut values = re
+        .captures_iter(comments)
+        .filter_map(|c| c.get(1).and_then(|m| m.as_str().parse::<f64>().ok()));
+    let value = values.next()?;
+    if values.all(|other| other == value) {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Find a comma separated list of values in comments like
+/// "frame times = 0,1.5,3" (`name` being "frame times").
+fn values_from_comments(comments: &str, name: &str) -> Option<Vec<f64>> {
+    let re = Regex::new(&format!(
+        r"(?m){} = ([0-9.eE+-]+(?:,[0-9.eE+-]+)*)$",
+        regex::escape(name)
+    ))
+    .unwrap();
+    let values = re.captures(comments)?.get(1)?.as_str();
+    values.split(',').map(|v| v.parse::<f64>().ok()).collect()
+}
+
+/// Find the unit of the x-axis in comments written by a previous pipeline run,
+/// the last "x_unit: ..." entry wins.
+fn axis_unit_from_comments(comments: &str) -> AxisUnit {
+    let re = Regex::new(r"(?m)^# x_unit: ([a-z0-9-]+)$").unwrap();
+    re.captures_iter(comments)
+        .filter_map(|c| c.get(1).and_then(|m| m.as_str().parse::<AxisUnit>().ok()))
+        .last()
+        .unwrap_or_default()
+}
+
+/// Layout in the header info of CSV files whose first column is the x-axis
+/// of all frames.
+const SHARED_X_LAYOUT: &str = "shared-x";
+
+/// Separate the channel columns (see `Dataset::write`) from the x- and
+/// y-columns of the frames, with `shared_x` the x- and y-columns are returned
+/// as pairs as well.
+fn split_channel_columns(
+    columns: Array2<f64>,
+    names: &[String],
+    shared_x: bool,
+) -> Result<(Array2<f64>, Channels)> {
+    if !shared_x && names.is_empty() {
+        return Ok((columns, BTreeMap::new()));
+    }
+    let first = shared_x as usize;
+    let per_frame = 2 - first + names.len();
+    let ncols = columns.ncols();
+    if ncols < first || !(ncols - first).is_multiple_of(per_frame) {
+        return Err(anyhow!(
+            "{} columns do not fit frames with the channels {:?} of the header",
+            ncols,
+            names
+        ));
+    }
+    // index of the y-column of each frame, the channels follow it
+    let ys: Vec<usize> = (first..ncols)
+        .step_by(per_frame)
+        .map(|start| start + 1 - first)
+        .collect();
+    let data = if shared_x {
+        pairs_from_shared_x(columns.column(0), columns.select(Axis(1), &ys).view())
+    } else {
+        let pairs: Vec<usize> = ys.iter().flat_map(|y| [y - 1, *y]).collect();
+        columns.select(Axis(1), &pairs)
+    };
+    let channels = names
+        .iter()
+        .enumerate()
+        .map(|(j, name)| {
+            let channel: Vec<usize> = ys.iter().map(|y| y + 1 + j).collect();
+            (name.clone(), columns.select(Axis(1), &channel))
+        })
+        .collect();
+    Ok((data, channels))
+}
+
+/// Build the x/y column pairs of a dataset from a shared x-axis and the
+/// y-values of each frame (one column per frame).
+pub fn pairs_from_shared_x(x: ArrayView1<f64>, ys: ArrayView2<f64>) -> Array2<f64> {
+    Array2::from_shape_fn((x.len(), 2 * ys.ncols()), |(i, j)| {
+        if j % 2 == 0 {
+            x[i]
+        } else {
+            ys[[i, j / 2]]
+        }
+    })
+}
+
+/// Build the x/y column pairs of a dataset from frames of possibly different
+/// length, shorter frames are padded with NaN.
+fn array_from_frames(frames: &[(Vec<f64>, Vec<f64>)]) -> Array2<f64> {
+    let nrows = frames.iter().map(|(xs, _)| xs.len()).max().unwrap_or(0);
+    Array2::from_shape_fn((nrows, frames.len() * 2), |(i, j)| {
+        let (xs, ys) = &frames[j / 2];
+        let column = if j % 2 == 0 { xs } else { ys };
+        column.get(i).copied().unwrap_or(f64::NAN)
+    })
 }
 
 pub struct Pipeline {
     pub transformations: Vec<Box<dyn TransformerGUI>>,
 }
 
-/// Match name of tranformation struct in yaml header to identifier of transformation struct
-macro_rules! parse_yaml_transformer {
-     ( $transformer_struct_name:ident, $yaml_segment:ident, $( $x:ident ),* ) => { // x = transformer struct identifiers
-        match $transformer_struct_name {
-        $(
-            stringify!($x) => {
-                    let transformer: $x = serde_yaml::from_str($yaml_segment)
-                        .with_context(|| format!("Offending YAML input:\n{}", $yaml_segment))?;
-                    Ok(Box::new(transformer))
-            }
-        )*
-        _ => Err(anyhow!("Input string matches no known transformer:\n{}", $yaml_segment)),
+/// Parse a single segment of the yaml header as a transformer, if it contains
+/// 'transformation: ...' entry. A 'when: ...' entry makes the transformer
+/// conditional.
+pub(crate) fn yaml_segment_to_transform(segment: &String) -> Result<Box<dyn TransformerGUI>> {
+    let config: Mapping = serde_yaml::from_str(segment)
+        .with_context(|| format!("Offending YAML input:\n{}", segment))?;
+    yaml_mapping_to_transform(&config)
+}
+
+/// Build the transformer declared by the 'transformation: ...' entry of a
+/// document of the yaml header (see `yaml_segment_to_transform`).
+pub(crate) fn yaml_mapping_to_transform(config: &Mapping) -> Result<Box<dyn TransformerGUI>> {
+    let transformer = yaml_mapping_to_plain_transform(config)?;
+    match config.get("when") {
+        None => Ok(transformer),
+        Some(when) => {
+            let when = serde_yaml::from_value(when.clone())
+                .with_context(|| format!("Offending YAML input:\n{:?}", config))?;
+            Ok(Box::new(ConditionalTransform::new(when, transformer)))
         }
-    };
+    }
 }
 
-/// Parse a single segment of the yaml header as a transformer, if it contains
-/// 'transformation: ...' entry.
-fn yaml_segment_to_transform(segment: &String) -> Result<Box<dyn TransformerGUI>> {
-    let re = Regex::new(r"(?m)^transformation: ([a-zA-Z]*)$").unwrap();
-    let transformer_struct_name = match re
-        .captures(segment)
-        .and_then(|c| c.get(1)) // get first capture group
-        .map(|c| c.as_str()) // make it a str
-    {
-        None => return Err(anyhow!(format!("No transformer declared in input string: {}", segment))),
+fn yaml_mapping_to_plain_transform(config: &Mapping) -> Result<Box<dyn TransformerGUI>> {
+    let transformer_struct_name = match config.get("transformation").and_then(Value::as_str) {
+        None => return Err(anyhow!("No transformer declared in input: {:?}", config)),
         Some(name) => name,
     };
-    parse_yaml_transformer!(
-        transformer_struct_name,
-        segment,
-        // REGISTER: New transformer must be registered here to be parsable from yaml headers
-        AlignTransform,
-        AppendTransform,
-        AverageTransform,
-        CalibrationTransform,
-        CountConversionTransform,
-        DespikeTransform,
-        BaselineTransform,
-        FinningTransform,
-        IntegrateTransform,
-        MaskTransform,
-        NormalizeTransform,
-        OffsetTransform,
-        RamanShiftTransform,
-        ReshapeTransform,
-        SelectTransform,
-        SubtractTransform
-    )
+    let registration = transformer_by_tag(transformer_struct_name)
+        .ok_or_else(|| anyhow!("Input matches no known transformer:\n{:?}", config))?;
+    (registration.from_yaml)(Value::Mapping(config.clone()))
+        .with_context(|| format!("Offending YAML input:\n{:?}", config))
 }
 
 impl Pipeline {
     pub fn from_cli_args(cli_args: Vec<Vec<String>>) -> Self {
         let mut transformations: Vec<Box<dyn TransformerGUI>> = vec![];
+        let mut cli_args = cli_args.into_iter();
         // set gui flag so we know we must not react to plotting commands
         // which would cause a panic
-        for subargs in cli_args {
-            // REGISTER: new transformers must be entered here manually
-            // (consider using a macro in the future)
+        while let Some(subargs) = cli_args.next() {
             if let Some(command) = subargs.first() {
                 match command.as_str() {
-                    "align" => transformations.push(Box::new(AlignTransform::parse_from(subargs))),
-                    "append" => {
-                        transformations.push(Box::new(AppendTransform::parse_from(subargs)))
-                    }
-                    "average" => {
-                        transformations.push(Box::new(AverageTransform::parse_from(subargs)))
-                    }
-                    "baseline" => {
-                        transformations.push(Box::new(BaselineTransform::parse_from(subargs)))
-                    }
-                    "calibrate" => {
-                        transformations.push(Box::new(CalibrationTransform::parse_from(subargs)))
-                    }
-                    "despike" => {
-                        transformations.push(Box::new(DespikeTransform::parse_from(subargs)))
+                    "repeat" => {
+                        let mut repeat = RepeatTransform::parse_from(subargs);
+                        // the following commands form the repeated sub-pipeline
+                        let nested: Vec<Vec<String>> =
+                            cli_args.by_ref().take(repeat.commands).collect();
+                        repeat.steps = Pipeline::from_cli_args(nested).transformations;
+                        transformations.push(Box::new(repeat));
                     }
-                    "finning" => {
-                        transformations.push(Box::new(FinningTransform::parse_from(subargs)))
-                    }
-                    "mask" => transformations.push(Box::new(MaskTransform::parse_from(subargs))),
-                    "offset" => {
-                        transformations.push(Box::new(OffsetTransform::parse_from(subargs)))
-                    }
-                    "reshape" => {
-                        transformations.push(Box::new(ReshapeTransform::parse_from(subargs)))
-                    }
-                    "select" => {
-                        transformations.push(Box::new(SelectTransform::parse_from(subargs)))
-                    }
-                    "shift" => {
-                        transformations.push(Box::new(RamanShiftTransform::parse_from(subargs)))
-                    }
-                    "subtract" => {
-                        transformations.push(Box::new(SubtractTransform::parse_from(subargs)))
-                    }
-                    "count-conversion" => transformations
-                        .push(Box::new(CountConversionTransform::parse_from(subargs))),
-                    "integrate" => {
-                        transformations.push(Box::new(IntegrateTransform::parse_from(subargs)))
-                    }
-                    "normalize" => {
-                        transformations.push(Box::new(NormalizeTransform::parse_from(subargs)))
+                    "when" => {
+                        let mut conditional = ConditionalTransform::parse_from(subargs);
+                        // the condition applies to the step of the next
+                        // command, which may itself take further commands
+                        let mut following = Pipeline::from_cli_args(cli_args.by_ref().collect())
+                            .transformations
+                            .into_iter();
+                        conditional.step = following.next();
+                        conditional.gui_text_buffer = conditional.when.to_string();
+                        transformations.push(Box::new(conditional));
+                        transformations.extend(following);
                     }
                     "default" => transformations = default_transformations(),
-                    _ => {} // transformers for which GUI is not implemented:
-                            // "mask" => transformations.push(Box::new(MaskTransform::parse_from(subargs))),
+                    name => {
+                        // commands of the program (e.g. "gui") add no step
+                        if let Some(registration) = transformer_by_name(name) {
+                            let step = (registration.parse)(subargs).unwrap_or_else(|e| e.exit());
+                            transformations.push(step);
+                        }
+                    }
                 }
             };
         }
         Self { transformations }
     }
+    /// Read the steps of the pipeline from the comment header of a processed
+    /// file (see `Header::parse`).
     pub fn from_yaml_header(yaml_header: &str) -> Result<Self> {
-        let mut transformations = vec![];
-        for segment in yaml_header.split("---") {
-            let segment = segment.replace("# ", "").trim().to_string();
-            if segment.contains("transformation: ") {
-                transformations.push(yaml_segment_to_transform(&segment)?);
+        let transformations = Header::parse(yaml_header)?
+            .steps
+            .iter()
+            .map(yaml_mapping_to_transform)
+            .collect::<Result<_>>()?;
+        Ok(Self { transformations })
+    }
+    /// Check the parameters of all transformations against the dataset they
+    /// would be applied to, without running them. Returns all problems found.
+    pub fn validate(&self, ds: &Dataset, context: &PipelineContext) -> Vec<String> {
+        let mut shape = ds.shape();
+        let mut problems = vec![];
+        for (i, transformation) in self.transformations.iter().enumerate() {
+            for problem in transformation.validate(&mut shape, context) {
+                problems.push(format!("step {}: {}", i + 1, problem));
             }
         }
-        Ok(Self { transformations })
+        problems
     }
-    pub fn apply(&mut self, ds: &mut Dataset) -> Result<()> {
+    /// Apply all transformations to the dataset. In a dry run, the dataset is
+    /// left untouched and the problems found by `validate` are reported to
+    /// the diagnostics of the context instead.
+    pub fn apply(&mut self, ds: &mut Dataset, context: &PipelineContext) -> Result<()> {
+        if context.dry_run {
+            let problems = self.validate(ds, context);
+            if problems.is_empty() {
+                return Ok(());
+            }
+            for problem in problems.iter() {
+                context.diagnostics.warn(problem);
+            }
+            return Err(anyhow!("found {} problem(s) in pipeline", problems.len()));
+        }
         for transformation in &mut self.transformations {
-            transformation.apply(ds)?;
+            transformation.apply(ds, context)?;
         }
         Ok(())
     }
 }
 
+/// Steps of the `default` command: the pipeline of the file set in the
+/// config file, or the built-in one if there is none.
 pub fn default_transformations() -> Vec<Box<dyn TransformerGUI>> {
+    let Some(path) = &UserConfig::global().pipeline else {
+        return builtin_transformations();
+    };
+    let pipeline = input_data_to_string(&Some(path.to_owned()))
+        .and_then(|input_string| Pipeline::from_yaml_header(&input_string));
+    match pipeline {
+        Ok(mut pipeline) => {
+            pipeline
+                .transformations
+                .iter_mut()
+                .for_each(|trnsf| trnsf.update_text_buffers());
+            pipeline.transformations
+        }
+        Err(err) => {
+            UserConfig::warn(format!(
+                "using the built-in default pipeline, unable to read the pipeline of {}: {err}",
+                path.display()
+            ));
+            builtin_transformations()
+        }
+    }
+}
+
+fn builtin_transformations() -> Vec<Box<dyn TransformerGUI>> {
     let mut transformations: Vec<Box<dyn TransformerGUI>> = vec![];
     transformations.push(Box::new(ReshapeTransform { rows: 1340 }));
     transformations.push(Box::new(FinningTransform {
         threshold: 2.5,
         iterations: 4,
+        replaced: vec![],
     }));
-    transformations.push(Box::new(AverageTransform {}));
+    transformations.push(Box::new(AverageTransform::default()));
     transformations.push(Box::new(OffsetTransform {
         offset: 0.05,
         percentile: true,
         target_frames: None,
+        quantile_method: QuantileMethod::default(),
+        min_points: 1,
         gui_text_buffers: OffsetIOBuffers {
             value: "0.05".into(),
         },
     }));
     let mut rst = RamanShiftTransform {
-        wavelength: 532.1,
+        wavelength: UserConfig::global().laser_wavelength.unwrap_or(532.1),
         refractive_index: 1.000264,
         correction: Some(0.0),
         ..Default::default()
@@ -491,8 +1429,15 @@ pub fn default_transformations() -> Vec<Box<dyn TransformerGUI>> {
 
 #[cfg(test)]
 mod tests {
+    use super::{
+        input_data_to_string, CsvFormat, Dataset, FloatFormat, InputFormat, Locale, Pipeline,
+        PipelineContext,
+    };
+    use crate::metadata::{Header, SCHEMA_VERSION};
     use crate::transformations::finning::FinningTransform;
+    use ndarray::{array, s};
     use serde_yaml;
+    use std::path::Path;
 
     #[test]
     fn test_parse_header() {
@@ -530,4 +1475,225 @@ mod tests {
         }
         assert_eq!(commands_yaml, vec!["foo".to_string()]);
     }
+
+    #[test]
+    fn test_crop_x_range() {
+        let dataset = Dataset::new_test_dummy();
+        let cropped = dataset.crop_x_range(30.0, 52.0, None).unwrap();
+        assert_eq!(cropped.data.column(0), array![31., 41., 51.]);
+        assert_eq!(cropped.data.column(3).slice(s![..2]), array![34., 44.]);
+        assert!(cropped.data[[2, 3]].is_nan());
+        let resampled = dataset.crop_x_range(11.0, 51.0, Some(3)).unwrap();
+        assert_eq!(resampled.data.column(0), array![11., 31., 51.]);
+        assert_eq!(resampled.data.column(1), array![12., 32., 52.]);
+    }
+
+    #[test]
+    fn test_write() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.metadata = "preprocessor: arguments\n---\ntransformation: X\n---\n".to_owned();
+        dataset.previous_comments = "comments from input:\n# a comment\n".to_owned();
+        dataset.data[[0, 1]] = f64::NAN;
+        let mut buf = vec![];
+        dataset.write(&mut buf, FloatFormat::default()).unwrap();
+        let written = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines[0], "# rustman: metadata");
+        assert_eq!(
+            lines[3..10],
+            [
+                "# ---",
+                "# preprocessor: arguments",
+                "# ---",
+                "# transformation: X",
+                "# ---",
+                "# provenance: input",
+                "# comments: |",
+            ]
+        );
+        assert_eq!(
+            lines[12],
+            "1.100000e+01,NaN,1.300000e+01,1.400000e+01,1.500000e+01,1.600000e+01,1.700000e+01,1.800000e+01"
+        );
+        // the header is a single YAML stream, the comments of the input are
+        // read back as they were
+        let header = Header::parse(&written).unwrap();
+        assert_eq!(header.info.unwrap().schema, SCHEMA_VERSION);
+        assert_eq!(header.steps.len(), 1);
+        assert_eq!(
+            header.provenance.unwrap().comments,
+            dataset.previous_comments
+        );
+        // the shortest text, as written before the format could be chosen
+        let mut buf = vec![];
+        dataset.write(&mut buf, FloatFormat::Shortest).unwrap();
+        let written = String::from_utf8(buf).unwrap();
+        assert!(written.lines().nth(12).unwrap().starts_with("11,NaN,13,"));
+    }
+
+    #[test]
+    fn test_compressed_files() {
+        let dataset = Dataset::new_test_dummy();
+        let mut plain = vec![];
+        dataset.write(&mut plain, FloatFormat::default()).unwrap();
+        let dir = std::env::temp_dir().join(format!("raman-cli-tools-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["frames.csv", "frames.csv.gz", "frames.csv.zst"] {
+            let path = dir.join(name);
+            dataset.write_file(&path, FloatFormat::default()).unwrap();
+            let read = input_data_to_string(&Some(path.clone())).unwrap();
+            assert_eq!(read.as_bytes(), plain, "{}", name);
+            let written = std::fs::read(&path).unwrap();
+            assert_eq!(written == plain, name == "frames.csv", "{}", name);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_csv_formats() {
+        let path = Some(std::path::PathBuf::from("test/test_frames_export.csv"));
+        let format = CsvFormat {
+            header: true,
+            locale: Locale::De,
+            ..CsvFormat::new('#', ',')
+        };
+        let dataset = Dataset::from_csv_with(&path, &format).unwrap();
+        assert_eq!(dataset.data.shape(), &[3, 4]);
+        assert_eq!(dataset.data.row(0), array![500.5, 10.25, 500.5, 20.0]);
+        // the second frame is shorter
+        assert_eq!(dataset.data[[2, 1]], 12.0);
+        assert!(dataset.data[[2, 2]].is_nan() && dataset.data[[2, 3]].is_nan());
+        let labels: Vec<_> = dataset
+            .frame_metadata
+            .iter()
+            .map(|meta| meta.label.as_deref())
+            .collect();
+        assert_eq!(labels, vec![Some("sample A"), Some("sample B")]);
+        // without the header option, the column names are invalid numbers
+        let format = CsvFormat {
+            locale: Locale::De,
+            ..CsvFormat::new('#', ',')
+        };
+        let error = Dataset::from_csv_with(&path, &format).unwrap_err();
+        assert!(error.to_string().contains("\"wavelength\" in row 1"));
+    }
+
+    #[test]
+    fn test_input_formats() {
+        assert_eq!(InputFormat::of(Path::new("a.spe")), InputFormat::Spe);
+        assert_eq!(InputFormat::of(Path::new("a.TXT")), InputFormat::Txt);
+        assert_eq!(InputFormat::of(Path::new("a.txt.gz")), InputFormat::Txt);
+        assert_eq!(InputFormat::of(Path::new("a.csv.zst")), InputFormat::Csv);
+        assert_eq!(InputFormat::of(Path::new("a")), InputFormat::Csv);
+        // columns aligned by blanks, as written by spectrometer software
+        let path = std::env::temp_dir().join(format!("blanks-{}.txt", std::process::id()));
+        std::fs::write(&path, "# exported\n  500.0\t 10.0   \n  501.5  12.5\n\n").unwrap();
+        let dataset = Dataset::from_file(&Some(path.clone()), &CsvFormat::new('#', ',')).unwrap();
+        assert_eq!(dataset.data, array![[500.0, 10.0], [501.5, 12.5]]);
+        assert!(dataset.previous_comments.contains("# exported"));
+        // a given delimiter is used as is
+        let error = Dataset::from_file(&Some(path.clone()), &CsvFormat::new('#', ';'));
+        assert!(error.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_shared_x() {
+        let mut dataset = Dataset::new_test_dummy();
+        // every frame of the dummy has its own x-axis
+        assert!(dataset.shared_x_axis().is_none());
+        let x = dataset.data.column(0).to_owned();
+        for mut column in dataset.data.columns_mut().into_iter().step_by(2) {
+            column.assign(&x);
+        }
+        dataset.metadata = "transformation: MedianTransform\n---\n".to_owned();
+        assert_eq!(dataset.shared_x_axis(), Some(x.view()));
+        let path = std::env::temp_dir().join(format!("shared-x-{}.csv", std::process::id()));
+        let mut file = std::fs::File::create(&path).unwrap();
+        dataset
+            .write_shared_x(&mut file, FloatFormat::Shortest)
+            .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        let first_row = written.lines().find(|line| !line.starts_with('#')).unwrap();
+        assert_eq!(first_row.split(',').count(), 1 + dataset.data.ncols() / 2);
+        // read back as x- and y-column of each frame
+        let read = Dataset::from_csv(&Some(path.clone()), '#', ',').unwrap();
+        assert_eq!(read.data, dataset.data);
+        // the marker of the input does not apply to the output made from it
+        let mut pairs = vec![];
+        read.write(&mut pairs, FloatFormat::Shortest).unwrap();
+        std::fs::write(&path, pairs).unwrap();
+        let reread = Dataset::from_csv(&Some(path.clone()), '#', ',').unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(reread.data, dataset.data);
+        // frames on their own x-axis
+        dataset.data[[0, 2]] += 1.0;
+        assert!(dataset.shared_x_axis().is_none());
+        assert!(dataset
+            .write_shared_x(&mut vec![], FloatFormat::Shortest)
+            .is_err());
+    }
+
+    #[test]
+    fn test_channels() {
+        let mut dataset = Dataset::new_test_dummy_shared_x();
+        let baseline = dataset.data.slice(s![.., 1..;2]).mapv(|y| y / 2.0);
+        assert!(dataset
+            .set_channel("baseline", baseline.slice(s![..2, ..]).to_owned())
+            .is_err());
+        dataset.set_channel("baseline", baseline.clone()).unwrap();
+        dataset
+            .set_channel("sigma", baseline.mapv(f64::sqrt))
+            .unwrap();
+        let path = std::env::temp_dir().join(format!("channels-{}.csv", std::process::id()));
+        for shared_x in [false, true] {
+            let mut file = std::fs::File::create(&path).unwrap();
+            match shared_x {
+                true => dataset.write_shared_x(&mut file, FloatFormat::Shortest),
+                false => dataset.write(&mut file, FloatFormat::Shortest),
+            }
+            .unwrap();
+            let written = std::fs::read_to_string(&path).unwrap();
+            assert!(written.contains("# channels:\n# - baseline\n# - sigma\n"));
+            // x (once if shared), y, y_baseline and y_sigma of each frame
+            let first_row = written.lines().find(|line| !line.starts_with('#')).unwrap();
+            let frames = dataset.data.ncols() / 2;
+            let expected = if shared_x { 1 + 3 * frames } else { 4 * frames };
+            assert_eq!(first_row.split(',').count(), expected);
+            let read = Dataset::from_csv(&Some(path.clone()), '#', ',').unwrap();
+            assert_eq!(read.data, dataset.data);
+            assert_eq!(read.channels, dataset.channels);
+        }
+        std::fs::remove_file(&path).unwrap();
+        // averaged frames have no baseline of their own
+        dataset.data = dataset.data.slice(s![.., ..2]).to_owned();
+        assert_eq!(
+            dataset.drop_mismatched_channels(),
+            vec!["baseline".to_owned(), "sigma".to_owned()]
+        );
+        assert!(dataset.channels.is_empty());
+    }
+
+    #[test]
+    fn test_validate_pipeline() {
+        let dataset = Dataset::new_test_dummy();
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let mut pipeline = Pipeline::from_cli_args(vec![
+            args("select 2 3 5"),
+            args("reshape 3"),
+            args("integrate 10,20"),
+        ]);
+        let context = PipelineContext {
+            dry_run: true,
+            ..Default::default()
+        };
+        let mut transformed = dataset.clone();
+        assert!(pipeline.apply(&mut transformed, &context).is_err());
+        assert_eq!(transformed.data, dataset.data);
+        let problems = context.diagnostics.take();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].starts_with("step 1: frame number 5"));
+        assert!(problems[1].starts_with("step 2: cannot reshape"));
+        assert!(problems[2].starts_with("step 3: x-value 10"));
+    }
 }
@@ -0,0 +1,260 @@
+use crate::common::config_dir;
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+/// Keys of the config file, in the order they are documented.
+const KEYS: [&str; 6] = [
+    "delimiter",
+    "comment",
+    "laser-wavelength",
+    "conversion-factor",
+    "pipeline",
+    "theme",
+];
+
+/// Defaults of the user, read from `config.toml` in the config directory at
+/// startup, so constants of the instrument need not be typed again for every
+/// run. Arguments given on the command line take precedence.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UserConfig {
+    /// delimiting character of the input
+    pub delimiter: Option<char>,
+    /// character starting a comment in the input
+    pub comment: Option<char>,
+    /// laser wavelength in nm, used by the Raman shift
+    pub laser_wavelength: Option<f64>,
+    /// count to photoelectron conversion factor of the detector
+    pub conversion_factor: Option<f64>,
+    /// file with a pipeline in its header (e.g. a processed file), run by the
+    /// `default` command instead of the built-in pipeline
+    pub pipeline: Option<PathBuf>,
+    pub theme: Option<GuiTheme>,
+}
+
+/// Colors of the GUI.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GuiTheme {
+    /// dark or light, as the operating system
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl FromStr for GuiTheme {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "system" => Ok(GuiTheme::System),
+            "dark" => Ok(GuiTheme::Dark),
+            "light" => Ok(GuiTheme::Light),
+            _ => Err(anyhow!("unknown theme \"{s}\", use system, dark or light")),
+        }
+    }
+}
+
+static USER_CONFIG: OnceLock<UserConfig> = OnceLock::new();
+
+/// Problems with the config file, found before a pipeline runs, reported
+/// with the warnings of the next run (see `take_warnings`).
+static CONFIG_WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+impl UserConfig {
+    /// `RUSTMAN_CONFIG` if set, otherwise `config.toml` in the config
+    /// directory.
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("RUSTMAN_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// The config read at the first call, empty if there is no config file.
+    /// A config file that can not be read is reported and ignored, so a
+    /// typo does not stop the program. Tests always get the built-in
+    /// defaults, whatever the config file of the user holds.
+    pub fn global() -> &'static UserConfig {
+        USER_CONFIG.get_or_init(|| {
+            if cfg!(test) {
+                return UserConfig::default();
+            }
+            Self::load().unwrap_or_else(|err| {
+                Self::warn(format!("ignoring config file: {err:#}"));
+                UserConfig::default()
+            })
+        })
+    }
+
+    /// Keep a problem with the config file to be reported with the
+    /// warnings of the next run.
+    pub fn warn(message: impl Into<String>) {
+        if let Ok(mut warnings) = CONFIG_WARNINGS.lock() {
+            warnings.push(message.into());
+        }
+    }
+
+    /// Remove and return the problems with the config file found so far.
+    pub fn take_warnings() -> Vec<String> {
+        CONFIG_WARNINGS
+            .lock()
+            .map(|mut warnings| std::mem::take(&mut *warnings))
+            .unwrap_or_default()
+    }
+
+    /// The stored config, empty if there is none.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path().filter(|path| path.exists()) else {
+            return Ok(UserConfig::default());
+        };
+        let text = std::fs::read_to_string(&path)?;
+        Self::from_toml(&text).with_context(|| format!("{}", path.display()))
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = Self::path().ok_or_else(|| anyhow!("no config directory to store config in"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, self.to_toml()?)?;
+        Ok(path)
+    }
+
+    /// Set a key to the value given as text, e.g. on the command line.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "delimiter" => self.delimiter = Some(parse_char(value)?),
+            "comment" => self.comment = Some(parse_char(value)?),
+            "laser-wavelength" => self.laser_wavelength = Some(parse_positive(value)?),
+            "conversion-factor" => self.conversion_factor = Some(parse_positive(value)?),
+            "pipeline" => self.pipeline = Some(std::fs::canonicalize(value)?),
+            "theme" => self.theme = Some(value.parse()?),
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    /// Remove a key, so the built-in default applies again.
+    pub fn unset(&mut self, key: &str) -> Result<()> {
+        match key {
+            "delimiter" => self.delimiter = None,
+            "comment" => self.comment = None,
+            "laser-wavelength" => self.laser_wavelength = None,
+            "conversion-factor" => self.conversion_factor = None,
+            "pipeline" => self.pipeline = None,
+            "theme" => self.theme = None,
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+}
+
+fn unknown_key(key: &str) -> anyhow::Error {
+    anyhow!("unknown key \"{key}\", use one of {}", KEYS.join(", "))
+}
+
+fn parse_char(value: &str) -> Result<char> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(anyhow!("expected a single character, got \"{value}\"")),
+    }
+}
+
+fn parse_positive(value: &str) -> Result<f64> {
+    match value.parse::<f64>() {
+        Ok(number) if number > 0.0 => Ok(number),
+        _ => Err(anyhow!("expected a positive number, got \"{value}\"")),
+    }
+}
+
+/// Show or change the defaults in the config file.
+#[derive(Parser, Deserialize, Debug)]
+pub struct ConfigCommand {
+    #[clap(subcommand)]
+    pub action: Option<ConfigAction>,
+}
+
+#[derive(Subcommand, Deserialize, Debug)]
+pub enum ConfigAction {
+    /// Print the config file (the default).
+    Show,
+    /// Print the path of the config file, `RUSTMAN_CONFIG` if set.
+    Path,
+    /// Set a key: delimiter, comment, laser-wavelength, conversion-factor, pipeline or theme.
+    Set { key: String, value: String },
+    /// Remove a key, so the built-in default applies again.
+    Unset { key: String },
+}
+
+impl ConfigCommand {
+    /// Carry out the action, returns the text to print.
+    pub fn run(&self) -> Result<String> {
+        let path = UserConfig::path().ok_or_else(|| anyhow!("no config directory found"))?;
+        match &self.action {
+            None | Some(ConfigAction::Show) => {
+                let config = UserConfig::load()?;
+                Ok(format!("# {}\n{}", path.display(), config.to_toml()?))
+            }
+            Some(ConfigAction::Path) => Ok(format!("{}\n", path.display())),
+            Some(ConfigAction::Set { key, value }) => {
+                let mut config = UserConfig::load()?;
+                config.set(key, value)?;
+                config.save()?;
+                Ok("".to_owned())
+            }
+            Some(ConfigAction::Unset { key }) => {
+                let mut config = UserConfig::load()?;
+                config.unset(key)?;
+                config.save()?;
+                Ok("".to_owned())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GuiTheme, UserConfig};
+
+    #[test]
+    fn test_user_config() {
+        let config = UserConfig::from_toml(
+            "delimiter = \";\"\nlaser-wavelength = 785.0\ntheme = \"dark\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.delimiter, Some(';'));
+        assert_eq!(config.laser_wavelength, Some(785.0));
+        assert_eq!(config.theme, Some(GuiTheme::Dark));
+        assert_eq!(config.comment, None);
+        // typos are reported instead of being ignored silently
+        assert!(UserConfig::from_toml("laser_wavelength = 785.0").is_err());
+        let mut changed = config.clone();
+        changed.set("conversion-factor", "2.5").unwrap();
+        changed.unset("theme").unwrap();
+        assert_eq!(changed.conversion_factor, Some(2.5));
+        assert_eq!(changed.theme, None);
+        // problems are kept for the warnings of the next run
+        UserConfig::warn("ignoring config file");
+        assert_eq!(UserConfig::take_warnings(), vec!["ignoring config file"]);
+        assert!(UserConfig::take_warnings().is_empty());
+        assert!(changed.set("delimiter", "ab").is_err());
+        assert!(changed.set("laser-wavelength", "-1").is_err());
+        assert!(changed.set("wavelength", "785").is_err());
+        let written = changed.to_toml().unwrap();
+        assert_eq!(UserConfig::from_toml(&written).unwrap(), changed);
+    }
+}
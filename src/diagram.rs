@@ -0,0 +1,351 @@
+use crate::common::Pipeline;
+use anyhow::{anyhow, Result};
+use serde_yaml::{Mapping, Value};
+
+/// Language of a diagram of the pipeline, e.g. for the documentation of the
+/// data processing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagramFormat {
+    /// Graphviz, render with `dot -Tsvg`
+    Dot,
+    /// Mermaid flowchart, rendered by many markdown viewers
+    Mermaid,
+}
+
+impl std::str::FromStr for DiagramFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(DiagramFormat::Dot),
+            "mermaid" => Ok(DiagramFormat::Mermaid),
+            _ => Err(anyhow!(
+                "unknown diagram format \"{}\", use dot or mermaid",
+                s
+            )),
+        }
+    }
+}
+
+/// Longest parameter value shown in a node, longer values are shortened.
+const MAX_VALUE_LENGTH: usize = 40;
+
+/// Structure of a pipeline step, read from the YAML configuration it writes
+/// into the metadata, so that every transformation is covered.
+enum Node {
+    Step {
+        title: String,
+        parameters: Vec<String>,
+    },
+    /// step that only runs if the condition holds
+    Condition { when: String, step: Box<Node> },
+    /// steps nested in a step, e.g. the steps of a loop
+    Block {
+        title: String,
+        parameters: Vec<String>,
+        steps: Vec<Node>,
+    },
+}
+
+impl Node {
+    fn from_config(config: &str) -> Result<Self> {
+        let mapping: Mapping = serde_yaml::from_str(config)?;
+        Node::from_mapping(&mapping)
+    }
+    fn from_mapping(mapping: &Mapping) -> Result<Self> {
+        let title = mapping
+            .get("transformation")
+            .and_then(|name| name.as_str())
+            .ok_or_else(|| anyhow!("no transformation declared in step"))?;
+        let title = title.strip_suffix("Transform").unwrap_or(title).to_owned();
+        let parameters = mapping
+            .iter()
+            .filter_map(|(key, value)| {
+                let key = key.as_str()?;
+                let skip = ["transformation", "when", "steps"].contains(&key) || value.is_null();
+                (!skip).then(|| format!("{}: {}", key, format_value(value)))
+            })
+            .collect();
+        let node = match mapping.get("steps").and_then(|steps| steps.as_sequence()) {
+            None => Node::Step { title, parameters },
+            Some(steps) => Node::Block {
+                title,
+                parameters,
+                steps: steps
+                    .iter()
+                    .map(|step| {
+                        step.as_mapping()
+                            .ok_or_else(|| anyhow!("nested step is not a YAML mapping"))
+                            .and_then(Node::from_mapping)
+                    })
+                    .collect::<Result<_>>()?,
+            },
+        };
+        match mapping.get("when") {
+            None => Ok(node),
+            Some(when) => Ok(Node::Condition {
+                when: format_value(when),
+                step: Box::new(node),
+            }),
+        }
+    }
+}
+
+/// Value of a parameter on a single line, in YAML flow style.
+fn format_value(value: &Value) -> String {
+    let text = match value {
+        Value::Null => "null".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.to_owned(),
+        Value::Sequence(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(format_value)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Mapping(mapping) => format!(
+            "{{{}}}",
+            mapping
+                .iter()
+                .map(|(k, v)| format!("{}: {}", format_value(k), format_value(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Tagged(tagged) => format_value(&tagged.value),
+    };
+    match text.char_indices().nth(MAX_VALUE_LENGTH) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text,
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Shape {
+    /// input and output of the pipeline
+    Terminal,
+    Step,
+    Decision,
+}
+
+/// Collects the lines of the diagram in the syntax of the chosen format.
+struct DiagramWriter {
+    format: DiagramFormat,
+    lines: Vec<String>,
+    nodes: usize,
+    clusters: usize,
+    depth: usize,
+}
+
+/// End of a node an edge to the following node starts from, with the label
+/// of that edge.
+type Exit = (String, Option<&'static str>);
+
+impl DiagramWriter {
+    fn push(&mut self, line: String) {
+        self.lines
+            .push(format!("{}{}", "    ".repeat(self.depth), line));
+    }
+    fn node(&mut self, label: &[String], shape: Shape) -> String {
+        self.nodes += 1;
+        let id = format!("n{}", self.nodes);
+        let line = match self.format {
+            DiagramFormat::Dot => {
+                let shape = match shape {
+                    Shape::Terminal => "oval",
+                    Shape::Step => "box",
+                    Shape::Decision => "diamond",
+                };
+                let label: Vec<String> = label.iter().map(|l| l.replace('"', "\\\"")).collect();
+                format!("{} [label=\"{}\", shape={}];", id, label.join("\\n"), shape)
+            }
+            DiagramFormat::Mermaid => {
+                let label: Vec<String> = label.iter().map(|l| l.replace('"', "#quot;")).collect();
+                let label = label.join("<br/>");
+                match shape {
+                    Shape::Terminal => format!("{}([\"{}\"])", id, label),
+                    Shape::Step => format!("{}[\"{}\"]", id, label),
+                    Shape::Decision => format!("{}{{\"{}\"}}", id, label),
+                }
+            }
+        };
+        self.push(line);
+        id
+    }
+    fn edge(&mut self, from: &Exit, to: &str, dashed: bool) {
+        let (from, label) = from;
+        let line = match (self.format, label) {
+            (DiagramFormat::Dot, None) if dashed => format!("{} -> {} [style=dashed];", from, to),
+            (DiagramFormat::Dot, None) => format!("{} -> {};", from, to),
+            (DiagramFormat::Dot, Some(label)) if dashed => {
+                format!("{} -> {} [label=\"{}\", style=dashed];", from, to, label)
+            }
+            (DiagramFormat::Dot, Some(label)) => {
+                format!("{} -> {} [label=\"{}\"];", from, to, label)
+            }
+            (DiagramFormat::Mermaid, None) if dashed => format!("{} -.-> {}", from, to),
+            (DiagramFormat::Mermaid, None) => format!("{} --> {}", from, to),
+            (DiagramFormat::Mermaid, Some(label)) if dashed => {
+                format!("{} -.->|{}| {}", from, label, to)
+            }
+            (DiagramFormat::Mermaid, Some(label)) => format!("{} -->|{}| {}", from, label, to),
+        };
+        self.push(line);
+    }
+    fn begin_cluster(&mut self, label: &[String]) {
+        self.clusters += 1;
+        match self.format {
+            DiagramFormat::Dot => {
+                self.push(format!("subgraph cluster_{} {{", self.clusters));
+                self.depth += 1;
+                let label: Vec<String> = label.iter().map(|l| l.replace('"', "\\\"")).collect();
+                self.push(format!("label=\"{}\";", label.join("\\n")));
+                self.push("style=dashed;".to_owned());
+            }
+            DiagramFormat::Mermaid => {
+                let label: Vec<String> = label.iter().map(|l| l.replace('"', "#quot;")).collect();
+                self.push(format!(
+                    "subgraph cluster_{} [\"{}\"]",
+                    self.clusters,
+                    label.join("<br/>")
+                ));
+                self.depth += 1;
+            }
+        }
+    }
+    fn end_cluster(&mut self) {
+        self.depth -= 1;
+        match self.format {
+            DiagramFormat::Dot => self.push("}".to_owned()),
+            DiagramFormat::Mermaid => self.push("end".to_owned()),
+        }
+    }
+    /// Draw the node connected to the ends of the previous nodes, returns
+    /// the ends the next node is connected to.
+    fn draw(&mut self, node: &Node, previous: Vec<Exit>) -> Vec<Exit> {
+        match node {
+            Node::Step { title, parameters } => {
+                let label: Vec<String> = std::iter::once(title.clone())
+                    .chain(parameters.iter().cloned())
+                    .collect();
+                let id = self.node(&label, Shape::Step);
+                for exit in previous.iter() {
+                    self.edge(exit, &id, false);
+                }
+                vec![(id, None)]
+            }
+            Node::Condition { when, step } => {
+                let id = self.node(&[format!("when {}", when)], Shape::Decision);
+                for exit in previous.iter() {
+                    self.edge(exit, &id, false);
+                }
+                let mut exits = self.draw(step, vec![(id.clone(), Some("yes"))]);
+                exits.push((id, Some("no")));
+                exits
+            }
+            Node::Block {
+                title,
+                parameters,
+                steps,
+            } => {
+                let label: Vec<String> = std::iter::once(title.clone())
+                    .chain(parameters.iter().cloned())
+                    .collect();
+                self.begin_cluster(&label);
+                let first_node = self.nodes + 1;
+                let mut exits = previous;
+                for step in steps.iter() {
+                    exits = self.draw(step, exits);
+                }
+                self.end_cluster();
+                // the nested steps run again from the first one
+                if !steps.is_empty() {
+                    let first = format!("n{}", first_node);
+                    for exit in exits.iter() {
+                        self.edge(exit, &first, true);
+                    }
+                }
+                exits
+            }
+        }
+    }
+}
+
+/// Diagram of the steps of the pipeline with their parameters, from the
+/// input to the output. Conditions are drawn as decisions, nested steps
+/// (e.g. of a loop) as a group.
+pub fn pipeline_diagram(pipeline: &Pipeline, format: DiagramFormat) -> Result<String> {
+    let nodes = pipeline
+        .transformations
+        .iter()
+        .map(|step| Node::from_config(&step.config_to_string()?))
+        .collect::<Result<Vec<_>>>()?;
+    let mut writer = DiagramWriter {
+        format,
+        lines: vec![],
+        nodes: 0,
+        clusters: 0,
+        depth: 1,
+    };
+    let input = writer.node(&["input".to_owned()], Shape::Terminal);
+    let mut exits = vec![(input, None)];
+    for node in nodes.iter() {
+        exits = writer.draw(node, exits);
+    }
+    let output = writer.node(&["output".to_owned()], Shape::Terminal);
+    for exit in exits.iter() {
+        writer.edge(exit, &output, false);
+    }
+    let body = writer.lines.join("\n");
+    Ok(match format {
+        DiagramFormat::Dot => format!("digraph pipeline {{\n    rankdir=TB;\n{}\n}}\n", body),
+        DiagramFormat::Mermaid => format!("flowchart TD\n{}\n", body),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pipeline_diagram, DiagramFormat};
+    use crate::common::Pipeline;
+
+    #[test]
+    fn test_pipeline_diagram() {
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let pipeline = Pipeline::from_cli_args(vec![
+            vec!["when".to_owned(), "nframes > 1".to_owned()],
+            args("average"),
+            args("repeat 3 --commands 2"),
+            args("offset 1"),
+            args("offset 2"),
+        ]);
+        let dot = pipeline_diagram(&pipeline, DiagramFormat::Dot).unwrap();
+        let expected = [
+            "n1 [label=\"input\", shape=oval];",
+            "n2 [label=\"when nframes > 1\", shape=diamond];",
+            "n1 -> n2;",
+            "n3 [label=\"Average\\nresample: false\\ninterpolation: linear\", shape=box];",
+            "n2 -> n3 [label=\"yes\"];",
+            "subgraph cluster_1 {",
+            "label=\"Repeat\\ntimes: 3\";",
+            "n4 [label=\"Offset\\noffset: 1.0\\npercentile: false\", shape=box];",
+            "n3 -> n4;",
+            "n2 -> n4 [label=\"no\"];",
+            "n5 -> n4 [style=dashed];",
+            "n5 -> n6;",
+        ];
+        for line in expected {
+            assert!(dot.contains(line), "{} not in\n{}", line, dot);
+        }
+        let mermaid = pipeline_diagram(&pipeline, DiagramFormat::Mermaid).unwrap();
+        assert!(mermaid.starts_with("flowchart TD\n"));
+        for line in [
+            "n2{\"when nframes > 1\"}",
+            "n2 -->|no| n4",
+            "subgraph cluster_1 [\"Repeat<br/>times: 3\"]",
+            "n5 -.-> n4",
+        ] {
+            assert!(mermaid.contains(line), "{} not in\n{}", line, mermaid);
+        }
+    }
+}
@@ -2,49 +2,103 @@
 
 use std::{
     collections::{HashMap, VecDeque},
-    path::PathBuf,
-    sync::mpsc::{channel, Receiver, Sender},
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, TryRecvError},
 };
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use eframe::egui;
 use egui::{Color32, Slider, Ui};
-use egui_plot::{Legend, Line, Plot, PlotPoints};
+use egui_plot::{
+    CoordinatesFormatter, Corner, Legend, Line, LineStyle, MarkerShape, Plot, PlotBounds,
+    PlotMemory, PlotPoint, PlotPoints, Points, Polygon, Text,
+};
 use image::ColorType;
-use ndarray_stats::QuantileExt;
+use serde_yaml::Value;
 use sha256::digest;
 
 use crate::{
+    bundle::write_bundle,
     cli::Preprocessor,
-    common::{default_transformations, Dataset, Pair, Pipeline},
+    common::{
+        default_transformations, open_data_file, yaml_segment_to_transform, AxisUnit, Compression,
+        Dataset, Pair, Pipeline,
+    },
+    config::{GuiTheme, UserConfig},
+    diagram::{pipeline_diagram, DiagramFormat},
+    gui_dialogs::{
+        DialogAnswer, DialogPurpose, DialogRequest, DialogService, CSV, INPUT, PNG, SPE, TXT, ZIP,
+    },
+    gui_file_watcher::FileWatcher,
     gui_plot_extensions::{
-        IntegrateExtensionGUI, MaskExtensionGUI, NormalizeExtensionGUI, PlotExtensionGUI,
-        PlotExtensionResult, SplineExtensionGUI,
+        AlignExtensionGUI, IntegrateExtensionGUI, MaskExtensionGUI, NormalizeExtensionGUI,
+        PlotExtensionGUI, PlotExtensionResult, ReplacedPixelsExtensionGUI, SplineExtensionGUI,
+    },
+    gui_recent::RecentFiles,
+    history::format_history,
+    input_layout::{check_layout, LayoutCheck},
+    plot::{
+        draw_colorbar, viridis, ColorScaling, Colormap, Heatmap, HeatmapSettings, StackOffset,
+        PALETTE,
     },
-    plot::PALETTE,
+    registry::{Registration, TRANSFORMERS},
     transformations::{
         align::AlignTransform,
-        append::AppendTransform,
-        average::AverageTransform,
+        append::{AppendMismatch, AppendTransform},
+        arithmetic::{ArithmeticTransform, Operation},
+        average::{parse_weight_values, AverageTransform, AverageWeights},
         baseline::BaselineTransform,
-        calibration::CalibrationTransform,
+        bose_einstein::BoseEinsteinTransform,
+        calibration::{CalibrationTransform, Lamp},
+        clip::ClipTransform,
+        coadd_regions::CoaddRegionsTransform,
+        condition::{Condition, ConditionalTransform},
         count_conversion::CountConversionTransform,
-        despike::DespikeTransform,
+        deconvolve::{DeconvolutionMethod, DeconvolveTransform, LineShape},
+        derived::DerivedQuantity,
+        despike::{suggest_siglim, suggest_threshold, DespikeMethod, DespikeTransform},
+        drop_nan::DropNanTransform,
         finning::FinningTransform,
         integrate::IntegrateTransform,
-        mask_pixels::MaskTransform,
-        normalize::{NormalizeIOBuffers, NormalizeTransform},
-        offset::OffsetTransform,
+        kinetics::KineticsTransform,
+        kmeans::{KmeansOutput, KmeansTransform},
+        mask_pixels::{MaskMethod, MaskTransform},
+        mcr::{McrOutput, McrTransform},
+        median::MedianTransform,
+        normalize::NormalizeTransform,
+        offset::{OffsetTransform, QuantileMethod},
+        offset_stack::OffsetStackTransform,
+        peak_pick::PeakPickTransform,
+        plugin::{
+            available_plugins, plugin_dir, PluginParameter, PluginParameterKind, PluginTransform,
+        },
+        reject_outliers::RejectOutliersTransform,
+        repeat::RepeatTransform,
         reshape::ReshapeTransform,
+        response_correction::ResponseCorrectionTransform,
+        scale::ScaleTransform,
+        script::ScriptTransform,
         select::SelectTransform,
         shift::RamanShiftTransform,
+        similarity::{SimilarityMeasure, SimilarityTransform},
         subtract::SubtractTransform,
-        Transformer,
+        time_axis::TimeAxisTransform,
+        weight::WeightTransform,
+        x_shift::XShiftTransform,
+        PipelineContext, Transformer,
     },
+    utils::{linear_resample_array, lininterp, Interpolation},
 };
 
 pub fn gui_loop(mut preprocessor: Preprocessor) -> Result<()> {
+    let theme = UserConfig::global().theme.unwrap_or_default();
     let options = eframe::NativeOptions {
+        follow_system_theme: theme == GuiTheme::System,
+        default_theme: match theme {
+            GuiTheme::Light => eframe::Theme::Light,
+            _ => eframe::Theme::Dark,
+        },
         // initial_window_size: Some(egui::vec2(800.0, 600.0)),
         // maximized: true,
         ..Default::default()
@@ -55,17 +109,12 @@ pub fn gui_loop(mut preprocessor: Preprocessor) -> Result<()> {
         .iter_mut()
         .for_each(|tranformation| tranformation.update_text_buffers());
     let dataset = preprocessor.get_input_data()?;
-    // prepare file loading dialog in sub-thread
-    let (tx_input_path, rx_input_path) = channel::<Option<PathBuf>>();
-    let (tx_output_path, rx_output_path) = channel::<PathBuf>();
-    spawn_file_loader_thread(rx_input_path, tx_output_path);
     let _result = eframe::run_native(
         "Raman GUI",
         options,
-        Box::new(move |_cc| {
+        Box::new(move |cc| {
             Box::new(RamanGuiApp {
-                request_file_load: tx_input_path,
-                filepath_to_load: rx_output_path,
+                dialogs: DialogService::spawn(cc.egui_ctx.clone()),
                 pipeline,
                 dataset: dataset.clone(),
                 initial_dataset: dataset,
@@ -76,80 +125,146 @@ pub fn gui_loop(mut preprocessor: Preprocessor) -> Result<()> {
     Ok(())
 }
 
-fn spawn_file_loader_thread(
-    rx_input_path: Receiver<Option<PathBuf>>,
-    tx_output_path: Sender<PathBuf>,
-) {
-    std::thread::spawn(move || {
-        loop {
-            match rx_input_path.recv() {
-                Err(_) => break,
-                // no file to load requested
-                Ok(requested_file) => match requested_file {
-                    None => {
-                        std::thread::sleep(std::time::Duration::from_millis(10));
-                        continue;
-                    }
-                    Some(fp) => {
-                        let dir = fp.parent().unwrap_or(std::path::Path::new(""));
-                        if let Some(output_filepath) = rfd::FileDialog::new()
-                            .set_directory(dir)
-                            .add_filter("CSV", &["csv"])
-                            .add_filter("SPE", &["spe"])
-                            .pick_file()
-                        {
-                            let _result = tx_output_path.send(output_filepath);
-                        }
-                    }
-                },
-            }
-            // drain receiver so no further file open dialogs pop up
-            // if load button is clicked several times
-            while let Ok(_) = rx_input_path.try_recv() {}
-        }
-    });
-}
-
 struct RamanGuiApp {
+    /// hash of the cached dataset entering the active step, empty for the
+    /// initial dataset
+    active_input_hash: String,
     active_step: Option<usize>,
     add_step: Option<usize>,
+    /// whether the input of the active step is drawn behind the plot
+    compare_input: bool,
+    comparison_points: Option<Vec<PlotPoints>>,
+    /// points of each channel of the plotted dataset, see `Dataset::channels`
+    channel_points: Vec<(String, Vec<PlotPoints>)>,
+    /// step to attach a condition to, or to remove its condition from
+    condition_step: Option<usize>,
     dataset_cache: HashMap<String, Dataset>,
     dataset: Dataset,
+    /// file dialogs shown in a thread of their own
+    dialogs: DialogService,
+    envelope: Option<Envelope>,
     error_messages: VecDeque<String>,
-    filepath_to_load: Receiver<PathBuf>,
+    /// input file chosen in the dialog, loaded on the next pipeline run
+    file_to_load: Option<PathBuf>,
+    /// further files chosen with the input file, appended to it by the
+    /// first steps of the pipeline once it is loaded
+    files_to_append: Vec<PathBuf>,
     force_update: bool,
+    frame_coloring: FrameColoring,
+    frame_coloring_values: String,
+    frame_groups: String,
+    heatmap: Option<Heatmap>,
+    heatmap_settings: HeatmapSettings,
+    /// frame clicked in the heatmap, counting from 1
+    heatmap_frame: Option<usize>,
+    /// processing history of the input file, shown in a window while set
+    history: Option<String>,
     initial_dataset: Dataset,
     input_file_path: PathBuf,
-    insert_transformer: InsertTransformer,
+    /// watches the input file for changes while set
+    input_watcher: Option<FileWatcher>,
+    /// layout problems of the input data, checked once after loading
+    input_layout: Option<LayoutCheck>,
+    /// transformer chosen to be inserted into the pipeline
+    insert_transformer: Option<&'static Registration>,
+    /// whether the plots keep their visible region when the pipeline runs again
+    keep_view: bool,
+    nest_step: Option<usize>,
     last_dataset_hash: String,
+    last_preview_hash: String,
+    left_panel_rect: egui::Rect,
     output_file_path: PathBuf,
+    /// peaks marked by double-clicking a curve
+    peak_markers: Vec<PeakMarker>,
+    /// whether a double-click marks a peak instead of resetting the view
+    picking_peaks: bool,
     pipeline: Pipeline,
+    pipeline_job: Option<PipelineJob>,
+    /// screenshot of the plot panel waiting for the file to save it to
+    pending_screenshot: Option<egui::ColorImage>,
     plot_extension: Option<Box<dyn PlotExtensionGUI>>,
+    plot_layout: PlotLayout,
     plot_points: Vec<PlotPoints>,
     preprocessor: Preprocessor,
+    /// first lines of the input file, read when the preview is opened
+    raw_preview: Option<RawPreview>,
+    /// number of lines shown in the raw input preview
+    raw_preview_lines: usize,
+    /// input files opened recently and bookmarked directories
+    recent: RecentFiles,
+    /// names of the columns if the plotted dataset is a table of results
+    /// (one frame per column) instead of spectra
+    result_columns: Option<ResultColumns>,
+    release_step: Option<usize>,
+    /// input file to be read again on the next pipeline run
+    reload_input: bool,
     reload_pipeline: bool,
     remove_step: Option<usize>,
-    request_file_load: Sender<Option<PathBuf>>,
+    /// whether the channels of the frames are drawn as dashed lines
+    show_channels: bool,
+    showing_preview: bool,
+    sparklines: Vec<Option<Sparkline>>,
+    /// thumbnails of the frames entering each step, for the frame pickers
+    step_inputs: Vec<Option<Sparkline>>,
+    suggest_step: Option<usize>,
+    /// visible region of each plot in the last frame
+    view_bounds: Vec<PlotBounds>,
+    view_change: Option<ViewChange>,
+    visible_export_points: usize,
+    visible_x_range: Option<Pair<f64>>,
+    /// offset between successive frames, None if frames overlap
+    waterfall: Option<StackOffset>,
+    /// offset resolved for the plotted dataset
+    waterfall_offset: Option<f64>,
+    /// whether the input file is read again when it changes on disk
+    watch_input: bool,
 }
 
 impl eframe::App for RamanGuiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // Re-run data pipeline, if hash of pipeline configuration changed
-        if let Err(e) = self.run_pipeline_on_change() {
-            // error_message is reset by run_pipeline_on_change, if it runs through
-            self.error_messages
-                .push_front(format!("Could not run pipeline: {e}"));
+        while let Some(answer) = self.dialogs.try_answer() {
+            self.handle_dialog_answer(answer);
+        }
+        self.watch_input_file(ctx);
+        // while a slider is dragged, a decimated copy of the data gives quick feedback
+        let slider_dragged = ctx.dragged_id().is_some()
+            && ctx
+                .input(|i| i.pointer.press_origin())
+                .is_some_and(|pos| self.left_panel_rect.contains(pos));
+        if slider_dragged && self.can_preview() {
+            // a full resolution run would be outdated when the drag ends
+            self.cancel_pipeline_job();
+            self.run_preview_pipeline_on_change();
+        } else {
+            if self.showing_preview {
+                // drag ended, recompute the full resolution result
+                self.showing_preview = false;
+                self.force_update = true;
+            }
+            // Re-run data pipeline, if hash of pipeline configuration changed
+            if let Err(e) = self.run_pipeline_on_change() {
+                // error_message is reset by run_pipeline_on_change, if it runs through
+                self.error_messages
+                    .push_front(format!("Could not run pipeline: {e}"));
+            }
+            self.poll_pipeline_job();
+        }
+        if self.pipeline_job.is_some() {
+            // keep polling for results of the background run
+            ctx.request_repaint();
         }
+        self.status_bar(ctx);
         // put forms for transformers into side panel
         self.left_panel(ctx);
         // put plot and other visual information in center panel
         let plot_panel_rect = self.plot_panel(ctx);
+        self.history_window(ctx);
 
         // handle events
         ctx.input(|input_state| {
             input_state.raw.events.iter().for_each(|event| match event {
                 egui::Event::Screenshot { image, .. } => {
-                    self.save_screenshot(input_state, plot_panel_rect, image)
+                    self.request_screenshot_file(input_state, plot_panel_rect, image)
                 }
                 // TODO: for some reason, this freezes the app
                 // egui::Event::Key {
@@ -175,98 +290,24 @@ impl eframe::App for RamanGuiApp {
 impl RamanGuiApp {
     fn add_transformation_form(&mut self, ui: &mut Ui, i: usize) {
         egui::ComboBox::from_label("select transformation")
-            .selected_text(format!("{:?}", self.insert_transformer))
+            .selected_text(self.insert_transformer.map_or("None", |t| t.label))
             .show_ui(ui, |ui| {
-                // REGISTER: put new transformers here to make them show up in GUI
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Align,
-                    "Align",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Append,
-                    "Append File",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Average,
-                    "Average",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Calibrate,
-                    "Calibration",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::CountConversion,
-                    "Count-Conversion",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Baseline,
-                    "Draw Baseline",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Despike,
-                    "Despiking",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Finning,
-                    "Finning",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Integrate,
-                    "Integrate",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Mask,
-                    "Mask Points",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Normalize,
-                    "Normalize",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Offset,
-                    "Offset",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::RamanShift,
-                    "Raman Shift",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Reshape,
-                    "Reshape",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Select,
-                    "Select Frames",
-                );
-                ui.selectable_value(
-                    &mut self.insert_transformer,
-                    InsertTransformer::Subtract,
-                    "Subtract Frames",
-                );
+                for transformer in TRANSFORMERS.iter() {
+                    ui.selectable_value(
+                        &mut self.insert_transformer,
+                        Some(transformer),
+                        transformer.label,
+                    );
+                }
             });
         ui.horizontal(|ui| {
             if ui.button("Cancel").clicked() {
                 self.add_step = None;
-                self.insert_transformer = InsertTransformer::None;
+                self.insert_transformer = None;
             }
             if ui.button("OK").clicked() {
                 self.insert_transformation(i);
-                if self.insert_transformer != InsertTransformer::None {
+                if self.insert_transformer.is_some() {
                     // if inserted transform was not None (which does not get inserted into pipeline), set it active
                     self.plot_extension = self
                         .pipeline
@@ -277,14 +318,14 @@ impl RamanGuiApp {
                         .get_plot_extension(self.dataset.clone());
                     self.active_step = Some(i);
                 }
-                self.insert_transformer = InsertTransformer::None;
+                self.insert_transformer = None;
                 self.add_step = None;
             }
         });
     }
 
     fn left_panel(&mut self, ctx: &egui::Context) {
-        egui::SidePanel::left("leftpanel")
+        let resp = egui::SidePanel::left("leftpanel")
             .min_width(250.0)
             .show(ctx, |ui| {
                 egui::ScrollArea::vertical().show(ui, |ui| {
@@ -297,6 +338,20 @@ impl RamanGuiApp {
                     let mut delimiter = self.preprocessor.args.delimiter.to_string();
                     ui.text_edit_singleline(&mut delimiter);
                     self.preprocessor.args.delimiter = delimiter.chars().next().unwrap_or(',');
+                    ui.label("Notes:");
+                    let mut note = self.preprocessor.args.note.clone().unwrap_or_default();
+                    let notes = egui::TextEdit::multiline(&mut note)
+                        .desired_rows(2)
+                        .hint_text("sample ID, observations, ...");
+                    if ui
+                        .add(notes)
+                        .on_hover_text("written to the metadata of saved outputs")
+                        .changed()
+                    {
+                        self.preprocessor.args.note = Some(note).filter(|note| !note.is_empty());
+                    }
+                    self.input_layout_warnings(ui);
+                    self.raw_input_preview(ui);
                     ui.horizontal(|ui| {
                         ui.heading("Transformation Pipeline");
                         if ui
@@ -305,7 +360,30 @@ impl RamanGuiApp {
                             .clicked()
                         {
                             self.pipeline.transformations = default_transformations();
+                            for warning in UserConfig::take_warnings() {
+                                self.error_messages.push_front(warning);
+                            }
+                        }
+                        if ui
+                            .small_button("Check pipeline")
+                            .on_hover_text("Check parameters against the data without running.")
+                            .clicked()
+                        {
+                            self.check_pipeline();
                         }
+                        ui.menu_button("Diagram", |ui| {
+                            for (label, format) in [
+                                ("copy as Graphviz (DOT)", DiagramFormat::Dot),
+                                ("copy as Mermaid", DiagramFormat::Mermaid),
+                            ] {
+                                if ui.button(label).clicked() {
+                                    self.copy_pipeline_diagram(ui, format);
+                                    ui.close_menu();
+                                }
+                            }
+                        })
+                        .response
+                        .on_hover_text("Copy a diagram of the pipeline to the clipboard.");
                     });
                     let n_steps = self.pipeline.transformations.len();
                     for i in 0..n_steps {
@@ -335,8 +413,21 @@ impl RamanGuiApp {
                         _ = self.pipeline.transformations.remove(step);
                         self.remove_step = None;
                     }
+                    if let Some(step) = self.suggest_step.take() {
+                        self.suggest_parameters(step);
+                    }
+                    if let Some(step) = self.nest_step.take() {
+                        self.nest_next_step(step);
+                    }
+                    if let Some(step) = self.release_step.take() {
+                        self.release_last_step(step);
+                    }
+                    if let Some(step) = self.condition_step.take() {
+                        self.toggle_condition(step);
+                    }
                 });
             });
+        self.left_panel_rect = resp.response.rect;
     }
 
     fn plot_panel(&mut self, ctx: &egui::Context) -> egui::Rect {
@@ -344,38 +435,263 @@ impl RamanGuiApp {
         let resp = panel.show(ctx, |ui| {
             self.file_panel(ui, ctx);
             let mut allow_pan_when_extension_active = true;
+            let mut extension_active = false;
             if let Some(ext) = &mut self.plot_extension {
+                extension_active = *ext.get_is_active_reference();
                 ext.modify_ui(ui);
                 allow_pan_when_extension_active =
                     // allow panning if plot extension is not active
                     // or if panning is allowed by the extension
                     ext.is_pan_allowed() || !*ext.get_is_active_reference()
             };
-            Plot::new("plot")
-                .height(ctx.screen_rect().height() * 0.8)
-                .legend(Legend::default())
-                .allow_drag(allow_pan_when_extension_active)
-                .show(ui, |plot_ui| {
-                    let mut colorcycle = PALETTE.iter().cycle();
-                    // plot scans
-                    for (i, pts) in self.plot_points.iter().enumerate() {
-                        match pts {
+            self.plot_layout_controls(ui);
+            self.view_controls(ui);
+            let colors = self.frame_colors(ui);
+            if let Some(columns) = self.result_columns.clone() {
+                self.results_plot(ui, &columns, ctx.screen_rect().height() * 0.8);
+            }
+            let groups = match self.plot_layout {
+                _ if self.result_columns.is_some() => vec![],
+                PlotLayout::Overlay => vec![(0..self.plot_points.len()).collect()],
+                PlotLayout::Stacked => self.parse_frame_groups(),
+                PlotLayout::Envelope => {
+                    if self.envelope.is_none() {
+                        self.envelope = envelope_from_dataset(&self.dataset);
+                    }
+                    vec![vec![]]
+                }
+                PlotLayout::Heatmap => {
+                    self.update_heatmap(ctx);
+                    if let Some(heatmap) = &self.heatmap {
+                        heatmap.draw_colorbar(ui);
+                    }
+                    // the frame clicked in the heatmap is plotted below it
+                    match self
+                        .heatmap_frame
+                        .filter(|frame| *frame <= self.plot_points.len())
+                    {
+                        Some(frame) => {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("frame {}", frame));
+                                if ui
+                                    .button("extract")
+                                    .on_hover_text("Add a step selecting this frame.")
+                                    .clicked()
+                                {
+                                    self.extract_frame(frame);
+                                }
+                                if ui.button("close").clicked() {
+                                    self.heatmap_frame = None;
+                                }
+                            });
+                            vec![vec![], vec![frame - 1]]
+                        }
+                        None => vec![vec![]],
+                    }
+                }
+            };
+            if self.compare_input && self.comparison_points.is_none() {
+                self.comparison_points = Some(self.comparison_dataset().to_plot_points());
+            }
+            if self.waterfall_offset.is_none() {
+                self.waterfall_offset = self.waterfall.map(|stack| stack.resolve(&self.dataset));
+            }
+            let offset = self.waterfall_offset.unwrap_or(0.0);
+            let unit = self.dataset.x_unit.symbol();
+            let mut double_clicked = None;
+            let mut view_bounds = vec![];
+            let plot_height = ctx.screen_rect().height() * 0.8 / groups.len().max(1) as f32;
+            for (n, group) in groups.iter().enumerate() {
+                // the first plot keeps the id of the single overlay plot
+                let plot_id = if n == 0 {
+                    "plot".to_owned()
+                } else {
+                    format!("plot {}", n + 1)
+                };
+                // frames hidden in the legend of this plot in the last frame
+                let hidden = PlotMemory::load(ctx, ui.make_persistent_id(egui::Id::new(&plot_id)))
+                    .map(|memory| memory.hidden_items)
+                    .unwrap_or_default();
+                let visible_frames: Vec<usize> = group
+                    .iter()
+                    .copied()
+                    .filter(|i| !hidden.contains(&self.frame_name(*i)))
+                    .collect();
+                let plot_response = Plot::new(plot_id)
+                    .height(plot_height)
+                    .legend(Legend::default())
+                    .allow_drag(allow_pan_when_extension_active)
+                    .link_axis("frame groups", true, false)
+                    .link_cursor("frame groups", true, false)
+                    .allow_double_click_reset(!self.picking_peaks)
+                    .coordinates_formatter(
+                        Corner::LeftBottom,
+                        CoordinatesFormatter::new(move |point, _| {
+                            format!("x: {:.2} {}\ny: {:.4}", point.x, unit, point.y)
+                        }),
+                    )
+                    .show(ui, |plot_ui| {
+                        match &self.view_change {
+                            Some(ViewChange::Limits(bounds)) if n == 0 => {
+                                plot_ui.set_plot_bounds(*bounds)
+                            }
+                            Some(ViewChange::Keep(bounds)) => {
+                                if let Some(bounds) = bounds.get(n) {
+                                    plot_ui.set_plot_bounds(*bounds)
+                                }
+                            }
+                            Some(ViewChange::Reset) => plot_ui.set_auto_bounds(true.into()),
+                            _ => {}
+                        }
+                        if let (PlotLayout::Envelope, Some(envelope)) =
+                            (self.plot_layout, &self.envelope)
+                        {
+                            draw_envelope(plot_ui, envelope);
+                        }
+                        if let (PlotLayout::Heatmap, Some(heatmap), 0) =
+                            (self.plot_layout, &self.heatmap, n)
+                        {
+                            heatmap.draw(plot_ui);
+                            if plot_ui.response().clicked() && !extension_active {
+                                if let Some(frame) = plot_ui
+                                    .pointer_coordinate()
+                                    .and_then(|point| heatmap.frame_at(point.y))
+                                {
+                                    self.heatmap_frame = Some(frame);
+                                }
+                            }
+                        }
+                        // input of the active step in the background
+                        if let (true, Some(comparison)) =
+                            (self.compare_input, &self.comparison_points)
+                        {
+                            for i in group.iter() {
+                                if let Some(PlotPoints::Owned(ps)) = comparison.get(*i) {
+                                    let pts = shifted_points(ps, *i as f64 * offset);
+                                    plot_ui.line(
+                                        Line::new(pts).color(Color32::from_gray(170)).name("input"),
+                                    );
+                                }
+                            }
+                        }
+                        // plot scans
+                        for i in group.iter() {
                             // Since PlotPoints is an Enum, we have to do a little
                             // unwrapping here in order to clone the points.
                             // Points must be cloned because `Line` needs to own them.
-                            PlotPoints::Owned(ps) => {
-                                let pts = PlotPoints::Owned(ps.clone());
-                                let color = colorcycle.next().unwrap(); // since we cycle, there will always be a next element
-                                plot_ui.line(Line::new(pts).color(*color).name(i + 1));
+                            if let PlotPoints::Owned(ps) = &self.plot_points[*i] {
+                                let pts = shifted_points(ps, *i as f64 * offset);
+                                // color by frame number, so frames keep their color in every layout
+                                plot_ui.line(
+                                    Line::new(pts).color(colors[*i]).name(self.frame_name(*i)),
+                                );
                             }
-                            _ => {}
                         }
-                    }
-                    // plot extension elements
-                    if let Some(ext) = &mut self.plot_extension {
-                        ext.modify_plot(plot_ui)
-                    }
-                });
+                        // channels of the frames, e.g. a kept baseline
+                        if self.show_channels {
+                            for (name, points) in self.channel_points.iter() {
+                                for i in group.iter() {
+                                    if let Some(PlotPoints::Owned(ps)) = points.get(*i) {
+                                        let pts = shifted_points(ps, *i as f64 * offset);
+                                        plot_ui.line(
+                                            Line::new(pts)
+                                                .color(colors[*i])
+                                                .style(LineStyle::dashed_dense())
+                                                .name(format!("{} {}", self.frame_name(*i), name)),
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                        // data tip for the frame closest to the cursor
+                        if plot_ui.response().hovered() {
+                            if let Some((i, point)) =
+                                plot_ui.pointer_coordinate().and_then(|cursor| {
+                                    nearest_frame_at(&self.plot_points, group, cursor, offset)
+                                })
+                            {
+                                plot_ui.points(
+                                    Points::new(vec![[point.x, point.y]])
+                                        .radius(4.0)
+                                        .color(colors[i]),
+                                );
+                                plot_ui.text(
+                                    Text::new(
+                                        point,
+                                        format!("frame {}: {:.4}", self.frame_name(i), point.y),
+                                    )
+                                    .anchor(egui::Align2::LEFT_BOTTOM)
+                                    .color(colors[i]),
+                                );
+                            }
+                        }
+                        // peaks marked on the frames of this plot
+                        for marker in self.peak_markers.iter() {
+                            if let Some(point) = marker.position(&self.plot_points, group, offset) {
+                                let color =
+                                    colors.get(marker.frame).copied().unwrap_or(Color32::GRAY);
+                                plot_ui.points(
+                                    Points::new(vec![[point.x, point.y]])
+                                        .shape(MarkerShape::Down)
+                                        .filled(true)
+                                        .radius(5.0)
+                                        .color(color),
+                                );
+                                plot_ui.text(
+                                    Text::new(point, format!("{:.1} {}", point.x, unit))
+                                        .anchor(egui::Align2::CENTER_BOTTOM)
+                                        .color(color),
+                                );
+                            }
+                        }
+                        if self.picking_peaks
+                            && !extension_active
+                            && plot_ui.response().double_clicked()
+                        {
+                            if let Some(cursor) = plot_ui.pointer_coordinate() {
+                                // a marker close to the cursor is removed,
+                                // otherwise the closest curve is marked
+                                let screen = plot_ui.screen_from_plot(cursor);
+                                let hit = self.peak_markers.iter().position(|marker| {
+                                    marker
+                                        .position(&self.plot_points, group, offset)
+                                        .is_some_and(|point| {
+                                            plot_ui.screen_from_plot(point).distance(screen) < 8.0
+                                        })
+                                });
+                                double_clicked = match hit {
+                                    Some(k) => Some(PeakClick::Remove(k)),
+                                    None => {
+                                        nearest_frame_at(&self.plot_points, group, cursor, offset)
+                                            .map(|(frame, point)| {
+                                                PeakClick::Add(PeakMarker { x: point.x, frame })
+                                            })
+                                    }
+                                };
+                            }
+                        }
+                        // plot extension elements
+                        if let Some(ext) = &mut self.plot_extension {
+                            ext.set_visible_frames(&visible_frames);
+                            ext.modify_plot(plot_ui)
+                        }
+                    });
+                if n == 0 {
+                    let bounds = plot_response.transform.bounds();
+                    self.visible_x_range = Some(Pair {
+                        a: bounds.min()[0],
+                        b: bounds.max()[0],
+                    });
+                }
+                view_bounds.push(*plot_response.transform.bounds());
+            }
+            self.view_bounds = view_bounds;
+            self.view_change = None;
+            match double_clicked {
+                Some(PeakClick::Add(marker)) => self.peak_markers.push(marker),
+                Some(PeakClick::Remove(k)) => _ = self.peak_markers.remove(k),
+                None => {}
+            }
             // error log
             let scroll_area = egui::ScrollArea::vertical().max_height(100.0);
             while self.error_messages.len() > 5 {
@@ -392,10 +708,16 @@ impl RamanGuiApp {
     }
 
     fn file_panel(&mut self, ui: &mut Ui, ctx: &egui::Context) {
-        let mut out_text = self
+        let out_path = self
             .output_file_path
             .to_str()
-            .unwrap_or("non UTF-8 characters in filepath are not allowed");
+            .unwrap_or("non UTF-8 characters in filepath are not allowed")
+            .to_owned();
+        let mut out_text = out_path.as_str();
+        let mut save_visible = false;
+        let mut save_bundle = false;
+        let mut show_history = false;
+        let mut process_like = false;
 
         ui.horizontal(|ui| {
             ui.vertical(|ui| {
@@ -422,179 +744,1365 @@ impl RamanGuiApp {
                 let button_width = 70.0;
                 let b = egui::Button::new("...").min_size(egui::Vec2::new(button_width, 10.));
                 if ui.add(b).clicked() {
-                    self.request_file_load
-                        .send(Some(self.input_file_path.clone()))
-                        .expect("this should not have happend, please file an issue");
+                    self.dialogs.request(
+                        DialogRequest::open(DialogPurpose::LoadInput, &self.input_file_path)
+                            .multiple()
+                            .filter(INPUT)
+                            .filter(CSV)
+                            .filter(SPE)
+                            .filter(TXT),
+                    );
+                }
+                let b = egui::Button::new(egui::WidgetText::from("reload"))
+                    .min_size(egui::Vec2::new(button_width, 10.));
+                if ui
+                    .add(b)
+                    .on_hover_text("Read the input file again and rerun the pipeline.")
+                    .clicked()
+                {
+                    self.reload_input = true;
                 }
+                self.recent_menu(ui);
                 let b = egui::Button::new(egui::WidgetText::from("save csv"))
                     .min_size(egui::Vec2::new(button_width, 10.));
                 if ui.add(b).clicked() {
-                    let dir = self
-                        .output_file_path
-                        .parent()
-                        .unwrap_or(std::path::Path::new(""));
-                    let filename = self
-                        .output_file_path
-                        .file_name()
-                        .map(|name| name.to_str().unwrap_or_default())
-                        .unwrap_or_default();
-                    if let Some(filepath) = rfd::FileDialog::new()
-                        .set_directory(dir)
-                        .add_filter("CSV", &["csv"])
-                        .set_file_name(&filename)
-                        .save_file()
-                    {
-                        let handle = std::fs::File::create(filepath).unwrap();
-                        let wrt = std::io::BufWriter::new(handle);
-                        self.dataset.write(wrt).unwrap();
-                    }
+                    self.dialogs.request(
+                        DialogRequest::save(DialogPurpose::SaveCsv, &self.output_file_path)
+                            .filter(CSV),
+                    );
+                }
+                let b = egui::Button::new(egui::WidgetText::from("save visible"))
+                    .min_size(egui::Vec2::new(button_width, 10.));
+                if ui
+                    .add(b)
+                    .on_hover_text("Save only the visible x-range as CSV.")
+                    .clicked()
+                {
+                    save_visible = true;
+                }
+                let b = egui::Button::new(egui::WidgetText::from("save bundle"))
+                    .min_size(egui::Vec2::new(button_width, 10.));
+                if ui
+                    .add(b)
+                    .on_hover_text(
+                        "Save input, pipeline, the data after every step and the result as ZIP archive.",
+                    )
+                    .clicked()
+                {
+                    save_bundle = true;
                 }
                 let b = egui::Button::new(egui::WidgetText::from("save plot"))
                     .min_size(egui::Vec2::new(button_width, 10.));
                 if ui.add(b).clicked() {
                     ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot);
                 }
+                let b = egui::Button::new(egui::WidgetText::from("history"))
+                    .min_size(egui::Vec2::new(button_width, 10.));
+                if ui
+                    .add(b)
+                    .on_hover_text("Show the processing history recorded in the input file.")
+                    .clicked()
+                {
+                    show_history = true;
+                }
+                let b = egui::Button::new(egui::WidgetText::from("process like…"))
+                    .min_size(egui::Vec2::new(button_width, 10.));
+                if ui
+                    .add(b)
+                    .on_hover_text(
+                        "Apply the pipeline of a processed file to the loaded data, the data of the file is not loaded.",
+                    )
+                    .clicked()
+                {
+                    process_like = true;
+                }
+            });
+            ui.vertical(|ui| {
+                ui.checkbox(&mut self.reload_pipeline, "reload pipeline?")
+                    .on_hover_text("When opening a processed file, load its input data and pipeline instead of its data. Use \"process like…\" to take only the pipeline.");
+                ui.checkbox(&mut self.watch_input, "watch input file")
+                    .on_hover_text("Read the input file again and rerun the pipeline whenever it changes on disk, e.g. during an acquisition.");
+                ui.horizontal(|ui| {
+                    ui.add(egui::DragValue::new(&mut self.visible_export_points));
+                    ui.label("points")
+                        .on_hover_text("Resample visible region on this many points (0 = off).");
+                });
             });
-            ui.checkbox(&mut self.reload_pipeline, "reload pipeline?")
         });
-    }
-
-    fn run_pipeline_on_change(&mut self) -> Result<()> {
-        // check if pipeline from previous run should be loaded
-        if self.preprocessor.reload_pipeline {
-            self.preprocessor.reload_pipeline = false;
-            // TODO: Refactor to make this part DRY
-            let input_string =
-                crate::common::input_data_to_string(&Some(self.input_file_path.to_owned()))?;
-            let prp_result =
-                Preprocessor::from_yaml_header(&input_string, true).map_err(|e| eprintln!("{e}"));
-            if prp_result.is_ok() {
-                let mut prp = prp_result.unwrap();
-                self.initial_dataset = prp.get_input_data()?;
-                self.dataset = self.initial_dataset.clone();
-                self.pipeline = Pipeline::from_yaml_header(&input_string)?;
-                self.pipeline
-                    .transformations
-                    .iter_mut()
-                    .for_each(|trnsf| trnsf.update_text_buffers());
-                self.input_file_path = prp.args.filepath.unwrap_or(PathBuf::default());
-                self.output_file_path = make_output_filepath(&self.input_file_path);
-            }
+        if save_bundle {
+            self.request_bundle_file();
         }
-        // check if new file should be loaded
-        if let Ok(filepath) = self.filepath_to_load.try_recv() {
-            self.output_file_path = make_output_filepath(&filepath);
-            self.input_file_path = filepath.clone();
-            self.preprocessor.args.filepath = Some(filepath.clone());
-            // if the input file can be parsed as a result from a previous run, load the prev. run
-            let input_string = if filepath
-                .extension()
-                .as_ref()
-                .is_some_and(|ext| *ext == "csv")
-            {
-                crate::common::input_data_to_string(&Some(self.input_file_path.to_owned()))?
-            } else {
-                "".to_string()
-            };
-            let prp_result =
-                Preprocessor::from_yaml_header(&input_string, true).map_err(|e| eprintln!("{e}"));
-            if prp_result.is_ok() && self.reload_pipeline {
-                let mut prp = prp_result.unwrap();
-                dbg!("get input");
-                self.initial_dataset = prp.get_input_data()?;
-                self.dataset = self.initial_dataset.clone();
-                self.pipeline = Pipeline::from_yaml_header(&input_string).map_err(|err| {
-                    eprintln!(
-                        "WARNING: Unable to read pipeline from input file {:?}: {}",
-                        self.input_file_path
-                            .file_name()
-                            .and_then(|name| name.to_str())
-                            .unwrap_or("(unreadable file name)"),
-                        err
-                    );
-                    err
-                })?;
-                self.pipeline
-                    .transformations
-                    .iter_mut()
-                    .for_each(|trnsf| trnsf.update_text_buffers());
-                self.input_file_path = prp.args.filepath.unwrap_or(PathBuf::default());
-                self.output_file_path = make_output_filepath(&self.input_file_path);
-            } else {
-                let ds = self.preprocessor.get_input_data()?;
-                self.initial_dataset = ds;
-                self.dataset = self.initial_dataset.clone();
-            }
-            self.force_update = true;
-            self.dataset_cache = HashMap::new(); // reset cache
+        if show_history {
+            self.show_history();
         }
-
-        // detect change by the hash of the serialized pipeline configuration
-        let pipeline_hash = {
-            let conf_str: String = self
-                .pipeline
-                .transformations
-                .iter()
-                .map(|trnsf| trnsf.config_to_string().unwrap())
-                .collect();
-            digest(conf_str)
-        };
-        // if the pipeline did not change, we do nothing
-        if self.last_dataset_hash == pipeline_hash && !self.force_update {
-            return Ok(());
+        if process_like {
+            self.dialogs.request(
+                DialogRequest::open(DialogPurpose::ProcessLike, &self.input_file_path).filter(CSV),
+            );
         }
-        self.last_dataset_hash = pipeline_hash;
-        self.force_update = false;
-        self.dataset = self.initial_dataset.clone();
-        // otherwise, we re-apply the transformations, reusing cache if possible
-        let mut last_transformer_hash = "".to_owned();
-        for (i, trnsf) in self.pipeline.transformations.iter_mut().enumerate() {
-            let is_last_iter = self.active_step.map(|n| n == i).unwrap_or_default();
-            if is_last_iter && !trnsf.should_plot_dataset_state_after_transformation() {
-                // if the dataset is to be plotted before the transformation
-                // happens, we can stop iterating here
-                break;
+        if save_visible && self.visible_x_range.is_some() {
+            self.dialogs.request(
+                DialogRequest::save(
+                    DialogPurpose::SaveVisible,
+                    &suffixed_path(&self.output_file_path, "visible"),
+                )
+                .filter(CSV),
+            );
+        }
+    }
+
+    /// Menu with the recently opened files and the bookmarked directories.
+    fn recent_menu(&mut self, ui: &mut Ui) {
+        let mut open = None;
+        let mut browse = None;
+        let mut toggle_bookmark = false;
+        let input_dir = self
+            .input_file_path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map(|dir| std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_owned()));
+        ui.menu_button("recent", |ui| {
+            if self.recent.files.is_empty() {
+                ui.label("no recent files");
             }
-            // use hash to salt new hash, to make hashes depend on the whole
-            // history of the data pipeline
-            let hash = digest(trnsf.config_to_string().unwrap() + &last_transformer_hash);
-            if let Some(cache) = self.dataset_cache.get(&hash) {
-                self.dataset = cache.clone();
-            } else {
-                if let Err(err) = trnsf.apply(&mut self.dataset) {
-                    self.error_messages.push_front(err.to_string());
-                    break;
+            for file in self.recent.files.iter() {
+                if ui.button(file.display().to_string()).clicked() {
+                    open = Some(file.clone());
+                    ui.close_menu();
                 }
-                self.dataset_cache
-                    .insert(hash.clone(), self.dataset.clone());
             }
-            if is_last_iter {
-                break;
+            ui.separator();
+            for dir in self.recent.bookmarks.iter() {
+                if ui
+                    .button(format!("📁 {}", dir.display()))
+                    .on_hover_text("Open a file in this directory.")
+                    .clicked()
+                {
+                    browse = Some(dir.clone());
+                    ui.close_menu();
+                }
             }
-            last_transformer_hash = hash;
+            if let Some(dir) = &input_dir {
+                let label = if self.recent.is_bookmarked(dir) {
+                    "remove bookmark of input directory"
+                } else {
+                    "bookmark input directory"
+                };
+                if ui.button(label).clicked() {
+                    toggle_bookmark = true;
+                    ui.close_menu();
+                }
+            }
+        });
+        if let Some(file) = open {
+            self.file_to_load = Some(file);
         }
-        // update the plot
-        if let Some(trnsf) = self
-            .active_step
-            .and_then(|step| self.pipeline.transformations.get(step))
-        {
-            self.plot_extension = trnsf.get_plot_extension(self.dataset.clone());
-        } else {
-            self.plot_extension = None;
+        if let Some(dir) = browse {
+            self.dialogs.request(
+                DialogRequest::open_in(DialogPurpose::LoadInput, &dir)
+                    .multiple()
+                    .filter(INPUT)
+                    .filter(CSV)
+                    .filter(SPE)
+                    .filter(TXT),
+            );
         }
-        self.plot_points = self.dataset.to_plot_points();
+        if let (true, Some(dir)) = (toggle_bookmark, input_dir) {
+            self.recent.toggle_bookmark(&dir);
+            self.save_recent_files();
+        }
+    }
 
-        Ok(())
+    /// Add an append step for each further file chosen with the input file,
+    /// in the order they were chosen, at the start of the pipeline.
+    fn append_chosen_files(&mut self) {
+        let files = std::mem::take(&mut self.files_to_append);
+        for (i, file) in files.into_iter().enumerate() {
+            self.pipeline.transformations.insert(
+                i,
+                Box::new(AppendTransform {
+                    filepath: Some(file),
+                    comment: self.preprocessor.args.comment,
+                    delimiter: self.preprocessor.args.delimiter,
+                    horizontal: false,
+                    mismatch: AppendMismatch::default(),
+                    interpolation: Interpolation::default(),
+                }),
+            );
+        }
     }
 
-    fn transformer_form(&mut self, ui: &mut Ui, i: usize) {
-        ui.group(|ui| {
-            let trnsf = self.pipeline.transformations.get_mut(i).unwrap();
-            trnsf.render_form(ui);
-            ui.horizontal(|ui| {
-                if ui.button("Remove").clicked() {
-                    self.remove_step = Some(i);
-                    self.force_update = true;
+    fn save_recent_files(&mut self) {
+        if let Err(e) = self.recent.save() {
+            self.error_messages
+                .push_front(format!("Could not save recent files: {e}"));
+        }
+    }
+
+    /// Keep a watcher on the input file while watching is switched on and
+    /// schedule a reload when the file changed.
+    fn watch_input_file(&mut self, ctx: &egui::Context) {
+        if !self.watch_input {
+            self.input_watcher = None;
+            return;
+        }
+        if self
+            .input_watcher
+            .as_ref()
+            .is_none_or(|watcher| watcher.path() != self.input_file_path)
+        {
+            self.input_watcher = Some(FileWatcher::spawn(&self.input_file_path, ctx.clone()));
+        }
+        if self
+            .input_watcher
+            .as_ref()
+            .is_some_and(|watcher| watcher.changed())
+        {
+            self.reload_input = true;
+        }
+    }
+
+    fn handle_dialog_answer(&mut self, answer: DialogAnswer) {
+        let filepath = answer.path;
+        match answer.purpose {
+            DialogPurpose::LoadInput => {
+                self.file_to_load = Some(filepath);
+                self.files_to_append = answer.further;
+            }
+            DialogPurpose::ProcessLike => self.process_like_file(filepath),
+            DialogPurpose::SaveBundle => self.save_bundle(&filepath),
+            DialogPurpose::SaveCsv => self.save_csv(&filepath),
+            DialogPurpose::SavePeaks => self.save_peak_markers(&filepath),
+            DialogPurpose::SavePlot => self.save_screenshot(&filepath),
+            DialogPurpose::SaveVisible => self.save_visible_region(&filepath),
+        }
+    }
+
+    fn save_csv(&mut self, filepath: &Path) {
+        // the notes may have been edited after loading the input
+        let mut dataset = self.dataset.clone();
+        let written = self
+            .preprocessor
+            .refresh_metadata(&mut dataset)
+            .and_then(|_| dataset.write_file(filepath, self.preprocessor.args.float_format));
+        if let Err(err) = written {
+            self.error_messages
+                .push_front(format!("Could not save CSV: {err}"));
+        }
+    }
+
+    /// Replace the pipeline by the one in the header of a processed file,
+    /// keeping the loaded data.
+    fn process_like_file(&mut self, filepath: PathBuf) {
+        let pipeline = crate::common::input_data_to_string(&Some(filepath))
+            .and_then(|input_string| Pipeline::from_yaml_header(&input_string));
+        match pipeline {
+            Ok(mut pipeline) => {
+                pipeline
+                    .transformations
+                    .iter_mut()
+                    .for_each(|trnsf| trnsf.update_text_buffers());
+                self.pipeline = pipeline;
+                self.force_update = true;
+            }
+            Err(err) => self
+                .error_messages
+                .push_front(format!("Could not read pipeline: {err}")),
+        }
+    }
+
+    fn show_history(&mut self) {
+        match format_history(&self.initial_dataset.previous_comments) {
+            Ok(history) => self.history = Some(history),
+            Err(err) => self
+                .error_messages
+                .push_front(format!("Could not read processing history: {err}")),
+        }
+    }
+
+    /// Collapsible panel with the first lines of the input file and the shape
+    /// they were parsed into, to spot wrong delimiter or comment settings.
+    /// Warn if the input data does not look like x/y column pairs and offer
+    /// to read it another way.
+    fn input_layout_warnings(&mut self, ui: &mut Ui) {
+        let layout = self
+            .input_layout
+            .get_or_insert_with(|| check_layout(&self.initial_dataset.data));
+        for warning in layout.warnings.iter() {
+            ui.colored_label(Color32::from_rgb(255, 0, 0), warning);
+        }
+        let mut chosen = None;
+        for fix in layout.fixes.iter() {
+            if ui.button(fix.to_string()).clicked() {
+                chosen = Some(*fix);
+            }
+        }
+        let Some(fix) = chosen else {
+            return;
+        };
+        if let Err(err) = fix.apply(&mut self.initial_dataset) {
+            self.error_messages
+                .push_front(format!("Could not {fix}: {err}"));
+            return;
+        }
+        self.input_layout = None;
+        self.view_change = Some(ViewChange::Reset);
+        self.force_update = true;
+        self.dataset_cache = HashMap::new();
+    }
+
+    fn raw_input_preview(&mut self, ui: &mut Ui) {
+        egui::CollapsingHeader::new("Raw input preview").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("lines:");
+                ui.add(egui::DragValue::new(&mut self.raw_preview_lines).clamp_range(1..=1000));
+            });
+            let outdated = self.raw_preview.as_ref().is_none_or(|preview| {
+                preview.path != self.input_file_path || preview.lines != self.raw_preview_lines
+            });
+            if outdated {
+                self.raw_preview = Some(RawPreview::read(
+                    &self.input_file_path,
+                    self.raw_preview_lines,
+                ));
+            }
+            let comment = self.preprocessor.args.comment;
+            let delimiter = self.preprocessor.args.delimiter;
+            let data = &self.initial_dataset.data;
+            ui.label(format!(
+                "parsed: {} points, {} frames",
+                data.nrows(),
+                data.ncols() / 2
+            ));
+            let Some(preview) = &self.raw_preview else {
+                return;
+            };
+            match &preview.text {
+                Ok(lines) => {
+                    if let Some(line) = lines.iter().find(|line| !line.starts_with(comment)) {
+                        ui.label(format!(
+                            "first data line: {} fields separated by {:?}",
+                            line.split(delimiter).count(),
+                            delimiter
+                        ));
+                    }
+                    egui::ScrollArea::both()
+                        .id_source("raw input preview")
+                        .max_height(300.0)
+                        .show(ui, |ui| {
+                            for line in lines {
+                                let text = egui::RichText::new(line).monospace();
+                                // comments in another color than the data
+                                if line.starts_with(comment) {
+                                    ui.label(text.color(Color32::from_rgb(110, 150, 110)));
+                                } else {
+                                    ui.label(text);
+                                }
+                            }
+                        });
+                }
+                Err(err) => {
+                    ui.label(err);
+                }
+            }
+        });
+    }
+
+    fn history_window(&mut self, ctx: &egui::Context) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Processing history")
+            .open(&mut open)
+            .default_height(400.0)
+            .show(ctx, |ui| {
+                if ui.button("copy").clicked() {
+                    ui.output_mut(|output| output.copied_text = history.clone());
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    ui.monospace(history);
+                });
+            });
+        if !open {
+            self.history = None;
+        }
+    }
+
+    fn plot_layout_controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("plot layout")
+                .selected_text(format!("{:?}", self.plot_layout))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.plot_layout, PlotLayout::Overlay, "Overlay");
+                    ui.selectable_value(&mut self.plot_layout, PlotLayout::Stacked, "Stacked");
+                    ui.selectable_value(
+                        &mut self.plot_layout,
+                        PlotLayout::Envelope,
+                        "Envelope",
+                    );
+                    ui.selectable_value(&mut self.plot_layout, PlotLayout::Heatmap, "Heatmap");
+                });
+            ui.checkbox(&mut self.compare_input, "compare with input")
+                .on_hover_text(
+                    "Draw the data entering the selected step (the raw data if no step is selected) behind the plot.",
+                );
+            if !self.channel_points.is_empty() {
+                ui.checkbox(&mut self.show_channels, "show channels")
+                    .on_hover_text(
+                        "Draw the further quantities of the frames, e.g. a kept baseline, as dashed lines.",
+                    );
+            }
+            let mut waterfall = self.waterfall.is_some();
+            ui.checkbox(&mut waterfall, "waterfall")
+                .on_hover_text("Offset each frame vertically from the previous one.");
+            let mut stack = match (waterfall, self.waterfall) {
+                (false, _) => None,
+                (true, None) => Some(StackOffset::Auto),
+                (true, Some(stack)) => Some(stack),
+            };
+            if let Some(current) = stack {
+                let mut auto = current == StackOffset::Auto;
+                ui.checkbox(&mut auto, "auto offset")
+                    .on_hover_text("Offset by the median intensity range of the frames.");
+                stack = Some(match (auto, current) {
+                    (true, _) => StackOffset::Auto,
+                    (false, StackOffset::Fixed(mut offset)) => {
+                        // up to a few times the automatic offset
+                        let max = 4.0 * StackOffset::Auto.resolve(&self.dataset);
+                        let max = if max > 0.0 { max.max(offset) } else { offset.max(1.0) };
+                        ui.add(Slider::new(&mut offset, 0.0..=max).text("stack offset"))
+                            .on_hover_text("Only the plot is offset, the data stays untouched.");
+                        StackOffset::Fixed(offset)
+                    }
+                    // start from the offset that was shown before
+                    (false, StackOffset::Auto) => {
+                        StackOffset::Fixed(self.waterfall_offset.unwrap_or(1.0))
+                    }
+                });
+            }
+            if stack != self.waterfall {
+                self.waterfall = stack;
+                self.waterfall_offset = None;
+            }
+            if self.plot_layout == PlotLayout::Stacked {
+                ui.label("frame groups:");
+                ui.text_edit_singleline(&mut self.frame_groups).on_hover_text(
+                    "Frame numbers separated by spaces, groups separated by commas, e.g. \"1 2, 3\". \
+                    Leave empty to plot every frame separately.",
+                );
+            }
+        });
+        if self.plot_layout == PlotLayout::Heatmap {
+            self.heatmap_controls(ui);
+        }
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("frame colors")
+                .selected_text(format!("{:?}", self.frame_coloring))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut self.frame_coloring,
+                        FrameColoring::Categorical,
+                        "Categorical",
+                    );
+                    ui.selectable_value(&mut self.frame_coloring, FrameColoring::Index, "Index");
+                    ui.selectable_value(
+                        &mut self.frame_coloring,
+                        FrameColoring::Exposure,
+                        "Exposure",
+                    );
+                    ui.selectable_value(&mut self.frame_coloring, FrameColoring::Time, "Time");
+                    ui.selectable_value(&mut self.frame_coloring, FrameColoring::Values, "Values");
+                });
+            if self.frame_coloring == FrameColoring::Values {
+                ui.label("values:");
+                ui.text_edit_singleline(&mut self.frame_coloring_values)
+                    .on_hover_text("One value per frame separated by spaces, e.g. temperatures.");
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.picking_peaks, "pick peaks")
+                .on_hover_text(
+                    "Double-click a curve to mark a peak, double-click a marker to remove it.",
+                );
+            let has_markers = !self.peak_markers.is_empty();
+            ui.label(format!("{} peak(s) marked", self.peak_markers.len()));
+            if ui
+                .add_enabled(has_markers, egui::Button::new("save peaks"))
+                .on_hover_text("Save the marked peaks as CSV.")
+                .clicked()
+            {
+                self.dialogs.request(
+                    DialogRequest::save(
+                        DialogPurpose::SavePeaks,
+                        &suffixed_path(&self.output_file_path, "peaks"),
+                    )
+                    .filter(CSV),
+                );
+            }
+            if ui
+                .add_enabled(has_markers, egui::Button::new("clear peaks"))
+                .clicked()
+            {
+                self.peak_markers.clear();
+            }
+        });
+    }
+
+    /// Limits of the visible region of the first plot, to zoom to a region
+    /// by entering numbers.
+    fn view_controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            if let Some(bounds) = self.view_bounds.first() {
+                let (mut min, mut max) = (bounds.min(), bounds.max());
+                let mut changed = false;
+                for (axis, name) in ["x", "y"].iter().enumerate() {
+                    let speed = (max[axis] - min[axis]) * 0.005;
+                    ui.label(format!("{name}:"));
+                    changed |= ui
+                        .add(egui::DragValue::new(&mut min[axis]).speed(speed).max_decimals(3))
+                        .changed();
+                    ui.label("to");
+                    changed |= ui
+                        .add(egui::DragValue::new(&mut max[axis]).speed(speed).max_decimals(3))
+                        .changed();
+                }
+                if changed && min[0] < max[0] && min[1] < max[1] {
+                    self.view_change = Some(ViewChange::Limits(PlotBounds::from_min_max(min, max)));
+                }
+            }
+            if ui
+                .button("reset view")
+                .on_hover_text("Fit the plots to the data.")
+                .clicked()
+            {
+                self.view_change = Some(ViewChange::Reset);
+            }
+            ui.checkbox(&mut self.keep_view, "keep view").on_hover_text(
+                "Keep the visible region when the pipeline runs again instead of fitting the plots to the new data.",
+            );
+        });
+    }
+
+    /// Keep the visible region of the plots for newly plotted data, unless
+    /// another change of the region is pending.
+    fn keep_view_for_new_data(&mut self) {
+        if self.keep_view && self.view_change.is_none() && !self.view_bounds.is_empty() {
+            self.view_change = Some(ViewChange::Keep(self.view_bounds.clone()));
+        }
+    }
+
+    /// Scatter plot of a table of results (e.g. integrals) vs. frame number or time.
+    fn results_plot(&self, ui: &mut Ui, columns: &ResultColumns, height: f32) {
+        ui.horizontal(|ui| {
+            ui.heading("Results");
+            if ui
+                .button("copy to clipboard")
+                .on_hover_text("Copy the table as CSV, one row per frame.")
+                .clicked()
+            {
+                let table = results_to_csv(&self.dataset, columns);
+                ui.output_mut(|output| output.copied_text = table);
+            }
+        });
+        let x_label = columns.x_label.clone();
+        Plot::new("results")
+            .height(height)
+            .legend(Legend::default())
+            .x_axis_label(x_label.clone())
+            .label_formatter(move |name, value| {
+                if name.is_empty() {
+                    String::new()
+                } else {
+                    format!("{name}\n{x_label} {}: {:.4}", value.x, value.y)
+                }
+            })
+            .show(ui, |plot_ui| {
+                for (j, (pts, name)) in self.plot_points.iter().zip(&columns.names).enumerate() {
+                    if let PlotPoints::Owned(ps) = pts {
+                        let points: Vec<[f64; 2]> = ps.iter().map(|p| [p.x, p.y]).collect();
+                        plot_ui.points(
+                            Points::new(points)
+                                .radius(3.0)
+                                .color(PALETTE[j % PALETTE.len()])
+                                .name(name),
+                        );
+                    }
+                }
+            });
+    }
+
+    fn heatmap_controls(&mut self, ui: &mut Ui) {
+        let settings = &mut self.heatmap_settings;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("colormap")
+                .selected_text(format!("{:?}", settings.colormap))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut settings.colormap, Colormap::Viridis, "Viridis");
+                    ui.selectable_value(&mut settings.colormap, Colormap::Magma, "Magma");
+                    ui.selectable_value(&mut settings.colormap, Colormap::Grayscale, "Grayscale");
+                });
+            egui::ComboBox::from_label("color scaling")
+                .selected_text(format!("{:?}", settings.scaling))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut settings.scaling, ColorScaling::Linear, "Linear");
+                    ui.selectable_value(&mut settings.scaling, ColorScaling::Log, "Log");
+                    ui.selectable_value(
+                        &mut settings.scaling,
+                        ColorScaling::Percentile,
+                        "Percentile",
+                    )
+                    .on_hover_text("Histogram equalized, every color is used equally often.");
+                });
+        });
+        ui.horizontal(|ui| {
+            ui.label("color range:");
+            ui.add(Slider::new(&mut settings.range[0], 0.0..=1.0).text("low"));
+            ui.add(Slider::new(&mut settings.range[1], 0.0..=1.0).text("high"));
+            // keep a minimal width, so the range can be dragged back open
+            if settings.range[1] < settings.range[0] + 0.01 {
+                settings.range[1] = (settings.range[0] + 0.01).min(1.0);
+                settings.range[0] = settings.range[1] - 0.01;
+            }
+        });
+    }
+
+    /// Rebuild the heatmap texture if the dataset or the settings changed.
+    fn update_heatmap(&mut self, ctx: &egui::Context) {
+        if self
+            .heatmap
+            .as_ref()
+            .is_some_and(|heatmap| heatmap.settings == self.heatmap_settings)
+        {
+            return;
+        }
+        self.heatmap = Heatmap::new(ctx, &self.dataset, self.heatmap_settings);
+    }
+
+    /// Scalar per frame mapped onto the colormap, None for categorical colors.
+    fn frame_color_values(&self) -> Result<Option<Vec<f64>>> {
+        let n_frames = self.plot_points.len();
+        let values = match self.frame_coloring {
+            FrameColoring::Categorical => return Ok(None),
+            FrameColoring::Index => (1..=n_frames).map(|i| i as f64).collect(),
+            FrameColoring::Exposure => self
+                .dataset
+                .exposures()
+                .ok_or_else(|| anyhow!("exposure time is not known for all frames"))?,
+            FrameColoring::Time => self
+                .dataset
+                .times()
+                .ok_or_else(|| anyhow!("time is not known for all frames"))?,
+            FrameColoring::Values => self
+                .frame_coloring_values
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|_| anyhow!("could not parse color values"))?,
+        };
+        if values.len() != n_frames {
+            return Err(anyhow!(
+                "got {} color values for {} frames",
+                values.len(),
+                n_frames
+            ));
+        }
+        Ok(Some(values))
+    }
+
+    /// Name of the frame (counting from 0) in legends and data tips, its label
+    /// if the input has one, otherwise its number.
+    fn frame_name(&self, i: usize) -> String {
+        self.dataset
+            .frame_metadata
+            .get(i)
+            .and_then(|meta| meta.label.clone())
+            .unwrap_or_else(|| (i + 1).to_string())
+    }
+
+    /// Color of each frame, draws a colorbar if frames are colored by value.
+    fn frame_colors(&self, ui: &mut Ui) -> Vec<Color32> {
+        let n_frames = self.plot_points.len();
+        let categorical = (0..n_frames).map(|i| PALETTE[i % PALETTE.len()]).collect();
+        let values = match self.frame_color_values() {
+            Ok(Some(values)) => values,
+            Ok(None) => return categorical,
+            Err(e) => {
+                ui.colored_label(Color32::RED, format!("{e}, using categorical colors"));
+                return categorical;
+            }
+        };
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let range = if max > min { max - min } else { 1.0 };
+        draw_colorbar(ui, min, max, Colormap::Viridis);
+        values.iter().map(|v| viridis((v - min) / range)).collect()
+    }
+
+    /// Parse the frame groups of the stacked plot layout into 0-based frame
+    /// indices, frames not in any group end up in an extra last group.
+    fn parse_frame_groups(&self) -> Vec<Vec<usize>> {
+        let n_frames = self.plot_points.len();
+        let mut groups: Vec<Vec<usize>> = self
+            .frame_groups
+            .split(',')
+            .map(|group| {
+                group
+                    .split_whitespace()
+                    .filter_map(|str| str.parse::<usize>().ok())
+                    .filter(|n| *n >= 1 && *n <= n_frames)
+                    .map(|n| n - 1)
+                    .collect::<Vec<usize>>()
+            })
+            .filter(|group| !group.is_empty())
+            .collect();
+        if groups.is_empty() {
+            return (0..n_frames).map(|i| vec![i]).collect();
+        }
+        let ungrouped: Vec<usize> = (0..n_frames)
+            .filter(|i| !groups.iter().any(|group| group.contains(i)))
+            .collect();
+        if !ungrouped.is_empty() {
+            groups.push(ungrouped);
+        }
+        groups
+    }
+
+    fn save_visible_region(&mut self, filepath: &Path) {
+        let Some(Pair { a: left, b: right }) = self.visible_x_range else {
+            return;
+        };
+        let n_points = match self.visible_export_points {
+            0 => None,
+            n => Some(n),
+        };
+        let mut visible = match self.dataset.crop_x_range(left, right, n_points) {
+            Ok(ds) => ds,
+            Err(err) => {
+                self.error_messages
+                    .push_front(format!("Could not export visible region: {err}"));
+                return;
+            }
+        };
+        let written = self
+            .preprocessor
+            .refresh_metadata(&mut visible)
+            .and_then(|_| visible.write_file(filepath, self.preprocessor.args.float_format));
+        if let Err(err) = written {
+            self.error_messages
+                .push_front(format!("Could not export visible region: {err}"));
+        }
+    }
+
+    /// Name and input of every step, None if not all steps have run, so their
+    /// results are not cached.
+    fn bundle_steps(&self) -> Option<Vec<(String, &Dataset)>> {
+        let n_steps = self.pipeline.transformations.len();
+        (1..=n_steps)
+            .map(|step| {
+                let config = self.pipeline.transformations[step - 1]
+                    .config_to_string()
+                    .unwrap_or_default();
+                let name = config
+                    .lines()
+                    .find_map(|line| line.strip_prefix("transformation: "))
+                    .unwrap_or("step")
+                    .to_owned();
+                self.step_input(step).map(|dataset| (name, dataset))
+            })
+            .collect()
+    }
+
+    fn request_bundle_file(&mut self) {
+        if self.bundle_steps().is_none() {
+            self.error_messages
+                .push_front("Could not save bundle: run the pipeline first.".to_owned());
+            return;
+        }
+        let mut filepath = self.output_file_path.to_owned();
+        filepath.set_extension("zip");
+        self.dialogs
+            .request(DialogRequest::save(DialogPurpose::SaveBundle, &filepath).filter(ZIP));
+    }
+
+    /// Save a reproducible record of the processing as ZIP archive, see
+    /// `write_bundle`. All steps must have run, so their results are cached.
+    fn save_bundle(&mut self, filepath: &Path) {
+        let Some(steps) = self.bundle_steps() else {
+            self.error_messages
+                .push_front("Could not save bundle: run the pipeline first.".to_owned());
+            return;
+        };
+        let mut result = steps
+            .last()
+            .map(|(_, dataset)| *dataset)
+            .unwrap_or(&self.initial_dataset)
+            .clone();
+        let written = self
+            .preprocessor
+            .refresh_metadata(&mut result)
+            .and_then(|_| std::fs::File::create(filepath).map_err(anyhow::Error::from))
+            .and_then(|file| {
+                write_bundle(
+                    file,
+                    &self.input_file_path,
+                    &steps,
+                    &result,
+                    self.preprocessor.args.float_format,
+                )
+            });
+        if let Err(err) = written {
+            self.error_messages
+                .push_front(format!("Could not save bundle: {err}"));
+        }
+    }
+
+    fn save_peak_markers(&mut self, filepath: &Path) {
+        let table = peak_markers_to_csv(&self.peak_markers, &self.plot_points, self.dataset.x_unit);
+        if let Err(err) = std::fs::write(filepath, table) {
+            self.error_messages
+                .push_front(format!("Could not save peaks: {err}"));
+        }
+    }
+
+    fn copy_pipeline_diagram(&mut self, ui: &mut Ui, format: DiagramFormat) {
+        match pipeline_diagram(&self.pipeline, format) {
+            Ok(diagram) => ui.output_mut(|output| output.copied_text = diagram),
+            Err(err) => self
+                .error_messages
+                .push_front(format!("Could not draw pipeline diagram: {err}")),
+        }
+    }
+
+    /// Report all problems of the pipeline parameters in the error log.
+    fn check_pipeline(&mut self) {
+        let context = match self.preprocessor.get_context() {
+            Ok(context) => context,
+            Err(err) => {
+                self.error_messages
+                    .push_front(format!("Pipeline check: {err}"));
+                return;
+            }
+        };
+        let problems = self.pipeline.validate(&self.initial_dataset, &context);
+        let message = if problems.is_empty() {
+            "Pipeline check: no problems found.".to_owned()
+        } else {
+            format!("Pipeline check:\n{}", problems.join("\n"))
+        };
+        self.error_messages.push_front(message);
+    }
+
+    fn pipeline_hash(&self) -> String {
+        let conf_str: String = self
+            .pipeline
+            .transformations
+            .iter()
+            .map(|trnsf| trnsf.config_to_string().unwrap())
+            .collect();
+        digest(conf_str)
+    }
+
+    /// Whether the pipeline can run on a decimated copy of the input data,
+    /// which holds only some of the frames and pixels, so steps referring to
+    /// frames or pixels by their number would act on the wrong data.
+    fn can_preview(&self) -> bool {
+        !self
+            .pipeline
+            .transformations
+            .iter()
+            .any(|trnsf| trnsf.refers_to_frames_or_pixels())
+    }
+
+    /// Run the pipeline on a decimated copy of the input data, bypassing the
+    /// cache, to give instant feedback while a slider is dragged. The steps
+    /// run on copies, so the reports of the steps in the forms stay those of
+    /// the full resolution run.
+    fn run_preview_pipeline_on_change(&mut self) {
+        let pipeline_hash = self.pipeline_hash();
+        if self.showing_preview && self.last_preview_hash == pipeline_hash {
+            return;
+        }
+        self.last_preview_hash = pipeline_hash;
+        self.showing_preview = true;
+        let frame_step = (self.initial_dataset.data.ncols() / 2).div_ceil(PREVIEW_MAX_FRAMES);
+        let mut dataset = self
+            .initial_dataset
+            .decimated(PREVIEW_ROW_STEP, frame_step.max(1));
+        let context = match self.preprocessor.get_context() {
+            Ok(context) => PipelineContext {
+                skip_provenance: true,
+                ..context
+            },
+            Err(err) => {
+                self.error_messages
+                    .push_front(format!("Could not preview pipeline: {err}"));
+                return;
+            }
+        };
+        for (i, trnsf) in self.pipeline.transformations.iter().enumerate() {
+            let is_last_iter = self.active_step.map(|n| n == i).unwrap_or_default();
+            if is_last_iter && !trnsf.should_plot_dataset_state_after_transformation() {
+                break;
+            }
+            let applied = trnsf
+                .config_to_string()
+                .and_then(|config| yaml_segment_to_transform(&config))
+                .and_then(|mut copy| copy.apply(&mut dataset, &context));
+            if let Err(err) = applied {
+                self.error_messages
+                    .push_front(format!("Could not preview pipeline: {err}"));
+                break;
+            }
+            if is_last_iter {
+                break;
+            }
+        }
+        dataset.drop_mismatched_channels();
+        self.plot_points = dataset.to_plot_points();
+        self.channel_points = dataset.channel_plot_points();
+        self.envelope = None;
+        self.heatmap = None;
+        self.waterfall_offset = None;
+        self.keep_view_for_new_data();
+    }
+
+    fn run_pipeline_on_change(&mut self) -> Result<()> {
+        // check if pipeline from previous run should be loaded
+        if self.preprocessor.reload_pipeline {
+            self.preprocessor.reload_pipeline = false;
+            // TODO: Refactor to make this part DRY
+            let input_string =
+                crate::common::input_data_to_string(&Some(self.input_file_path.to_owned()))?;
+            let prp_result =
+                Preprocessor::from_yaml_header(&input_string, true).map_err(|e| eprintln!("{e}"));
+            if prp_result.is_ok() {
+                let mut prp = prp_result.unwrap();
+                // reuse the seed of the previous run to reproduce it
+                self.preprocessor.args.seed = prp.args.seed.or(self.preprocessor.args.seed);
+                self.preprocessor.args.note = prp.args.note.clone();
+                self.initial_dataset = prp.get_input_data()?;
+                self.dataset = self.initial_dataset.clone();
+                self.input_layout = None;
+                self.pipeline = Pipeline::from_yaml_header(&input_string)?;
+                self.pipeline
+                    .transformations
+                    .iter_mut()
+                    .for_each(|trnsf| trnsf.update_text_buffers());
+                self.input_file_path = prp.args.filepath.unwrap_or(PathBuf::default());
+                self.output_file_path = make_output_filepath(&self.input_file_path);
+            }
+        }
+        // check if new file should be loaded
+        if let Some(filepath) = self.file_to_load.take() {
+            self.output_file_path = make_output_filepath(&filepath);
+            self.input_file_path = filepath.clone();
+            self.preprocessor.args.filepath = Some(filepath.clone());
+            // if the input file can be parsed as a result from a previous run, load the prev. run
+            let input_string = if filepath
+                .extension()
+                .as_ref()
+                .is_some_and(|ext| *ext == "csv")
+            {
+                crate::common::input_data_to_string(&Some(self.input_file_path.to_owned()))?
+            } else {
+                "".to_string()
+            };
+            let prp_result =
+                Preprocessor::from_yaml_header(&input_string, true).map_err(|e| eprintln!("{e}"));
+            if prp_result.is_ok() && self.reload_pipeline {
+                let mut prp = prp_result.unwrap();
+                self.preprocessor.args.seed = prp.args.seed.or(self.preprocessor.args.seed);
+                self.preprocessor.args.note = prp.args.note.clone();
+                dbg!("get input");
+                self.initial_dataset = prp.get_input_data()?;
+                self.dataset = self.initial_dataset.clone();
+                self.pipeline = Pipeline::from_yaml_header(&input_string).map_err(|err| {
+                    eprintln!(
+                        "WARNING: Unable to read pipeline from input file {:?}: {}",
+                        self.input_file_path
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .unwrap_or("(unreadable file name)"),
+                        err
+                    );
+                    err
+                })?;
+                self.pipeline
+                    .transformations
+                    .iter_mut()
+                    .for_each(|trnsf| trnsf.update_text_buffers());
+                self.input_file_path = prp.args.filepath.unwrap_or(PathBuf::default());
+                self.output_file_path = make_output_filepath(&self.input_file_path);
+            } else {
+                let ds = self.preprocessor.get_input_data()?;
+                self.initial_dataset = ds;
+                self.dataset = self.initial_dataset.clone();
+            }
+            self.append_chosen_files();
+            self.input_layout = None;
+            self.recent.add_file(&filepath);
+            self.save_recent_files();
+            // the region of the previous file is meaningless for the new one
+            self.view_change = Some(ViewChange::Reset);
+            self.force_update = true;
+            self.dataset_cache = HashMap::new(); // reset cache
+        }
+        // read the input file again, keeping pipeline and view
+        if std::mem::take(&mut self.reload_input) {
+            self.preprocessor.args.filepath = Some(self.input_file_path.clone());
+            self.initial_dataset = self.preprocessor.get_input_data()?;
+            self.input_layout = None;
+            self.force_update = true;
+            self.dataset_cache = HashMap::new();
+        }
+
+        // detect change by the hash of the serialized pipeline configuration
+        let pipeline_hash = self.pipeline_hash();
+        // if the pipeline did not change, we do nothing
+        if self.last_dataset_hash == pipeline_hash && !self.force_update {
+            return Ok(());
+        }
+        self.last_dataset_hash = pipeline_hash;
+        self.force_update = false;
+        // a run of the previous configuration is outdated now
+        self.cancel_pipeline_job();
+        self.dataset = self.initial_dataset.clone();
+        // otherwise, we re-apply the transformations, reusing cache if possible
+        let mut last_transformer_hash = "".to_owned();
+        // steps that are not reached in this run get no sparkline
+        self.sparklines = vec![None; self.pipeline.transformations.len()];
+        self.step_inputs = vec![None; self.pipeline.transformations.len()];
+        self.active_input_hash = "".to_owned();
+        // steps after the last cached one, to be run in the background
+        let mut pending_steps = vec![];
+        for (i, trnsf) in self.pipeline.transformations.iter().enumerate() {
+            let is_last_iter = self.active_step.map(|n| n == i).unwrap_or_default();
+            // inputs of steps after a pending one are known once it ran
+            if pending_steps.is_empty() && trnsf.picks_frames() {
+                self.step_inputs[i] = Some(frame_thumbnails(&self.dataset));
+            }
+            if is_last_iter {
+                self.active_input_hash = last_transformer_hash.clone();
+            }
+            if is_last_iter && !trnsf.should_plot_dataset_state_after_transformation() {
+                // if the dataset is to be plotted before the transformation
+                // happens, we can stop iterating here
+                break;
+            }
+            // use hash to salt new hash, to make hashes depend on the whole
+            // history of the data pipeline
+            let hash = digest(trnsf.config_to_string().unwrap() + last_transformer_hash.as_str());
+            match self.dataset_cache.get(&hash) {
+                Some(cache) if pending_steps.is_empty() => {
+                    self.dataset = cache.clone();
+                    self.sparklines[i] = Some(sparkline_from_dataset(&self.dataset));
+                }
+                // the worker gets its own copy of the transformer, the one
+                // in the form stays editable while the pipeline runs
+                _ => pending_steps.push((
+                    i,
+                    hash.clone(),
+                    yaml_segment_to_transform(&trnsf.config_to_string()?)?,
+                )),
+            }
+            if is_last_iter {
+                break;
+            }
+            last_transformer_hash = hash;
+        }
+        if pending_steps.is_empty() {
+            self.show_pipeline_result();
+        } else {
+            let context = self.preprocessor.get_context()?;
+            self.pipeline_job = Some(PipelineJob {
+                step: pending_steps[0].0,
+                last_step: pending_steps[pending_steps.len() - 1].0,
+                context: context.clone(),
+                messages: spawn_pipeline_job(pending_steps, self.dataset.clone(), context),
+            });
+        }
+        Ok(())
+    }
+
+    /// Take over the results of the pipeline run in the background, plot the
+    /// dataset once the run is over.
+    fn poll_pipeline_job(&mut self) {
+        let Some(job) = &mut self.pipeline_job else {
+            return;
+        };
+        let finished = loop {
+            match job.messages.try_recv() {
+                Ok(JobMessage::StepDone {
+                    step,
+                    hash,
+                    dataset,
+                    transformer,
+                }) => {
+                    self.sparklines[step] = Some(sparkline_from_dataset(&dataset));
+                    if self
+                        .pipeline
+                        .transformations
+                        .get(step + 1)
+                        .is_some_and(|next| next.picks_frames())
+                    {
+                        self.step_inputs[step + 1] = Some(frame_thumbnails(&dataset));
+                    }
+                    self.dataset_cache.insert(hash, (*dataset).clone());
+                    self.dataset = *dataset;
+                    // the configuration is unchanged (the run would have been
+                    // cancelled otherwise), but the copy holds the report of the run
+                    if let Some(trnsf) = self.pipeline.transformations.get_mut(step) {
+                        *trnsf = transformer;
+                        trnsf.update_text_buffers();
+                    }
+                    job.step = step + 1;
+                }
+                Ok(JobMessage::Failed(err)) => self.error_messages.push_front(err),
+                Err(TryRecvError::Empty) => break false,
+                Err(TryRecvError::Disconnected) => break true,
+            }
+        };
+        for warning in job.context.diagnostics.take() {
+            self.error_messages.push_front(warning);
+        }
+        if !finished {
+            return;
+        }
+        self.pipeline_job = None;
+        self.show_pipeline_result();
+    }
+
+    fn cancel_pipeline_job(&mut self) {
+        if let Some(job) = self.pipeline_job.take() {
+            job.context.progress.cancel();
+        }
+    }
+
+    fn status_bar(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("statusbar").show(ctx, |ui| {
+            ui.horizontal(|ui| match &self.pipeline_job {
+                Some(job) => {
+                    ui.spinner();
+                    ui.label(format!(
+                        "Running step {} of {}",
+                        job.step + 1,
+                        job.last_step + 1
+                    ));
+                    ui.add(
+                        egui::ProgressBar::new(job.context.progress.fraction())
+                            .desired_width(200.0)
+                            .show_percentage(),
+                    );
+                    if ui.button("Abort").clicked() {
+                        self.cancel_pipeline_job();
+                        self.show_pipeline_result();
+                    }
+                }
+                None => {
+                    ui.label("Ready");
+                }
+            });
+        });
+    }
+
+    /// Update the plot with the dataset the last pipeline run ended with.
+    fn show_pipeline_result(&mut self) {
+        if let Some(trnsf) = self
+            .active_step
+            .and_then(|step| self.pipeline.transformations.get(step))
+        {
+            self.plot_extension = trnsf.get_plot_extension(self.dataset.clone());
+        } else {
+            self.plot_extension = None;
+        }
+        // the last step applied to the plotted dataset
+        let plotted_step = match self.active_step {
+            None => self.pipeline.transformations.len().checked_sub(1),
+            Some(step) => match self.pipeline.transformations.get(step) {
+                Some(trnsf) if trnsf.should_plot_dataset_state_after_transformation() => Some(step),
+                _ => step.checked_sub(1),
+            },
+        };
+        self.result_columns = plotted_step
+            .and_then(|step| self.pipeline.transformations.get(step))
+            .and_then(|trnsf| trnsf.result_columns());
+        self.plot_points = self.dataset.to_plot_points();
+        self.channel_points = self.dataset.channel_plot_points();
+        self.envelope = None;
+        self.heatmap = None;
+        self.comparison_points = None;
+        self.waterfall_offset = None;
+        self.keep_view_for_new_data();
+    }
+
+    /// The dataset entering the active step, or the initial dataset if no
+    /// step is active.
+    fn comparison_dataset(&self) -> &Dataset {
+        self.dataset_cache
+            .get(&self.active_input_hash)
+            .unwrap_or(&self.initial_dataset)
+    }
+
+    /// Add a step selecting `frame` of the plotted dataset, right after the
+    /// step the plotted dataset results from, and show the spectrum.
+    fn extract_frame(&mut self, frame: usize) {
+        let position = match self.active_step {
+            None => self.pipeline.transformations.len(),
+            Some(step) => match self.pipeline.transformations.get(step) {
+                Some(trnsf) if trnsf.should_plot_dataset_state_after_transformation() => step + 1,
+                _ => step,
+            },
+        };
+        self.pipeline.transformations.insert(
+            position,
+            Box::new(SelectTransform {
+                frames: vec![frame],
+                invert: false,
+            }),
+        );
+        self.active_step = None;
+        self.heatmap_frame = None;
+        self.plot_layout = PlotLayout::Overlay;
+    }
+
+    /// Move the step following `step` into the steps nested in it.
+    fn nest_next_step(&mut self, step: usize) {
+        let can_nest = step + 1 < self.pipeline.transformations.len()
+            && self.pipeline.transformations[step].nested_steps().is_some();
+        if !can_nest {
+            return;
+        }
+        let next = self.pipeline.transformations.remove(step + 1);
+        if let Some(nested) = self.pipeline.transformations[step].nested_steps() {
+            nested.push(next);
+        }
+        self.active_step = None;
+    }
+
+    /// Move the last step nested in `step` out of it, right after it.
+    fn release_last_step(&mut self, step: usize) {
+        if let Some(last) = self
+            .pipeline
+            .transformations
+            .get_mut(step)
+            .and_then(|trnsf| trnsf.nested_steps())
+            .and_then(|nested| nested.pop())
+        {
+            self.pipeline.transformations.insert(step + 1, last);
+        }
+        self.active_step = None;
+    }
+
+    /// Attach a condition to `step`, or remove the condition it has.
+    fn toggle_condition(&mut self, step: usize) {
+        let Some(trnsf) = self.pipeline.transformations.get_mut(step) else {
+            return;
+        };
+        if trnsf.condition().is_some() {
+            match trnsf.take_conditional_step() {
+                Some(inner) => *trnsf = inner,
+                None => _ = self.pipeline.transformations.remove(step),
+            }
+        } else {
+            let inner = self.pipeline.transformations.remove(step);
+            self.pipeline.transformations.insert(
+                step,
+                Box::new(ConditionalTransform::new(Condition::default(), inner)),
+            );
+        }
+        self.active_step = None;
+        self.force_update = true;
+    }
+
+    /// The dataset entering step `step`, if it is in the cache.
+    fn step_input(&self, step: usize) -> Option<&Dataset> {
+        if step == 0 {
+            return Some(&self.initial_dataset);
+        }
+        let mut hash = "".to_owned();
+        for trnsf in self.pipeline.transformations.get(..step)? {
+            hash = digest(trnsf.config_to_string().ok()? + hash.as_str());
+        }
+        self.dataset_cache.get(&hash)
+    }
+
+    /// Fill the form of a step with parameters estimated from the data
+    /// entering it.
+    fn suggest_parameters(&mut self, step: usize) {
+        let Some(input) = self.step_input(step).cloned() else {
+            self.error_messages.push_front(
+                "The data entering this step is not available yet, wait for the pipeline to finish."
+                    .to_owned(),
+            );
+            return;
+        };
+        let Some(trnsf) = self.pipeline.transformations.get_mut(step) else {
+            return;
+        };
+        if let Err(err) = self
+            .preprocessor
+            .get_context()
+            .and_then(|context| trnsf.suggest_parameters(&input, &context))
+        {
+            self.error_messages
+                .push_front(format!("Could not suggest parameters: {}", err));
+        }
+    }
+
+    fn transformer_form(&mut self, ui: &mut Ui, i: usize) {
+        ui.group(|ui| {
+            if let Some(Some(sparkline)) = self.sparklines.get(i) {
+                draw_sparkline(ui, i, sparkline);
+            }
+            let n_steps = self.pipeline.transformations.len();
+            let trnsf = self.pipeline.transformations.get_mut(i).unwrap();
+            trnsf.render_form(ui);
+            if let Some(nested) = trnsf.nested_steps() {
+                let n_nested = nested.len();
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(i + 1 < n_steps, egui::Button::new("nest next step"))
+                        .on_hover_text("Move the step below into this one.")
+                        .clicked()
+                    {
+                        self.nest_step = Some(i);
+                    }
+                    if ui
+                        .add_enabled(n_nested > 0, egui::Button::new("release last step"))
+                        .on_hover_text("Move the last nested step out, below this one.")
+                        .clicked()
+                    {
+                        self.release_step = Some(i);
+                    }
+                });
+            }
+            if trnsf.suggests_parameters()
+                && ui
+                    .button("auto-suggest")
+                    .on_hover_text("Estimate parameters from the noise of the data entering this step, as a starting point.")
+                    .clicked()
+            {
+                self.suggest_step = Some(i);
+            }
+            if let Some(Some(thumbnails)) = self.step_inputs.get(i) {
+                trnsf.render_frame_picker(ui, i, thumbnails);
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Remove").clicked() {
+                    self.remove_step = Some(i);
+                    self.force_update = true;
+                };
+                let label = match trnsf.condition() {
+                    Some(_) => "Remove condition",
+                    None => "Add condition",
+                };
+                if ui
+                    .button(label)
+                    .on_hover_text("Only run this step if the data entering it fulfils a condition.")
+                    .clicked()
+                {
+                    self.condition_step = Some(i);
                 };
                 if self.active_step.is_some() && self.active_step.unwrap() == i {
                     if ui.button("OK").clicked() {
@@ -617,136 +2125,500 @@ impl RamanGuiApp {
     }
 
     fn insert_transformation(&mut self, i: usize) {
-        let trnsf: Box<dyn TransformerGUI> = match &self.insert_transformer {
-            // REGISTER
-            InsertTransformer::None => return,
-            InsertTransformer::Align => Box::new(AlignTransform { cost_max_abs: 0.1 }),
-            InsertTransformer::Append => Box::new(AppendTransform {
-                filepath: Some(PathBuf::from("")),
-                delimiter: ',',
-                comment: '#',
-                horizontal: false,
-            }),
-            InsertTransformer::Average => Box::new(AverageTransform {}),
-            InsertTransformer::Baseline => Box::new(BaselineTransform {
-                points: vec![],
-                store: false,
-            }),
-            InsertTransformer::Calibrate => Box::new(CalibrationTransform::default()),
-            InsertTransformer::CountConversion => Box::new(CountConversionTransform::default()),
-            InsertTransformer::Despike => Box::new(DespikeTransform {
-                siglim: 10.0,
-                flim: 10.0,
-            }),
-            InsertTransformer::Finning => Box::new(FinningTransform {
-                threshold: 2.5,
-                iterations: 4,
-            }),
-            InsertTransformer::Integrate => Box::new(IntegrateTransform {
-                bounds: vec![],
-                local_baseline: true,
-            }),
-            InsertTransformer::Mask => Box::new(MaskTransform { mask: vec![] }),
-            InsertTransformer::Normalize => {
-                let iterx = self.dataset.data.axis_iter(ndarray::Axis(1)).step_by(2);
-                let itery = self
-                    .dataset
-                    .data
-                    .axis_iter(ndarray::Axis(1))
-                    .skip(1)
-                    .step_by(2);
-                let x_max: f64 = iterx
-                    .zip(itery)
-                    .map(|(xs, ys)| {
-                        let idx = ys.argmax_skipnan().unwrap_or(0);
-                        xs[idx]
-                    })
-                    .sum::<f64>()
-                    / self.dataset.data.ncols() as f64
-                    * 2.0;
-                Box::new(NormalizeTransform {
-                    xi: x_max,
-                    xj: None,
-                    filter_range: None,
-                    local_baseline: false,
-                    target_frames: None,
-                    gui_text_buffers: NormalizeIOBuffers::default(),
-                })
-            }
-            InsertTransformer::Offset => Box::new(OffsetTransform {
-                offset: 0.0,
-                percentile: false,
-                target_frames: None,
-                gui_text_buffers: crate::transformations::offset::OffsetIOBuffers {
-                    value: 0.0.to_string(),
-                },
-            }),
-            InsertTransformer::RamanShift => Box::new({
-                let mut rst = RamanShiftTransform {
-                    wavelength: 532.1,
-                    refractive_index: 1.000264,
-                    correction: Some(0.0),
-                    ..Default::default()
-                };
-                rst.update_text_buffers();
-                rst
-            }),
-            InsertTransformer::Reshape => Box::new(ReshapeTransform { rows: 1340 }),
-            InsertTransformer::Select => Box::new(SelectTransform {
-                frames: vec![],
-                invert: true,
-            }),
-            InsertTransformer::Subtract => Box::new(SubtractTransform {
-                direct: false,
-                minuends: None,
-                subtrahend: 1,
-            }),
-        };
-        self.pipeline.transformations.insert(i, trnsf);
+        if let Some(transformer) = self.insert_transformer {
+            let trnsf = (transformer.new)(&self.dataset);
+            self.pipeline.transformations.insert(i, trnsf);
+        }
     }
 
-    fn save_screenshot(
+    /// Keep the plot panel region of the screenshot until the file to save it
+    /// to is chosen.
+    fn request_screenshot_file(
         &mut self,
         input_state: &egui::InputState,
         rect: egui::Rect,
         image: &std::sync::Arc<egui::ColorImage>,
     ) {
+        let pixels_per_point = input_state.pixels_per_point();
+        let region = egui::Rect::from_two_pos(rect.left_top(), rect.right_bottom());
+        self.pending_screenshot = Some(image.region(&region, Some(pixels_per_point)));
         let mut filepath = self.output_file_path.to_owned();
         filepath.set_extension("png");
-        let dir = filepath.parent().unwrap_or(std::path::Path::new(""));
-        let filename = filepath
-            .file_name()
-            .map(|name| name.to_str().unwrap_or_default())
-            .unwrap_or_default();
-        if let Some(filepath) = rfd::FileDialog::new()
-            .set_directory(dir)
-            .add_filter("PNG", &["png"])
-            .set_file_name(&filename)
-            .save_file()
-        {
-            let pixels_per_point = input_state.pixels_per_point();
-            let region = egui::Rect::from_two_pos(rect.left_top(), rect.right_bottom());
-            let top_left_corner = image.region(&region, Some(pixels_per_point));
-            let _ = image::save_buffer(
-                filepath,
-                top_left_corner.to_owned().as_raw(),
-                top_left_corner.size[0] as u32,
-                top_left_corner.size[1] as u32,
-                ColorType::Rgba8,
-            )
-            .map_err(|e| eprintln!("Error while saving screenshot: {e}"));
+        self.dialogs
+            .request(DialogRequest::save(DialogPurpose::SavePlot, &filepath).filter(PNG));
+    }
+
+    fn save_screenshot(&mut self, filepath: &Path) {
+        let Some(screenshot) = self.pending_screenshot.take() else {
+            return;
+        };
+        let _ = image::save_buffer(
+            filepath,
+            screenshot.as_raw(),
+            screenshot.size[0] as u32,
+            screenshot.size[1] as u32,
+            ColorType::Rgba8,
+        )
+        .map_err(|e| eprintln!("Error while saving screenshot: {e}"));
+    }
+}
+
+/// Decimated frames of the dataset state after a pipeline step, drawn as a
+/// small preview next to the step's form.
+type Sparkline = Vec<Vec<[f64; 2]>>;
+
+/// Pipeline steps running in a background thread, so the GUI stays
+/// responsive and the run can be aborted when the pipeline is changed.
+struct PipelineJob {
+    messages: Receiver<JobMessage>,
+    /// shared with the worker, to follow its progress and collect warnings
+    context: PipelineContext,
+    /// index of the step currently running
+    step: usize,
+    /// index of the step the run ends with
+    last_step: usize,
+}
+
+enum JobMessage {
+    /// a step finished, `transformer` is the copy that ran it
+    StepDone {
+        step: usize,
+        hash: String,
+        dataset: Box<Dataset>,
+        transformer: Box<dyn TransformerGUI>,
+    },
+    Failed(String),
+}
+
+/// Apply `steps` (index, hash of the dataset after the step, transformer) to
+/// `dataset` in a new thread, sending the result of each step. The thread
+/// ends after the last step, on error or when cancelled through the progress
+/// of `context`.
+fn spawn_pipeline_job(
+    steps: Vec<(usize, String, Box<dyn TransformerGUI>)>,
+    mut dataset: Dataset,
+    context: PipelineContext,
+) -> Receiver<JobMessage> {
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        let progress = &context.progress;
+        for (step, hash, mut transformer) in steps {
+            if progress.is_cancelled() {
+                return;
+            }
+            progress.report(0.0);
+            if let Err(err) = transformer.apply(&mut dataset, &context) {
+                if !progress.is_cancelled() {
+                    let _result = tx.send(JobMessage::Failed(err.to_string()));
+                }
+                return;
+            }
+            let message = JobMessage::StepDone {
+                step,
+                hash,
+                dataset: Box::new(dataset.clone()),
+                transformer,
+            };
+            if tx.send(message).is_err() {
+                return; // job was dropped by the GUI
+            }
+        }
+    });
+    rx
+}
+
+/// Only every n-th pixel is processed in the preview while dragging a slider.
+const PREVIEW_ROW_STEP: usize = 4;
+/// Largest number of frames processed in the preview while dragging a slider.
+const PREVIEW_MAX_FRAMES: usize = 16;
+
+/// Maximum number of points per frame in a sparkline.
+const SPARKLINE_POINTS: usize = 100;
+/// Maximum number of frames in a sparkline.
+const SPARKLINE_FRAMES: usize = 8;
+
+fn sparkline_from_dataset(dataset: &Dataset) -> Sparkline {
+    let step = (dataset.data.nrows() / SPARKLINE_POINTS).max(1);
+    dataset
+        .data
+        .axis_iter(ndarray::Axis(1))
+        .step_by(2)
+        .zip(dataset.data.axis_iter(ndarray::Axis(1)).skip(1).step_by(2))
+        .take(SPARKLINE_FRAMES)
+        .map(|(xs, ys)| {
+            xs.iter()
+                .zip(ys)
+                .step_by(step)
+                .map(|(x, y)| [*x, *y])
+                .collect()
+        })
+        .collect()
+}
+
+/// Maximum number of points per frame in a frame thumbnail.
+const THUMBNAIL_POINTS: usize = 60;
+/// Maximum number of frames offered in a frame picker.
+const THUMBNAIL_FRAMES: usize = 200;
+
+/// One miniature line per frame (all frames, unlike a sparkline).
+fn frame_thumbnails(dataset: &Dataset) -> Sparkline {
+    let step = (dataset.data.nrows() / THUMBNAIL_POINTS).max(1);
+    dataset
+        .data
+        .axis_iter(ndarray::Axis(1))
+        .step_by(2)
+        .zip(dataset.data.axis_iter(ndarray::Axis(1)).skip(1).step_by(2))
+        .take(THUMBNAIL_FRAMES)
+        .map(|(xs, ys)| {
+            xs.iter()
+                .zip(ys)
+                .step_by(step)
+                .filter(|(x, y)| x.is_finite() && y.is_finite())
+                .map(|(x, y)| [*x, *y])
+                .collect()
+        })
+        .collect()
+}
+
+/// Row of frame thumbnails labeled with their (1-based) frame number, the
+/// number of the clicked frame is returned.
+fn frame_picker(
+    ui: &mut Ui,
+    id: &str,
+    thumbnails: &Sparkline,
+    is_selected: impl Fn(usize) -> bool,
+) -> Option<usize> {
+    let mut clicked = None;
+    egui::ScrollArea::horizontal().id_source(id).show(ui, |ui| {
+        ui.horizontal(|ui| {
+            for (j, pts) in thumbnails.iter().enumerate() {
+                let frame = j + 1;
+                ui.vertical(|ui| {
+                    Plot::new(format!("{id} frame {frame}"))
+                        .width(60.0)
+                        .height(30.0)
+                        .show_axes(false)
+                        .show_grid(false)
+                        .show_x(false)
+                        .show_y(false)
+                        .allow_zoom(false)
+                        .allow_drag(false)
+                        .allow_scroll(false)
+                        .allow_boxed_zoom(false)
+                        .allow_double_click_reset(false)
+                        .show(ui, |plot_ui| {
+                            let color = PALETTE[j % PALETTE.len()];
+                            plot_ui.line(Line::new(PlotPoints::from(pts.clone())).color(color));
+                        });
+                    if ui
+                        .selectable_label(is_selected(frame), frame.to_string())
+                        .clicked()
+                    {
+                        clicked = Some(frame);
+                    }
+                });
+            }
+        });
+    });
+    clicked
+}
+
+fn draw_sparkline(ui: &mut Ui, i: usize, sparkline: &Sparkline) {
+    Plot::new(format!("sparkline {i}"))
+        .height(40.0)
+        .show_axes(false)
+        .show_grid(false)
+        .show_x(false)
+        .show_y(false)
+        .allow_zoom(false)
+        .allow_drag(false)
+        .allow_scroll(false)
+        .allow_boxed_zoom(false)
+        .allow_double_click_reset(false)
+        .show(ui, |plot_ui| {
+            for (j, pts) in sparkline.iter().enumerate() {
+                let color = PALETTE[j % PALETTE.len()];
+                plot_ui.line(Line::new(PlotPoints::from(pts.clone())).color(color));
+            }
+        });
+}
+
+/// Per-pixel minimum, median and maximum of all frames, on the x-axis of the first frame.
+struct Envelope {
+    min: Vec<[f64; 2]>,
+    median: Vec<[f64; 2]>,
+    max: Vec<[f64; 2]>,
+}
+
+/// Maximum number of segments the envelope band is drawn with.
+const ENVELOPE_SEGMENTS: usize = 500;
+
+fn envelope_from_dataset(dataset: &Dataset) -> Option<Envelope> {
+    if dataset.data.ncols() < 2 {
+        return None;
+    }
+    let grid = dataset.data.column(0);
+    // frames with a different x-axis are resampled on the grid of the first frame
+    let frames: Vec<_> = dataset
+        .data
+        .axis_iter(ndarray::Axis(1))
+        .step_by(2)
+        .zip(dataset.data.axis_iter(ndarray::Axis(1)).skip(1).step_by(2))
+        .map(|(xs, ys)| {
+            if xs == grid {
+                ys.to_owned()
+            } else {
+                linear_resample_array(&xs, &ys, &grid)
+            }
+        })
+        .collect();
+    let mut envelope = Envelope {
+        min: vec![],
+        median: vec![],
+        max: vec![],
+    };
+    for (i, x) in grid.iter().enumerate() {
+        let mut values: Vec<f64> = frames
+            .iter()
+            .map(|ys| ys[i])
+            .filter(|y| y.is_finite())
+            .collect();
+        if values.is_empty() {
+            continue;
+        }
+        values.sort_by(|a, b| a.total_cmp(b));
+        let n = values.len();
+        // for an odd number of values, both indices point to the middle value
+        let median = 0.5 * (values[(n - 1) / 2] + values[n / 2]);
+        envelope.min.push([*x, values[0]]);
+        envelope.median.push([*x, median]);
+        envelope.max.push([*x, values[n - 1]]);
+    }
+    Some(envelope)
+}
+
+fn draw_envelope(plot_ui: &mut egui_plot::PlotUi, envelope: &Envelope) {
+    let color = PALETTE[0];
+    let fill = Color32::from_rgba_unmultiplied(color.r(), color.g(), color.b(), 60);
+    // the band is not convex, so it is drawn as a series of (convex) quads
+    let step = (envelope.min.len() / ENVELOPE_SEGMENTS).max(1);
+    let indices: Vec<usize> = (0..envelope.min.len())
+        .step_by(step)
+        .chain(std::iter::once(envelope.min.len().saturating_sub(1)))
+        .collect();
+    for pair in indices.windows(2) {
+        let (i, j) = (pair[0], pair[1]);
+        if i == j {
+            continue;
+        }
+        let quad = vec![
+            envelope.min[i],
+            envelope.min[j],
+            envelope.max[j],
+            envelope.max[i],
+        ];
+        plot_ui.polygon(
+            Polygon::new(PlotPoints::from(quad))
+                .fill_color(fill)
+                .stroke(egui::Stroke::NONE),
+        );
+    }
+    plot_ui.line(
+        Line::new(PlotPoints::from(envelope.min.clone()))
+            .color(fill)
+            .name("min/max"),
+    );
+    plot_ui.line(
+        Line::new(PlotPoints::from(envelope.max.clone()))
+            .color(fill)
+            .name("min/max"),
+    );
+    plot_ui.line(
+        Line::new(PlotPoints::from(envelope.median.clone()))
+            .color(color)
+            .name("median"),
+    );
+}
+
+/// Points of a frame shifted vertically by `dy`, e.g. in a waterfall plot.
+fn shifted_points(points: &[PlotPoint], dy: f64) -> PlotPoints {
+    PlotPoints::Owned(
+        points
+            .iter()
+            .map(|p| PlotPoint::new(p.x, p.y + dy))
+            .collect(),
+    )
+}
+
+/// Find the frame (out of `frames`) whose curve passes closest to the cursor,
+/// returns its index and the point on the curve at the cursor's x-position.
+/// Frame `i` is drawn shifted by `i * offset` (waterfall plot).
+fn nearest_frame_at(
+    plot_points: &[PlotPoints],
+    frames: &[usize],
+    cursor: PlotPoint,
+    offset: f64,
+) -> Option<(usize, PlotPoint)> {
+    frames
+        .iter()
+        .filter_map(|i| {
+            let y = plot_points
+                .get(*i)?
+                .points()
+                .windows(2)
+                .find(|pts| {
+                    (pts[0].x <= cursor.x && cursor.x <= pts[1].x)
+                        || (pts[1].x <= cursor.x && cursor.x <= pts[0].x)
+                })
+                .map(|pts| {
+                    if pts[0].x == pts[1].x {
+                        pts[0].y
+                    } else {
+                        lininterp(cursor.x, pts[0].x, pts[1].x, pts[0].y, pts[1].y)
+                    }
+                })?
+                + *i as f64 * offset;
+            y.is_finite().then_some((*i, PlotPoint::new(cursor.x, y)))
+        })
+        .min_by(|(_, p1), (_, p2)| (p1.y - cursor.y).abs().total_cmp(&(p2.y - cursor.y).abs()))
+}
+
+/// Peak marked by double-clicking a curve, it stays on the curve of its
+/// frame when the pipeline changes.
+#[derive(Clone, Debug, PartialEq)]
+struct PeakMarker {
+    x: f64,
+    /// index of the frame, counting from 0
+    frame: usize,
+}
+
+enum PeakClick {
+    Add(PeakMarker),
+    /// index of the marker to remove
+    Remove(usize),
+}
+
+impl PeakMarker {
+    /// Point on the curve of the marked frame, None if the frame is not
+    /// among `frames` or the curve does not reach the marked x-value.
+    fn position(
+        &self,
+        plot_points: &[PlotPoints],
+        frames: &[usize],
+        offset: f64,
+    ) -> Option<PlotPoint> {
+        if !frames.contains(&self.frame) {
+            return None;
+        }
+        nearest_frame_at(
+            plot_points,
+            &[self.frame],
+            PlotPoint::new(self.x, 0.0),
+            offset,
+        )
+        .map(|(_, point)| point)
+    }
+}
+
+/// Marked peaks as CSV, sorted by position, with the intensity of the
+/// marked frame at each peak.
+fn peak_markers_to_csv(
+    markers: &[PeakMarker],
+    plot_points: &[PlotPoints],
+    unit: AxisUnit,
+) -> String {
+    let mut csv = match unit {
+        AxisUnit::Unknown => "position,intensity,frame\n".to_owned(),
+        unit => format!("position ({}),intensity,frame\n", unit.symbol()),
+    };
+    let mut markers = markers.to_vec();
+    markers.sort_by(|m1, m2| m1.x.total_cmp(&m2.x));
+    for marker in markers.iter() {
+        let intensity = marker
+            .position(plot_points, &[marker.frame], 0.0)
+            .map_or(f64::NAN, |point| point.y);
+        csv += &format!("{},{},{}\n", marker.x, intensity, marker.frame + 1);
+    }
+    csv
+}
+
+/// First lines of an input file as text.
+struct RawPreview {
+    path: PathBuf,
+    lines: usize,
+    /// the lines, or why they could not be read
+    text: Result<Vec<String>, String>,
+}
+
+impl RawPreview {
+    fn read(path: &Path, lines: usize) -> Self {
+        let text = if path.extension().unwrap_or_default() == "spe" {
+            Err("SPE files are binary, no text to preview".to_owned())
+        } else {
+            open_data_file(path)
+                .and_then(|file| {
+                    BufReader::new(file)
+                        .lines()
+                        .take(lines)
+                        .collect::<std::io::Result<Vec<String>>>()
+                        .map_err(anyhow::Error::from)
+                })
+                .map_err(|err| format!("Could not read input file: {err}"))
+        };
+        Self {
+            path: path.to_owned(),
+            lines,
+            text,
         }
     }
 }
 
-fn make_output_filepath(filepath: &PathBuf) -> PathBuf {
+/// Names of the x-axis and the columns of a dataset that holds a table of
+/// results (one frame per column, all sharing the x-values) instead of spectra.
+#[derive(Clone, Debug)]
+pub struct ResultColumns {
+    pub x_label: String,
+    pub names: Vec<String>,
+}
+
+/// Table of results as CSV, the x-value followed by one value per column.
+fn results_to_csv(dataset: &Dataset, columns: &ResultColumns) -> String {
+    let mut csv = format!("{},{}\n", columns.x_label, columns.names.join(","));
+    for row in dataset.data.rows() {
+        let frame = row.first().copied().unwrap_or(f64::NAN);
+        let values: Vec<String> = row
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|v| v.to_string())
+            .collect();
+        csv += &format!("{},{}\n", frame, values.join(","));
+    }
+    csv
+}
+
+pub(crate) fn make_output_filepath(filepath: &PathBuf) -> PathBuf {
+    // compressed input gives compressed output
+    let compression = Compression::of(filepath);
     let mut filepath = filepath.to_owned();
+    if compression != Compression::None {
+        filepath.set_extension("");
+    }
     filepath.set_extension("");
     let mut fp = filepath.to_str().unwrap().to_owned();
     fp.extend("_processed.csv".chars());
+    fp.push_str(compression.extension());
     PathBuf::from(fp)
 }
 
+/// Output file path with `_{suffix}.csv` instead of the extension, for
+/// exports next to the processed file.
+fn suffixed_path(filepath: &Path, suffix: &str) -> PathBuf {
+    let mut filepath = filepath.to_owned();
+    filepath.set_extension("");
+    PathBuf::from(format!("{}_{suffix}.csv", filepath.display()))
+}
+
 impl RamanGuiApp {
     fn new(preprocessor: Preprocessor) -> Self {
         let ds = Dataset::default();
@@ -760,31 +2632,72 @@ impl RamanGuiApp {
             .filepath
             .clone()
             .unwrap_or(PathBuf::default());
-        let (tx_input_file, _) = channel::<Option<PathBuf>>();
-        let (_, rx_output_path) = channel::<PathBuf>();
 
         Self {
+            active_input_hash: "".to_owned(),
             active_step: None,
             add_step: None,
+            compare_input: false,
+            comparison_points: None,
+            channel_points: vec![],
+            condition_step: None,
             dataset_cache: HashMap::new(),
             dataset: ds.clone(),
+            dialogs: DialogService::disconnected(),
+            envelope: None,
             error_messages: VecDeque::with_capacity(10),
-            filepath_to_load: rx_output_path,
+            file_to_load: None,
+            files_to_append: vec![],
+            input_watcher: None,
             force_update: true,
+            frame_coloring: FrameColoring::Categorical,
+            frame_coloring_values: "".to_owned(),
+            frame_groups: "".to_owned(),
+            heatmap: None,
+            heatmap_settings: HeatmapSettings::default(),
+            heatmap_frame: None,
+            history: None,
             initial_dataset: ds,
             input_file_path,
-            insert_transformer: InsertTransformer::None,
+            input_layout: None,
+            insert_transformer: None,
+            keep_view: true,
+            nest_step: None,
             last_dataset_hash: "".to_owned(),
+            last_preview_hash: "".to_owned(),
+            left_panel_rect: egui::Rect::NOTHING,
             output_file_path,
+            peak_markers: vec![],
+            picking_peaks: false,
             pipeline: Pipeline {
                 transformations: vec![],
             },
+            pipeline_job: None,
+            pending_screenshot: None,
             plot_extension: Some(Box::new(SplineExtensionGUI::new(vec![]))),
+            plot_layout: PlotLayout::Overlay,
             plot_points: pts,
+            result_columns: None,
             preprocessor,
+            raw_preview: None,
+            raw_preview_lines: 20,
+            recent: RecentFiles::load(),
+            reload_input: false,
             reload_pipeline: true,
+            release_step: None,
             remove_step: None,
-            request_file_load: tx_input_file,
+            show_channels: true,
+            showing_preview: false,
+            sparklines: vec![],
+            step_inputs: vec![],
+            suggest_step: None,
+            view_bounds: vec![],
+            view_change: None,
+            visible_export_points: 0,
+            visible_x_range: None,
+            waterfall: None,
+            waterfall_offset: None,
+            watch_input: false,
         }
     }
 }
@@ -820,29 +2733,44 @@ fn draw_fallable_text_edit(ui: &mut Ui, input: &mut String, num: FloatInput) {
     text_edit.show(ui);
 }
 
-#[derive(Debug, PartialEq)]
-enum InsertTransformer {
-    // REGISTER: put new transformers here to make them show up in GUI
-    None,
-    Align,
-    Append,
-    Average,
-    Baseline,
-    Calibrate,
-    CountConversion,
-    Despike,
-    Finning,
-    Integrate,
-    Mask,
-    Normalize,
-    Offset,
-    RamanShift,
-    Reshape,
-    Select,
-    Subtract,
-}
-
-pub trait TransformerGUI: Transformer {
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum FrameColoring {
+    /// cycle through a palette of distinct colors
+    Categorical,
+    /// map frame number onto colormap
+    Index,
+    /// map exposure time onto colormap
+    Exposure,
+    /// map start of exposure onto colormap, e.g. to follow a kinetics run
+    Time,
+    /// map user supplied values onto colormap
+    Values,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum PlotLayout {
+    /// all frames in a single plot
+    Overlay,
+    /// frame groups in vertically stacked plots with linked x-axes
+    Stacked,
+    /// per-pixel min/max band and median of all frames
+    Envelope,
+    /// frames as rows of an image, intensities mapped onto a colormap
+    Heatmap,
+}
+
+/// Change of the visible region of the plots, requested outside of the plots.
+#[derive(Debug, Clone, PartialEq)]
+enum ViewChange {
+    /// show the given region in the first plot (the x-axes are linked)
+    Limits(PlotBounds),
+    /// keep the region of every plot, e.g. when the plotted data changes
+    Keep(Vec<PlotBounds>),
+    /// fit the plots to the data
+    Reset,
+}
+
+pub trait TransformerGUI: Transformer + Send {
     fn render_form(&mut self, ui: &mut Ui) -> ();
     fn get_plot_extension(&self, _ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
         None
@@ -853,12 +2781,103 @@ pub trait TransformerGUI: Transformer {
     fn should_plot_dataset_state_after_transformation(&self) -> bool {
         true
     }
+    /// Whether the form shows thumbnails of the input frames to pick from.
+    fn picks_frames(&self) -> bool {
+        false
+    }
+    /// Names of the columns if the transformation turns the spectra into a
+    /// table of results, with one frame per column and one row per frame.
+    fn result_columns(&self) -> Option<ResultColumns> {
+        None
+    }
+    #[allow(unused)] // only unused in default implementation
+    fn render_frame_picker(&mut self, ui: &mut Ui, step: usize, thumbnails: &Sparkline) {}
+    /// Whether the form offers to estimate its parameters from the data.
+    fn suggests_parameters(&self) -> bool {
+        false
+    }
+    #[allow(unused)] // only unused in default implementation
+    fn suggest_parameters(&mut self, input: &Dataset, context: &PipelineContext) -> Result<()> {
+        Ok(())
+    }
+    /// Steps nested in this one, e.g. the steps a loop repeats.
+    fn nested_steps(&mut self) -> Option<&mut Vec<Box<dyn TransformerGUI>>> {
+        None
+    }
+    /// The condition under which the step runs, None if it always runs.
+    fn condition(&self) -> Option<&Condition> {
+        None
+    }
+    /// Take the step out of a conditional step, to remove the condition.
+    fn take_conditional_step(&mut self) -> Option<Box<dyn TransformerGUI>> {
+        None
+    }
 }
 
 impl TransformerGUI for AlignTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Align");
         ui.add(Slider::new(&mut self.cost_max_abs, 0.01..=1.0).text("tuning parameter"));
+        interpolation_combo(ui, &mut self.interpolation);
+        ui.horizontal(|ui| {
+            ui.label("reference frame:");
+            ui.add(egui::DragValue::new(&mut self.reference).clamp_range(1..=usize::MAX));
+        });
+        let mut use_window = self.window.is_some();
+        ui.checkbox(&mut use_window, "compare only inside window")
+            .on_hover_text("e.g. around a sharp band, more robust for noisy frames");
+        if use_window {
+            let mut window = self.window.unwrap_or_default();
+            ui.horizontal(|ui| {
+                ui.label("from:");
+                ui.add(egui::DragValue::new(&mut window.a));
+                ui.label("to:");
+                ui.add(egui::DragValue::new(&mut window.b));
+            });
+            self.window = Some(window);
+        } else {
+            self.window = None;
+        }
+        let mut fit_stretch = self.stretch.is_some();
+        ui.checkbox(&mut fit_stretch, "fit stretch of x-axis")
+            .on_hover_text("for frames that differ in dispersion, e.g. after grating drift");
+        if fit_stretch {
+            let mut stretch = self.stretch.unwrap_or(0.01);
+            ui.add(
+                egui::DragValue::new(&mut stretch)
+                    .speed(0.001)
+                    .clamp_range(0.0..=0.5)
+                    .prefix("max. "),
+            );
+            self.stretch = Some(stretch);
+        } else {
+            self.stretch = None;
+        }
+        if !self.manual_shifts.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} frame(s) shifted by hand",
+                    self.manual_shifts.len()
+                ));
+                if ui.button("clear").clicked() {
+                    self.manual_shifts.clear();
+                }
+            });
+        }
+    }
+    fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
+        Some(Box::new(AlignExtensionGUI::new(
+            ds,
+            self.reference,
+            &self.shifts,
+            &self.manual_shifts,
+        )))
+    }
+    fn update_from_plot_extension(&mut self, ext: PlotExtensionResult) -> () {
+        match ext {
+            PlotExtensionResult::Align(shifts) => self.manual_shifts = shifts,
+            _ => panic!("Align transformer got wrong plot extension result. This should not have happend, please file an issue."),
+        }
     }
 }
 
@@ -872,12 +2891,89 @@ impl TransformerGUI for AppendTransform {
         ui.text_edit_singleline(&mut fp);
         self.filepath = Some(PathBuf::from(fp));
         ui.checkbox(&mut self.horizontal, "as new rows?");
+        if !self.horizontal {
+            egui::ComboBox::from_label("if rows differ")
+                .selected_text(format!("{:?}", self.mismatch))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.mismatch, AppendMismatch::Error, "Error");
+                    ui.selectable_value(&mut self.mismatch, AppendMismatch::Resample, "Resample");
+                    ui.selectable_value(&mut self.mismatch, AppendMismatch::Pad, "Pad");
+                });
+            if self.mismatch == AppendMismatch::Resample {
+                interpolation_combo(ui, &mut self.interpolation);
+            }
+        }
+    }
+}
+
+impl TransformerGUI for ResponseCorrectionTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Response Correction");
+        ui.label("Lamp spectrum file:");
+        let mut fp = match &self.filepath {
+            None => "".to_owned(),
+            Some(fp) => format!("{}", fp.display()),
+        };
+        ui.text_edit_singleline(&mut fp);
+        self.filepath = Some(PathBuf::from(fp));
+        ui.label("Certified lamp emission file (optional):");
+        let mut reference = match &self.reference {
+            None => "".to_owned(),
+            Some(fp) => format!("{}", fp.display()),
+        };
+        ui.text_edit_singleline(&mut reference);
+        self.reference = if reference.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(reference))
+        };
     }
 }
 
 impl TransformerGUI for AverageTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Average");
+        ui.checkbox(&mut self.resample, "resample frames on differing x-axes")
+            .on_hover_text(
+                "interpolate frames onto the x-axis of the first frame instead of failing",
+            );
+        if self.resample {
+            interpolation_combo(ui, &mut self.interpolation);
+        }
+        let mut by_exposure = self.weights == Some(AverageWeights::Exposure);
+        ui.checkbox(&mut by_exposure, "weight by exposure time");
+        if by_exposure {
+            self.weights = Some(AverageWeights::Exposure);
+            return;
+        }
+        ui.label("Weights (comma separated, one per frame, empty for none):");
+        let input = &mut self.gui_text_buffers.weights;
+        let text_edit = if input.trim().is_empty() {
+            self.weights = None;
+            egui::TextEdit::singleline(input)
+        } else {
+            match parse_weight_values(input) {
+                Ok(weights) => {
+                    self.weights = Some(AverageWeights::Values(weights));
+                    egui::TextEdit::singleline(input)
+                }
+                // indicator input error in red, no other error handling
+                Err(_) => {
+                    egui::TextEdit::singleline(input).text_color(Color32::from_rgb(255, 0, 0))
+                }
+            }
+        };
+        text_edit.show(ui);
+    }
+    fn update_text_buffers(&mut self) -> () {
+        self.gui_text_buffers.weights = match &self.weights {
+            Some(AverageWeights::Values(weights)) => weights
+                .iter()
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+            _ => "".to_owned(),
+        };
     }
 }
 
@@ -885,14 +2981,51 @@ impl TransformerGUI for BaselineTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Draw Baseline");
         ui.checkbox(&mut self.store, "Store baseline separately");
+        ui.add_enabled(
+            !self.store,
+            egui::Checkbox::new(&mut self.keep, "Keep baseline as channel"),
+        )
+        .on_hover_text(
+            "Subtract the baseline and keep it in the \"baseline\" channel of each frame.",
+        );
+        ui.add_enabled(
+            !self.store,
+            egui::Checkbox::new(&mut self.interpolate, "Interpolate between frames"),
+        )
+        .on_hover_text("Frames without points of their own take the baseline interpolated between the closest frames with points, instead of the points drawn on all frames.");
+        ui.horizontal(|ui| {
+            let mut snap = self.snap.is_some();
+            ui.checkbox(&mut snap, "Snap points to data")
+                .on_hover_text("Move each point onto the data of each frame: its y-value becomes the median of the intensities around it.");
+            if snap {
+                let mut half_width = self.snap.unwrap_or(2);
+                ui.add(
+                    egui::DragValue::new(&mut half_width)
+                        .clamp_range(0..=50)
+                        .prefix("± ")
+                        .suffix(" pixels"),
+                );
+                self.snap = Some(half_width);
+            } else {
+                self.snap = None;
+            }
+        });
     }
-    fn get_plot_extension(&self, _ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
-        let ext = SplineExtensionGUI::new(self.points.iter().map(|pt| [pt.a, pt.b]).collect());
+    fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
+        let ext = SplineExtensionGUI::with_frame_points(
+            self.points.iter().map(|pt| [pt.a, pt.b]).collect(),
+            &self.frame_points,
+            ds,
+            self.snap,
+        );
         Some(Box::new(ext))
     }
     fn update_from_plot_extension(&mut self, ext: PlotExtensionResult) -> () {
         match ext {
-            PlotExtensionResult::Spline(points) => self.points = points,
+            PlotExtensionResult::Spline(points, frame_points) => {
+                self.points = points;
+                self.frame_points = frame_points;
+            }
             _ => {
                 panic!("Baseline transformer got wrong plot extension result. This should not have happend, please file an issue.")
             }
@@ -926,6 +3059,179 @@ impl TransformerGUI for CalibrationTransform {
         if ui.button("+").clicked() {
             self.points.push(Pair { a: 1.0, b: 1.0 });
         }
+        ui.add(Slider::new(&mut self.order, 1..=4).text("polynomial order"));
+        if !self.residuals.is_empty() {
+            let max_residual = self.residuals.iter().map(|r| r.abs()).fold(0.0, f64::max);
+            ui.label(format!("largest residual: {:.4}", max_residual));
+        }
+        ui.separator();
+        let mut automatic = self.lamp_frame.is_some();
+        ui.checkbox(&mut automatic, "automatic (lamp spectrum)");
+        if !automatic {
+            self.lamp_frame = None;
+            return;
+        }
+        let mut frame = self.lamp_frame.unwrap_or(1);
+        ui.add(
+            egui::DragValue::new(&mut frame)
+                .clamp_range(1..=usize::MAX)
+                .prefix("lamp frame: "),
+        );
+        self.lamp_frame = Some(frame);
+        egui::ComboBox::from_label("lamp")
+            .selected_text(match self.lamp {
+                None => "none",
+                Some(Lamp::Neon) => "neon",
+                Some(Lamp::Argon) => "argon",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.lamp, None, "none");
+                ui.selectable_value(&mut self.lamp, Some(Lamp::Neon), "neon");
+                ui.selectable_value(&mut self.lamp, Some(Lamp::Argon), "argon");
+            });
+        ui.add(
+            egui::DragValue::new(&mut self.tolerance)
+                .speed(0.05)
+                .clamp_range(0.0..=f64::MAX)
+                .prefix("tolerance: "),
+        );
+        ui.add(Slider::new(&mut self.min_height, 0.0..=1.0).text("min. peak height"));
+    }
+}
+
+impl TransformerGUI for CoaddRegionsTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Co-add Regions");
+        let mut from_input = self.regions.is_none();
+        ui.checkbox(&mut from_input, "number of regions from input")
+            .on_hover_text("SPE files with several regions of interest record their number.");
+        self.regions = match (from_input, self.regions) {
+            (true, _) => None,
+            (false, current) => {
+                let mut regions = current.unwrap_or(2);
+                ui.add(Slider::new(&mut regions, 1..=16).text("regions"));
+                Some(regions)
+            }
+        };
+        ui.label("weights (comma separated, empty: all 1)");
+        let mut weights = self
+            .weights
+            .as_ref()
+            .map(|ws| {
+                ws.iter()
+                    .map(|w| w.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .unwrap_or_default();
+        ui.text_edit_singleline(&mut weights);
+        let parsed: Vec<f64> = weights
+            .split(',')
+            .filter_map(|w| w.trim().parse().ok())
+            .collect();
+        self.weights = (!parsed.is_empty()).then_some(parsed);
+        ui.label("weight map file (empty: none)");
+        let mut fp = match &self.weight_map {
+            None => "".to_owned(),
+            Some(fp) => format!("{}", fp.display()),
+        };
+        ui.text_edit_singleline(&mut fp);
+        self.weight_map = (!fp.is_empty()).then(|| PathBuf::from(fp));
+    }
+}
+
+impl TransformerGUI for ConditionalTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.horizontal(|ui| {
+            ui.label("when");
+            let input = &mut self.gui_text_buffer;
+            let text_edit = match input.parse::<Condition>() {
+                Ok(condition) => {
+                    self.when = condition;
+                    egui::TextEdit::singleline(input)
+                }
+                // indicator input error in red, no other error handling
+                Err(_) => {
+                    egui::TextEdit::singleline(input).text_color(Color32::from_rgb(255, 0, 0))
+                }
+            };
+            text_edit.show(ui).response.on_hover_text(
+                "e.g. \"nframes > 1\" or \"xmax > 3500 and npoints >= 100\" (properties: nframes, npoints, xmin, xmax)",
+            );
+        });
+        if self.skipped {
+            ui.label("skipped in the last run");
+        }
+        if let Some(step) = self.step.as_mut() {
+            step.render_form(ui);
+        }
+    }
+    fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
+        self.step.as_ref()?.get_plot_extension(ds)
+    }
+    fn update_from_plot_extension(&mut self, ext: PlotExtensionResult) -> () {
+        if let Some(step) = self.step.as_mut() {
+            step.update_from_plot_extension(ext);
+        }
+    }
+    fn update_text_buffers(&mut self) -> () {
+        self.gui_text_buffer = self.when.to_string();
+        if let Some(step) = self.step.as_mut() {
+            step.update_text_buffers();
+        }
+    }
+    fn should_plot_dataset_state_after_transformation(&self) -> bool {
+        self.step
+            .as_ref()
+            .is_none_or(|step| step.should_plot_dataset_state_after_transformation())
+    }
+    fn picks_frames(&self) -> bool {
+        self.step.as_ref().is_some_and(|step| step.picks_frames())
+    }
+    fn result_columns(&self) -> Option<ResultColumns> {
+        self.step.as_ref()?.result_columns()
+    }
+    fn render_frame_picker(&mut self, ui: &mut Ui, step: usize, thumbnails: &Sparkline) {
+        if let Some(inner) = self.step.as_mut() {
+            inner.render_frame_picker(ui, step, thumbnails);
+        }
+    }
+    fn suggests_parameters(&self) -> bool {
+        self.step
+            .as_ref()
+            .is_some_and(|step| step.suggests_parameters())
+    }
+    fn suggest_parameters(&mut self, input: &Dataset, context: &PipelineContext) -> Result<()> {
+        match self.step.as_mut() {
+            Some(step) => step.suggest_parameters(input, context),
+            None => Ok(()),
+        }
+    }
+    fn nested_steps(&mut self) -> Option<&mut Vec<Box<dyn TransformerGUI>>> {
+        self.step.as_mut()?.nested_steps()
+    }
+    fn condition(&self) -> Option<&Condition> {
+        Some(&self.when)
+    }
+    fn take_conditional_step(&mut self) -> Option<Box<dyn TransformerGUI>> {
+        self.step.take()
+    }
+}
+
+impl TransformerGUI for BoseEinsteinTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Bose-Einstein Correction");
+        ui.label("Temperature in Kelvin");
+        draw_fallable_text_edit(
+            ui,
+            &mut self.gui_text_buffers.temperature,
+            FloatInput::Number(&mut self.temperature),
+        );
+        ui.checkbox(&mut self.reduced, "reduced intensity")
+            .on_hover_text("multiply by the Raman shift as well");
+    }
+    fn update_text_buffers(&mut self) -> () {
+        self.gui_text_buffers.temperature = self.temperature.to_string();
     }
 }
 
@@ -951,11 +3257,126 @@ impl TransformerGUI for CountConversionTransform {
     }
 }
 
+impl TransformerGUI for DeconvolveTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Deconvolution");
+        egui::ComboBox::from_label("method")
+            .selected_text(format!("{:?}", self.method))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(
+                    &mut self.method,
+                    DeconvolutionMethod::RichardsonLucy,
+                    "Richardson-Lucy",
+                );
+                ui.selectable_value(&mut self.method, DeconvolutionMethod::Fsd, "FSD")
+                    .on_hover_text("Fourier self-deconvolution");
+            });
+        egui::ComboBox::from_label("line shape")
+            .selected_text(format!("{:?}", self.shape))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.shape, LineShape::Gaussian, "Gaussian");
+                ui.selectable_value(&mut self.shape, LineShape::Lorentzian, "Lorentzian");
+            });
+        ui.horizontal(|ui| {
+            ui.label("FWHM:");
+            ui.add(
+                egui::DragValue::new(&mut self.fwhm)
+                    .speed(0.1)
+                    .clamp_range(0.001..=f64::MAX),
+            );
+        });
+        match self.method {
+            DeconvolutionMethod::RichardsonLucy => {
+                ui.add(Slider::new(&mut self.iterations, 1..=500).text("iterations"));
+            }
+            DeconvolutionMethod::Fsd => {
+                ui.add(Slider::new(&mut self.narrowing, 1.0..=5.0).text("narrowing factor"));
+            }
+        }
+    }
+}
+
 impl TransformerGUI for DespikeTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Despiking");
-        ui.add(Slider::new(&mut self.siglim, 0.0..=100.0).text("sigma limit"));
-        ui.add(Slider::new(&mut self.flim, 0.0..=100.0).text("flim"));
+        egui::ComboBox::from_label("method")
+            .selected_text(format!("{:?}", self.method))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.method, DespikeMethod::Laplace, "Laplace");
+                ui.selectable_value(&mut self.method, DespikeMethod::Finning, "Finning");
+                ui.selectable_value(&mut self.method, DespikeMethod::Temporal, "Temporal");
+            });
+        match self.method {
+            DespikeMethod::Laplace => {
+                ui.add(Slider::new(&mut self.siglim, 0.0..=100.0).text("sigma limit"));
+                ui.add(Slider::new(&mut self.flim, 0.0..=100.0).text("flim"));
+                ui.add(Slider::new(&mut self.iterations, 1..=10).text("iterations"));
+                let mut readnoise_from_profile = self.readnoise.is_none();
+                ui.checkbox(
+                    &mut readnoise_from_profile,
+                    "read noise from instrument profile",
+                );
+                if readnoise_from_profile {
+                    self.readnoise = None;
+                } else {
+                    let mut readnoise = self.readnoise.unwrap_or(6.0);
+                    ui.add(Slider::new(&mut readnoise, 0.0..=50.0).text("read noise (e⁻)"));
+                    self.readnoise = Some(readnoise);
+                }
+                let mut gain_from_data = self.gain.is_none();
+                ui.checkbox(
+                    &mut gain_from_data,
+                    "gain from instrument profile or file metadata",
+                );
+                if gain_from_data {
+                    self.gain = None;
+                } else {
+                    let mut gain = self.gain.unwrap_or(1.0);
+                    ui.add(Slider::new(&mut gain, 0.1..=20.0).text("gain (e⁻/count)"));
+                    self.gain = Some(gain);
+                }
+            }
+            DespikeMethod::Finning => {
+                ui.add(Slider::new(&mut self.threshold, 1.0..=10.0).text("threshold"));
+                ui.add(Slider::new(&mut self.iterations, 1..=20).text("max. iterations"));
+            }
+            DespikeMethod::Temporal => {
+                ui.add(Slider::new(&mut self.threshold, 1.0..=10.0).text("threshold"));
+                ui.add(Slider::new(&mut self.window, 1..=10).text("frame window"));
+            }
+        }
+        ui.label(format!("{} pixels replaced", self.replaced.len()));
+    }
+    fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
+        Some(Box::new(ReplacedPixelsExtensionGUI::new(
+            &self.replaced,
+            &ds,
+        )))
+    }
+    fn suggests_parameters(&self) -> bool {
+        true
+    }
+    fn suggest_parameters(&mut self, input: &Dataset, context: &PipelineContext) -> Result<()> {
+        match self.method {
+            DespikeMethod::Laplace => {
+                let (gain, readnoise) = self.gain_and_readnoise(input, context)?;
+                self.siglim = suggest_siglim(input, gain, readnoise)?;
+            }
+            DespikeMethod::Finning | DespikeMethod::Temporal => {
+                self.threshold = suggest_threshold(input, self.method)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TransformerGUI for DropNanTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Drop Missing Rows");
+        ui.label("Removes rows in which all frames are missing.");
+    }
+    fn get_plot_extension(&self, _ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
+        None
     }
 }
 
@@ -976,12 +3397,27 @@ impl TransformerGUI for FinningTransform {
             self.iterations = niter;
         }
     }
+    fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
+        Some(Box::new(ReplacedPixelsExtensionGUI::new(
+            &self.replaced,
+            &ds,
+        )))
+    }
+    fn suggests_parameters(&self) -> bool {
+        true
+    }
+    fn suggest_parameters(&mut self, input: &Dataset, _context: &PipelineContext) -> Result<()> {
+        self.threshold = suggest_threshold(input, DespikeMethod::Finning)?;
+        Ok(())
+    }
 }
 
 impl TransformerGUI for IntegrateTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Integration");
         ui.checkbox(&mut self.local_baseline, "Subtract local baseline?");
+        ui.checkbox(&mut self.uncertainty, "Add uncertainties")
+            .on_hover_text("Follow each integral by its uncertainty, estimated from the noise next to the integration window.");
         for (i, Pair { a: left, b: right }) in self.bounds.iter_mut().enumerate() {
             ui.label(format!("Integration window {}", i + 1));
             ui.horizontal(|ui| {
@@ -993,6 +3429,25 @@ impl TransformerGUI for IntegrateTransform {
                 ui.add(egui::DragValue::new(right));
             });
         }
+        ui.label("Derived quantities, one per line:");
+        let input = &mut self.gui_text_buffers.derived;
+        let derived = input
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.parse::<DerivedQuantity>())
+            .collect::<Result<Vec<_>>>();
+        let text_edit = match derived {
+            Ok(derived) => {
+                self.derived = derived;
+                egui::TextEdit::multiline(input)
+            }
+            // indicator input error in red, no other error handling
+            Err(_) => egui::TextEdit::multiline(input).text_color(Color32::from_rgb(255, 0, 0)),
+        };
+        text_edit
+            .show(ui)
+            .response
+            .on_hover_text("e.g. \"ratio_OD = I(2250..2280) / I(3350..3450)\"");
     }
 
     fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
@@ -1010,21 +3465,303 @@ impl TransformerGUI for IntegrateTransform {
         }
     }
 
-    fn update_text_buffers(&mut self) -> () {}
+    fn update_text_buffers(&mut self) -> () {
+        self.gui_text_buffers.derived = self
+            .derived
+            .iter()
+            .map(|quantity| format!("{}\n", quantity))
+            .collect();
+    }
 
     fn should_plot_dataset_state_after_transformation(&self) -> bool {
         false
     }
+
+    fn result_columns(&self) -> Option<ResultColumns> {
+        let mut names = window_names(&self.bounds);
+        if self.uncertainty {
+            names = names
+                .into_iter()
+                .flat_map(|name| {
+                    let uncertainty = format!("{name} uncertainty");
+                    [name, uncertainty]
+                })
+                .collect();
+        }
+        names.extend(self.derived.iter().map(|quantity| quantity.name.clone()));
+        Some(ResultColumns {
+            x_label: "frame".to_owned(),
+            names,
+        })
+    }
+}
+
+/// Legend entries of integration windows.
+fn window_names(bounds: &[Pair<f64>]) -> Vec<String> {
+    bounds
+        .iter()
+        .map(|Pair { a, b }| format!("{a} to {b}"))
+        .collect()
+}
+
+impl TransformerGUI for KineticsTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Kinetics");
+        ui.checkbox(&mut self.local_baseline, "Subtract local baseline?");
+        let mut use_interval = self.interval.is_some();
+        ui.checkbox(&mut use_interval, "fixed time between frames")
+            .on_hover_text("Otherwise the time stamps of the frames (SPE files) are used.");
+        if use_interval {
+            let mut interval = self.interval.unwrap_or(1.0);
+            ui.horizontal(|ui| {
+                ui.label("interval (s):");
+                ui.add(egui::DragValue::new(&mut interval).clamp_range(0.001..=f64::MAX));
+            });
+            self.interval = Some(interval);
+        } else {
+            self.interval = None;
+        }
+        for (i, Pair { a: left, b: right }) in self.bounds.iter_mut().enumerate() {
+            ui.label(format!("Integration window {}", i + 1));
+            ui.horizontal(|ui| {
+                ui.label("Left bound:");
+                ui.add(egui::DragValue::new(left));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Right bound:");
+                ui.add(egui::DragValue::new(right));
+            });
+        }
+    }
+
+    fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
+        Some(Box::new(IntegrateExtensionGUI {
+            dataset: ds,
+            bounds: self.bounds.to_owned(),
+            ..Default::default()
+        }))
+    }
+
+    fn update_from_plot_extension(&mut self, ext: PlotExtensionResult) -> () {
+        match ext {
+            PlotExtensionResult::Integrate(bounds) => self.bounds = bounds,
+            _ => panic!("Kinetics transformer got wrong plot extension result. This should not have happend, please file an issue."),
+        }
+    }
+
+    fn should_plot_dataset_state_after_transformation(&self) -> bool {
+        false
+    }
+
+    fn result_columns(&self) -> Option<ResultColumns> {
+        Some(ResultColumns {
+            x_label: "time (s)".to_owned(),
+            names: window_names(&self.bounds),
+        })
+    }
+}
+
+impl TransformerGUI for PeakPickTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Peak Picking");
+        ui.checkbox(&mut self.local_baseline, "Subtract local baseline?");
+        for (i, Pair { a: left, b: right }) in self.bounds.iter_mut().enumerate() {
+            ui.label(format!("Peak window {}", i + 1));
+            ui.horizontal(|ui| {
+                ui.label("Left bound:");
+                ui.add(egui::DragValue::new(left));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Right bound:");
+                ui.add(egui::DragValue::new(right));
+            });
+        }
+    }
+
+    fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
+        Some(Box::new(IntegrateExtensionGUI {
+            dataset: ds,
+            bounds: self.bounds.to_owned(),
+            ..Default::default()
+        }))
+    }
+
+    fn update_from_plot_extension(&mut self, ext: PlotExtensionResult) -> () {
+        match ext {
+            PlotExtensionResult::Integrate(bounds) => self.bounds = bounds,
+            _ => panic!("Peak picking transformer got wrong plot extension result. This should not have happend, please file an issue."),
+        }
+    }
+
+    fn should_plot_dataset_state_after_transformation(&self) -> bool {
+        false
+    }
+
+    fn result_columns(&self) -> Option<ResultColumns> {
+        let names = window_names(&self.bounds)
+            .into_iter()
+            .flat_map(|name| {
+                ["position", "intensity", "centroid", "FWHM"].map(|value| format!("{name} {value}"))
+            })
+            .collect();
+        Some(ResultColumns {
+            x_label: "frame".to_owned(),
+            names,
+        })
+    }
+}
+
+impl TransformerGUI for KmeansTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("K-Means Clustering");
+        ui.add(Slider::new(&mut self.clusters, 1..=10).text("clusters"));
+        ui.add(Slider::new(&mut self.max_iterations, 1..=1000).text("max. iterations"));
+        egui::ComboBox::from_label("result")
+            .selected_text(format!("{:?}", self.output))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.output, KmeansOutput::Means, "Means");
+                ui.selectable_value(&mut self.output, KmeansOutput::Assignments, "Assignments");
+            });
+        let mut is_map = self.map_width.is_some();
+        ui.checkbox(&mut is_map, "frames are a map")
+            .on_hover_text("One frame per position, row by row.");
+        self.map_width = match (is_map, self.map_width) {
+            (false, _) => None,
+            (true, current) => {
+                let mut width = current.unwrap_or(10);
+                ui.add(Slider::new(&mut width, 1..=200).text("positions per row"));
+                Some(width)
+            }
+        };
+        if self.assignments.is_empty() {
+            return;
+        }
+        let sizes: Vec<String> = (1..=self.clusters)
+            .map(|k| {
+                self.assignments
+                    .iter()
+                    .filter(|a| **a == k)
+                    .count()
+                    .to_string()
+            })
+            .collect();
+        ui.label(format!("frames per cluster: {}", sizes.join(", ")));
+        if let Some(width) = self.map_width {
+            // one square per position, colored by its cluster
+            let rows = self.assignments.len().div_ceil(width);
+            let size = (200.0 / width.max(rows) as f32).clamp(2.0, 12.0);
+            let (rect, _) = ui.allocate_exact_size(
+                egui::vec2(width as f32 * size, rows as f32 * size),
+                egui::Sense::hover(),
+            );
+            for (i, cluster) in self.assignments.iter().enumerate() {
+                let min =
+                    rect.min + egui::vec2((i % width) as f32 * size, (i / width) as f32 * size);
+                ui.painter().rect_filled(
+                    egui::Rect::from_min_size(min, egui::vec2(size, size)),
+                    0.0,
+                    PALETTE[(cluster - 1) % PALETTE.len()],
+                );
+            }
+        }
+    }
+
+    fn result_columns(&self) -> Option<ResultColumns> {
+        match self.output {
+            KmeansOutput::Means => None,
+            KmeansOutput::Assignments => Some(ResultColumns {
+                x_label: "frame".to_owned(),
+                names: vec!["cluster".to_owned()],
+            }),
+        }
+    }
+}
+
+impl TransformerGUI for McrTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("MCR-ALS");
+        ui.add(Slider::new(&mut self.components, 1..=10).text("components"));
+        ui.add(Slider::new(&mut self.max_iterations, 1..=1000).text("max. iterations"));
+        ui.horizontal(|ui| {
+            ui.label("tolerance (%):");
+            ui.add(
+                egui::DragValue::new(&mut self.tolerance)
+                    .speed(1e-6)
+                    .clamp_range(0.0..=1.0),
+            );
+        });
+        egui::ComboBox::from_label("result")
+            .selected_text(format!("{:?}", self.output))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.output, McrOutput::Spectra, "Spectra");
+                ui.selectable_value(
+                    &mut self.output,
+                    McrOutput::Concentrations,
+                    "Concentrations",
+                );
+            });
+        if let Some(lack_of_fit) = self.lack_of_fit {
+            ui.label(format!(
+                "lack of fit {:.3} % after {} iterations",
+                lack_of_fit, self.iterations
+            ));
+        }
+    }
+
+    fn result_columns(&self) -> Option<ResultColumns> {
+        match self.output {
+            McrOutput::Spectra => None,
+            McrOutput::Concentrations => Some(ResultColumns {
+                x_label: "frame".to_owned(),
+                names: (1..=self.components)
+                    .map(|k| format!("component {}", k))
+                    .collect(),
+            }),
+        }
+    }
 }
 
 impl TransformerGUI for MaskTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Mask Points");
+        egui::ComboBox::from_label("replace by")
+            .selected_text(match self.method {
+                MaskMethod::CrossFrameMean => "mean of other frames",
+                MaskMethod::Interpolate => "interpolation",
+                MaskMethod::Nan => "missing value",
+            })
+            .show_ui(ui, |ui| {
+                ui.selectable_value(
+                    &mut self.method,
+                    MaskMethod::CrossFrameMean,
+                    "mean of other frames",
+                )
+                .on_hover_text("Fails for pixels masked in all frames.");
+                ui.selectable_value(&mut self.method, MaskMethod::Interpolate, "interpolation")
+                    .on_hover_text("Linear between the neighbouring pixels of the same frame.");
+                ui.selectable_value(&mut self.method, MaskMethod::Nan, "missing value");
+            });
+        if !self.range.is_empty() {
+            match &self.frames {
+                Some(frames) => ui.label(format!("masked x-ranges in frames {:?}:", frames)),
+                None => ui.label("masked x-ranges:"),
+            };
+            let mut removed = None;
+            for (i, Pair { a, b }) in self.range.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{} to {}", a, b));
+                    if ui.small_button("remove").clicked() {
+                        removed = Some(i);
+                    }
+                });
+            }
+            if let Some(i) = removed {
+                self.range.remove(i);
+            }
+        }
     }
     fn get_plot_extension(&self, ds: Dataset) -> Option<Box<dyn PlotExtensionGUI>> {
-        let ext = MaskExtensionGUI {
-            ..MaskExtensionGUI::from_mask(&self.mask, ds)
-        };
+        let ext = MaskExtensionGUI::from_mask(&self.mask, ds);
         Some(Box::new(ext))
     }
     fn update_from_plot_extension(&mut self, result: PlotExtensionResult) -> () {
@@ -1038,6 +3775,12 @@ impl TransformerGUI for MaskTransform {
     }
 }
 
+impl TransformerGUI for MedianTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Median");
+    }
+}
+
 impl TransformerGUI for NormalizeTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Normalize");
@@ -1087,6 +3830,22 @@ impl TransformerGUI for OffsetTransform {
         ui.label("Offset: ");
         if self.percentile {
             ui.add(Slider::new(&mut self.offset, 0.0..=1.0));
+            egui::ComboBox::from_label("quantile method")
+                .selected_text(format!("{:?}", self.quantile_method))
+                .show_ui(ui, |ui| {
+                    let method = &mut self.quantile_method;
+                    ui.selectable_value(method, QuantileMethod::Nearest, "Nearest");
+                    ui.selectable_value(method, QuantileMethod::Lower, "Lower");
+                    ui.selectable_value(method, QuantileMethod::Higher, "Higher");
+                    ui.selectable_value(method, QuantileMethod::Midpoint, "Midpoint");
+                    ui.selectable_value(method, QuantileMethod::Linear, "Linear");
+                });
+            ui.horizontal(|ui| {
+                ui.label("min. valid points:");
+                ui.add(egui::DragValue::new(&mut self.min_points).clamp_range(1..=usize::MAX));
+            })
+            .response
+            .on_hover_text("frames with fewer valid points are skipped with a warning");
         } else {
             draw_fallable_text_edit(
                 ui,
@@ -1117,6 +3876,130 @@ impl TransformerGUI for OffsetTransform {
     }
 }
 
+impl TransformerGUI for OffsetStackTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Stack Offset");
+        let mut auto = self.offset == StackOffset::Auto;
+        ui.checkbox(&mut auto, "auto offset")
+            .on_hover_text("Offset by the median intensity range of the frames.");
+        self.offset = match (auto, self.offset) {
+            (true, _) => StackOffset::Auto,
+            (false, StackOffset::Fixed(mut offset)) => {
+                ui.horizontal(|ui| {
+                    ui.label("offset:");
+                    ui.add(egui::DragValue::new(&mut offset).speed(0.1));
+                });
+                StackOffset::Fixed(offset)
+            }
+            (false, StackOffset::Auto) => StackOffset::Fixed(self.applied_offset.unwrap_or(1.0)),
+        };
+    }
+}
+
+impl TransformerGUI for PluginTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Plugin");
+        let available = self
+            .gui_text_buffers
+            .available
+            .get_or_insert_with(available_plugins)
+            .clone();
+        if available.is_empty() {
+            ui.label(format!("no plugins found in {:?}", plugin_dir()));
+            return;
+        }
+        egui::ComboBox::from_label("plugin")
+            .selected_text(&self.plugin)
+            .show_ui(ui, |ui| {
+                for description in available.iter() {
+                    if ui
+                        .selectable_label(self.plugin == description.name, &description.name)
+                        .on_hover_text(&description.about)
+                        .clicked()
+                    {
+                        self.plugin = description.name.clone();
+                        self.parameters.clear();
+                        self.update_text_buffers();
+                    }
+                }
+            });
+        let Some(description) = available.iter().find(|d| d.name == self.plugin) else {
+            return;
+        };
+        ui.label(&description.about);
+        // the form is rendered from the parameters the plugin describes
+        for (i, expected) in description.parameters.iter().enumerate() {
+            let value = self
+                .parameters
+                .iter()
+                .find(|p| p.name == expected.name)
+                .map_or(expected.default.clone(), |p| p.value.clone());
+            let parsed = if expected.kind == PluginParameterKind::Boolean {
+                let mut checked = value.as_bool().unwrap_or(false);
+                ui.checkbox(&mut checked, &expected.name)
+                    .on_hover_text(&expected.help);
+                Some(Value::from(checked))
+            } else {
+                let Some(input) = self.gui_text_buffers.values.get_mut(i) else {
+                    continue;
+                };
+                let parsed = match expected.kind {
+                    PluginParameterKind::Number => {
+                        input.trim().parse::<f64>().ok().map(Value::from)
+                    }
+                    PluginParameterKind::Integer => {
+                        input.trim().parse::<i64>().ok().map(Value::from)
+                    }
+                    _ => Some(Value::from(input.as_str())),
+                };
+                ui.horizontal(|ui| {
+                    let text_edit = match parsed {
+                        Some(_) => egui::TextEdit::singleline(input),
+                        // indicator input error in red, no other error handling
+                        None => egui::TextEdit::singleline(input)
+                            .text_color(Color32::from_rgb(255, 0, 0)),
+                    };
+                    ui.add(text_edit);
+                    ui.label(&expected.name).on_hover_text(&expected.help);
+                });
+                parsed
+            };
+            if let Some(parsed) = parsed.filter(|parsed| *parsed != value) {
+                self.parameters.retain(|p| p.name != expected.name);
+                self.parameters.push(PluginParameter {
+                    name: expected.name.clone(),
+                    value: parsed,
+                });
+            }
+        }
+    }
+    fn update_text_buffers(&mut self) -> () {
+        let available = self
+            .gui_text_buffers
+            .available
+            .get_or_insert_with(available_plugins);
+        let Some(description) = available.iter().find(|d| d.name == self.plugin) else {
+            return;
+        };
+        self.gui_text_buffers.values = description
+            .parameters
+            .iter()
+            .map(|expected| {
+                let value = self
+                    .parameters
+                    .iter()
+                    .find(|p| p.name == expected.name)
+                    .map_or(&expected.default, |p| &p.value);
+                match value {
+                    Value::String(text) => text.clone(),
+                    Value::Number(number) => number.to_string(),
+                    _ => "".to_owned(),
+                }
+            })
+            .collect();
+    }
+}
+
 impl TransformerGUI for RamanShiftTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Raman Shift");
@@ -1140,6 +4023,18 @@ impl TransformerGUI for RamanShiftTransform {
             &mut self.gui_text_buffers.refractive_index,
             FloatInput::Number(&mut self.refractive_index),
         );
+        ui.checkbox(&mut self.inverse, "inverse (shift back to wavelength)");
+        if !self.inverse {
+            let mut cutoff = self.rayleigh_cutoff.is_some();
+            ui.checkbox(&mut cutoff, "remove Rayleigh line");
+            if cutoff {
+                let mut width = self.rayleigh_cutoff.unwrap_or(100.0);
+                ui.add(Slider::new(&mut width, 0.0..=1000.0).text("cutoff (cm⁻¹)"));
+                self.rayleigh_cutoff = Some(width);
+            } else {
+                self.rayleigh_cutoff = None;
+            }
+        }
     }
     fn update_text_buffers(&mut self) -> () {
         self.gui_text_buffers.wavelength = self.wavelength.to_string();
@@ -1150,6 +4045,84 @@ impl TransformerGUI for RamanShiftTransform {
     }
 }
 
+impl TransformerGUI for RejectOutliersTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Reject Outliers");
+        let mut by_correlation = self.min_correlation.is_some();
+        ui.checkbox(&mut by_correlation, "by correlation with median")
+            .on_hover_text("Reject frames whose shape differs from the pixel-wise median.");
+        self.min_correlation = match (by_correlation, self.min_correlation) {
+            (false, _) => None,
+            (true, current) => {
+                let mut min_correlation = current.unwrap_or(0.9);
+                ui.add(Slider::new(&mut min_correlation, 0.0..=1.0).text("min. correlation"));
+                Some(min_correlation)
+            }
+        };
+        let mut by_counts = self.sigma.is_some();
+        ui.checkbox(&mut by_counts, "by total counts")
+            .on_hover_text("Reject frames whose total counts deviate from the mean.");
+        self.sigma = match (by_counts, self.sigma) {
+            (false, _) => None,
+            (true, current) => {
+                let mut sigma = current.unwrap_or(3.0);
+                ui.add(Slider::new(&mut sigma, 0.5..=10.0).text("max. deviation (σ)"));
+                Some(sigma)
+            }
+        };
+        ui.checkbox(&mut self.weight, "down-weight instead of rejecting")
+            .on_hover_text("Keep all frames, outliers get a quality weight below 1.");
+        if self.weight {
+            if !self.weights.is_empty() {
+                let weights: Vec<String> =
+                    self.weights.iter().map(|w| format!("{:.3}", w)).collect();
+                ui.label(format!("weights: {}", weights.join(", ")));
+            }
+        } else if self.rejected.is_empty() {
+            ui.label("no frames rejected");
+        } else {
+            let frames: Vec<String> = self.rejected.iter().map(|n| n.to_string()).collect();
+            ui.label(format!("rejected frames: {}", frames.join(", ")));
+        }
+    }
+}
+
+impl TransformerGUI for RepeatTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Repeat");
+        ui.add(Slider::new(&mut self.times, 1..=50).text("max. passes"));
+        let mut use_tolerance = self.tolerance.is_some();
+        ui.checkbox(&mut use_tolerance, "stop when converged")
+            .on_hover_text("Stop once a pass changes the intensities by less than the tolerance (relative RMS change).");
+        if use_tolerance {
+            let mut tolerance = self.tolerance.unwrap_or(1e-3);
+            ui.horizontal(|ui| {
+                ui.label("tolerance:");
+                ui.add(
+                    egui::DragValue::new(&mut tolerance)
+                        .speed(1e-4)
+                        .clamp_range(0.0..=1.0),
+                );
+            });
+            self.tolerance = Some(tolerance);
+        } else {
+            self.tolerance = None;
+        }
+        ui.label(format!("{} passes in the last run", self.passes));
+        for step in self.steps.iter_mut() {
+            ui.group(|ui| step.render_form(ui));
+        }
+    }
+    fn update_text_buffers(&mut self) -> () {
+        for step in self.steps.iter_mut() {
+            step.update_text_buffers();
+        }
+    }
+    fn nested_steps(&mut self) -> Option<&mut Vec<Box<dyn TransformerGUI>>> {
+        Some(&mut self.steps)
+    }
+}
+
 impl TransformerGUI for ReshapeTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Reshape");
@@ -1160,6 +4133,110 @@ impl TransformerGUI for ReshapeTransform {
     }
 }
 
+impl TransformerGUI for ScaleTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Scale");
+        ui.label("Factor: ");
+        draw_fallable_text_edit(
+            ui,
+            &mut self.gui_text_buffers.factor,
+            FloatInput::Number(&mut self.factor),
+        );
+        ui.label("Add after scaling: ");
+        draw_fallable_text_edit(
+            ui,
+            &mut self.gui_text_buffers.add,
+            FloatInput::Number(&mut self.add),
+        );
+        let mut selection: String = self
+            .target_frames
+            .as_ref()
+            .map(|frames| frames.iter().map(|n| format!("{} ", n)).collect())
+            .unwrap_or_default();
+        ui.label("Select frames to scale: ");
+        ui.text_edit_singleline(&mut selection);
+        self.target_frames = if selection.is_empty() {
+            None
+        } else {
+            Some(
+                selection
+                    .split_whitespace()
+                    .filter_map(|str| str.parse::<usize>().ok())
+                    .collect(),
+            )
+        };
+    }
+    fn update_text_buffers(&mut self) -> () {
+        self.gui_text_buffers.factor = self.factor.to_string();
+        self.gui_text_buffers.add = self.add.to_string();
+    }
+}
+
+impl TransformerGUI for ClipTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Clip");
+        ui.label("Floor: ");
+        draw_fallable_text_edit(
+            ui,
+            &mut self.gui_text_buffers.floor,
+            FloatInput::Number(&mut self.floor),
+        );
+        ui.checkbox(&mut self.nan, "replace by missing values");
+        let mut selection: String = self
+            .target_frames
+            .as_ref()
+            .map(|frames| frames.iter().map(|n| format!("{} ", n)).collect())
+            .unwrap_or_default();
+        ui.label("Select frames to clip: ");
+        ui.text_edit_singleline(&mut selection);
+        self.target_frames = if selection.is_empty() {
+            None
+        } else {
+            Some(
+                selection
+                    .split_whitespace()
+                    .filter_map(|str| str.parse::<usize>().ok())
+                    .collect(),
+            )
+        };
+    }
+    fn update_text_buffers(&mut self) -> () {
+        self.gui_text_buffers.floor = self.floor.to_string();
+    }
+}
+
+impl TransformerGUI for ScriptTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Script");
+        let error = self.syntax_error();
+        let mut text_edit = egui::TextEdit::multiline(&mut self.script).code_editor();
+        // indicator input error in red, the message is shown on hover
+        if error.is_some() {
+            text_edit = text_edit.text_color(Color32::from_rgb(255, 0, 0));
+        }
+        text_edit.show(ui).response.on_hover_text(error.unwrap_or_else(|| {
+            "Rhai script evaluated for every point, the new value is its result or y. Variables: x, y, i, frame, nframes, npoints, exposure, gain, time.".to_owned()
+        }));
+        let mut selection: String = self
+            .target_frames
+            .as_ref()
+            .map(|frames| frames.iter().map(|n| format!("{} ", n)).collect())
+            .unwrap_or_default();
+        ui.label("Select frames to apply script to: ");
+        ui.text_edit_singleline(&mut selection);
+        self.target_frames = if selection.is_empty() {
+            None
+        } else {
+            Some(
+                selection
+                    .split_whitespace()
+                    .filter_map(|str| str.parse::<usize>().ok())
+                    .collect(),
+            )
+        };
+    }
+}
+
 impl TransformerGUI for SelectTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Select Frames");
@@ -1174,12 +4251,158 @@ impl TransformerGUI for SelectTransform {
     fn should_plot_dataset_state_after_transformation(&self) -> bool {
         false
     }
+    fn picks_frames(&self) -> bool {
+        true
+    }
+    fn render_frame_picker(&mut self, ui: &mut Ui, step: usize, thumbnails: &Sparkline) {
+        ui.label("click frames to (de)select them:");
+        let picked = frame_picker(ui, &format!("select {step}"), thumbnails, |frame| {
+            self.frames.contains(&frame)
+        });
+        if let Some(frame) = picked {
+            toggle_frame(&mut self.frames, frame);
+        }
+    }
+}
+
+impl TransformerGUI for SimilarityTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Similarity Matrix");
+        egui::ComboBox::from_label("measure")
+            .selected_text(format!("{:?}", self.measure))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(
+                    &mut self.measure,
+                    SimilarityMeasure::Correlation,
+                    "Correlation",
+                );
+                ui.selectable_value(&mut self.measure, SimilarityMeasure::Cosine, "Cosine");
+                ui.selectable_value(&mut self.measure, SimilarityMeasure::Hqi, "HQI");
+                ui.selectable_value(
+                    &mut self.measure,
+                    SimilarityMeasure::Angle,
+                    "Spectral angle",
+                );
+            });
+        let mut cluster = self.cluster.is_some();
+        ui.checkbox(&mut cluster, "find clusters").on_hover_text(
+            "Group frames in which every pair is at least as similar as the threshold.",
+        );
+        self.cluster = match (cluster, self.cluster) {
+            (false, _) => None,
+            (true, current) => {
+                let (mut threshold, range) = match self.measure {
+                    SimilarityMeasure::Angle => (current.unwrap_or(5.0), 0.0..=90.0),
+                    _ => (current.unwrap_or(0.95), -1.0..=1.0),
+                };
+                ui.add(Slider::new(&mut threshold, range).text("threshold"));
+                Some(threshold)
+            }
+        };
+        // frames ordered from the least similar to the others
+        let mut frames: Vec<usize> = (0..self.mean_similarity.len()).collect();
+        let score = |i: usize| self.measure.score(self.mean_similarity[i]);
+        frames.sort_by(|&i, &j| score(i).total_cmp(&score(j)));
+        if let Some(&outlier) = frames.first() {
+            ui.label(format!(
+                "least similar: frame {} (mean {:.4})",
+                outlier + 1,
+                self.mean_similarity[outlier]
+            ));
+        }
+        for (k, cluster) in self.clusters.iter().enumerate() {
+            let frames: Vec<String> = cluster.iter().map(|n| n.to_string()).collect();
+            ui.label(format!("cluster {}: frames {}", k + 1, frames.join(", ")));
+        }
+    }
+
+    fn result_columns(&self) -> Option<ResultColumns> {
+        if self.mean_similarity.is_empty() {
+            return None;
+        }
+        Some(ResultColumns {
+            x_label: "frame".to_owned(),
+            names: (1..=self.mean_similarity.len())
+                .map(|k| format!("frame {}", k))
+                .collect(),
+        })
+    }
+}
+
+/// Choice of the interpolation used to resample frames.
+fn interpolation_combo(ui: &mut Ui, interpolation: &mut Interpolation) {
+    egui::ComboBox::from_label("interpolation")
+        .selected_text(format!("{:?}", interpolation))
+        .show_ui(ui, |ui| {
+            ui.selectable_value(interpolation, Interpolation::Linear, "Linear");
+            ui.selectable_value(interpolation, Interpolation::Pchip, "Pchip")
+                .on_hover_text("monotone cubic, keeps sharp bands on coarse grids");
+        });
+}
+
+/// Add `frame` to `frames` or remove it if already present, keeping them sorted.
+fn toggle_frame(frames: &mut Vec<usize>, frame: usize) {
+    match frames.iter().position(|f| *f == frame) {
+        Some(pos) => {
+            frames.remove(pos);
+        }
+        None => {
+            frames.push(frame);
+            frames.sort_unstable();
+        }
+    }
+}
+
+impl TransformerGUI for ArithmeticTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Arithmetic");
+        egui::ComboBox::from_label("operation")
+            .selected_text(format!("{:?}", self.operation))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.operation, Operation::Add, "Add");
+                ui.selectable_value(&mut self.operation, Operation::Subtract, "Subtract");
+                ui.selectable_value(&mut self.operation, Operation::Multiply, "Multiply");
+                ui.selectable_value(&mut self.operation, Operation::Divide, "Divide");
+                ui.selectable_value(&mut self.operation, Operation::Ratio, "Ratio")
+                    .on_hover_text("divide, missing values where the reference is zero");
+            });
+        ui.checkbox(&mut self.direct, "direct combination of y-values");
+        if !self.direct {
+            interpolation_combo(ui, &mut self.interpolation);
+        }
+    }
+    fn picks_frames(&self) -> bool {
+        true
+    }
+    fn render_frame_picker(&mut self, ui: &mut Ui, step: usize, thumbnails: &Sparkline) {
+        ui.label("click frames to combine with the reference (none: all frames):");
+        let picked = frame_picker(ui, &format!("targets {step}"), thumbnails, |frame| {
+            self.targets.as_ref().is_some_and(|ts| ts.contains(&frame))
+        });
+        if let Some(frame) = picked {
+            let mut targets = self.targets.take().unwrap_or_default();
+            toggle_frame(&mut targets, frame);
+            if !targets.is_empty() {
+                self.targets = Some(targets);
+            }
+        }
+        ui.label("click the reference frame:");
+        let picked = frame_picker(ui, &format!("reference {step}"), thumbnails, |frame| {
+            frame == self.reference
+        });
+        if let Some(frame) = picked {
+            self.reference = frame;
+        }
+    }
 }
 
 impl TransformerGUI for SubtractTransform {
     fn render_form(&mut self, ui: &mut Ui) -> () {
         ui.heading("Subtract Frames");
         ui.checkbox(&mut self.direct, "direct subtraction of y-values");
+        if !self.direct {
+            interpolation_combo(ui, &mut self.interpolation);
+        }
         let mut minuends: String = match &self.minuends {
             None => "".to_owned(),
             Some(ms) => ms.iter().map(|n| format!("{} ", n)).collect(),
@@ -1200,4 +4423,114 @@ impl TransformerGUI for SubtractTransform {
             self.subtrahend = s;
         }
     }
+    fn picks_frames(&self) -> bool {
+        true
+    }
+    fn render_frame_picker(&mut self, ui: &mut Ui, step: usize, thumbnails: &Sparkline) {
+        ui.label("click frames to subtract from (none: all frames):");
+        let picked = frame_picker(ui, &format!("minuends {step}"), thumbnails, |frame| {
+            self.minuends.as_ref().is_some_and(|ms| ms.contains(&frame))
+        });
+        if let Some(frame) = picked {
+            let mut minuends = self.minuends.take().unwrap_or_default();
+            toggle_frame(&mut minuends, frame);
+            if !minuends.is_empty() {
+                self.minuends = Some(minuends);
+            }
+        }
+        ui.label("click the frame to subtract:");
+        let picked = frame_picker(ui, &format!("subtrahend {step}"), thumbnails, |frame| {
+            frame == self.subtrahend
+        });
+        if let Some(frame) = picked {
+            self.subtrahend = frame;
+        }
+    }
+}
+
+impl TransformerGUI for TimeAxisTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Time Axis");
+        ui.horizontal(|ui| {
+            ui.label("dead time (s):");
+            ui.add(egui::DragValue::new(&mut self.dead_time).clamp_range(0.0..=f64::MAX));
+        })
+        .response
+        .on_hover_text("time between the end of an exposure and the start of the next");
+        let mut known = self.exposure.is_none();
+        ui.checkbox(&mut known, "exposure time from the frames");
+        if known {
+            self.exposure = None;
+        } else {
+            let mut exposure = self.exposure.unwrap_or(1.0);
+            ui.horizontal(|ui| {
+                ui.label("exposure (s):");
+                ui.add(egui::DragValue::new(&mut exposure).clamp_range(0.001..=f64::MAX));
+            });
+            self.exposure = Some(exposure);
+        }
+        ui.checkbox(&mut self.overwrite, "replace existing time stamps");
+    }
+}
+
+impl TransformerGUI for WeightTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Frame Weights");
+        ui.label("Weights (one per frame, between 0 and 1):");
+        let input = &mut self.gui_text_buffers.weights;
+        let parsing_result: Result<Vec<f64>, _> = input
+            .split_whitespace()
+            .map(|str| str.parse::<f64>())
+            .collect();
+        let text_edit = match parsing_result {
+            Ok(weights) => {
+                self.weights = weights;
+                egui::TextEdit::singleline(input)
+            }
+            // indicator input error in red, no other error handling
+            Err(_) => egui::TextEdit::singleline(input).text_color(Color32::from_rgb(255, 0, 0)),
+        };
+        text_edit.show(ui);
+    }
+    fn update_text_buffers(&mut self) -> () {
+        self.gui_text_buffers.weights = self.weights.iter().map(|w| format!("{} ", w)).collect();
+    }
+}
+
+impl TransformerGUI for XShiftTransform {
+    fn render_form(&mut self, ui: &mut Ui) -> () {
+        ui.heading("Shift X-Axis");
+        ui.label("Shifts (one for all frames or one per frame):");
+        for (i, shift) in self.shifts.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(format!("{}:", i + 1));
+                ui.add(egui::DragValue::new(shift).speed(0.1));
+            });
+        }
+        ui.horizontal(|ui| {
+            if ui.button("+").clicked() {
+                self.shifts.push(0.0);
+            }
+            if ui.button("-").clicked() && self.shifts.len() > 1 {
+                self.shifts.pop();
+            }
+        });
+        let mut selection: String = self
+            .target_frames
+            .as_ref()
+            .map(|frames| frames.iter().map(|n| format!("{} ", n)).collect())
+            .unwrap_or_default();
+        ui.label("Select frames to shift: ");
+        ui.text_edit_singleline(&mut selection);
+        self.target_frames = if selection.is_empty() {
+            None
+        } else {
+            Some(
+                selection
+                    .split_whitespace()
+                    .filter_map(|str| str.parse::<usize>().ok())
+                    .collect(),
+            )
+        };
+    }
 }
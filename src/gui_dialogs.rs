@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// Files offered by a dialog, e.g. all CSV files including compressed ones.
+#[derive(Debug, Clone, Copy)]
+pub struct FileFilter {
+    pub name: &'static str,
+    pub extensions: &'static [&'static str],
+}
+
+/// All files that can be read as input.
+pub const INPUT: FileFilter = FileFilter {
+    name: "Spectra",
+    extensions: &["csv", "gz", "zst", "spe", "txt"],
+};
+pub const CSV: FileFilter = FileFilter {
+    name: "CSV",
+    extensions: &["csv", "gz", "zst"],
+};
+pub const PNG: FileFilter = FileFilter {
+    name: "PNG",
+    extensions: &["png"],
+};
+pub const SPE: FileFilter = FileFilter {
+    name: "SPE",
+    extensions: &["spe"],
+};
+pub const TXT: FileFilter = FileFilter {
+    name: "TXT",
+    extensions: &["txt"],
+};
+pub const ZIP: FileFilter = FileFilter {
+    name: "ZIP",
+    extensions: &["zip"],
+};
+
+/// What the chosen file is for, handed back with the path so the GUI knows
+/// what to do with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DialogPurpose {
+    LoadInput,
+    ProcessLike,
+    SaveBundle,
+    SaveCsv,
+    SavePeaks,
+    SavePlot,
+    SaveVisible,
+}
+
+#[derive(Debug, Clone)]
+pub struct DialogRequest {
+    purpose: DialogPurpose,
+    /// whether a new file name is asked for instead of an existing file
+    save: bool,
+    /// whether several existing files can be picked at once
+    multiple: bool,
+    directory: PathBuf,
+    file_name: Option<String>,
+    filters: Vec<FileFilter>,
+}
+
+impl DialogRequest {
+    /// Pick an existing file, starting in the directory of `near`.
+    pub fn open(purpose: DialogPurpose, near: &Path) -> Self {
+        Self {
+            purpose,
+            save: false,
+            multiple: false,
+            directory: near.parent().unwrap_or(Path::new("")).to_owned(),
+            file_name: None,
+            filters: vec![],
+        }
+    }
+
+    /// Pick an existing file, starting in `directory`.
+    pub fn open_in(purpose: DialogPurpose, directory: &Path) -> Self {
+        Self {
+            directory: directory.to_owned(),
+            ..Self::open(purpose, directory)
+        }
+    }
+
+    /// Choose a file to save to, suggesting `suggestion`.
+    pub fn save(purpose: DialogPurpose, suggestion: &Path) -> Self {
+        Self {
+            save: true,
+            file_name: suggestion
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            ..Self::open(purpose, suggestion)
+        }
+    }
+
+    pub fn filter(mut self, filter: FileFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Allow to pick several files, the first is the path of the answer.
+    pub fn multiple(mut self) -> Self {
+        self.multiple = true;
+        self
+    }
+
+    /// The chosen paths, empty if the dialog was cancelled.
+    fn show(&self) -> Vec<PathBuf> {
+        let mut dialog = rfd::FileDialog::new().set_directory(&self.directory);
+        for filter in self.filters.iter() {
+            dialog = dialog.add_filter(filter.name, filter.extensions);
+        }
+        if let Some(file_name) = &self.file_name {
+            dialog = dialog.set_file_name(file_name);
+        }
+        if self.save {
+            dialog.save_file().into_iter().collect()
+        } else if self.multiple {
+            dialog.pick_files().unwrap_or_default()
+        } else {
+            dialog.pick_file().into_iter().collect()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DialogAnswer {
+    pub purpose: DialogPurpose,
+    pub path: PathBuf,
+    /// further files picked in a dialog for several files
+    pub further: Vec<PathBuf>,
+}
+
+/// Shows file dialogs in a thread of its own, so the GUI keeps drawing while
+/// a dialog is open. Requests are sent as messages, the chosen paths come
+/// back tagged with the purpose of the request; cancelled dialogs send
+/// nothing.
+pub struct DialogService {
+    requests: Sender<DialogRequest>,
+    answers: Receiver<DialogAnswer>,
+}
+
+impl DialogService {
+    /// Start the dialog thread, `ctx` is repainted when a path was chosen,
+    /// so the answer is handled without waiting for user input.
+    pub fn spawn(ctx: egui::Context) -> Self {
+        let (requests, rx_requests) = channel::<DialogRequest>();
+        let (tx_answers, answers) = channel::<DialogAnswer>();
+        std::thread::spawn(move || {
+            while let Ok(request) = rx_requests.recv() {
+                let mut paths = request.show().into_iter();
+                if let Some(path) = paths.next() {
+                    let answer = DialogAnswer {
+                        purpose: request.purpose,
+                        path,
+                        further: paths.collect(),
+                    };
+                    if tx_answers.send(answer).is_err() {
+                        break;
+                    }
+                    ctx.request_repaint();
+                }
+                // drain requests made while the dialog was open, so no
+                // further dialogs pop up if a button was clicked several times
+                while rx_requests.try_recv().is_ok() {}
+            }
+        });
+        Self { requests, answers }
+    }
+
+    /// A service without thread, requests are dropped.
+    pub fn disconnected() -> Self {
+        let (requests, _) = channel::<DialogRequest>();
+        let (_, answers) = channel::<DialogAnswer>();
+        Self { requests, answers }
+    }
+
+    pub fn request(&self, request: DialogRequest) {
+        // fails only for a disconnected service
+        let _result = self.requests.send(request);
+    }
+
+    pub fn try_answer(&self) -> Option<DialogAnswer> {
+        self.answers.try_recv().ok()
+    }
+}
@@ -0,0 +1,76 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// Time between two looks at the watched file.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches a file in a thread of its own by polling its modification time
+/// and size, e.g. the input file while a spectrometer is still writing it.
+/// A change is reported once the file stopped changing for one poll
+/// interval, so a file is not read half written.
+pub struct FileWatcher {
+    path: PathBuf,
+    changes: Receiver<()>,
+    stop: Arc<AtomicBool>,
+}
+
+impl FileWatcher {
+    /// Start watching `path`, `ctx` is repainted when the file changed, so
+    /// the change is handled without waiting for user input.
+    pub fn spawn(path: &Path, ctx: egui::Context) -> Self {
+        let (tx_changes, changes) = channel::<()>();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread_path = path.to_owned();
+        std::thread::spawn(move || {
+            let mut reported = file_state(&thread_path);
+            let mut previous = reported;
+            while !thread_stop.load(Ordering::Relaxed) {
+                std::thread::sleep(POLL_INTERVAL);
+                let current = file_state(&thread_path);
+                // report only settled changes
+                if current == previous && current != reported && current.is_some() {
+                    if tx_changes.send(()).is_err() {
+                        break;
+                    }
+                    ctx.request_repaint();
+                    reported = current;
+                }
+                previous = current;
+            }
+        });
+        Self {
+            path: path.to_owned(),
+            changes,
+            stop,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether the file changed since the last call.
+    pub fn changed(&self) -> bool {
+        let mut changed = false;
+        while self.changes.try_recv().is_ok() {
+            changed = true;
+        }
+        changed
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Modification time and size of the file, None if it can not be read.
+fn file_state(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
@@ -1,22 +1,32 @@
+use std::collections::BTreeMap;
 use std::ops::Index;
 
 use egui::{Color32, Ui};
-use egui_plot::{Line, PlotPoint, PlotPoints, PlotUi, Points};
+use egui_plot::{Line, LineStyle, MarkerShape, PlotPoint, PlotPoints, PlotUi, Points, Polygon};
 use ndarray::Axis;
 use noisy_float::{prelude::Float, types::N64};
 use splines::{Key, Spline};
 
 use crate::{
     common::{Dataset, Pair},
+    transformations::{
+        align::FrameShift,
+        baseline::{snapped_y, FramePoint},
+        despike::ReplacedPixel,
+    },
     utils::nearest_index,
 };
 
 #[derive(Debug)]
 pub enum PlotExtensionResult {
+    Align(Vec<FrameShift>),
     Integrate(Vec<Pair<f64>>),
     Mask(Vec<Pair<usize>>),
     Normalize((f64, Option<f64>)),
-    Spline(Vec<Pair<f64>>),
+    /// display only, there is nothing to take over into the transformer
+    ReplacedPixels,
+    /// shared points and points of single frames
+    Spline(Vec<Pair<f64>>, Vec<FramePoint>),
 }
 
 pub trait PlotExtensionGUI {
@@ -32,11 +42,32 @@ pub trait PlotExtensionGUI {
     fn is_pan_allowed(&self) -> bool {
         true
     }
+    /// Frames (0-based) shown in the plot about to be extended, i.e. in its
+    /// frame group and not hidden in the legend.
+    fn set_visible_frames(&mut self, _frames: &[usize]) {}
 }
 
 impl PlotExtensionGUI for MaskExtensionGUI {
+    fn modify_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let extension_toggle_label = self.extension_toggle_label();
+            ui.toggle_value(&mut self.mask_mode_enabled, extension_toggle_label);
+            ui.toggle_value(&mut self.rectangle_mode, "Rectangle")
+                .on_hover_text(
+                    "Drag a rectangle to mask all points inside it in the visible frames.",
+                );
+        });
+    }
+    fn is_pan_allowed(&self) -> bool {
+        !self.rectangle_mode
+    }
+    fn set_visible_frames(&mut self, frames: &[usize]) {
+        self.visible_frames = frames.to_vec();
+    }
     fn modify_plot(&mut self, plot_ui: &mut PlotUi) {
-        if self.mask_mode_enabled {
+        if self.mask_mode_enabled && self.rectangle_mode {
+            self.drag_rectangle(plot_ui);
+        } else if self.mask_mode_enabled {
             if plot_ui.response().clicked() {
                 self.add_point(plot_ui);
             }
@@ -69,26 +100,72 @@ impl PlotExtensionGUI for MaskExtensionGUI {
 }
 
 impl PlotExtensionGUI for SplineExtensionGUI {
+    fn modify_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let extension_toggle_label = self.extension_toggle_label();
+            ui.toggle_value(&mut self.add_point_mode_enabled, extension_toggle_label)
+                .on_hover_text("Click to add a point, right-click to remove one, drag a point to move it.");
+            ui.toggle_value(&mut self.preview, "Preview")
+                .on_hover_text("Show the data minus the baseline, always shown while a point is dragged.");
+            if self.n_frames > 1 {
+                let name = |frame: Option<usize>| match frame {
+                    Some(frame) => format!("frame {}", frame),
+                    None => "all frames".to_owned(),
+                };
+                let mut frame = self.frame;
+                egui::ComboBox::from_label("draw on")
+                    .selected_text(name(frame))
+                    .show_ui(ui, |ui| {
+                        for choice in std::iter::once(None).chain((1..=self.n_frames).map(Some)) {
+                            ui.selectable_value(&mut frame, choice, name(choice));
+                        }
+                    })
+                    .response
+                    .on_hover_text("Frames with points of their own do not use the points drawn on all frames.");
+                self.select_frame(frame);
+            }
+        });
+    }
     fn modify_plot(&mut self, plot_ui: &mut PlotUi) {
-        self.draw_spline(plot_ui);
         if self.add_point_mode_enabled {
+            self.drag_point(plot_ui);
             if plot_ui.response().clicked() {
                 self.add_point(plot_ui);
             } else if plot_ui.response().secondary_clicked() {
                 self.remove_point(plot_ui);
             }
+        } else {
+            self.hovered_point = None;
+            self.dragged_point = None;
+        }
+        if self.preview || self.dragged_point.is_some() {
+            self.draw_preview(plot_ui);
         }
+        self.draw_spline(plot_ui);
+    }
+    fn is_pan_allowed(&self) -> bool {
+        // dragging a point must not move the plot
+        self.hovered_point.is_none() && self.dragged_point.is_none()
     }
     fn get_extension_result(&self) -> PlotExtensionResult {
-        let pts = self
-            .points
+        let mut shared = vec![];
+        let mut frame_points = vec![];
+        let sets = self
+            .stashed
             .iter()
-            .map(|[a, b]| Pair {
-                a: a.to_owned(),
-                b: b.to_owned(),
-            })
-            .collect();
-        PlotExtensionResult::Spline(pts)
+            .chain(std::iter::once((&self.frame, &self.points)));
+        for (frame, points) in sets {
+            match frame {
+                None => shared = points.iter().map(|[a, b]| Pair { a: *a, b: *b }).collect(),
+                Some(frame) => frame_points.extend(points.iter().map(|[x, y]| FramePoint {
+                    frame: *frame,
+                    x: *x,
+                    y: *y,
+                })),
+            }
+        }
+        frame_points.sort_by_key(|point| point.frame);
+        PlotExtensionResult::Spline(shared, frame_points)
     }
 
     fn get_is_active_reference(&mut self) -> &mut bool {
@@ -100,32 +177,174 @@ impl PlotExtensionGUI for SplineExtensionGUI {
     }
 }
 
-/// Draw spline baseline that is subtracted from all scans.
+/// Draw spline baseline that is subtracted from all scans, or from single
+/// frames.
 #[derive(Debug)]
 pub struct SplineExtensionGUI {
     pub add_point_mode_enabled: bool,
+    /// points of the frame drawn on
     pub points: Vec<[f64; 2]>,
     pub spline: splines::Spline<f64, f64>,
+    /// frame drawn on, None for all frames
+    frame: Option<usize>,
+    /// points of the frames not drawn on
+    stashed: BTreeMap<Option<usize>, Vec<[f64; 2]>>,
+    n_frames: usize,
+    /// data the points are snapped to
+    dataset: Dataset,
+    /// half width of the window the points are snapped to, in pixels
+    snap: Option<usize>,
+    /// point under the cursor, which is moved when dragged
+    hovered_point: Option<usize>,
+    dragged_point: Option<usize>,
+    /// show the data minus the baseline
+    preview: bool,
 }
 
+/// Distance (relative to the plot size) below which a point is hit by the
+/// cursor.
+const POINT_HIT_DISTANCE: f64 = 0.02;
+
 impl SplineExtensionGUI {
     pub fn new(points: Vec<[f64; 2]>) -> SplineExtensionGUI {
         let mut spl = Self {
             points,
             add_point_mode_enabled: false,
             spline: Spline::from_vec(vec![]),
+            frame: None,
+            stashed: BTreeMap::new(),
+            n_frames: 1,
+            dataset: Dataset::default(),
+            snap: None,
+            hovered_point: None,
+            dragged_point: None,
+            preview: false,
         };
         spl.update_spline();
         spl
     }
+    /// Extension for the shared points and the points of single frames of
+    /// `dataset`, added points are snapped to the data if `snap` is given.
+    pub fn with_frame_points(
+        points: Vec<[f64; 2]>,
+        frame_points: &[FramePoint],
+        dataset: Dataset,
+        snap: Option<usize>,
+    ) -> SplineExtensionGUI {
+        let mut spl = Self::new(points);
+        for FramePoint { frame, x, y } in frame_points.iter() {
+            spl.stashed.entry(Some(*frame)).or_default().push([*x, *y]);
+        }
+        for points in spl.stashed.values_mut() {
+            points.sort_by(|pt1, pt2| pt1[0].total_cmp(&pt2[0]));
+        }
+        spl.n_frames = dataset.data.ncols() / 2;
+        spl.dataset = dataset;
+        spl.snap = snap;
+        spl
+    }
+    /// Switch to drawing on `frame` (all frames for None).
+    fn select_frame(&mut self, frame: Option<usize>) {
+        if frame == self.frame {
+            return;
+        }
+        let points = self.stashed.remove(&frame).unwrap_or_default();
+        let previous = std::mem::replace(&mut self.points, points);
+        self.stashed.insert(self.frame, previous);
+        self.frame = frame;
+        self.spline = Spline::from_vec(vec![]);
+        self.update_spline();
+    }
     fn add_point(&mut self, plot_ui: &mut PlotUi) {
         if let Some(point) = plot_ui.pointer_coordinate() {
-            self.points.push([point.x, point.y])
+            let y = self.snapped(point.x).unwrap_or(point.y);
+            self.points.push([point.x, y])
         }
         self.points
             .sort_by(|pt1, pt2| pt1[0].partial_cmp(&pt2[0]).unwrap());
         self.update_spline();
     }
+    /// Move the point under the cursor while it is dragged with the primary
+    /// button.
+    fn drag_point(&mut self, plot_ui: &mut PlotUi) {
+        let response = plot_ui.response().clone();
+        let Some(cursor) = plot_ui.pointer_coordinate() else {
+            self.hovered_point = None;
+            return;
+        };
+        if self.dragged_point.is_none() {
+            let span = {
+                let [xmin, ymin] = plot_ui.plot_bounds().min();
+                let [xmax, ymax] = plot_ui.plot_bounds().max();
+                (xmax - xmin, ymax - ymin)
+            };
+            self.hovered_point = self.nearest_point_index(cursor, span).filter(|i| {
+                let [x, y] = self.points[*i];
+                ((x - cursor.x) / span.0).hypot((y - cursor.y) / span.1) < POINT_HIT_DISTANCE
+            });
+            if response.drag_started_by(egui::PointerButton::Primary) {
+                self.dragged_point = self.hovered_point;
+            }
+        }
+        let Some(index) = self.dragged_point else {
+            return;
+        };
+        if response.drag_stopped() {
+            self.dragged_point = None;
+            return;
+        }
+        let moved = [cursor.x, self.snapped(cursor.x).unwrap_or(cursor.y)];
+        self.points[index] = moved;
+        self.points.sort_by(|pt1, pt2| pt1[0].total_cmp(&pt2[0]));
+        self.dragged_point = self.points.iter().position(|pt| *pt == moved);
+        self.update_spline();
+    }
+    /// Draw the frames drawn on minus the baseline.
+    fn draw_preview(&self, plot_ui: &mut PlotUi) {
+        if self.points.len() < 2 {
+            return;
+        }
+        let frames = match self.frame {
+            Some(frame) => frame..=frame,
+            None => 1..=self.n_frames,
+        };
+        for frame in frames.filter(|frame| 2 * frame <= self.dataset.data.ncols()) {
+            let xs = self.dataset.data.column(2 * frame - 2);
+            let ys = self.dataset.data.column(2 * frame - 1);
+            let points: Vec<[f64; 2]> = xs
+                .iter()
+                .zip(ys.iter())
+                .filter_map(|(x, y)| self.spline.sample(*x).map(|baseline| [*x, y - baseline]))
+                .collect();
+            plot_ui.line(
+                Line::new(points)
+                    .color(Color32::GRAY)
+                    .style(LineStyle::dashed_dense())
+                    .name("baseline subtracted"),
+            );
+        }
+    }
+    /// Local median of the frame drawn on at `x`, mean of the local medians
+    /// of all frames if drawing on all frames.
+    fn snapped(&self, x: f64) -> Option<f64> {
+        let half_width = self.snap?;
+        let frames = match self.frame {
+            Some(frame) => frame..=frame,
+            None => 1..=self.n_frames,
+        };
+        let values: Vec<f64> = frames
+            .filter(|frame| 2 * frame <= self.dataset.data.ncols())
+            .filter_map(|frame| {
+                snapped_y(
+                    self.dataset.data.column(2 * frame - 2),
+                    self.dataset.data.column(2 * frame - 1),
+                    x,
+                    half_width,
+                )
+            })
+            .collect();
+        (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+    }
     fn remove_point(&mut self, plot_ui: &mut PlotUi) {
         if let Some(point) = plot_ui.pointer_coordinate() {
             let span = {
@@ -382,12 +601,18 @@ pub struct MaskedPoint {
     pub pixel: usize,
 }
 
-/// Draw spline baseline that is subtracted from all scans.
+/// Mask single points by clicking them, or all points inside a rectangle.
 #[derive(Debug)]
 pub struct MaskExtensionGUI {
     pub mask_mode_enabled: bool,
+    /// drag a rectangle instead of clicking single points
+    pub rectangle_mode: bool,
     pub points: Vec<Pair<usize>>,
     pub dataset: Dataset,
+    /// corners of the rectangle being dragged
+    rectangle: Option<(PlotPoint, PlotPoint)>,
+    /// frames (0-based) the rectangle applies to
+    visible_frames: Vec<usize>,
 }
 
 impl MaskExtensionGUI {
@@ -419,6 +644,65 @@ impl MaskExtensionGUI {
             }
         }
     }
+    /// Track a rectangle dragged with the primary button, draw it while
+    /// dragging and mask the points inside it once it is released.
+    fn drag_rectangle(&mut self, plot_ui: &mut PlotUi) {
+        let response = plot_ui.response().clone();
+        if response.drag_started_by(egui::PointerButton::Primary) {
+            self.rectangle = plot_ui.pointer_coordinate().map(|point| (point, point));
+        }
+        if let (true, Some((_, corner)), Some(point)) = (
+            response.dragged_by(egui::PointerButton::Primary),
+            self.rectangle.as_mut(),
+            plot_ui.pointer_coordinate(),
+        ) {
+            *corner = point;
+        }
+        let Some((start, end)) = self.rectangle else {
+            return;
+        };
+        if response.drag_stopped() {
+            self.mask_rectangle(start, end);
+            self.rectangle = None;
+        } else if response.dragged() {
+            let corners = vec![
+                [start.x, start.y],
+                [end.x, start.y],
+                [end.x, end.y],
+                [start.x, end.y],
+            ];
+            plot_ui.polygon(
+                Polygon::new(PlotPoints::from(corners))
+                    .fill_color(Color32::from_rgba_unmultiplied(255, 0, 0, 30))
+                    .stroke(egui::Stroke::new(1.0, Color32::RED)),
+            );
+        }
+    }
+    /// Mask the points of the visible frames between the corners `a` and
+    /// `b`, points masked already are kept once.
+    fn mask_rectangle(&mut self, a: PlotPoint, b: PlotPoint) {
+        let (xmin, xmax) = (a.x.min(b.x), a.x.max(b.x));
+        let (ymin, ymax) = (a.y.min(b.y), a.y.max(b.y));
+        for frame_idx in self.visible_frames.iter() {
+            if 2 * frame_idx + 1 >= self.dataset.data.ncols() {
+                continue;
+            }
+            let x = self.dataset.data.column(2 * frame_idx);
+            let y = self.dataset.data.column(2 * frame_idx + 1);
+            for (pixel_idx, (xi, yi)) in x.iter().zip(y.iter()).enumerate() {
+                let point = Pair {
+                    a: frame_idx + 1,
+                    b: pixel_idx + 1,
+                };
+                if (xmin..=xmax).contains(xi)
+                    && (ymin..=ymax).contains(yi)
+                    && !self.points.iter().any(|p| p.a == point.a && p.b == point.b)
+                {
+                    self.points.push(point);
+                }
+            }
+        }
+    }
     fn neareast_index_to_cursor(&mut self, plot_ui: &PlotUi) -> Option<Pair<usize>> {
         let mut previous_nearest = (
             1,                // frame number
@@ -453,8 +737,11 @@ impl Default for MaskExtensionGUI {
     fn default() -> Self {
         Self {
             mask_mode_enabled: true,
+            rectangle_mode: false,
             points: vec![],
             dataset: Dataset::default(),
+            rectangle: None,
+            visible_frames: vec![],
         }
     }
 }
@@ -479,6 +766,149 @@ fn distance_cursor(plot_ui: &PlotUi, xi: f64, yi: f64) -> Option<N64> {
     }
 }
 
+// ---- AlignExtension --------------------------------------------------------
+
+/// Show the reference frame of an alignment and shift frames by hand, where
+/// the optimizer matched the wrong bands. The slider starts at the shift of
+/// the last run, the moved frame is previewed as dashed line.
+pub struct AlignExtensionGUI {
+    pub is_active: bool,
+    /// frame moved by the slider, counting from 1
+    frame: usize,
+    reference: usize,
+    /// shift of each frame in the last run
+    applied: Vec<f64>,
+    /// shifts given by hand, by frame
+    manual: BTreeMap<usize, f64>,
+    /// largest shift the slider reaches
+    max_shift: f64,
+    /// aligned frames of the last run
+    dataset: Dataset,
+}
+
+impl AlignExtensionGUI {
+    pub fn new(dataset: Dataset, reference: usize, applied: &[f64], manual: &[FrameShift]) -> Self {
+        let frames = dataset.data.ncols() / 2;
+        let frame = (1..=frames).find(|frame| *frame != reference).unwrap_or(1);
+        let manual: BTreeMap<usize, f64> = manual
+            .iter()
+            .map(|FrameShift { frame, shift }| (*frame, *shift))
+            .collect();
+        // a tenth of the x-range, or more to reach the shifts known already
+        let xs = dataset.data.slice(ndarray::s![.., 0..2 * frames;2]);
+        let (min, max) = xs
+            .iter()
+            .filter(|x| x.is_finite())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
+                (min.min(*x), max.max(*x))
+            });
+        let max_shift = applied
+            .iter()
+            .chain(manual.values())
+            .map(|shift| shift.abs())
+            .fold((max - min) / 10.0, f64::max);
+        Self {
+            is_active: true,
+            frame,
+            reference,
+            applied: applied.to_vec(),
+            manual,
+            max_shift: if max_shift.is_finite() && max_shift > 0.0 {
+                max_shift
+            } else {
+                1.0
+            },
+            dataset,
+        }
+    }
+
+    fn applied_shift(&self, frame: usize) -> f64 {
+        self.applied.get(frame - 1).copied().unwrap_or(0.0)
+    }
+
+    fn frame_line(&self, frame: usize, offset: f64) -> Vec<[f64; 2]> {
+        let xs = self.dataset.data.column(2 * frame - 2);
+        let ys = self.dataset.data.column(2 * frame - 1);
+        xs.iter().zip(ys).map(|(x, y)| [x + offset, *y]).collect()
+    }
+}
+
+impl PlotExtensionGUI for AlignExtensionGUI {
+    fn get_extension_result(&self) -> PlotExtensionResult {
+        PlotExtensionResult::Align(
+            self.manual
+                .iter()
+                .map(|(frame, shift)| FrameShift {
+                    frame: *frame,
+                    shift: *shift,
+                })
+                .collect(),
+        )
+    }
+
+    fn get_is_active_reference(&mut self) -> &mut bool {
+        &mut self.is_active
+    }
+
+    fn extension_toggle_label(&self) -> String {
+        "Shift Frames by Hand".to_owned()
+    }
+
+    fn modify_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let label = self.extension_toggle_label();
+            ui.toggle_value(&mut self.is_active, label);
+            if !self.is_active {
+                return;
+            }
+            let frames = (self.dataset.data.ncols() / 2).max(1);
+            ui.label("frame:");
+            ui.add(egui::DragValue::new(&mut self.frame).clamp_range(1..=frames));
+            if self.frame == self.reference {
+                ui.label("(reference)");
+                return;
+            }
+            let applied = self.applied_shift(self.frame);
+            let mut shift = self.manual.get(&self.frame).copied().unwrap_or(applied);
+            let slider =
+                egui::Slider::new(&mut shift, -self.max_shift..=self.max_shift).text("shift");
+            if ui.add(slider).changed() {
+                self.manual.insert(self.frame, shift);
+            }
+            if self.manual.contains_key(&self.frame)
+                && ui
+                    .button("Optimize")
+                    .on_hover_text("Use the optimized shift for this frame again.")
+                    .clicked()
+            {
+                self.manual.remove(&self.frame);
+            }
+        });
+    }
+
+    fn modify_plot(&mut self, plot_ui: &mut PlotUi) {
+        if !self.is_active || 2 * self.frame.max(self.reference) > self.dataset.data.ncols() {
+            return;
+        }
+        plot_ui.line(
+            Line::new(PlotPoints::from(self.frame_line(self.reference, 0.0)))
+                .color(Color32::GRAY)
+                .width(3.0)
+                .name(format!("reference (frame {})", self.reference)),
+        );
+        if let Some(shift) = self.manual.get(&self.frame) {
+            // the plotted frame is moved by the shift of the last run already
+            let offset = shift - self.applied_shift(self.frame);
+            plot_ui.line(
+                Line::new(PlotPoints::from(self.frame_line(self.frame, offset)))
+                    .color(Color32::RED)
+                    .style(LineStyle::dashed_dense())
+                    .name(format!("frame {} shifted by {:.3}", self.frame, shift)),
+            );
+        }
+    }
+}
+
 // ---- NormalizeExtension ----------------------------------------------------
 
 pub struct NormalizeExtensionGUI {
@@ -530,3 +960,112 @@ impl PlotExtensionGUI for NormalizeExtensionGUI {
         false
     }
 }
+
+// ---- ReplacedPixelsExtension -----------------------------------------------
+
+/// Mark pixels replaced by a despike algorithm and show what was removed
+/// (original minus despiked intensity), to check that no genuine Raman bands
+/// were clipped.
+pub struct ReplacedPixelsExtensionGUI {
+    pub is_active: bool,
+    pub show_removed: bool,
+    /// positions of the replaced pixels in the plot
+    points: Vec<[f64; 2]>,
+    /// removed component of each frame with replaced pixels
+    removed: Vec<(usize, Vec<[f64; 2]>)>,
+}
+
+impl ReplacedPixelsExtensionGUI {
+    pub fn new(replaced: &[ReplacedPixel], dataset: &Dataset) -> Self {
+        let points = replaced
+            .iter()
+            .filter_map(|ReplacedPixel { frame, pixel, .. }| {
+                let x = dataset.data.get([*pixel, 2 * frame - 2])?;
+                let y = dataset.data.get([*pixel, 2 * frame - 1])?;
+                Some([*x, *y])
+            })
+            .collect();
+        // a pixel may be replaced several times, the first record holds the
+        // intensity of the input
+        let mut originals: BTreeMap<usize, BTreeMap<usize, f64>> = BTreeMap::new();
+        for ReplacedPixel {
+            frame,
+            pixel,
+            original,
+        } in replaced.iter()
+        {
+            originals
+                .entry(*frame)
+                .or_default()
+                .entry(*pixel)
+                .or_insert(*original);
+        }
+        let removed = originals
+            .into_iter()
+            .filter(|(frame, _)| *frame >= 1 && 2 * frame <= dataset.data.ncols())
+            .map(|(frame, pixels)| {
+                let xs = dataset.data.column(2 * frame - 2);
+                let ys = dataset.data.column(2 * frame - 1);
+                let trace = (0..dataset.data.nrows())
+                    .map(|i| {
+                        let removed = pixels.get(&i).map(|orig| orig - ys[i]).unwrap_or(0.0);
+                        [xs[i], removed]
+                    })
+                    .collect();
+                (frame, trace)
+            })
+            .collect();
+        Self {
+            is_active: true,
+            show_removed: false,
+            points,
+            removed,
+        }
+    }
+}
+
+impl PlotExtensionGUI for ReplacedPixelsExtensionGUI {
+    fn get_extension_result(&self) -> PlotExtensionResult {
+        PlotExtensionResult::ReplacedPixels
+    }
+
+    fn get_is_active_reference(&mut self) -> &mut bool {
+        &mut self.is_active
+    }
+
+    fn extension_toggle_label(&self) -> String {
+        format!("Show Replaced Pixels ({})", self.points.len())
+    }
+
+    fn modify_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let label = self.extension_toggle_label();
+            ui.toggle_value(&mut self.is_active, label);
+            ui.toggle_value(&mut self.show_removed, "Show Removed Component")
+                .on_hover_text("Original minus despiked intensity of frames with replaced pixels.");
+        });
+    }
+
+    fn modify_plot(&mut self, plot_ui: &mut PlotUi) {
+        if self.show_removed {
+            for (frame, trace) in self.removed.iter() {
+                plot_ui.line(
+                    Line::new(PlotPoints::from(trace.clone()))
+                        .color(Color32::RED)
+                        .style(LineStyle::dashed_dense())
+                        .name(format!("removed (frame {frame})")),
+                );
+            }
+        }
+        if self.is_active {
+            plot_ui.points(
+                Points::new(self.points.clone())
+                    .shape(MarkerShape::Circle)
+                    .filled(false)
+                    .radius(5.)
+                    .color(Color32::RED)
+                    .name("replaced"),
+            );
+        }
+    }
+}
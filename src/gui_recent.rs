@@ -0,0 +1,91 @@
+use crate::common::config_dir;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Number of recently opened files that are remembered.
+const MAX_RECENT_FILES: usize = 10;
+
+/// Input files opened recently and bookmarked directories, kept in the
+/// config directory so they survive restarts of the GUI.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RecentFiles {
+    /// most recent first
+    #[serde(default)]
+    pub files: Vec<PathBuf>,
+    #[serde(default)]
+    pub bookmarks: Vec<PathBuf>,
+}
+
+impl RecentFiles {
+    fn storage_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("recent.yaml"))
+    }
+
+    /// The stored list, empty if there is none or it can not be read.
+    pub fn load() -> Self {
+        Self::storage_path()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|yaml| serde_yaml::from_str(&yaml).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::storage_path()
+            .ok_or_else(|| anyhow!("no config directory to store recent files in"))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Put `path` first, dropping the oldest files beyond the maximum.
+    pub fn add_file(&mut self, path: &Path) {
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        self.files.retain(|file| *file != path);
+        self.files.insert(0, path);
+        self.files.truncate(MAX_RECENT_FILES);
+    }
+
+    pub fn is_bookmarked(&self, dir: &Path) -> bool {
+        self.bookmarks.iter().any(|bookmark| bookmark == dir)
+    }
+
+    /// Bookmark `dir`, or remove its bookmark.
+    pub fn toggle_bookmark(&mut self, dir: &Path) {
+        if self.is_bookmarked(dir) {
+            self.bookmarks.retain(|bookmark| bookmark != dir);
+        } else {
+            self.bookmarks.push(dir.to_owned());
+            self.bookmarks.sort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RecentFiles, MAX_RECENT_FILES};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn test_recent_files() {
+        let mut recent = RecentFiles::default();
+        for i in 0..=MAX_RECENT_FILES {
+            recent.add_file(&PathBuf::from(format!("/no/such/dir/{i}.csv")));
+        }
+        recent.add_file(Path::new("/no/such/dir/3.csv"));
+        assert_eq!(recent.files.len(), MAX_RECENT_FILES);
+        assert_eq!(recent.files[0], Path::new("/no/such/dir/3.csv"));
+        assert_eq!(
+            recent.files[1],
+            PathBuf::from(format!("/no/such/dir/{MAX_RECENT_FILES}.csv"))
+        );
+        // the oldest file was dropped
+        assert!(!recent.files.contains(&PathBuf::from("/no/such/dir/0.csv")));
+        recent.toggle_bookmark(Path::new("/no/such/dir"));
+        assert!(recent.is_bookmarked(Path::new("/no/such/dir")));
+        recent.toggle_bookmark(Path::new("/no/such/dir"));
+        assert!(recent.bookmarks.is_empty());
+    }
+}
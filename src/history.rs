@@ -0,0 +1,173 @@
+use crate::metadata::Header;
+use anyhow::Result;
+use serde_yaml::{Mapping, Value};
+
+/// One pipeline run in the processing history of a file.
+#[derive(Debug, Clone, Default)]
+pub struct Run {
+    /// file the run read its data from, if it was given
+    pub input: Option<String>,
+    /// version of the program, unknown for headers without schema
+    pub version: Option<String>,
+    pub arguments: Option<Mapping>,
+    pub steps: Vec<Mapping>,
+}
+
+/// Processing history of a dataset, read from the comments of its input
+/// (`Dataset::previous_comments`): the file name and one run per processed
+/// file the data went through, oldest first.
+///
+/// The header of a processed file holds the comments of its own input, so
+/// the runs are found by following these nested headers down to the raw
+/// data.
+pub fn processing_history(comments: &str) -> (Option<String>, Vec<Run>) {
+    let (file, mut comments) = split_source(comments);
+    let mut runs = vec![];
+    while let Some(header) = comments.as_deref().and_then(run_header) {
+        let (input, input_comments) = match &header.provenance {
+            Some(provenance) => split_source(&provenance.comments),
+            None => (None, None),
+        };
+        runs.push(Run {
+            input,
+            version: header.info.map(|info| info.version),
+            arguments: header.preprocessor,
+            steps: header.steps,
+        });
+        comments = input_comments;
+    }
+    runs.reverse();
+    (file, runs)
+}
+
+/// The header of a pipeline run in the comments, if they hold one.
+fn run_header(comments: &str) -> Option<Header> {
+    Header::parse(comments)
+        .ok()
+        .filter(|header| header.preprocessor.is_some() || !header.steps.is_empty())
+}
+
+/// Split comments read from an input into the name of the file given in
+/// their first line ("comments from input file <name>:") and the comments
+/// of the file.
+fn split_source(comments: &str) -> (Option<String>, Option<String>) {
+    let (first, rest) = comments.split_once('\n').unwrap_or((comments, ""));
+    let file = first
+        .strip_prefix("comments from input file ")
+        .and_then(|name| name.strip_suffix(':'))
+        .map(str::to_owned);
+    if file.is_none() && first != "comments from input:" {
+        // comments of a raw input, without the line naming the file
+        return (None, Some(comments.to_owned()));
+    }
+    (file, (!rest.is_empty()).then(|| rest.to_owned()))
+}
+
+/// Human readable processing history, see `processing_history`.
+pub fn format_history(comments: &str) -> Result<String> {
+    let (file, runs) = processing_history(comments);
+    let mut text = format!(
+        "Processing history of {}\n",
+        file.as_deref().unwrap_or("the input")
+    );
+    if runs.is_empty() {
+        text += "\nno pipeline runs recorded, the file holds raw data\n";
+    }
+    for (i, run) in runs.iter().enumerate() {
+        text += &format!(
+            "\n{}. run with version {}\n",
+            i + 1,
+            run.version
+                .as_deref()
+                .unwrap_or("unknown (header without schema)")
+        );
+        text += &format!(
+            "   input: {}\n",
+            run.input.as_deref().unwrap_or("(not recorded)")
+        );
+        if let Some(arguments) = &run.arguments {
+            let arguments: Mapping = arguments
+                .iter()
+                .filter(|(key, value)| key.as_str() != Some("preprocessor") && !value.is_null())
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            text += "   arguments:\n";
+            text += &indented(&Value::Mapping(arguments), 6)?;
+        }
+        if run.steps.is_empty() {
+            text += "   no steps\n";
+        }
+        for (n, step) in run.steps.iter().enumerate() {
+            let name = step
+                .get("transformation")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown transformation");
+            text += &format!("   step {}: {}\n", n + 1, name);
+            let parameters: Mapping = step
+                .iter()
+                .filter(|(key, value)| key.as_str() != Some("transformation") && !value.is_null())
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            if !parameters.is_empty() {
+                text += &indented(&Value::Mapping(parameters), 6)?;
+            }
+        }
+    }
+    Ok(text)
+}
+
+/// YAML of the value with every line indented by `width` spaces.
+fn indented(value: &Value, width: usize) -> Result<String> {
+    Ok(serde_yaml::to_string(value)?
+        .lines()
+        .map(|line| format!("{}{}\n", " ".repeat(width), line))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_history, processing_history};
+    use crate::common::{Dataset, Pipeline};
+    use crate::transformations::PipelineContext;
+
+    #[test]
+    fn test_processing_history() {
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.previous_comments =
+            "comments from input file raw.csv:\n# exposure time = 1.5\n".to_owned();
+        dataset.metadata = "preprocessor: arguments\ncomment: '#'\n---\n".to_owned();
+        let mut pipeline = Pipeline::from_cli_args(vec![args("offset 1")]);
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        // the processed file is processed again
+        let mut processed = Dataset::new_test_dummy_shared_x();
+        processed.previous_comments = format!(
+            "comments from input file processed.csv:\n{}",
+            dataset.comment_header()
+        );
+        processed.metadata = "preprocessor: arguments\ncomment: '#'\n---\n".to_owned();
+        let mut pipeline = Pipeline::from_cli_args(vec![args("average"), args("offset 2")]);
+        pipeline
+            .apply(&mut processed, &PipelineContext::default())
+            .unwrap();
+        let comments = format!(
+            "comments from input file result.csv:\n{}",
+            processed.comment_header()
+        );
+        let (file, runs) = processing_history(&comments);
+        assert_eq!(file.as_deref(), Some("result.csv"));
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].input.as_deref(), Some("raw.csv"));
+        assert_eq!(runs[0].steps.len(), 1);
+        assert_eq!(runs[1].input.as_deref(), Some("processed.csv"));
+        assert_eq!(runs[1].steps.len(), 2);
+        let text = format_history(&comments).unwrap();
+        assert!(text.contains("1. run with version"));
+        assert!(text.contains("   step 2: OffsetTransform\n      offset: 2.0\n"));
+        // raw data has no history
+        let (_, runs) = processing_history("comments from input file raw.csv:\n# a\n");
+        assert!(runs.is_empty());
+    }
+}
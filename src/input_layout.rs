@@ -0,0 +1,182 @@
+use crate::common::{pairs_from_shared_x, Dataset, FrameMetadata};
+use crate::transformations::{reshape::ReshapeTransform, Transformer};
+use anyhow::Result;
+use ndarray::{s, Array2, ArrayView1};
+
+/// Another way to read a table of numbers whose columns do not look like the
+/// x- and y-column of each frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayoutFix {
+    /// the first column is the x-axis of all frames, the others are y-columns
+    SharedX,
+    /// the frames are stored in rows instead of columns
+    Transpose,
+    /// several frames of this many rows are stored one after another
+    Reshape(usize),
+}
+
+impl std::fmt::Display for LayoutFix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutFix::SharedX => write!(f, "treat the first column as x-axis of all frames"),
+            LayoutFix::Transpose => write!(f, "transpose (frames stored in rows)"),
+            LayoutFix::Reshape(rows) => write!(f, "reshape into frames of {} rows", rows),
+        }
+    }
+}
+
+impl LayoutFix {
+    /// Rearrange the data of the dataset, frame metadata is only kept if it
+    /// is the same for all frames.
+    pub fn apply(self, dataset: &mut Dataset) -> Result<()> {
+        let data = match self {
+            LayoutFix::SharedX => {
+                pairs_from_shared_x(dataset.data.column(0), dataset.data.slice(s![.., 1..]))
+            }
+            LayoutFix::Transpose => dataset.data.t().to_owned(),
+            LayoutFix::Reshape(rows) => return ReshapeTransform { rows }.transform(dataset),
+        };
+        let shared_metadata = FrameMetadata {
+            exposure: dataset.shared_exposure(),
+            gain: dataset.shared_gain(),
+            ..Default::default()
+        };
+        dataset.frame_metadata = vec![shared_metadata; data.ncols() / 2];
+        dataset.data = data;
+        Ok(())
+    }
+}
+
+/// What looks wrong with the layout of parsed input data and the fixes that
+/// may apply, both empty if the columns look like x/y pairs.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LayoutCheck {
+    pub warnings: Vec<String>,
+    pub fixes: Vec<LayoutFix>,
+}
+
+/// Check if the columns of the data look like the x- and y-column of each
+/// frame: an even number of columns and sorted x-axes.
+pub fn check_layout(data: &Array2<f64>) -> LayoutCheck {
+    let mut check = LayoutCheck {
+        warnings: layout_warnings(data),
+        fixes: vec![],
+    };
+    if check.warnings.is_empty() {
+        return check;
+    }
+    if data.ncols() > 1 && is_sorted(data.column(0)) {
+        check.fixes.push(LayoutFix::SharedX);
+    }
+    // frames of two points are no spectra
+    if data.ncols() > 2 && layout_warnings(&data.t().to_owned()).is_empty() {
+        check.fixes.push(LayoutFix::Transpose);
+    }
+    if let Some(rows) = stacked_frame_rows(data) {
+        check.fixes.push(LayoutFix::Reshape(rows));
+    }
+    check
+}
+
+fn layout_warnings(data: &Array2<f64>) -> Vec<String> {
+    let mut warnings = vec![];
+    let (nrows, ncols) = data.dim();
+    // too little data to tell
+    if nrows < 2 || ncols == 0 {
+        return warnings;
+    }
+    if ncols % 2 == 1 {
+        warnings.push(format!(
+            "the input has an odd number of columns ({}), expected the x- and y-column of each frame",
+            ncols
+        ));
+    }
+    let unsorted: Vec<String> = (0..ncols - ncols % 2)
+        .step_by(2)
+        .filter(|j| !is_sorted(data.column(*j)))
+        .map(|j| (j / 2 + 1).to_string())
+        .collect();
+    if !unsorted.is_empty() {
+        warnings.push(format!(
+            "the x-axis of frame(s) {} is not sorted",
+            unsorted.join(", ")
+        ));
+    }
+    warnings
+}
+
+/// Whether the values ascend or descend, ignoring missing values.
+fn is_sorted(values: ArrayView1<f64>) -> bool {
+    let finite: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    let steps = || finite.windows(2).map(|w| w[1] - w[0]);
+    steps().all(|step| step >= 0.0) || steps().all(|step| step <= 0.0)
+}
+
+/// Number of rows of frames stored one after another in the same columns,
+/// if every x-column starts over after this many rows.
+fn stacked_frame_rows(data: &Array2<f64>) -> Option<usize> {
+    let (nrows, ncols) = data.dim();
+    if ncols % 2 == 1 || nrows < 4 {
+        return None;
+    }
+    let x = data.column(0);
+    let ascending = x[1] >= x[0];
+    let rows = (1..nrows).find(|i| (x[*i] >= x[i - 1]) != ascending)?;
+    let stacked = nrows % rows == 0
+        && (0..ncols).step_by(2).all(|j| {
+            (0..nrows)
+                .step_by(rows)
+                .all(|i| is_sorted(data.slice(s![i..i + rows, j])))
+        });
+    (rows > 1 && stacked).then_some(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_layout, LayoutFix};
+    use crate::common::Dataset;
+    use ndarray::array;
+
+    #[test]
+    fn test_check_layout() {
+        // x/y pairs
+        let pairs = array![[1.0, 5.0, 1.0, 3.0], [2.0, 4.0, 2.0, 6.0]];
+        assert!(check_layout(&pairs).warnings.is_empty());
+        // one x-column and three y-columns
+        let shared_x = array![
+            [1.0, 5.0, 3.0, 1.0],
+            [2.0, 4.0, 6.0, 0.0],
+            [3.0, 1.0, 2.0, 2.0]
+        ];
+        let check = check_layout(&shared_x);
+        assert_eq!(check.warnings.len(), 1);
+        assert_eq!(check.fixes, vec![LayoutFix::SharedX]);
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = shared_x;
+        LayoutFix::SharedX.apply(&mut dataset).unwrap();
+        assert_eq!(dataset.data.ncols(), 6);
+        assert!(check_layout(&dataset.data).warnings.is_empty());
+        // frames in rows
+        let rows = array![
+            [1.0, 2.0, 3.0],
+            [5.0, 1.0, 4.0],
+            [1.0, 2.0, 3.0],
+            [0.0, 2.0, 1.0]
+        ];
+        assert!(check_layout(&rows).fixes.contains(&LayoutFix::Transpose));
+        // two frames of three rows in one pair of columns
+        let stacked = array![
+            [1.0, 5.0],
+            [2.0, 1.0],
+            [3.0, 4.0],
+            [1.0, 2.0],
+            [2.0, 6.0],
+            [3.0, 3.0]
+        ];
+        let check = check_layout(&stacked);
+        assert_eq!(check.fixes, vec![LayoutFix::Reshape(3)]);
+        dataset.data = stacked;
+        LayoutFix::Reshape(3).apply(&mut dataset).unwrap();
+        assert_eq!(dataset.data.dim(), (3, 4));
+    }
+}
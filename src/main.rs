@@ -1,11 +1,29 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
+mod bundle;
 mod cli;
 mod common;
+mod config;
+mod diagram;
 mod gui;
+mod gui_dialogs;
+mod gui_file_watcher;
 mod gui_plot_extensions;
+mod gui_recent;
+mod history;
+mod input_layout;
+mod metadata;
+mod output;
+mod pipeline_diff;
 mod plot;
+mod registry;
+mod replay;
+mod rmbin;
+mod schema;
 mod spe_rs;
+mod streaming;
 mod transformations;
+mod tui;
+mod update;
 mod utils;
 
 mod test;
@@ -14,25 +32,109 @@ use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::time::Duration;
 
-use crate::cli::Preprocessor;
+use crate::cli::{Commands, Preprocessor};
 use ansi_term::Colour::Yellow;
 use anyhow::{anyhow, Result};
 use common::{input_data_to_string, Dataset, Pipeline};
+use diagram::pipeline_diagram;
 use gui::gui_loop;
+use history::format_history;
+use input_layout::check_layout;
+use metadata::data_sha256;
+use output::{warnings_json_lines, OutputFormat};
 use plot::PlotWindow;
+use replay::replay;
+use schema::pipeline_schema;
 use sha256::digest;
+use tui::tui_loop;
+use update::check_update;
 
 fn main() -> Result<()> {
     //gui_loop()?;
     //return Ok(());
     let mut preprocessor = Preprocessor::from_cli_args();
+    let command = preprocessor.args.command.take();
     if preprocessor.gui_mode {
         gui_loop(preprocessor)?;
+    } else if let Some(Commands::Tui) = command {
+        tui_loop(preprocessor)?;
+    } else if let Some(Commands::DiffPipeline(command)) = &command {
+        print!("{}", command.run()?);
+    } else if let Some(Commands::Config(command)) = &command {
+        print!("{}", command.run()?);
+    } else if preprocessor.args.check_update {
+        println!("{}", check_update()?);
+    } else if let Some(Commands::Schema) = command {
+        print!("{}", pipeline_schema()?);
+    } else if let Some(Commands::History) = command {
+        let dataset = preprocessor.get_input_data()?;
+        print!("{}", format_history(&dataset.previous_comments)?);
+    } else if let Some(format) = preprocessor.args.diagram {
+        print!(
+            "{}",
+            pipeline_diagram(&preprocessor.get_pipeline(), format)?
+        );
     } else {
-        let mut pipeline = preprocessor.get_pipeline();
-        let mut dataset = preprocessor.get_input_data()?;
-        pipeline.apply(&mut dataset)?;
-        preprocessor.print_dataset(&dataset)?;
+        let mut replayed_input_sha256 = None;
+        let mut replay_warnings = vec![];
+        let mut pipeline = if let Some(Commands::Replay(command)) = &command {
+            let replay = replay(&preprocessor, command.input.as_deref())?;
+            preprocessor = replay.preprocessor;
+            replayed_input_sha256 = replay.input_sha256;
+            replay_warnings = replay.warnings;
+            replay.pipeline
+        } else {
+            preprocessor.get_pipeline()
+        };
+        let context = preprocessor.get_context()?;
+        for warning in replay_warnings {
+            context.diagnostics.warn(warning);
+        }
+        let (dataset, result) = if preprocessor.args.stream && !context.dry_run {
+            match preprocessor.stream_input_data(&mut pipeline, &context) {
+                Ok(dataset) => (dataset, Ok(())),
+                Err(err) => (Dataset::default(), Err(err)),
+            }
+        } else {
+            let mut dataset = preprocessor.get_input_data()?;
+            if replayed_input_sha256.is_some_and(|hash| hash != data_sha256(dataset.data.view())) {
+                context
+                    .diagnostics
+                    .warn("the input data differs from the one of the replayed run");
+            }
+            let layout = check_layout(&dataset.data);
+            for warning in layout.warnings {
+                context.diagnostics.warn(warning);
+            }
+            if !layout.fixes.is_empty() {
+                let fixes: Vec<String> = layout.fixes.iter().map(|fix| fix.to_string()).collect();
+                context
+                    .diagnostics
+                    .warn(format!("the input may be read with: {}", fixes.join("; ")));
+            }
+            let result = pipeline.apply(&mut dataset, &context);
+            (dataset, result)
+        };
+        let json_lines = preprocessor.args.output_format == OutputFormat::JsonLines;
+        // the JSON document holds the warnings, if it is written
+        let in_document = preprocessor.args.output_format == OutputFormat::Json
+            && result.is_ok()
+            && !context.dry_run;
+        let warnings = context.diagnostics.take();
+        if json_lines {
+            // warnings are records of the run like the results
+            print!("{}", warnings_json_lines(&warnings)?);
+        } else if !preprocessor.args.quiet && !in_document {
+            for warning in warnings.iter() {
+                eprintln!("{}", warning);
+            }
+        }
+        result?;
+        if !context.dry_run {
+            preprocessor.print_dataset(&dataset, &pipeline, &warnings)?;
+        } else if !json_lines && !preprocessor.args.quiet {
+            eprintln!("No problems found in pipeline.");
+        }
     }
     // if preprocessor.args.watch {
     //    run_file_watch()?;
@@ -46,8 +148,8 @@ fn main() -> Result<()> {
 fn run_once(mut preprocessor: Preprocessor) -> Result<(), anyhow::Error> {
     let mut dataset = preprocessor.get_input_data()?;
     let mut pipeline = preprocessor.get_pipeline();
-    pipeline.apply(&mut dataset)?;
-    preprocessor.print_dataset(&dataset)?;
+    pipeline.apply(&mut dataset, &preprocessor.get_context()?)?;
+    preprocessor.print_dataset(&dataset, &pipeline, &[])?;
     Ok(())
 }
 
@@ -113,7 +215,10 @@ fn run_file_watch() -> Result<(), anyhow::Error> {
                 }
                 Ok(pipeline) => pipeline,
             };
-            if let Err(e) = pipeline.apply(&mut dataset) {
+            let result = inner_preprocessor
+                .get_context()
+                .and_then(|context| pipeline.apply(&mut dataset, &context));
+            if let Err(e) = result {
                 let msg = format!("Unable to apply pipeline:\n\n{:?}", e);
                 info(&info_arcmutex, msg);
                 continue;
@@ -126,10 +231,7 @@ fn run_file_watch() -> Result<(), anyhow::Error> {
                     ))
                 }
                 Some(filepath) => {
-                    let filepath = filepath.clone();
-                    let handle = std::fs::File::create(filepath)?;
-                    let wrt = std::io::BufWriter::new(handle);
-                    dataset.write(wrt)?;
+                    dataset.write_file(filepath, preprocessor.args.float_format)?;
                 }
             };
 
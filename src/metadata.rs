@@ -0,0 +1,357 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use ndarray::ArrayView2;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+/// Version of the layout of the comment header, increased whenever a reader
+/// of the previous version would misread headers of the new one.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// First document of the comment header, e.g.
+///
+/// ```yaml
+/// rustman: metadata
+/// schema: 1
+/// version: 0.1.0 (git commit 1a2b3c4)
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeaderInfo {
+    pub rustman: String,
+    pub schema: u32,
+    pub version: String,
+    /// "shared-x" if the first column is the x-axis of all frames
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub layout: Option<String>,
+    /// names of the channels, whose columns follow the y-column of each frame
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+}
+
+/// Version of the program, with the git commit it was built from if known.
+pub fn program_version() -> String {
+    let mut version = env!("CARGO_PKG_VERSION").to_string();
+    if let Some(sha) = option_env!("PROJECT_VERSION") {
+        version += format!(" (git commit {})", sha).as_str()
+    };
+    version
+}
+
+impl HeaderInfo {
+    pub fn new(layout: Option<&str>) -> Self {
+        Self {
+            rustman: "metadata".to_owned(),
+            schema: SCHEMA_VERSION,
+            version: program_version(),
+            layout: layout.map(str::to_owned),
+            channels: vec![],
+        }
+    }
+}
+
+/// Last document of the comment header, holding the comments of the input
+/// the dataset was read from (including the header of a processed input).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub provenance: String,
+    pub comments: String,
+}
+
+impl Provenance {
+    pub fn new(comments: &str) -> Self {
+        Self {
+            provenance: "input".to_owned(),
+            comments: comments.to_owned(),
+        }
+    }
+}
+
+/// Document of the comment header logging every step applied to the dataset,
+/// so a processed file can be audited later, e.g.
+///
+/// ```yaml
+/// log: provenance
+/// steps:
+/// - time: 2024-05-02T09:30:00Z
+///   version: 0.1.0 (git commit 1a2b3c4)
+///   input_sha256: 3b1f...
+///   step: 1
+/// ```
+///
+/// Readers of schema 1 skip it like any other unknown document.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProvenanceLog {
+    pub log: String,
+    pub steps: Vec<StepRecord>,
+}
+
+impl ProvenanceLog {
+    pub fn new(steps: &[StepRecord]) -> Self {
+        Self {
+            log: "provenance".to_owned(),
+            steps: steps.to_vec(),
+        }
+    }
+}
+
+/// One applied step of the `ProvenanceLog`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepRecord {
+    /// when the step started, in UTC
+    pub time: String,
+    /// version of the program that applied the step
+    pub version: String,
+    /// hash of the data entering the step, see `data_sha256`
+    pub input_sha256: String,
+    /// number of the document of the step among the step documents of the
+    /// header, counting from 1, which holds its configuration
+    pub step: usize,
+}
+
+impl StepRecord {
+    /// Record of the `step`-th step, started at `time` on data with the hash
+    /// `input_sha256`.
+    pub fn new(time: DateTime<Utc>, input_sha256: String, step: usize) -> Self {
+        Self {
+            time: time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            version: program_version(),
+            input_sha256,
+            step,
+        }
+    }
+}
+
+/// SHA256 of the values of `data` as little-endian 64 bit floats, row by
+/// row, so it can be recomputed from the numbers of a file.
+pub fn data_sha256(data: ArrayView2<f64>) -> String {
+    let bytes: Vec<u8> = data.iter().flat_map(|value| value.to_le_bytes()).collect();
+    sha256::digest(bytes)
+}
+
+/// Comment header of a processed file, read back as the documents of a YAML
+/// stream: header info, preprocessor arguments, one document per step, the
+/// provenance log of the steps and the provenance of the input, in this
+/// order.
+#[derive(Debug, Clone, Default)]
+pub struct Header {
+    /// missing in files written before the header was versioned
+    pub info: Option<HeaderInfo>,
+    pub preprocessor: Option<Mapping>,
+    pub steps: Vec<Mapping>,
+    pub log: Option<ProvenanceLog>,
+    pub provenance: Option<Provenance>,
+}
+
+impl Header {
+    /// Parse the header of a processed file, given either as the whole file
+    /// (only lines starting with "#" are read) or as the bare YAML stream of
+    /// `Dataset::metadata`.
+    ///
+    /// Files written before the header was versioned are read document by
+    /// document, skipping the documents that are no valid YAML (e.g. the
+    /// comments of their input).
+    pub fn parse(text: &str) -> Result<Self> {
+        let yaml = header_yaml(text);
+        let documents = match parse_documents(&yaml) {
+            Ok(documents) => documents,
+            Err(err) if declares_schema(&yaml) => return Err(err),
+            Err(_) => parse_legacy_documents(&yaml)?,
+        };
+        let mut header = Header::default();
+        for document in documents {
+            if document.contains_key("transformation") {
+                header.steps.push(document);
+            } else if document.contains_key("preprocessor") {
+                header.preprocessor = Some(document);
+            } else if document.contains_key("rustman") {
+                let info: HeaderInfo = serde_yaml::from_value(Value::Mapping(document))
+                    .context("Unable to parse header info")?;
+                if info.schema > SCHEMA_VERSION {
+                    return Err(anyhow!(
+                        "header was written with schema version {} by version {}, \
+                        this version reads up to schema version {}",
+                        info.schema,
+                        info.version,
+                        SCHEMA_VERSION
+                    ));
+                }
+                header.info = Some(info);
+            } else if document.contains_key("log") {
+                header.log = Some(
+                    serde_yaml::from_value(Value::Mapping(document))
+                        .context("Unable to parse provenance log")?,
+                );
+            } else if document.contains_key("provenance") {
+                header.provenance = Some(
+                    serde_yaml::from_value(Value::Mapping(document))
+                        .context("Unable to parse provenance of input")?,
+                );
+            }
+            // other documents (e.g. the region of an export) are kept in the
+            // file for the reader, but not needed to rerun the pipeline
+        }
+        Ok(header)
+    }
+}
+
+/// YAML stream of the header: the comment lines with the leading "# "
+/// removed, or the whole text if it has no comment lines.
+fn header_yaml(text: &str) -> String {
+    if !text.lines().any(|line| line.starts_with('#')) {
+        return text.to_owned();
+    }
+    text.lines()
+        .filter_map(|line| line.strip_prefix('#'))
+        .map(|line| format!("{}\n", line.strip_prefix(' ').unwrap_or(line)))
+        .collect()
+}
+
+fn declares_schema(yaml: &str) -> bool {
+    yaml.lines().any(|line| line.starts_with("rustman: "))
+}
+
+/// The non-empty documents of a YAML stream, each of which must be a mapping.
+fn parse_documents(yaml: &str) -> Result<Vec<Mapping>> {
+    let mut documents = vec![];
+    for (i, document) in serde_yaml::Deserializer::from_str(yaml).enumerate() {
+        let value = Value::deserialize(document)
+            .with_context(|| format!("Unable to parse document {} of header", i + 1))?;
+        match value {
+            Value::Null => {}
+            Value::Mapping(mapping) => documents.push(mapping),
+            other => {
+                return Err(anyhow!(
+                    "document {} of header is no mapping: {:?}",
+                    i + 1,
+                    other
+                ))
+            }
+        }
+    }
+    Ok(documents)
+}
+
+/// Documents of a header written before the header was versioned, as
+/// sections separated by "---" lines. Sections that are no valid YAML are
+/// skipped, unless they hold a step or the preprocessor arguments.
+fn parse_legacy_documents(yaml: &str) -> Result<Vec<Mapping>> {
+    let mut documents = vec![];
+    let mut section = String::new();
+    for line in yaml.lines().chain(std::iter::once("---")) {
+        if line.trim_end() != "---" {
+            section += line;
+            section += "\n";
+            continue;
+        }
+        match serde_yaml::from_str::<Value>(&section) {
+            Ok(Value::Mapping(mapping)) => documents.push(mapping),
+            Err(err)
+                if section.contains("transformation: ")
+                    || section.contains("preprocessor: arguments") =>
+            {
+                return Err(err).with_context(|| format!("Offending YAML input:\n{}", section))
+            }
+            _ => {}
+        }
+        section.clear();
+    }
+    Ok(documents)
+}
+
+/// YAML of a document of the header, to be appended to the metadata.
+pub fn document_yaml(document: &impl Serialize) -> Result<String> {
+    Ok(serde_yaml::to_string(document)? + "---\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{data_sha256, document_yaml, Header, HeaderInfo, Provenance, SCHEMA_VERSION};
+    use crate::common::Dataset;
+    use crate::transformations::{scale::ScaleTransform, PipelineContext, Transformer};
+
+    #[test]
+    fn test_parse_header() {
+        let mut yaml = document_yaml(&HeaderInfo::new(Some("shared-x"))).unwrap();
+        yaml += "preprocessor: arguments\ncomment: '#'\n---\n";
+        yaml += "transformation: OffsetTransform\noffset: 1.0\n---\n";
+        yaml += "export: visible region\nleft: 1\nright: 2\n---\n";
+        yaml += &serde_yaml::to_string(&Provenance::new(
+            "comments from input:\n# transformation: AverageTransform\n# ---\n# not: [yaml\n",
+        ))
+        .unwrap();
+        let text: String = yaml.lines().map(|line| format!("# {}\n", line)).collect();
+        let header = Header::parse(&(text + "1,2\n3,4\n")).unwrap();
+        assert_eq!(header.info.unwrap().layout.as_deref(), Some("shared-x"));
+        assert!(header.preprocessor.is_some());
+        // the steps of the input are part of its comments, not of the header
+        assert_eq!(header.steps.len(), 1);
+        assert!(header
+            .provenance
+            .unwrap()
+            .comments
+            .contains("# not: [yaml\n"));
+        // a newer schema is refused rather than misread
+        let newer = yaml_with_schema(SCHEMA_VERSION + 1);
+        assert!(Header::parse(&newer).is_err());
+    }
+
+    #[test]
+    fn test_provenance_log() {
+        let mut dataset = Dataset::new_test_dummy();
+        let input_sha256 = data_sha256(dataset.data.view());
+        let context = PipelineContext::default();
+        ScaleTransform::new(2.0)
+            .apply(&mut dataset, &context)
+            .unwrap();
+        ScaleTransform::new(0.5)
+            .apply(&mut dataset, &context)
+            .unwrap();
+        let header = Header::parse(&dataset.comment_header()).unwrap();
+        let log = header.log.clone().unwrap();
+        assert_eq!(log.steps, dataset.provenance_log);
+        assert_eq!(log.steps.len(), 2);
+        assert_eq!(log.steps[0].input_sha256, input_sha256);
+        assert_ne!(log.steps[1].input_sha256, input_sha256);
+        assert_eq!(header.steps[log.steps[1].step - 1]["factor"], 0.5);
+        // a run that is not written is not logged
+        let unlogged = PipelineContext {
+            skip_provenance: true,
+            ..Default::default()
+        };
+        ScaleTransform::new(1.0)
+            .apply(&mut dataset, &unlogged)
+            .unwrap();
+        assert_eq!(dataset.provenance_log.len(), 2);
+        // scaling back restores the input
+        assert_eq!(data_sha256(dataset.data.view()), input_sha256);
+    }
+
+    #[test]
+    fn test_parse_legacy_header() {
+        let text = "# Raman CLI Tools version 0.1.0.
+# ---
+# preprocessor: arguments
+# comment: '#'
+# ---
+# transformation: OffsetTransform
+# offset: 1.0
+# ---
+# comments from input file /data/input.csv:
+# exposure time = 1.5
+1,2
+";
+        let header = Header::parse(text).unwrap();
+        assert!(header.info.is_none());
+        assert!(header.preprocessor.is_some());
+        assert_eq!(header.steps.len(), 1);
+        // broken steps are reported, not skipped
+        let broken = text.replace("offset: 1.0", "offset: [1.0");
+        assert!(Header::parse(&broken).is_err());
+    }
+
+    fn yaml_with_schema(schema: u32) -> String {
+        let mut info = HeaderInfo::new(None);
+        info.schema = schema;
+        document_yaml(&info).unwrap()
+    }
+}
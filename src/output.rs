@@ -0,0 +1,446 @@
+use crate::common::{Dataset, Pipeline};
+use crate::gui::ResultColumns;
+use crate::metadata::{program_version, Header};
+use anyhow::{anyhow, Result};
+use ndarray::ArrayView1;
+use serde_yaml::{Mapping, Value};
+
+/// Format of the data written to stdout.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    /// the dataset as CSV, with the pipeline in the comment header
+    #[default]
+    Csv,
+    /// one JSON object per line, for scripts that parse the results
+    JsonLines,
+    /// a single JSON document with the metadata, warnings and results of the
+    /// run, for scripts that read the whole output at once
+    Json,
+    /// binary dataset for the next rustman invocation in a shell pipe
+    Rmbin,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(OutputFormat::Csv),
+            "json-lines" => Ok(OutputFormat::JsonLines),
+            "json" => Ok(OutputFormat::Json),
+            "rmbin" => Ok(OutputFormat::Rmbin),
+            _ => Err(anyhow!(
+                "unknown output format \"{}\", use csv, json-lines, json or rmbin",
+                s
+            )),
+        }
+    }
+}
+
+/// Arrangement of the columns of written CSV files.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Layout {
+    /// x- and y-column of each frame
+    #[default]
+    Pairs,
+    /// a single x-column followed by the y-column of each frame, if all
+    /// frames share their x-axis
+    SharedX,
+}
+
+impl std::str::FromStr for Layout {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pairs" => Ok(Layout::Pairs),
+            "shared-x" => Ok(Layout::SharedX),
+            _ => Err(anyhow!("unknown layout \"{}\", use pairs or shared-x", s)),
+        }
+    }
+}
+
+/// Number format of the values in written CSV files, given as in printf:
+/// "%.6e" (scientific, the default) or "%.3f" (fixed). With a fixed number
+/// of digits, changed values show up as changed lines only, which keeps diffs
+/// of outputs in version control readable. "shortest" writes the shortest
+/// text that reads back as the same value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FloatFormat {
+    Scientific(usize),
+    Fixed(usize),
+    Shortest,
+}
+
+impl Default for FloatFormat {
+    fn default() -> Self {
+        FloatFormat::Scientific(6)
+    }
+}
+
+impl std::str::FromStr for FloatFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "shortest" {
+            return Ok(FloatFormat::Shortest);
+        }
+        let invalid = || {
+            anyhow!(
+                "unknown float format \"{}\", use e.g. %.6e, %.3f or shortest",
+                s
+            )
+        };
+        let spec = s.strip_prefix('%').ok_or_else(invalid)?;
+        let (precision, kind) = spec.split_at(spec.len().saturating_sub(1));
+        // printf uses 6 digits if no precision is given
+        let precision = match precision.strip_prefix('.') {
+            None if precision.is_empty() => 6,
+            None => return Err(invalid()),
+            Some(digits) => digits.parse().map_err(|_| invalid())?,
+        };
+        match kind {
+            "e" => Ok(FloatFormat::Scientific(precision)),
+            "f" => Ok(FloatFormat::Fixed(precision)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for FloatFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FloatFormat::Scientific(precision) => write!(f, "%.{}e", precision),
+            FloatFormat::Fixed(precision) => write!(f, "%.{}f", precision),
+            FloatFormat::Shortest => write!(f, "shortest"),
+        }
+    }
+}
+
+/// Notation of the numbers in written CSV files, a shorthand for the kind
+/// of `FloatFormat`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Notation {
+    Fixed,
+    Scientific,
+}
+
+impl std::str::FromStr for Notation {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fixed" => Ok(Notation::Fixed),
+            "scientific" => Ok(Notation::Scientific),
+            _ => Err(anyhow!(
+                "unknown notation \"{}\", use fixed or scientific",
+                s
+            )),
+        }
+    }
+}
+
+impl FloatFormat {
+    /// The format with the number of digits and/or the notation replaced,
+    /// "shortest" is replaced by "%.6e" before.
+    pub fn with(self, precision: Option<usize>, notation: Option<Notation>) -> Self {
+        if precision.is_none() && notation.is_none() {
+            return self;
+        }
+        let (current_precision, current_notation) = match self {
+            FloatFormat::Scientific(precision) => (precision, Notation::Scientific),
+            FloatFormat::Fixed(precision) => (precision, Notation::Fixed),
+            FloatFormat::Shortest => (6, Notation::Scientific),
+        };
+        let precision = precision.unwrap_or(current_precision);
+        match notation.unwrap_or(current_notation) {
+            Notation::Fixed => FloatFormat::Fixed(precision),
+            Notation::Scientific => FloatFormat::Scientific(precision),
+        }
+    }
+    pub fn format(&self, value: f64) -> String {
+        if !value.is_finite() {
+            return value.to_string();
+        }
+        match self {
+            FloatFormat::Scientific(precision) => {
+                // exponent with sign and at least two digits as in printf,
+                // so the columns line up
+                let text = format!("{:.*e}", precision, value);
+                let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+                let exponent: i32 = exponent.parse().unwrap_or_default();
+                let sign = if exponent < 0 { '-' } else { '+' };
+                format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+            }
+            FloatFormat::Fixed(precision) => format!("{:.*}", precision, value),
+            FloatFormat::Shortest => value.to_string(),
+        }
+    }
+}
+
+/// Records of a run as JSON lines, each line an object whose "record" entry
+/// tells what it holds:
+///
+/// - "step": configuration and reports (e.g. fit residuals) of a step, as
+///   written into the metadata
+/// - "result": a row of the table of results (e.g. integrals), if the last
+///   step produces one, keyed by the column names
+/// - "frame": x- and y-values of a frame otherwise, followed by the values of
+///   its channels (e.g. "y_baseline")
+pub fn json_lines(dataset: &Dataset, pipeline: &Pipeline) -> Result<String> {
+    let mut records = vec![];
+    let steps = Header::parse(&dataset.metadata)?.steps;
+    for (i, config) in steps.into_iter().enumerate() {
+        let mut record = record("step");
+        record.insert("step".into(), (i + 1).into());
+        record.extend(config);
+        records.push(record);
+    }
+    match result_columns(pipeline) {
+        Some(columns) => {
+            for row in result_rows(dataset, &columns) {
+                let mut record = record("result");
+                record.extend(row);
+                records.push(record);
+            }
+        }
+        None => {
+            for frame in frames(dataset) {
+                let mut record = record("frame");
+                record.extend(frame);
+                records.push(record);
+            }
+        }
+    }
+    records.iter().map(json_line).collect()
+}
+
+/// Warnings of a run as JSON lines with "record": "warning".
+pub fn warnings_json_lines(warnings: &[String]) -> Result<String> {
+    warnings
+        .iter()
+        .map(|warning| {
+            let mut record = record("warning");
+            record.insert("message".into(), warning.as_str().into());
+            json_line(&record)
+        })
+        .collect()
+}
+
+fn json_line(record: &Mapping) -> Result<String> {
+    Ok(serde_json::to_string(record)? + "\n")
+}
+
+/// The run as a single, indented JSON object:
+///
+/// - "metadata": version of the program, arguments of the preprocessor,
+///   configuration and reports of each step and the log of the applied steps
+/// - "warnings": messages of the run
+/// - "columns" and "results": if the last step produces a table of results
+///   (e.g. integrals or peaks), the names of its columns (e.g. the windows)
+///   and its rows, keyed by the column names
+/// - "frames": x- and y-values and channels of each frame otherwise
+pub fn json_document(
+    dataset: &Dataset,
+    pipeline: &Pipeline,
+    warnings: &[String],
+) -> Result<String> {
+    let header = Header::parse(&dataset.metadata)?;
+    let mut metadata = Mapping::new();
+    metadata.insert("version".into(), program_version().into());
+    metadata.insert(
+        "arguments".into(),
+        header.preprocessor.map_or(Value::Null, Value::Mapping),
+    );
+    metadata.insert(
+        "steps".into(),
+        Value::Sequence(header.steps.into_iter().map(Value::Mapping).collect()),
+    );
+    metadata.insert("log".into(), serde_yaml::to_value(&dataset.provenance_log)?);
+    let mut document = Mapping::new();
+    document.insert("metadata".into(), Value::Mapping(metadata));
+    document.insert(
+        "warnings".into(),
+        Value::Sequence(warnings.iter().map(|w| w.as_str().into()).collect()),
+    );
+    match result_columns(pipeline) {
+        Some(columns) => {
+            let names = columns.names.iter().map(|name| name.as_str().into());
+            document.insert("columns".into(), Value::Sequence(names.collect()));
+            let rows = result_rows(dataset, &columns)
+                .into_iter()
+                .map(Value::Mapping);
+            document.insert("results".into(), Value::Sequence(rows.collect()));
+        }
+        None => {
+            let frames = frames(dataset).into_iter().map(Value::Mapping);
+            document.insert("frames".into(), Value::Sequence(frames.collect()));
+        }
+    }
+    Ok(serde_json::to_string_pretty(&document)? + "\n")
+}
+
+/// Columns of the table of results of the last step, if it produces one.
+fn result_columns(pipeline: &Pipeline) -> Option<ResultColumns> {
+    pipeline
+        .transformations
+        .last()
+        .and_then(|step| step.result_columns())
+}
+
+/// Rows of the table of results, keyed by the column names.
+fn result_rows(dataset: &Dataset, columns: &ResultColumns) -> Vec<Mapping> {
+    dataset
+        .data
+        .rows()
+        .into_iter()
+        .map(|row| {
+            let mut result = Mapping::new();
+            let x = row.first().copied().unwrap_or(f64::NAN);
+            result.insert(columns.x_label.as_str().into(), number(x));
+            for (name, value) in columns.names.iter().zip(row.iter().skip(1).step_by(2)) {
+                result.insert(name.as_str().into(), number(*value));
+            }
+            result
+        })
+        .collect()
+}
+
+/// Number, x- and y-values and channels (e.g. "y_baseline") of each frame.
+fn frames(dataset: &Dataset) -> Vec<Mapping> {
+    let values =
+        |column: ArrayView1<f64>| Value::Sequence(column.iter().map(|v| number(*v)).collect());
+    (0..dataset.data.ncols() / 2)
+        .map(|frame| {
+            let mut entry = Mapping::new();
+            entry.insert("frame".into(), (frame + 1).into());
+            entry.insert("x".into(), values(dataset.data.column(2 * frame)));
+            entry.insert("y".into(), values(dataset.data.column(2 * frame + 1)));
+            for (name, channel) in dataset.channels.iter() {
+                entry.insert(format!("y_{}", name).into(), values(channel.column(frame)));
+            }
+            entry
+        })
+        .collect()
+}
+
+fn record(kind: &str) -> Mapping {
+    let mut record = Mapping::new();
+    record.insert("record".into(), kind.into());
+    record
+}
+
+/// JSON knows no NaN, missing values (e.g. padding of shorter frames) are
+/// written as null.
+fn number(value: f64) -> Value {
+    if value.is_finite() {
+        value.into()
+    } else {
+        Value::Null
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_document, json_lines, warnings_json_lines, FloatFormat, Notation};
+    use crate::common::{Dataset, Pipeline};
+    use crate::transformations::PipelineContext;
+
+    #[test]
+    fn test_float_format() {
+        let format = |spec: &str, value: f64| spec.parse::<FloatFormat>().unwrap().format(value);
+        assert_eq!(format("%.6e", 123.456), "1.234560e+02");
+        assert_eq!(format("%.2e", -0.00123), "-1.23e-03");
+        assert_eq!(format("%e", 0.0), "0.000000e+00");
+        assert_eq!(format("%.3e", 1.5e-120), "1.500e-120");
+        assert_eq!(format("%.3f", 2.0 / 3.0), "0.667");
+        assert_eq!(format("%f", 1.0), "1.000000");
+        assert_eq!(format("shortest", 0.1), "0.1");
+        assert_eq!(format("%.6e", f64::NAN), "NaN");
+        for invalid in ["%d", "6e", "%.xe", "%6e", "%."] {
+            assert!(invalid.parse::<FloatFormat>().is_err(), "{}", invalid);
+        }
+        assert_eq!(FloatFormat::default().to_string(), "%.6e");
+        // the shorthands of --precision and --format
+        let default = FloatFormat::default();
+        assert_eq!(default.with(None, None), default);
+        assert_eq!(default.with(Some(3), None), FloatFormat::Scientific(3));
+        assert_eq!(
+            default.with(None, Some(Notation::Fixed)),
+            FloatFormat::Fixed(6)
+        );
+        assert_eq!(
+            FloatFormat::Shortest.with(Some(2), Some(Notation::Fixed)),
+            FloatFormat::Fixed(2)
+        );
+        assert!("engineering".parse::<Notation>().is_err());
+    }
+
+    #[test]
+    fn test_json_lines() {
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let mut pipeline = Pipeline::from_cli_args(vec![args("offset 1")]);
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data[[7, 3]] = f64::NAN;
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        let json = json_lines(&dataset, &pipeline).unwrap();
+        let lines: Vec<&str> = json.lines().collect();
+        assert_eq!(lines.len(), 1 + 4);
+        assert_eq!(
+            lines[0],
+            r#"{"record":"step","step":1,"transformation":"OffsetTransform","offset":1.0,"percentile":false,"target_frames":null}"#
+        );
+        assert_eq!(
+            lines[2],
+            r#"{"record":"frame","frame":2,"x":[13.0,23.0,33.0,43.0,53.0,63.0,73.0,83.0],"y":[15.0,25.0,35.0,45.0,55.0,65.0,75.0,null]}"#
+        );
+        // the integrals are written as rows of the table of results
+        let mut pipeline = Pipeline::from_cli_args(vec![args("integrate 11,81")]);
+        let mut dataset = Dataset::new_test_dummy();
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        let json = json_lines(&dataset, &pipeline).unwrap();
+        let lines: Vec<&str> = json.lines().collect();
+        assert_eq!(lines.len(), 1 + 4);
+        assert!(lines[1].starts_with(r#"{"record":"result","frame":1.0,"11 to 81":"#));
+        assert_eq!(
+            warnings_json_lines(&["pixel \"5\" masked".to_owned()]).unwrap(),
+            "{\"record\":\"warning\",\"message\":\"pixel \\\"5\\\" masked\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_json_document() {
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let mut pipeline = Pipeline::from_cli_args(vec![args("integrate 11,81 31,61")]);
+        let mut dataset = Dataset::new_test_dummy();
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        let json = json_document(&dataset, &pipeline, &["a warning".to_owned()]).unwrap();
+        let document: serde_yaml::Value = serde_yaml::from_str(&json).unwrap();
+        assert_eq!(
+            document["metadata"]["steps"][0]["transformation"],
+            "IntegrateTransform"
+        );
+        assert_eq!(document["metadata"]["log"].as_sequence().unwrap().len(), 1);
+        assert_eq!(document["warnings"][0], "a warning");
+        assert_eq!(document["columns"][0], "11 to 81");
+        assert_eq!(document["columns"][1], "31 to 61");
+        let results = document["results"].as_sequence().unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0]["frame"], 1.0);
+        assert!(results[0]["31 to 61"].is_number());
+        // without a table of results, the frames are written
+        let mut pipeline = Pipeline::from_cli_args(vec![args("offset 1")]);
+        let mut dataset = Dataset::new_test_dummy();
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        let json = json_document(&dataset, &pipeline, &[]).unwrap();
+        assert!(json.starts_with("{\n  \"metadata\": {\n"));
+        let document: serde_yaml::Value = serde_yaml::from_str(&json).unwrap();
+        assert_eq!(document["frames"].as_sequence().unwrap().len(), 4);
+        assert_eq!(document["frames"][1]["x"][0], 13.0);
+        assert!(document.get("results").is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::common::input_data_to_string;
+use crate::metadata::Header;
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::path::PathBuf;
+
+/// Compare the pipelines in the headers of two processed files.
+#[derive(Parser, Deserialize, Debug)]
+pub struct DiffPipelineCommand {
+    #[clap(parse(from_os_str), help = "first processed file")]
+    pub a: PathBuf,
+    #[clap(parse(from_os_str), help = "second processed file")]
+    pub b: PathBuf,
+}
+
+impl DiffPipelineCommand {
+    /// The differences as text to print.
+    pub fn run(&self) -> Result<String> {
+        let read = |path: &PathBuf| {
+            input_data_to_string(&Some(path.to_owned()))
+                .and_then(|text| Header::parse(&text))
+                .with_context(|| format!("Unable to read header of {}", path.display()))
+        };
+        let diff = diff_headers(&read(&self.a)?, &read(&self.b)?);
+        Ok(format!(
+            "a: {}\nb: {}\n{}",
+            self.a.display(),
+            self.b.display(),
+            diff
+        ))
+    }
+}
+
+/// Readable differences of the preprocessor arguments and the steps of two
+/// headers. Steps are paired by their transformation in order, so a step
+/// added to one of the pipelines does not make all following steps differ.
+pub fn diff_headers(a: &Header, b: &Header) -> String {
+    let mut lines = vec![];
+    let empty = Mapping::new();
+    let arguments = diff_mappings(
+        a.preprocessor.as_ref().unwrap_or(&empty),
+        b.preprocessor.as_ref().unwrap_or(&empty),
+    );
+    if !arguments.is_empty() {
+        lines.push("preprocessor arguments:".to_owned());
+        lines.extend(arguments);
+    }
+    let tags_a: Vec<&str> = a.steps.iter().map(step_tag).collect();
+    let tags_b: Vec<&str> = b.steps.iter().map(step_tag).collect();
+    for pair in align(&tags_a, &tags_b) {
+        match pair {
+            (Some(i), Some(j)) => {
+                let changes = diff_mappings(&a.steps[i], &b.steps[j]);
+                if changes.is_empty() {
+                    continue;
+                }
+                let position = match i == j {
+                    true => format!("step {}", i + 1),
+                    false => format!("step {} / {}", i + 1, j + 1),
+                };
+                lines.push(format!("~ {} {}:", position, tags_a[i]));
+                lines.extend(changes);
+            }
+            (Some(i), None) => lines.push(format!("- step {} {} (only in a)", i + 1, tags_a[i])),
+            (None, Some(j)) => lines.push(format!("+ step {} {} (only in b)", j + 1, tags_b[j])),
+            (None, None) => {}
+        }
+    }
+    if lines.is_empty() {
+        return "the pipelines are the same\n".to_owned();
+    }
+    lines.iter().map(|line| format!("{line}\n")).collect()
+}
+
+fn step_tag(step: &Mapping) -> &str {
+    step.get("transformation")
+        .and_then(Value::as_str)
+        .unwrap_or("(unknown)")
+}
+
+/// Indices of the steps of `a` and `b` in order, paired where both have the
+/// same transformation (longest common subsequence).
+fn align(a: &[&str], b: &[&str]) -> Vec<(Option<usize>, Option<usize>)> {
+    // common[i][j]: length of the longest common subsequence of a[i..], b[j..]
+    let mut common = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = match a[i] == b[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+    let mut pairs = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            pairs.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+            pairs.push((Some(i), None));
+            i += 1;
+        } else {
+            pairs.push((None, Some(j)));
+            j += 1;
+        }
+    }
+    pairs
+}
+
+/// One line per key whose value differs, in the order of the keys of `a`
+/// followed by the keys only in `b`.
+fn diff_mappings(a: &Mapping, b: &Mapping) -> Vec<String> {
+    let mut lines = vec![];
+    for (key, value) in a.iter() {
+        match b.get(key) {
+            Some(other) if other == value => {}
+            Some(other) => lines.push(format!(
+                "    {}: {} -> {}",
+                inline(key),
+                inline(value),
+                inline(other)
+            )),
+            None => lines.push(format!(
+                "    {}: {} -> (not set)",
+                inline(key),
+                inline(value)
+            )),
+        }
+    }
+    for (key, value) in b.iter().filter(|(key, _)| !a.contains_key(*key)) {
+        lines.push(format!(
+            "    {}: (not set) -> {}",
+            inline(key),
+            inline(value)
+        ));
+    }
+    lines
+}
+
+/// A YAML value on a single line.
+fn inline(value: &Value) -> String {
+    match value {
+        Value::Null => "~".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.to_owned(),
+        Value::Sequence(items) => {
+            let items: Vec<String> = items.iter().map(inline).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Mapping(mapping) => {
+            let entries: Vec<String> = mapping
+                .iter()
+                .map(|(key, value)| format!("{}: {}", inline(key), inline(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Value::Tagged(tagged) => format!("{} {}", tagged.tag, inline(&tagged.value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff_headers;
+    use crate::metadata::Header;
+
+    #[test]
+    fn test_diff_headers() {
+        let a = Header::parse(
+            "preprocessor: arguments\ndelimiter: ','\n---\n\
+             transformation: OffsetTransform\noffset: 0.05\n---\n\
+             transformation: FinningTransform\nthreshold: 2.5\n---\n\
+             transformation: AverageTransform\n---\n",
+        )
+        .unwrap();
+        let b = Header::parse(
+            "preprocessor: arguments\ndelimiter: ';'\n---\n\
+             transformation: OffsetTransform\noffset: 0.1\nframes: [1, 2]\n---\n\
+             transformation: AverageTransform\n---\n\
+             transformation: RamanShiftTransform\nwavelength: 532.1\n---\n",
+        )
+        .unwrap();
+        let diff = diff_headers(&a, &b);
+        assert_eq!(
+            diff,
+            "preprocessor arguments:\n    delimiter: , -> ;\n\
+             ~ step 1 OffsetTransform:\n    offset: 0.05 -> 0.1\n    frames: (not set) -> [1, 2]\n\
+             - step 2 FinningTransform (only in a)\n\
+             + step 3 RamanShiftTransform (only in b)\n"
+        );
+        assert_eq!(diff_headers(&a, &a), "the pipelines are the same\n");
+    }
+}
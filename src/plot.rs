@@ -3,11 +3,11 @@ use std::sync::{Arc, Mutex, MutexGuard};
 
 use crate::common::{Dataset, Pair};
 use crate::transformations::Transformer;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use eframe::egui;
 use egui::{Color32, Ui};
-use egui_plot::{Legend, Line, Plot, PlotPoints, PlotUi, Points, VLine};
+use egui_plot::{Legend, Line, Plot, PlotImage, PlotPoint, PlotPoints, PlotUi, Points, VLine};
 use ndarray::Axis;
 use serde::{Deserialize, Serialize};
 use splines::{self, Key, Spline};
@@ -23,6 +23,329 @@ pub static PALETTE: [Color32; 8] = [
     Color32::from_rgb(179, 179, 179),
 ];
 
+/// Control points of the viridis colormap, evenly spaced from 0 to 1.
+static VIRIDIS: [(u8, u8, u8); 9] = [
+    (68, 1, 84),
+    (71, 44, 122),
+    (59, 81, 139),
+    (44, 113, 142),
+    (33, 144, 141),
+    (39, 173, 129),
+    (92, 200, 99),
+    (170, 220, 50),
+    (253, 231, 37),
+];
+
+/// Control points of the magma colormap, evenly spaced from 0 to 1.
+static MAGMA: [(u8, u8, u8); 9] = [
+    (0, 0, 4),
+    (28, 16, 68),
+    (79, 18, 123),
+    (129, 37, 129),
+    (181, 54, 122),
+    (229, 80, 100),
+    (251, 135, 97),
+    (254, 194, 135),
+    (252, 253, 191),
+];
+
+static GRAYSCALE: [(u8, u8, u8); 2] = [(0, 0, 0), (255, 255, 255)];
+
+/// Map `t` in [0, 1] onto the viridis colormap, values outside are clamped.
+pub fn viridis(t: f64) -> Color32 {
+    Colormap::Viridis.color(t)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Colormap {
+    #[default]
+    Viridis,
+    Magma,
+    Grayscale,
+}
+
+impl std::str::FromStr for Colormap {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viridis" => Ok(Colormap::Viridis),
+            "magma" => Ok(Colormap::Magma),
+            "grayscale" => Ok(Colormap::Grayscale),
+            _ => Err(anyhow!(
+                "unknown colormap \"{}\", use \"viridis\", \"magma\" or \"grayscale\"",
+                s
+            )),
+        }
+    }
+}
+
+impl Colormap {
+    /// Map `t` in [0, 1] onto the colormap, values outside are clamped.
+    pub fn color(self, t: f64) -> Color32 {
+        let points: &[(u8, u8, u8)] = match self {
+            Colormap::Viridis => &VIRIDIS,
+            Colormap::Magma => &MAGMA,
+            Colormap::Grayscale => &GRAYSCALE,
+        };
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let pos = t * (points.len() - 1) as f64;
+        let i = (pos.floor() as usize).min(points.len() - 2);
+        let frac = pos - i as f64;
+        let (r0, g0, b0) = points[i];
+        let (r1, g1, b1) = points[i + 1];
+        let mix = |c0: u8, c1: u8| (c0 as f64 + frac * (c1 as f64 - c0 as f64)).round() as u8;
+        Color32::from_rgb(mix(r0, r1), mix(g0, g1), mix(b0, b1))
+    }
+}
+
+/// How values are mapped onto [0, 1] before looking up their color.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorScaling {
+    /// proportional to the value, from minimum to maximum
+    #[default]
+    Linear,
+    /// proportional to the logarithm of the value above the minimum
+    Log,
+    /// rank of the value among all values (histogram equalization), so weak
+    /// bands get as much of the colormap as strong ones
+    Percentile,
+}
+
+impl ColorScaling {
+    /// Scale `values` onto [0, 1], non-finite values become NaN.
+    pub fn scale(self, values: &[f64]) -> Vec<f64> {
+        let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        if sorted.is_empty() {
+            return vec![f64::NAN; values.len()];
+        }
+        sorted.sort_by(f64::total_cmp);
+        let min = sorted[0];
+        let max = sorted[sorted.len() - 1];
+        let range = if max > min { max - min } else { 1.0 };
+        values
+            .iter()
+            .map(|v| {
+                if !v.is_finite() {
+                    return f64::NAN;
+                }
+                match self {
+                    ColorScaling::Linear => (v - min) / range,
+                    ColorScaling::Log => (v - min).ln_1p() / range.ln_1p(),
+                    ColorScaling::Percentile => {
+                        let rank = sorted.partition_point(|s| s < v);
+                        rank as f64 / (sorted.len() - 1).max(1) as f64
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+// ---- Heatmap ---------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeatmapSettings {
+    pub colormap: Colormap,
+    pub scaling: ColorScaling,
+    /// part of the scaled values [0, 1] spanned by the colormap
+    pub range: [f64; 2],
+}
+
+impl Default for HeatmapSettings {
+    fn default() -> Self {
+        Self {
+            colormap: Colormap::default(),
+            scaling: ColorScaling::default(),
+            range: [0.0, 1.0],
+        }
+    }
+}
+
+/// Frames drawn as rows of an image, on the x-axis of the first frame.
+pub struct Heatmap {
+    pub settings: HeatmapSettings,
+    texture: egui::TextureHandle,
+    center: PlotPoint,
+    size: egui::Vec2,
+    /// intensities at the ends of the color range
+    pub limits: (f64, f64),
+}
+
+impl Heatmap {
+    pub fn new(ctx: &egui::Context, dataset: &Dataset, settings: HeatmapSettings) -> Option<Self> {
+        heatmap_from_dataset(dataset, settings).map(|(image, center, size, limits)| Heatmap {
+            settings,
+            texture: ctx.load_texture("heatmap", image, egui::TextureOptions::NEAREST),
+            center,
+            size,
+            limits,
+        })
+    }
+    pub fn draw(&self, plot_ui: &mut PlotUi) {
+        plot_ui.image(PlotImage::new(self.texture.id(), self.center, self.size));
+    }
+    /// Frame (counting from 1) whose row is drawn at `y`.
+    pub fn frame_at(&self, y: f64) -> Option<usize> {
+        let frame = y.round();
+        (frame >= 1.0 && frame <= self.size.y as f64).then_some(frame as usize)
+    }
+    pub fn draw_colorbar(&self, ui: &mut Ui) {
+        let (low, high) = self.limits;
+        draw_colorbar(ui, low, high, self.settings.colormap);
+    }
+}
+
+fn heatmap_from_dataset(
+    dataset: &Dataset,
+    settings: HeatmapSettings,
+) -> Option<(egui::ColorImage, PlotPoint, egui::Vec2, (f64, f64))> {
+    let n_frames = dataset.data.ncols() / 2;
+    let n_rows = dataset.data.nrows();
+    if n_frames == 0 || n_rows == 0 {
+        return None;
+    }
+    let xs = dataset.data.column(0);
+    let x_min = xs
+        .iter()
+        .copied()
+        .filter(|x| x.is_finite())
+        .reduce(f64::min)?;
+    let x_max = xs
+        .iter()
+        .copied()
+        .filter(|x| x.is_finite())
+        .reduce(f64::max)?;
+    let descending = xs[0] > xs[n_rows - 1];
+    // the top row of the image is the last frame, x increases to the right
+    let mut values = Vec::with_capacity(n_frames * n_rows);
+    for frame in (0..n_frames).rev() {
+        for col in 0..n_rows {
+            let row = if descending { n_rows - 1 - col } else { col };
+            values.push(dataset.data[[row, 2 * frame + 1]]);
+        }
+    }
+    let scaled = settings.scaling.scale(&values);
+    let [low, high] = settings.range;
+    let pixels = scaled
+        .iter()
+        .map(|t| {
+            if t.is_nan() {
+                Color32::TRANSPARENT
+            } else {
+                settings.colormap.color((t - low) / (high - low))
+            }
+        })
+        .collect();
+    // intensity with the scaled value closest to `t`
+    let value_at = |t: f64| {
+        values
+            .iter()
+            .zip(scaled.iter())
+            .filter(|(_, s)| !s.is_nan())
+            .min_by(|(_, s1), (_, s2)| (*s1 - t).abs().total_cmp(&(*s2 - t).abs()))
+            .map(|(v, _)| *v)
+            .unwrap_or(f64::NAN)
+    };
+    let image = egui::ColorImage {
+        size: [n_rows, n_frames],
+        pixels,
+    };
+    let center = PlotPoint::new((x_min + x_max) / 2.0, (n_frames as f64 + 1.0) / 2.0);
+    let size = egui::vec2((x_max - x_min) as f32, n_frames as f32);
+    Some((image, center, size, (value_at(low), value_at(high))))
+}
+
+pub fn draw_colorbar(ui: &mut Ui, min: f64, max: f64, colormap: Colormap) {
+    ui.horizontal(|ui| {
+        ui.label(format!("{min:.4}"));
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(200.0, 12.0), egui::Sense::hover());
+        let n_steps = 50;
+        let step_width = rect.width() / n_steps as f32;
+        for i in 0..n_steps {
+            let left = rect.left() + i as f32 * step_width;
+            let step_rect = egui::Rect::from_min_max(
+                egui::pos2(left, rect.top()),
+                egui::pos2(left + step_width + 0.5, rect.bottom()),
+            );
+            ui.painter().rect_filled(
+                step_rect,
+                0.0,
+                colormap.color(i as f64 / (n_steps - 1) as f64),
+            );
+        }
+        ui.label(format!("{max:.4}"));
+    });
+}
+
+// ---- StackOffset -----------------------------------------------------------
+
+/// Vertical offset between successive frames of a waterfall plot.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StackOffset {
+    /// median intensity range of the frames
+    Auto,
+    /// fixed offset in units of the intensity
+    Fixed(f64),
+}
+
+impl std::str::FromStr for StackOffset {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "auto" {
+            return Ok(StackOffset::Auto);
+        }
+        s.trim()
+            .parse::<f64>()
+            .map(StackOffset::Fixed)
+            .map_err(|_| anyhow!("could not parse stack offset, use \"auto\" or a number"))
+    }
+}
+
+impl StackOffset {
+    /// Offset between successive frames of `dataset`.
+    pub fn resolve(&self, dataset: &Dataset) -> f64 {
+        match self {
+            StackOffset::Fixed(offset) => *offset,
+            StackOffset::Auto => {
+                let mut ranges: Vec<f64> = dataset
+                    .data
+                    .axis_iter(Axis(1))
+                    .skip(1)
+                    .step_by(2)
+                    .map(|ys| {
+                        let (min, max) = ys
+                            .iter()
+                            .filter(|y| y.is_finite())
+                            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), y| {
+                                (min.min(*y), max.max(*y))
+                            });
+                        max - min
+                    })
+                    .filter(|range| range.is_finite())
+                    .collect();
+                if ranges.is_empty() {
+                    return 0.0;
+                }
+                ranges.sort_by(|a, b| a.total_cmp(b));
+                ranges[ranges.len() / 2]
+            }
+        }
+    }
+}
+
+/// Shift the intensities of each frame by its index times `offset`.
+pub fn stack_frames(dataset: &mut Dataset, offset: f64) {
+    dataset
+        .data
+        .axis_iter_mut(Axis(1))
+        .skip(1)
+        .step_by(2)
+        .enumerate()
+        .for_each(|(i, mut ys)| ys.iter_mut().for_each(|y| *y += i as f64 * offset));
+}
+
 // ---- PlotTransform ---------------------------------------------------------
 
 #[derive(Debug, Default, Parser, Serialize, Deserialize)]
@@ -41,6 +364,27 @@ pub struct PlotTransform {
         help = "if flag is set, plot intensity versus pixels"
     )]
     pub pixels: bool,
+    #[clap(
+        short,
+        long,
+        help = "Offset successive frames vertically (waterfall plot), either by a fixed amount or \"auto\"."
+    )]
+    #[serde(default)]
+    pub stack: Option<StackOffset>,
+    #[clap(
+        long,
+        action,
+        help = "if flag is set, plot frames as rows of an image (heatmap), click a row to show the frame"
+    )]
+    #[serde(default)]
+    pub heatmap: bool,
+    #[clap(
+        long,
+        default_value("viridis"),
+        help = "Colormap of the heatmap: \"viridis\", \"magma\" or \"grayscale\"."
+    )]
+    #[serde(default)]
+    pub colormap: Colormap,
     #[serde(skip)]
     #[clap(skip)]
     pub extensions: Vec<Arc<Mutex<dyn PlotExtension>>>,
@@ -55,21 +399,18 @@ impl Transformer for PlotTransform {
             // initial_window_size: Some(egui::vec2(800.0, 500.0)),
             ..Default::default()
         };
-        let ds_arcmutex = match self.pixels {
-            false => Arc::new(Mutex::new(dataset.clone())),
-            true => {
-                let mut ds = dataset.clone();
-                // replace spectral axis by pixel number
-                ds.data
-                    .axis_iter_mut(Axis(1))
-                    .step_by(2)
-                    .for_each(|mut col| {
-                        col.iter_mut().enumerate().for_each(|(i, x)| *x = i as f64)
-                    });
-                Arc::new(Mutex::new(ds))
-            }
-        };
-        let pw = PlotWindow::new(
+        let mut ds = dataset.clone();
+        if self.pixels {
+            // replace spectral axis by pixel number
+            ds.data
+                .axis_iter_mut(Axis(1))
+                .step_by(2)
+                .for_each(|mut col| col.iter_mut().enumerate().for_each(|(i, x)| *x = i as f64));
+        }
+        let offset = self.stack.map(|stack| stack.resolve(&ds)).unwrap_or(0.0);
+        stack_frames(&mut ds, offset);
+        let ds_arcmutex = Arc::new(Mutex::new(ds));
+        let mut pw = PlotWindow::new(
             ds_arcmutex.clone(),
             self.line_width,
             self.extensions.clone(),
@@ -77,12 +418,20 @@ impl Transformer for PlotTransform {
             self.x_lim,
             self.y_lim,
         );
+        if self.heatmap {
+            pw = pw.with_heatmap(HeatmapSettings {
+                colormap: self.colormap,
+                ..Default::default()
+            });
+        }
         eframe::run_native("Dataset Plot", options, Box::new(|_cc| Box::new(pw)));
         dataset.data = ds_arcmutex
             .lock()
             .expect("Unable to aquire lock to read data from plot.")
             .data
             .clone();
+        // the offsets are only for display
+        stack_frames(dataset, -offset);
         Ok(())
     }
 }
@@ -96,6 +445,10 @@ pub struct PlotWindow {
     info: Arc<Mutex<String>>,
     x_lim: Option<Pair<f64>>,
     y_lim: Option<Pair<f64>>,
+    heatmap_settings: Option<HeatmapSettings>,
+    heatmap: Option<Heatmap>,
+    /// frame clicked in the heatmap, counting from 1
+    heatmap_frame: Option<usize>,
 }
 
 impl PlotWindow {
@@ -115,6 +468,65 @@ impl PlotWindow {
             info,
             x_lim,
             y_lim,
+            heatmap_settings: None,
+            heatmap: None,
+            heatmap_frame: None,
+        }
+    }
+    /// Plot the frames as rows of an image instead of lines.
+    pub fn with_heatmap(mut self, settings: HeatmapSettings) -> Self {
+        self.heatmap_settings = Some(settings);
+        self
+    }
+    /// Heatmap of the frames and, below it, the frame clicked in the heatmap.
+    fn heatmap_plot(&mut self, ui: &mut Ui, settings: HeatmapSettings) {
+        let ds = self
+            .dataset_arcmutex
+            .lock()
+            .expect("Unable to get lock for dataset.");
+        if self.heatmap.is_none() {
+            self.heatmap = Heatmap::new(ui.ctx(), &ds, settings);
+        }
+        let Some(heatmap) = &self.heatmap else {
+            return;
+        };
+        heatmap.draw_colorbar(ui);
+        let height = ui.available_height();
+        let n_frames = ds.data.ncols() / 2;
+        let frame = self.heatmap_frame.filter(|frame| *frame <= n_frames);
+        Plot::new("Heatmap")
+            .height(if frame.is_some() {
+                height * 0.6
+            } else {
+                height
+            })
+            .link_axis("heatmap", true, false)
+            .show(ui, |plot_ui| {
+                heatmap.draw(plot_ui);
+                if plot_ui.response().clicked() {
+                    if let Some(frame) = plot_ui
+                        .pointer_coordinate()
+                        .and_then(|point| heatmap.frame_at(point.y))
+                    {
+                        self.heatmap_frame = Some(frame);
+                    }
+                }
+            });
+        if let Some(frame) = frame {
+            Plot::new("Heatmap frame")
+                .legend(Legend::default())
+                .link_axis("heatmap", true, false)
+                .show(ui, |plot_ui| {
+                    let points: PlotPoints = (0..ds.data.nrows())
+                        .map(|i| [ds.data[[i, 2 * frame - 2]], ds.data[[i, 2 * frame - 1]]])
+                        .collect();
+                    plot_ui.line(
+                        Line::new(points)
+                            .width(self.line_width)
+                            .color(PALETTE[(frame - 1) % PALETTE.len()])
+                            .name(frame),
+                    );
+                });
         }
     }
 }
@@ -144,6 +556,10 @@ impl eframe::App for PlotWindow {
                     self.info.lock().unwrap().as_str(),
                 );
             }
+            if let Some(settings) = self.heatmap_settings {
+                self.heatmap_plot(ui, settings);
+                return;
+            }
             let mut plot = Plot::new("Scans").legend(Legend::default());
             if let Some(x_lim) = self.x_lim {
                 plot = plot.include_x(x_lim.a);
@@ -358,3 +774,48 @@ impl SplineExtension {
         distances.first().map(|(index, _)| *index)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{stack_frames, ColorScaling, StackOffset};
+    use crate::common::Dataset;
+
+    #[test]
+    fn test_color_scaling() {
+        let values = [1.0, 2.0, 1000.0, f64::NAN, 3.0];
+        let linear = ColorScaling::Linear.scale(&values);
+        assert_eq!(linear[0], 0.0);
+        assert_eq!(linear[2], 1.0);
+        assert!(linear[3].is_nan());
+        // weak values get a larger share of the colormap
+        let log = ColorScaling::Log.scale(&values);
+        assert!(log[1] > 0.1);
+        assert_eq!(log[2], 1.0);
+        let percentile = ColorScaling::Percentile.scale(&values);
+        assert_eq!(percentile[0], 0.0);
+        assert_eq!(percentile[1], 1.0 / 3.0);
+        assert_eq!(percentile[4], 2.0 / 3.0);
+        assert_eq!(percentile[2], 1.0);
+    }
+
+    #[test]
+    fn test_stack_frames() {
+        let mut dataset = Dataset::new_test_dummy();
+        assert_eq!("auto".parse::<StackOffset>().unwrap(), StackOffset::Auto);
+        assert_eq!(
+            "2.5".parse::<StackOffset>().unwrap(),
+            StackOffset::Fixed(2.5)
+        );
+        assert!("two".parse::<StackOffset>().is_err());
+        // every frame spans 70 counts
+        let offset = StackOffset::Auto.resolve(&dataset);
+        assert_eq!(offset, 70.0);
+        stack_frames(&mut dataset, offset);
+        assert_eq!(dataset.data[[0, 1]], 12.0);
+        assert_eq!(dataset.data[[0, 7]], 18.0 + 3.0 * 70.0);
+        // x-values are not shifted
+        assert_eq!(dataset.data[[0, 6]], 17.0);
+        stack_frames(&mut dataset, -offset);
+        assert_eq!(dataset.data, Dataset::new_test_dummy().data);
+    }
+}
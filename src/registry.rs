@@ -0,0 +1,507 @@
+use crate::common::Dataset;
+use crate::config::UserConfig;
+use crate::gui::TransformerGUI;
+use crate::plot::StackOffset;
+use crate::schema::step_schema;
+use crate::transformations::{
+    align::AlignTransform,
+    append::{AppendMismatch, AppendTransform},
+    arithmetic::{ArithmeticTransform, Operation},
+    average::AverageTransform,
+    baseline::BaselineTransform,
+    bose_einstein::BoseEinsteinTransform,
+    calibration::CalibrationTransform,
+    clip::ClipTransform,
+    coadd_regions::CoaddRegionsTransform,
+    count_conversion::CountConversionTransform,
+    deconvolve::DeconvolveTransform,
+    despike::{DespikeMethod, DespikeTransform},
+    drop_nan::DropNanTransform,
+    finning::FinningTransform,
+    integrate::{IntegrateIOBuffers, IntegrateTransform},
+    kinetics::KineticsTransform,
+    kmeans::KmeansTransform,
+    mask_pixels::{MaskMethod, MaskTransform},
+    mcr::McrTransform,
+    median::MedianTransform,
+    normalize::NormalizeTransform,
+    offset::{OffsetIOBuffers, OffsetTransform, QuantileMethod},
+    offset_stack::OffsetStackTransform,
+    peak_pick::PeakPickTransform,
+    plugin::PluginTransform,
+    reject_outliers::RejectOutliersTransform,
+    repeat::RepeatTransform,
+    reshape::ReshapeTransform,
+    response_correction::ResponseCorrectionTransform,
+    scale::ScaleTransform,
+    script::ScriptTransform,
+    select::SelectTransform,
+    shift::RamanShiftTransform,
+    similarity::{SimilarityMeasure, SimilarityTransform},
+    subtract::SubtractTransform,
+    time_axis::TimeAxisTransform,
+    weight::WeightTransform,
+    x_shift::XShiftTransform,
+    Transformer,
+};
+use crate::utils::Interpolation;
+use anyhow::Result;
+use clap::{CommandFactory, Parser};
+use serde_yaml::Value;
+use std::path::PathBuf;
+
+/// A step of the pipeline as built from the registry.
+type Step = Box<dyn TransformerGUI>;
+
+/// Everything the program needs to know about a transformer: how it is
+/// called on the command line, in the YAML header and in the GUI, and how
+/// it is built in each of these places.
+pub struct Registration {
+    /// name of the command on the command line, e.g. "count-conversion"
+    pub name: &'static str,
+    /// value of the "transformation" entry in the YAML header, the name of
+    /// the struct
+    pub tag: &'static str,
+    /// name in the list of transformations of the GUI
+    pub label: &'static str,
+    /// parse the arguments of the command (including its name)
+    pub parse: fn(Vec<String>) -> Result<Step, clap::Error>,
+    /// read the configuration of a step from the YAML header
+    pub from_yaml: fn(Value) -> Result<Step>,
+    /// step with default parameters inserted in the GUI, some defaults are
+    /// taken from the data
+    pub new: fn(&Dataset) -> Step,
+    /// command with the arguments, for the help of the program
+    pub command: fn() -> clap::Command<'static>,
+    /// JSON Schema of the configuration in the YAML header
+    pub schema: fn() -> Result<Value>,
+}
+
+impl PartialEq for Registration {
+    fn eq(&self, other: &Self) -> bool {
+        self.tag == other.tag
+    }
+}
+
+macro_rules! transformer {
+    ( $name:literal, $x:ident, $label:literal, $about:literal, $new:expr ) => {
+        Registration {
+            name: $name,
+            tag: stringify!($x),
+            label: $label,
+            parse: |args| {
+                let mut step = $x::try_parse_from(args)?;
+                step.use_config_defaults(UserConfig::global());
+                Ok(Box::new(step))
+            },
+            from_yaml: |config| Ok(Box::new(serde_yaml::from_value::<$x>(config)?)),
+            new: $new,
+            command: || $x::command().name($name).about($about),
+            schema: || step_schema::<$x>(stringify!($x)),
+        }
+    };
+}
+
+/// All transformers, a new transformer is registered here and nowhere else.
+/// It shows up in the GUI in this order.
+pub static TRANSFORMERS: [Registration; 38] = [
+    transformer!("align", AlignTransform, "Align", "Align frames.", |_| {
+        Box::new(AlignTransform {
+            cost_max_abs: 0.1,
+            interpolation: Interpolation::default(),
+            reference: 1,
+            window: None,
+            stretch: None,
+            manual_shifts: vec![],
+            shifts: vec![],
+        })
+    }),
+    transformer!(
+        "append",
+        AppendTransform,
+        "Append File",
+        "Append a dataset from a further input file.",
+        |_| Box::new(AppendTransform {
+            filepath: Some(PathBuf::from("")),
+            delimiter: UserConfig::global().delimiter.unwrap_or(','),
+            comment: UserConfig::global().comment.unwrap_or('#'),
+            horizontal: false,
+            mismatch: AppendMismatch::default(),
+            interpolation: Interpolation::default(),
+        })
+    ),
+    transformer!(
+        "arithmetic",
+        ArithmeticTransform,
+        "Arithmetic",
+        "Add, subtract, multiply or divide frames by a reference frame.",
+        |_| Box::new(ArithmeticTransform::new(Operation::Divide, 1))
+    ),
+    transformer!(
+        "average",
+        AverageTransform,
+        "Average",
+        "Average intensity.",
+        |_| Box::new(AverageTransform::default())
+    ),
+    transformer!(
+        "bose-einstein",
+        BoseEinsteinTransform,
+        "Bose-Einstein Correction",
+        "Correct intensities for the thermal population of vibrational states.",
+        |_| Box::new(BoseEinsteinTransform::default())
+    ),
+    transformer!(
+        "calibration",
+        CalibrationTransform,
+        "Calibration",
+        "Apply a linear calibration to the wavelength axis.",
+        |_| Box::new(CalibrationTransform {
+            order: 1,
+            tolerance: 0.5,
+            min_height: 0.05,
+            ..Default::default()
+        })
+    ),
+    transformer!(
+        "clip",
+        ClipTransform,
+        "Clip",
+        "Clip intensities below a floor, e.g. negative noise after subtracting a dark frame.",
+        |_| Box::new(ClipTransform::default())
+    ),
+    transformer!(
+        "coadd-regions",
+        CoaddRegionsTransform,
+        "Co-add Regions",
+        "Co-add the regions of interest of each exposure with weights.",
+        |_| Box::new(CoaddRegionsTransform::new(None))
+    ),
+    transformer!(
+        "count-conversion",
+        CountConversionTransform,
+        "Count-Conversion",
+        "Convert from counts to photoelectrons per second.",
+        |_| Box::new(CountConversionTransform::default())
+    ),
+    transformer!(
+        "deconvolve",
+        DeconvolveTransform,
+        "Deconvolution",
+        "Narrow bands by Richardson-Lucy or Fourier self-deconvolution of a line shape.",
+        |_| Box::new(DeconvolveTransform::new(5.0))
+    ),
+    transformer!(
+        "baseline",
+        BaselineTransform,
+        "Draw Baseline",
+        "Subtract a spline baseline through the given points (from all frames).",
+        |_| Box::new(BaselineTransform {
+            points: vec![],
+            frame_points: vec![],
+            interpolate: false,
+            snap: None,
+            store: false,
+            keep: false,
+        })
+    ),
+    transformer!(
+        "despike",
+        DespikeTransform,
+        "Despiking",
+        "Remove spikes (laplace edge-detection, finning or temporal algorithm).",
+        |_| Box::new(DespikeTransform {
+            siglim: 10.0,
+            flim: 10.0,
+            method: DespikeMethod::Laplace,
+            threshold: 3.5,
+            iterations: 4,
+            window: 2,
+            gain: None,
+            readnoise: None,
+            replaced: vec![],
+        })
+    ),
+    transformer!(
+        "drop-nan",
+        DropNanTransform,
+        "Drop Missing Rows",
+        "Remove rows in which all frames are missing (NaN).",
+        |_| Box::new(DropNanTransform {})
+    ),
+    transformer!(
+        "finning",
+        FinningTransform,
+        "Finning",
+        "Apply finning despike algorithm.",
+        |_| Box::new(FinningTransform {
+            threshold: 2.5,
+            iterations: 4,
+            replaced: vec![],
+        })
+    ),
+    transformer!(
+        "weight",
+        WeightTransform,
+        "Frame Weights",
+        "Attach a quality weight to each frame, honored by average and mcr.",
+        |dataset| Box::new(WeightTransform {
+            weights: vec![1.0; dataset.data.ncols() / 2],
+            ..Default::default()
+        })
+    ),
+    transformer!(
+        "integrate",
+        IntegrateTransform,
+        "Integrate",
+        "Integrate frames in given interval(s).",
+        |_| Box::new(IntegrateTransform {
+            bounds: vec![],
+            local_baseline: true,
+            uncertainty: false,
+            derived: vec![],
+            gui_text_buffers: IntegrateIOBuffers::default(),
+        })
+    ),
+    transformer!(
+        "kmeans",
+        KmeansTransform,
+        "K-Means Clustering",
+        "Sort frames into clusters of similar spectra (k-means).",
+        |_| Box::new(KmeansTransform::new(2))
+    ),
+    transformer!(
+        "kinetics",
+        KineticsTransform,
+        "Kinetics",
+        "Integrate frames in given interval(s) vs. time of the frames.",
+        |_| Box::new(KineticsTransform {
+            bounds: vec![],
+            local_baseline: true,
+            interval: None,
+        })
+    ),
+    transformer!(
+        "mask",
+        MaskTransform,
+        "Mask Points",
+        "Manually mask data points by pixel and frame number or by x-range.",
+        |_| Box::new(MaskTransform {
+            mask: vec![],
+            range: vec![],
+            frames: None,
+            method: MaskMethod::default(),
+        })
+    ),
+    transformer!(
+        "mcr",
+        McrTransform,
+        "MCR-ALS",
+        "Resolve pure-component spectra and concentration profiles (MCR-ALS).",
+        |_| Box::new(McrTransform::new(2))
+    ),
+    transformer!(
+        "median",
+        MedianTransform,
+        "Median",
+        "Pixel-wise median of all frames.",
+        |_| Box::new(MedianTransform::default())
+    ),
+    transformer!(
+        "normalize",
+        NormalizeTransform,
+        "Normalize",
+        "Normalize frames.",
+        |dataset| Box::new(NormalizeTransform::at_mean_maximum(dataset))
+    ),
+    transformer!(
+        "offset",
+        OffsetTransform,
+        "Offset",
+        "Add offset to value columns.",
+        |_| Box::new(OffsetTransform {
+            offset: 0.0,
+            percentile: false,
+            target_frames: None,
+            quantile_method: QuantileMethod::default(),
+            min_points: 1,
+            gui_text_buffers: OffsetIOBuffers {
+                value: 0.0.to_string(),
+            },
+        })
+    ),
+    transformer!(
+        "peak-pick",
+        PeakPickTransform,
+        "Peak Picking",
+        "Position and intensity of the maximum, centroid and FWHM of bands, per frame.",
+        |_| Box::new(PeakPickTransform {
+            bounds: vec![],
+            local_baseline: true,
+        })
+    ),
+    transformer!(
+        "plugin",
+        PluginTransform,
+        "Plugin",
+        "Apply a transformation provided by a plugin (dynamic library in the plugin directory).",
+        |_| Box::new(PluginTransform::default())
+    ),
+    transformer!(
+        "shift",
+        RamanShiftTransform,
+        "Raman Shift",
+        "Calculate Raman shift.",
+        |_| {
+            let mut rst = RamanShiftTransform {
+                wavelength: UserConfig::global().laser_wavelength.unwrap_or(532.1),
+                refractive_index: 1.000264,
+                correction: Some(0.0),
+                ..Default::default()
+            };
+            rst.update_text_buffers();
+            Box::new(rst)
+        }
+    ),
+    transformer!(
+        "reject-outliers",
+        RejectOutliersTransform,
+        "Reject Outliers",
+        "Remove frames unlike the others (low correlation with the median or deviating total counts).",
+        |_| Box::new(RejectOutliersTransform::new(Some(0.9), None))
+    ),
+    transformer!(
+        "repeat",
+        RepeatTransform,
+        "Repeat",
+        "Apply the following command(s) several times.",
+        |_| Box::new(RepeatTransform {
+            times: 2,
+            tolerance: None,
+            commands: 1,
+            steps: vec![],
+            passes: 0,
+        })
+    ),
+    transformer!(
+        "reshape",
+        ReshapeTransform,
+        "Reshape",
+        "Reshape dataset into different form.",
+        |_| Box::new(ReshapeTransform { rows: 1340 })
+    ),
+    transformer!(
+        "response-correction",
+        ResponseCorrectionTransform,
+        "Response Correction",
+        "Divide frames by the instrument response derived from a calibrated lamp spectrum.",
+        |_| Box::new(ResponseCorrectionTransform {
+            filepath: Some(PathBuf::from("")),
+            reference: None,
+            comment: '#',
+            delimiter: ',',
+        })
+    ),
+    transformer!(
+        "scale",
+        ScaleTransform,
+        "Scale",
+        "Multiply intensities by a factor and add a constant.",
+        |_| Box::new(ScaleTransform::new(1.0))
+    ),
+    transformer!(
+        "script",
+        ScriptTransform,
+        "Script",
+        "Evaluate a Rhai script for every point of the frames.",
+        |_| Box::new(ScriptTransform::new("y"))
+    ),
+    transformer!(
+        "select",
+        SelectTransform,
+        "Select Frames",
+        "Select frames.",
+        |_| Box::new(SelectTransform {
+            frames: vec![],
+            invert: true,
+        })
+    ),
+    transformer!(
+        "x-shift",
+        XShiftTransform,
+        "Shift X-Axis",
+        "Shift the x-axis of frames by known amounts.",
+        |_| Box::new(XShiftTransform {
+            shifts: vec![0.0],
+            target_frames: None,
+        })
+    ),
+    transformer!(
+        "similarity",
+        SimilarityTransform,
+        "Similarity Matrix",
+        "Pairwise similarity (correlation, cosine or HQI) of all frames.",
+        |_| Box::new(SimilarityTransform::new(SimilarityMeasure::default()))
+    ),
+    transformer!(
+        "stack",
+        OffsetStackTransform,
+        "Stack Offset",
+        "Offset successive frames vertically (waterfall), baked into the data.",
+        |_| Box::new(OffsetStackTransform::new(StackOffset::Auto))
+    ),
+    transformer!(
+        "subtract",
+        SubtractTransform,
+        "Subtract Frames",
+        "Subtract frame from other frames.",
+        |_| Box::new(SubtractTransform {
+            direct: false,
+            minuends: None,
+            subtrahend: 1,
+            interpolation: Interpolation::default(),
+        })
+    ),
+    transformer!(
+        "time-axis",
+        TimeAxisTransform,
+        "Time Axis",
+        "Time stamps of frames from their exposure time, for files without time stamps.",
+        |_| Box::new(TimeAxisTransform::default())
+    ),
+];
+
+/// The transformer called `name` on the command line.
+pub fn transformer_by_name(name: &str) -> Option<&'static Registration> {
+    TRANSFORMERS.iter().find(|t| t.name == name)
+}
+
+/// The transformer tagged `tag` in the YAML header.
+pub fn transformer_by_tag(tag: &str) -> Option<&'static Registration> {
+    TRANSFORMERS.iter().find(|t| t.tag == tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TRANSFORMERS;
+    use crate::common::{yaml_segment_to_transform, Dataset};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_registry() {
+        let names: HashSet<_> = TRANSFORMERS.iter().map(|t| t.name).collect();
+        let tags: HashSet<_> = TRANSFORMERS.iter().map(|t| t.tag).collect();
+        assert_eq!(names.len(), TRANSFORMERS.len());
+        assert_eq!(tags.len(), TRANSFORMERS.len());
+        let dataset = Dataset::new_test_dummy();
+        for transformer in TRANSFORMERS.iter() {
+            // the steps inserted in the GUI are read back from the header
+            let step = (transformer.new)(&dataset);
+            let config = step.config_to_string().unwrap();
+            assert!(config.starts_with(&format!("transformation: {}\n", transformer.tag)));
+            let parsed = yaml_segment_to_transform(&config).unwrap();
+            assert_eq!(parsed.config_to_string().unwrap(), config);
+            assert_eq!((transformer.command)().get_name(), transformer.name);
+        }
+    }
+}
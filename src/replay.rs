@@ -0,0 +1,151 @@
+use crate::cli::Preprocessor;
+use crate::common::{input_data_to_string, is_stdin_path, Pipeline};
+use crate::metadata::Header;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Run the pipeline recorded in the header of a processed file again on the
+/// raw input of that run.
+#[derive(Parser, Deserialize, Debug)]
+pub struct ReplayCommand {
+    #[clap(
+        long,
+        parse(from_os_str),
+        help = "Raw input file of the run, if it was moved since."
+    )]
+    pub input: Option<PathBuf>,
+}
+
+/// Preprocessor and pipeline of the run that wrote a processed file.
+pub struct Replay {
+    pub preprocessor: Preprocessor,
+    pub pipeline: Pipeline,
+    /// hash of the data the first step of the run started from, see
+    /// `crate::metadata::data_sha256`, if the header logs the steps
+    pub input_sha256: Option<String>,
+    /// notices about the rebuilt run, e.g. that the raw input was moved,
+    /// for the diagnostics of the run
+    pub warnings: Vec<String>,
+}
+
+/// Rebuild the run that wrote the input file of `preprocessor` (a processed
+/// file) from its header. The raw input is read from the path recorded in
+/// the header, or from `input` if given. The output options of
+/// `preprocessor` (e.g. the output format) are kept.
+pub fn replay(preprocessor: &Preprocessor, input: Option<&Path>) -> Result<Replay> {
+    let text = input_data_to_string(&preprocessor.args.filepath)?;
+    let mut replayed = Preprocessor::from_yaml_header(&text, false)?;
+    let pipeline = Pipeline::from_yaml_header(&text)?;
+    let recorded = replayed.args.filepath.take();
+    let processed = preprocessor
+        .args
+        .filepath
+        .as_deref()
+        .filter(|path| !is_stdin_path(path));
+    let mut warnings = vec![];
+    replayed.args.filepath = Some(locate_input(
+        recorded.as_deref(),
+        processed,
+        input,
+        &mut warnings,
+    )?);
+    let args = &mut replayed.args;
+    args.output_format = preprocessor.args.output_format;
+    args.float_format = preprocessor.args.float_format;
+    args.layout = preprocessor.args.layout;
+    args.quiet = preprocessor.args.quiet;
+    args.validate = preprocessor.args.validate;
+    let input_sha256 = Header::parse(&text)?
+        .log
+        .and_then(|log| log.steps.first().map(|step| step.input_sha256.clone()));
+    Ok(Replay {
+        preprocessor: replayed,
+        pipeline,
+        input_sha256,
+        warnings,
+    })
+}
+
+/// Path of the raw input of a run: `input` if given, otherwise the path
+/// recorded in the header or a file of the same name next to the processed
+/// file, in case both were moved together, which is noted in `warnings`.
+fn locate_input(
+    recorded: Option<&Path>,
+    processed: Option<&Path>,
+    input: Option<&Path>,
+    warnings: &mut Vec<String>,
+) -> Result<PathBuf> {
+    if let Some(input) = input {
+        return match input.exists() {
+            true => Ok(input.to_owned()),
+            false => Err(anyhow!("input file {} does not exist", input.display())),
+        };
+    }
+    let recorded = match recorded {
+        Some(path) if !is_stdin_path(path) => path,
+        _ => {
+            return Err(anyhow!(
+                "the run read its input from STDIN, give the input file with --input"
+            ))
+        }
+    };
+    if recorded.exists() {
+        return Ok(recorded.to_owned());
+    }
+    let beside = processed
+        .and_then(|path| path.parent())
+        .zip(recorded.file_name())
+        .map(|(dir, name)| dir.join(name))
+        .filter(|path| path.exists());
+    match beside {
+        Some(path) => {
+            warnings.push(format!(
+                "input file {} was moved, reading {} instead",
+                recorded.display(),
+                path.display()
+            ));
+            Ok(path)
+        }
+        None => Err(anyhow!(
+            "input file {} of the run does not exist anymore, give its new location with --input",
+            recorded.display()
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::locate_input;
+    use std::path::Path;
+
+    #[test]
+    fn test_locate_input() {
+        let dir = std::env::temp_dir().join(format!("replay-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let raw = dir.join("raw.csv");
+        std::fs::write(&raw, "1,2\n").unwrap();
+        let processed = dir.join("raw_processed.csv");
+        let moved = Path::new("/no/such/dir/raw.csv");
+        let mut warnings = vec![];
+        assert_eq!(
+            locate_input(Some(&raw), None, None, &mut warnings).unwrap(),
+            raw
+        );
+        // the raw file was moved together with the processed one
+        assert_eq!(
+            locate_input(Some(moved), Some(&processed), None, &mut warnings).unwrap(),
+            raw
+        );
+        assert!(warnings[0].contains("was moved"));
+        let error = locate_input(Some(moved), None, None, &mut warnings).unwrap_err();
+        assert!(error.to_string().contains("--input"));
+        assert_eq!(
+            locate_input(Some(moved), None, Some(&raw), &mut warnings).unwrap(),
+            raw
+        );
+        assert!(locate_input(None, Some(&processed), None, &mut warnings).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use crate::common::{pairs_from_shared_x, AxisUnit, Dataset, FrameMetadata};
+use anyhow::{anyhow, Context, Result};
+use ndarray::{s, Array2, ArrayView1, ArrayView2};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// First bytes of every rmbin stream, the last byte before the newline is
+/// the version of the format.
+const MAGIC: &[u8; 8] = b"RMBIN\x00\x03\n";
+
+/// Magic bytes of version 2, which has no channels.
+const MAGIC_V2: &[u8; 8] = b"RMBIN\x00\x02\n";
+
+/// Magic bytes of version 1, which has no frame weights and no channels.
+const MAGIC_V1: &[u8; 8] = b"RMBIN\x00\x01\n";
+
+/// Compact binary format to pass a dataset from one rustman invocation to
+/// the next, e.g. `rustman a.spe despike -F rmbin | rustman - baseline`.
+/// Values are stored exactly, so nothing is lost to number formatting and
+/// nothing has to be parsed. Layout (integers and floats little endian):
+///
+/// - magic bytes `RMBIN\0\x03\n`
+/// - u64 length and UTF-8 text of the comment header, as in the CSV output
+/// - u8 unit of the x-axis (0 unknown, 1 nm, 2 cm⁻¹)
+/// - u64 number of frames with metadata, then exposure, gain, time and
+///   weight of each as f64 (NaN if unknown) and u64 length and UTF-8 text of
+///   its label (empty if unknown)
+/// - u8 layout of the values, 0: x- and y-column of each frame, 1: the
+///   frames share the first x-column
+/// - u64 rows, u64 columns, then the values row by row as f64 (with shared
+///   x-axis: the x-axis first, then the y-values of the frames row by row)
+/// - u64 number of channels, then u64 length and UTF-8 text of the name and
+///   the values (one per row and frame) row by row as f64 of each
+pub fn write_rmbin(dataset: &Dataset, mut buf: impl Write) -> Result<()> {
+    let header = dataset.comment_header();
+    buf.write_all(MAGIC)
+        .with_context(|| "Unable to write to buffer.".to_string())?;
+    buf.write_all(&(header.len() as u64).to_le_bytes())?;
+    buf.write_all(header.as_bytes())?;
+    let unit: u8 = match dataset.x_unit {
+        AxisUnit::Unknown => 0,
+        AxisUnit::Wavelength => 1,
+        AxisUnit::RamanShift => 2,
+    };
+    buf.write_all(&[unit])?;
+    buf.write_all(&(dataset.frame_metadata.len() as u64).to_le_bytes())?;
+    for meta in dataset.frame_metadata.iter() {
+        for value in [meta.exposure, meta.gain, meta.time, meta.weight] {
+            buf.write_all(&value.unwrap_or(f64::NAN).to_le_bytes())?;
+        }
+        let label = meta.label.as_deref().unwrap_or_default();
+        buf.write_all(&(label.len() as u64).to_le_bytes())?;
+        buf.write_all(label.as_bytes())?;
+    }
+    let shared_x = dataset.shared_x_axis();
+    buf.write_all(&[shared_x.is_some() as u8])?;
+    buf.write_all(&(dataset.data.nrows() as u64).to_le_bytes())?;
+    buf.write_all(&(dataset.data.ncols() as u64).to_le_bytes())?;
+    match shared_x {
+        Some(x) => {
+            for value in x.iter().chain(dataset.data.slice(s![.., 1..;2]).iter()) {
+                buf.write_all(&value.to_le_bytes())?;
+            }
+        }
+        None => {
+            for value in dataset.data.iter() {
+                buf.write_all(&value.to_le_bytes())?;
+            }
+        }
+    }
+    buf.write_all(&(dataset.channels.len() as u64).to_le_bytes())?;
+    for (name, values) in dataset.channels.iter() {
+        buf.write_all(&(name.len() as u64).to_le_bytes())?;
+        buf.write_all(name.as_bytes())?;
+        for value in values.iter() {
+            buf.write_all(&value.to_le_bytes())?;
+        }
+    }
+    buf.flush()
+        .with_context(|| String::from("Unable to write dataset to buffer."))?;
+    Ok(())
+}
+
+/// true if the bytes start like an rmbin stream
+pub fn is_rmbin(bytes: &[u8]) -> bool {
+    [MAGIC, MAGIC_V2, MAGIC_V1]
+        .iter()
+        .any(|magic| bytes.starts_with(*magic))
+}
+
+/// Read a dataset written by `write_rmbin`, the comment header ends up in the
+/// previous comments (like the comments of a CSV file).
+pub fn read_rmbin(bytes: &[u8]) -> Result<Dataset> {
+    if !is_rmbin(bytes) {
+        return Err(anyhow!("input is not in the rmbin format"));
+    }
+    let has_weights = !bytes.starts_with(MAGIC_V1);
+    let has_channels = bytes.starts_with(MAGIC);
+    let mut reader = RmbinReader {
+        bytes,
+        position: MAGIC.len(),
+    };
+    let header_len = reader.length()?;
+    let previous_comments = String::from_utf8(reader.take(header_len)?.to_vec())
+        .with_context(|| "rmbin comment header is not valid UTF-8")?;
+    let x_unit = match reader.take(1)?[0] {
+        0 => AxisUnit::Unknown,
+        1 => AxisUnit::Wavelength,
+        2 => AxisUnit::RamanShift,
+        unit => return Err(anyhow!("unknown axis unit {} in rmbin input", unit)),
+    };
+    let n_meta = reader.length()?;
+    let frame_metadata = (0..n_meta)
+        .map(|_| {
+            let mut value = || reader.float().map(|v| Some(v).filter(|v| !v.is_nan()));
+            let (exposure, gain, time) = (value()?, value()?, value()?);
+            let weight = if has_weights { value()? } else { None };
+            let label_len = reader.length()?;
+            let label = String::from_utf8(reader.take(label_len)?.to_vec())
+                .with_context(|| "rmbin frame label is not valid UTF-8")?;
+            Ok(FrameMetadata {
+                exposure,
+                gain,
+                time,
+                label: Some(label).filter(|label| !label.is_empty()),
+                weight,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let shared_x = match reader.take(1)?[0] {
+        0 => false,
+        1 => true,
+        layout => return Err(anyhow!("unknown layout {} in rmbin input", layout)),
+    };
+    let rows = reader.length()?;
+    let cols = reader.length()?;
+    // with a shared x-axis, only the one x-axis is stored
+    let stored_cols = if shared_x { cols / 2 + 1 } else { cols };
+    let size = rows
+        .checked_mul(stored_cols)
+        .and_then(|n| n.checked_mul(8))
+        .ok_or_else(|| anyhow!("rmbin input is truncated"))?;
+    let values: Vec<f64> = reader
+        .take(size)?
+        .chunks_exact(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+    let mut channels = BTreeMap::new();
+    let n_channels = if has_channels { reader.length()? } else { 0 };
+    for _ in 0..n_channels {
+        let name_len = reader.length()?;
+        let name = String::from_utf8(reader.take(name_len)?.to_vec())
+            .with_context(|| "rmbin channel name is not valid UTF-8")?;
+        let values = (0..rows * (cols / 2))
+            .map(|_| reader.float())
+            .collect::<Result<Vec<_>>>()?;
+        channels.insert(name, Array2::from_shape_vec((rows, cols / 2), values)?);
+    }
+    if reader.position != bytes.len() {
+        return Err(anyhow!(
+            "rmbin input has {} unexpected bytes at the end",
+            bytes.len() - reader.position
+        ));
+    }
+    let data = if shared_x {
+        let (x, ys) = values.split_at(rows);
+        let ys = ArrayView2::from_shape((rows, cols / 2), ys)?;
+        pairs_from_shared_x(ArrayView1::from(x), ys)
+    } else {
+        Array2::from_shape_vec((rows, cols), values)?
+    };
+    Ok(Dataset {
+        data,
+        metadata: String::new(),
+        previous_comments,
+        frame_metadata,
+        x_unit,
+        channels,
+        provenance_log: vec![],
+    })
+}
+
+struct RmbinReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> RmbinReader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| anyhow!("rmbin input is truncated"))?;
+        let taken = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(taken)
+    }
+    fn length(&mut self) -> Result<usize> {
+        let bytes = self.take(8)?.try_into().unwrap();
+        usize::try_from(u64::from_le_bytes(bytes)).map_err(anyhow::Error::from)
+    }
+    fn float(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_rmbin, read_rmbin, write_rmbin};
+    use crate::common::{AxisUnit, Dataset, FrameMetadata};
+    use ndarray::s;
+
+    #[test]
+    fn test_rmbin_round_trip() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data[[1, 3]] = f64::NAN;
+        dataset.data[[2, 3]] = 0.1 + 0.2;
+        dataset.metadata = "transformation: MedianTransform\n---\n".to_owned();
+        dataset.x_unit = AxisUnit::RamanShift;
+        dataset.frame_metadata = vec![
+            FrameMetadata {
+                exposure: Some(0.5),
+                gain: None,
+                time: Some(12.0),
+                label: Some("sample".to_owned()),
+                weight: Some(0.5),
+            };
+            dataset.data.ncols() / 2
+        ];
+        let sigma = Dataset::new_test_dummy()
+            .data
+            .slice(s![.., 1..;2])
+            .mapv(f64::sqrt);
+        dataset.set_channel("sigma", sigma).unwrap();
+        let mut bytes = vec![];
+        write_rmbin(&dataset, &mut bytes).unwrap();
+        assert!(is_rmbin(&bytes));
+        let read = read_rmbin(&bytes).unwrap();
+        // values are exact, including NaN
+        assert_eq!(read.data.shape(), dataset.data.shape());
+        assert!(read.data[[1, 3]].is_nan());
+        assert_eq!(read.data[[2, 3]], 0.1 + 0.2);
+        assert_eq!(read.data.column(5), dataset.data.column(5));
+        assert_eq!(read.frame_metadata, dataset.frame_metadata);
+        assert_eq!(read.x_unit, AxisUnit::RamanShift);
+        assert_eq!(read.channels, dataset.channels);
+        // the pipeline so far is kept in the comments, as with CSV
+        assert_eq!(read.previous_comments, dataset.comment_header());
+        assert!(read
+            .previous_comments
+            .contains("# transformation: MedianTransform\n"));
+        // frames sharing their x-axis store it once
+        let mut shared = dataset.clone();
+        let x = shared.data.column(0).to_owned();
+        for mut column in shared.data.columns_mut().into_iter().step_by(2) {
+            column.assign(&x);
+        }
+        let mut shared_bytes = vec![];
+        write_rmbin(&shared, &mut shared_bytes).unwrap();
+        assert!(shared_bytes.len() < bytes.len());
+        let read = read_rmbin(&shared_bytes).unwrap();
+        assert_eq!(read.data.column(6), x);
+        assert_eq!(read.data.column(7), shared.data.column(7));
+        // truncated input and CSV are rejected
+        assert!(read_rmbin(&bytes[..bytes.len() - 1]).is_err());
+        assert!(read_rmbin(b"# comment\n1,2\n").is_err());
+    }
+}
@@ -0,0 +1,498 @@
+use crate::cli::Cli;
+use crate::registry::TRANSFORMERS;
+use anyhow::{anyhow, Result};
+use clap::CommandFactory;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde_yaml::{Mapping, Value};
+
+/// JSON Schema of the YAML header (one schema for every segment between
+/// `---`), for editors to validate and complete pipelines written by hand.
+/// The properties of the steps are found by deserializing each transformer
+/// from a tracer that records the type every field asks for, their
+/// descriptions are the help texts of the command line.
+pub fn pipeline_schema() -> Result<String> {
+    let steps = TRANSFORMERS
+        .iter()
+        .map(|transformer| (transformer.schema)())
+        .collect::<Result<Vec<_>>>()?;
+    let mut preprocessor = object_schema::<Cli>()?;
+    if let Some(Value::Mapping(properties)) = preprocessor.get_mut("properties") {
+        // the subcommands are not part of the header
+        properties.remove("command");
+        properties.insert(
+            "preprocessor".into(),
+            schema([("const", "arguments".into())]),
+        );
+    }
+    if let Some(Value::Sequence(required)) = preprocessor.get_mut("required") {
+        required.insert(0, "preprocessor".into());
+    }
+    preprocessor.insert(
+        "description".into(),
+        "Arguments of the run the header was written by.".into(),
+    );
+    let root = schema([
+        ("$schema", "http://json-schema.org/draft-07/schema#".into()),
+        ("title", "Raman CLI Tools pipeline".into()),
+        (
+            "oneOf",
+            Value::Sequence(vec![
+                schema([("$ref", "#/definitions/preprocessor".into())]),
+                schema([("$ref", "#/definitions/step".into())]),
+            ]),
+        ),
+        (
+            "definitions",
+            schema([
+                ("preprocessor", Value::Mapping(preprocessor)),
+                ("step", schema([("oneOf", Value::Sequence(steps))])),
+            ]),
+        ),
+    ]);
+    // JSON is what editors expect
+    Ok(serde_json::to_string_pretty(&root)? + "\n")
+}
+
+/// Schema of a single step, tagged by the name of its transformer.
+pub(crate) fn step_schema<T: DeserializeOwned + CommandFactory>(name: &str) -> Result<Value> {
+    let mut step = object_schema::<T>()?;
+    let Some(Value::Mapping(properties)) = step.get_mut("properties") else {
+        return Err(anyhow!("{} is not deserialized from a YAML mapping", name));
+    };
+    // nested steps are recorded as values of any type
+    if let Some(Value::Mapping(steps)) = properties.get_mut("steps") {
+        steps.insert(
+            "items".into(),
+            schema([("$ref", "#/definitions/step".into())]),
+        );
+    }
+    properties.insert("transformation".into(), schema([("const", name.into())]));
+    properties.insert(
+        "when".into(),
+        schema([
+            ("type", "string".into()),
+            (
+                "description",
+                "Only run the step if the dataset fulfils this condition, e.g. \"nframes > 1\" (properties: nframes, npoints, xmin, xmax)."
+                    .into(),
+            ),
+        ]),
+    );
+    if let Some(Value::Sequence(required)) = step.get_mut("required") {
+        required.insert(0, "transformation".into());
+    }
+    step.insert("title".into(), name.into());
+    Ok(Value::Mapping(step))
+}
+
+/// Schema of a struct deserialized from a mapping, with the descriptions of
+/// its command line arguments. A field is required if deserializing fails
+/// without it.
+fn object_schema<T: DeserializeOwned + CommandFactory>() -> Result<Mapping> {
+    let mut traced = Value::Null;
+    T::deserialize(Tracer::new(&mut traced))
+        .map_err(|err| anyhow!("could not trace fields: {}", err))?;
+    let Value::Mapping(mut object) = traced else {
+        return Err(anyhow!("not deserialized from a YAML mapping"));
+    };
+    let command = T::command();
+    if let Some(Value::Mapping(properties)) = object.get_mut("properties") {
+        let names: Vec<String> = properties
+            .keys()
+            .filter_map(|key| key.as_str().map(|k| k.to_owned()))
+            .collect();
+        let required: Vec<Value> = names
+            .iter()
+            .filter(|name| {
+                let mut ignored = Value::Null;
+                let mut tracer = Tracer::new(&mut ignored);
+                tracer.omit = Some(name);
+                T::deserialize(tracer).is_err()
+            })
+            .map(|name| name.as_str().into())
+            .collect();
+        for arg in command.get_arguments() {
+            if let (Some(Value::Mapping(property)), Some(help)) =
+                (properties.get_mut(arg.get_id()), arg.get_help())
+            {
+                property.insert("description".into(), help.into());
+            }
+        }
+        object.insert("required".into(), Value::Sequence(required));
+    }
+    if let Some(about) = command.get_about() {
+        object.insert("description".into(), about.into());
+    }
+    Ok(object)
+}
+
+fn schema<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::Mapping(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .collect(),
+    )
+}
+
+#[derive(Debug)]
+struct TraceError(String);
+
+impl std::fmt::Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+impl de::Error for TraceError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        TraceError(msg.to_string())
+    }
+}
+
+/// Deserializer that hands out placeholder values and records the schema of
+/// the type that asks for them.
+struct Tracer<'a> {
+    schema: &'a mut Value,
+    /// field of the struct that is left out, to find out if it is required
+    omit: Option<&'a str>,
+}
+
+impl<'a> Tracer<'a> {
+    fn new(schema: &'a mut Value) -> Self {
+        Tracer { schema, omit: None }
+    }
+    fn record(&mut self, kind: &str) {
+        *self.schema = schema([("type", kind.into())]);
+    }
+}
+
+macro_rules! trace_scalar {
+    ( $( $method:ident => $kind:literal, $visit:ident($($sample:expr)?) );* $(;)? ) => {
+        $(
+            fn $method<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, TraceError> {
+                self.record($kind);
+                visitor.$visit($($sample)?)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Tracer<'a> {
+    type Error = TraceError;
+
+    trace_scalar!(
+        deserialize_bool => "boolean", visit_bool(false);
+        deserialize_i8 => "integer", visit_i64(0);
+        deserialize_i16 => "integer", visit_i64(0);
+        deserialize_i32 => "integer", visit_i64(0);
+        deserialize_i64 => "integer", visit_i64(0);
+        deserialize_f32 => "number", visit_f64(0.0);
+        deserialize_f64 => "number", visit_f64(0.0);
+        deserialize_str => "string", visit_str("");
+        deserialize_string => "string", visit_str("");
+        deserialize_bytes => "string", visit_str("");
+        deserialize_byte_buf => "string", visit_str("");
+        deserialize_identifier => "string", visit_str("");
+        deserialize_unit => "null", visit_unit();
+    );
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_u64(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_u64(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_u64(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        *self.schema = schema([("type", "integer".into()), ("minimum", 0.into())]);
+        visitor.visit_u64(0)
+    }
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        *self.schema = schema([
+            ("type", "string".into()),
+            ("minLength", 1.into()),
+            ("maxLength", 1.into()),
+        ]);
+        visitor.visit_char('#')
+    }
+    /// Any value is allowed, e.g. for values converted by hand. No
+    /// placeholder fits every such conversion, so sequences of them end
+    /// before the first item.
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, TraceError> {
+        *self.schema = Value::Mapping(Mapping::new());
+        Err(TraceError("value of any type".to_owned()))
+    }
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_any(visitor)
+    }
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let mut inner = Value::Null;
+        let result = visitor.visit_some(Tracer::new(&mut inner));
+        *self.schema = schema([(
+            "oneOf",
+            Value::Sequence(vec![inner, schema([("type", "null".into())])]),
+        )]);
+        result
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.deserialize_unit(visitor)
+    }
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        visitor.visit_newtype_struct(self)
+    }
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        let mut items = Value::Null;
+        let result = visitor.visit_seq(TraceSeq {
+            items: &mut items,
+            remaining: 1,
+        });
+        *self.schema = schema([("type", "array".into()), ("items", items)]);
+        result
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let mut items = Value::Null;
+        let result = visitor.visit_seq(TraceSeq {
+            items: &mut items,
+            remaining: len,
+        });
+        *self.schema = schema([
+            ("type", "array".into()),
+            ("items", items),
+            ("minItems", len.into()),
+            ("maxItems", len.into()),
+        ]);
+        result
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.deserialize_tuple(len, visitor)
+    }
+    fn deserialize_map<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, TraceError> {
+        self.record("object");
+        visitor.visit_map(&mut TraceMap {
+            fields: vec![],
+            properties: Mapping::new(),
+            current: None,
+        })
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let fields: Vec<&'static str> = fields
+            .iter()
+            .filter(|field| Some(**field) != self.omit)
+            .copied()
+            .collect();
+        let required: Vec<Value> = fields.iter().map(|field| (*field).into()).collect();
+        let mut map = TraceMap {
+            fields: fields.into_iter().rev().collect(),
+            properties: Mapping::new(),
+            current: None,
+        };
+        let result = visitor.visit_map(&mut map);
+        *self.schema = schema([
+            ("type", "object".into()),
+            ("properties", Value::Mapping(map.properties)),
+            ("required", Value::Sequence(required)),
+        ]);
+        result
+    }
+    /// Variants carrying values are written with a YAML tag (e.g. `!values`),
+    /// which the schema cannot describe, only their names are listed.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        *self.schema = schema([(
+            "enum",
+            Value::Sequence(variants.iter().map(|v| (*v).into()).collect()),
+        )]);
+        let first = variants
+            .first()
+            .ok_or_else(|| TraceError("enum without variants".to_owned()))?;
+        visitor.visit_enum(TraceEnum(first))
+    }
+}
+
+struct TraceSeq<'a> {
+    items: &'a mut Value,
+    remaining: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for TraceSeq<'a> {
+    type Error = TraceError;
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, TraceError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        // items that cannot be made up from placeholders end the sequence,
+        // their schema is recorded anyway
+        Ok(seed.deserialize(Tracer::new(self.items)).ok())
+    }
+}
+
+struct TraceMap {
+    /// fields still to hand out, the next one last
+    fields: Vec<&'static str>,
+    properties: Mapping,
+    current: Option<&'static str>,
+}
+
+impl<'de> MapAccess<'de> for &mut TraceMap {
+    type Error = TraceError;
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TraceError> {
+        self.current = self.fields.pop();
+        match self.current {
+            None => Ok(None),
+            Some(field) => seed.deserialize(field.into_deserializer()).map(Some),
+        }
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, TraceError> {
+        let field = self
+            .current
+            .ok_or_else(|| TraceError("value without key".to_owned()))?;
+        let mut property = Value::Null;
+        let result = seed.deserialize(Tracer::new(&mut property));
+        self.properties.insert(field.into(), property);
+        result
+    }
+}
+
+/// Hands out the first variant of an enum.
+struct TraceEnum(&'static str);
+
+impl<'de> EnumAccess<'de> for TraceEnum {
+    type Error = TraceError;
+    type Variant = Self;
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), TraceError> {
+        let variant = seed.deserialize(self.0.into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for TraceEnum {
+    type Error = TraceError;
+    fn unit_variant(self) -> Result<(), TraceError> {
+        Ok(())
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, TraceError> {
+        let mut ignored = Value::Null;
+        seed.deserialize(Tracer::new(&mut ignored))
+    }
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let mut ignored = Value::Null;
+        de::Deserializer::deserialize_tuple(Tracer::new(&mut ignored), len, visitor)
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let mut ignored = Value::Null;
+        de::Deserializer::deserialize_struct(Tracer::new(&mut ignored), "", fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pipeline_schema;
+
+    #[test]
+    fn test_pipeline_schema() {
+        let schema = pipeline_schema().unwrap();
+        let parsed: serde_yaml::Value = serde_yaml::from_str(&schema).unwrap();
+        let steps = parsed["definitions"]["step"]["oneOf"]
+            .as_sequence()
+            .unwrap();
+        let offset = steps
+            .iter()
+            .find(|step| step["title"] == "OffsetTransform")
+            .unwrap();
+        let properties = &offset["properties"];
+        assert_eq!(properties["transformation"]["const"], "OffsetTransform");
+        assert_eq!(properties["offset"]["type"], "number");
+        assert_eq!(properties["percentile"]["type"], "boolean");
+        assert_eq!(
+            properties["target_frames"]["oneOf"][0]["items"]["type"],
+            "integer"
+        );
+        assert!(properties["offset"]["description"].is_string());
+        let required = offset["required"].as_sequence().unwrap();
+        assert!(required.contains(&"offset".into()));
+        assert!(!required.contains(&"target_frames".into()));
+        // fields with a default value are optional
+        let despike = steps
+            .iter()
+            .find(|step| step["title"] == "DespikeTransform")
+            .unwrap();
+        assert_eq!(
+            despike["properties"]["method"]["enum"],
+            serde_yaml::from_str::<serde_yaml::Value>("[laplace, finning, temporal]").unwrap()
+        );
+        assert!(!despike["required"]
+            .as_sequence()
+            .unwrap()
+            .contains(&"method".into()));
+        // nested steps refer to the step definition
+        let repeat = steps
+            .iter()
+            .find(|step| step["title"] == "RepeatTransform")
+            .unwrap();
+        assert_eq!(
+            repeat["properties"]["steps"]["items"]["$ref"],
+            "#/definitions/step"
+        );
+        let preprocessor = &parsed["definitions"]["preprocessor"];
+        assert_eq!(preprocessor["properties"]["comment"]["maxLength"], 1);
+        assert!(preprocessor["properties"]["command"].is_null());
+    }
+}
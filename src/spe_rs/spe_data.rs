@@ -2,7 +2,7 @@ use std::{
     collections::HashMap,
     error::Error,
     fs::File,
-    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
     os::unix::fs::FileExt,
     path::Path,
 };
@@ -19,22 +19,99 @@ pub struct SpeData {
     frame_stride_bytes: u64,
     /// Exposure time in seconds
     exposure: f64,
+    /// Byte offset in the metadata following each frame and ticks per second
+    /// of the exposure start time stamp, if time stamps were recorded
+    time_stamp_format: Option<(u64, f64)>,
+    /// Start of the exposure of each frame in seconds since the start of the
+    /// experiment
+    time_stamps: Vec<f64>,
     /// Center wavelength in nanometer
     center_wavelength: f64,
     /// Grating
     grating: String,
     /// Wavelength axis
     wavelength_axis: Vec<f64>,
-    /// Intensity data ("frames")
+    /// Intensity data ("frames"), the counts of all regions of interest one
+    /// after another
     frames: Vec<Vec<u16>>,
+    /// Regions of interest on the sensor, read out in every frame
+    regions: Vec<SpeRegion>,
     /// Filename of SPE file
     filename: String,
     /// Creation datetime of SPE file
     created: String,
 }
 
+/// Reader of the frames of an SPE file, yields the counts of each frame and
+/// the start of its exposure in seconds, if time stamps were recorded.
+pub struct SpeFrames {
+    file: BufReader<File>,
+    pos: u64,
+    /// start of the XML footer, where the data section ends
+    end: u64,
+    /// the stride holds the counts followed by the per-frame metadata
+    stride_buf: Vec<u8>,
+    counts_len: usize,
+    time_stamp_format: Option<(u64, f64)>,
+}
+
+impl SpeFrames {
+    fn read_frame(&mut self) -> Result<(Vec<u16>, Option<f64>), Box<dyn Error>> {
+        self.file.read_exact(&mut self.stride_buf)?;
+        let counts_len = self.counts_len.min(self.stride_buf.len());
+        let frame: Vec<u16> = self.stride_buf[..counts_len]
+            .windows(2)
+            .step_by(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+            .collect();
+        let mut time_stamp = None;
+        if let Some((offset, ticks_per_second)) = self.time_stamp_format {
+            let start = counts_len + offset as usize;
+            if let Some(bytes) = self.stride_buf.get(start..start + 8) {
+                let ticks = i64::from_le_bytes(bytes.try_into()?);
+                time_stamp = Some(ticks as f64 / ticks_per_second);
+            }
+        }
+        Ok((frame, time_stamp))
+    }
+}
+
+impl Iterator for SpeFrames {
+    type Item = Result<(Vec<u16>, Option<f64>), Box<dyn Error>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let stride = self.stride_buf.len() as u64;
+        if stride == 0 || self.pos + stride > self.end {
+            return None;
+        }
+        self.pos += stride;
+        Some(self.read_frame())
+    }
+}
+
+/// A region of interest on the sensor, binned to a single row.
+#[derive(Debug)]
+pub struct SpeRegion {
+    /// Number of (binned) pixels
+    width: usize,
+    /// Wavelength of each pixel
+    wavelength_axis: Vec<f64>,
+}
+
 impl SpeData {
     pub fn from_path(filepath: &Path) -> Result<SpeData, Box<dyn Error + 'static>> {
+        let (mut data, frames) = SpeData::open(filepath)?;
+        for frame in frames {
+            let (counts, time_stamp) = frame?;
+            data.frames.push(counts);
+            data.time_stamps.extend(time_stamp);
+        }
+        Ok(data)
+    }
+
+    /// Read the XML footer of the file, but none of the frames, which are
+    /// read one at a time from the returned reader, e.g. to process files
+    /// larger than the memory.
+    pub fn open(filepath: &Path) -> Result<(SpeData, SpeFrames), Box<dyn Error + 'static>> {
         let mut file = File::open(filepath)?;
 
         // Read XML footer
@@ -50,24 +127,19 @@ impl SpeData {
         // Parse footer bytes into XML
         let xml_document = XMLTag::from_str(&xml_footer)?;
         let xml_index = xml_document.build_index();
-        let mut data = SpeData::empty_from_xml_index(xml_index)?;
+        let data = SpeData::empty_from_xml_index(xml_index)?;
 
-        // Read data section (assumes full vertical binning, for now)
+        // Data section follows the header (assumes full vertical binning, for now)
         file.seek(SeekFrom::Start(4100))?;
-        let mut pos = 4100;
-        let mut counts_buf = vec![0u8; data.frame_size_bytes as usize];
-        while pos + data.frame_stride_bytes <= xml_offset {
-            file.read_exact(&mut counts_buf)?;
-            let frame: Vec<u16> = counts_buf
-                .windows(2)
-                .step_by(2)
-                .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
-                .collect();
-            data.frames.push(frame);
-            pos += data.frame_stride_bytes;
-        }
-
-        Ok(data)
+        let frames = SpeFrames {
+            file: BufReader::new(file),
+            pos: 4100,
+            end: xml_offset,
+            stride_buf: vec![0u8; data.frame_stride_bytes as usize],
+            counts_len: data.frame_size_bytes as usize,
+            time_stamp_format: data.time_stamp_format,
+        };
+        Ok((data, frames))
     }
 
     pub fn write_csv<W: Write>(&self, wrt: &mut W) -> Result<(), Box<dyn Error>> {
@@ -92,6 +164,13 @@ impl SpeData {
         writeln!(wrt, "# center wavelength = {}", self.center_wavelength)?;
         writeln!(wrt, "# exposure time = {}", self.exposure)?;
         writeln!(wrt, "# frame count = {}", self.frame_count)?;
+        if self.regions.len() > 1 {
+            writeln!(wrt, "# regions = {}", self.regions.len())?;
+        }
+        if let Some(time_stamps) = self.get_time_stamps() {
+            let times: Vec<String> = time_stamps.iter().map(|t| t.to_string()).collect();
+            writeln!(wrt, "# frame times = {}", times.join(","))?;
+        }
         wrt.flush()?;
 
         Ok(String::from_utf8(wrt.into_inner()?)?)
@@ -101,10 +180,43 @@ impl SpeData {
         &self.wavelength_axis
     }
 
+    /// Number of frames measured, as stated in the XML footer
+    pub fn get_frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
     pub fn get_frames(&self) -> &[Vec<u16>] {
         &self.frames
     }
 
+    /// Number of regions of interest read out in every frame
+    pub fn get_region_count(&self) -> usize {
+        self.regions.len().max(1)
+    }
+
+    /// Wavelength axis and counts of each frame of a region of interest
+    /// (counting from 0), for files with more than one region
+    pub fn get_region(&self, region: usize) -> Option<(&[f64], Vec<&[u16]>)> {
+        let start: usize = self.regions.get(..region)?.iter().map(|r| r.width).sum();
+        let region = self.regions.get(region)?;
+        let frames = self
+            .frames
+            .iter()
+            .map(|frame| frame.get(start..start + region.width).unwrap_or_default())
+            .collect();
+        Some((&region.wavelength_axis, frames))
+    }
+
+    pub fn get_exposure(&self) -> f64 {
+        self.exposure
+    }
+
+    /// Start of the exposure of each frame in seconds, if recorded for all frames
+    pub fn get_time_stamps(&self) -> Option<&[f64]> {
+        (!self.frames.is_empty() && self.time_stamps.len() == self.frames.len())
+            .then_some(&self.time_stamps)
+    }
+
     fn empty_from_xml_index(index: HashMap<String, &XMLTag>) -> Result<Self, Box<dyn Error>> {
         let center_wavelength = index
             .get("SpeFormat/DataHistories/DataHistory/Origin/Experiment/Devices/Spectrometers/Spectrometer/Grating/CenterWavelength")
@@ -121,16 +233,31 @@ impl SpeData {
             .get("count")
             .ok_or("frame count not found in XML footer")?
             .parse::<u64>()?;
-        let frame_size_bytes = index
+        let region_block = index
             .get("SpeFormat/DataFormat/DataBlock/DataBlock")
-            .ok_or("region description not found in XML footer")?
+            .ok_or("region description not found in XML footer")?;
+        let region_blocks: Vec<&XMLTag> = index
+            .get("SpeFormat/DataFormat/DataBlock")
+            .map(|frame_block| {
+                frame_block
+                    .children
+                    .iter()
+                    .filter(|tag| tag.name == "DataBlock")
+                    .collect()
+            })
+            .unwrap_or_default();
+        // with several regions, only the frame block knows the size of all
+        let frame_block = index
+            .get("SpeFormat/DataFormat/DataBlock")
+            .filter(|_| region_blocks.len() > 1);
+        let frame_size_bytes = frame_block
+            .unwrap_or(region_block)
             .parameters
             .get("size")
             .ok_or("region size not found in XML footer")?
             .parse::<u64>()?;
-        let frame_stride_bytes = index
-            .get("SpeFormat/DataFormat/DataBlock/DataBlock")
-            .ok_or("stride length not found in XML footer")?
+        let frame_stride_bytes = frame_block
+            .unwrap_or(region_block)
             .parameters
             .get("stride")
             .ok_or("stride length not found in XML footer")?
@@ -140,10 +267,34 @@ impl SpeData {
             .ok_or("exposure time not found in XML footer")?
             .contents
             .parse::<u64>()? as f64 / 1000.0;
+        let time_stamp_format = index
+            .get("SpeFormat/MetaFormat/MetaBlock")
+            .and_then(|tag| exposure_started_format(tag));
         let wavelength_axis = index
             .get("SpeFormat/Calibrations/WavelengthMapping/Wavelength")
             .and_then(|&tag| convert_wavelength_string(&tag.contents).ok())
             .ok_or("Unable to extract wavelength axis")?;
+        let regions = if region_blocks.len() > 1 {
+            let sensor_mappings: Vec<&XMLTag> = index
+                .get("SpeFormat/Calibrations")
+                .map(|calibrations| {
+                    calibrations
+                        .children
+                        .iter()
+                        .filter(|tag| tag.name == "SensorMapping")
+                        .collect()
+                })
+                .unwrap_or_default();
+            region_blocks
+                .iter()
+                .enumerate()
+                .map(|(i, block)| {
+                    region_from_xml(block, &wavelength_axis, sensor_mappings.get(i).copied())
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            vec![]
+        };
         let filename = index
             .get("SpeFormat/DataHistories/DataHistory/Origin/Experiment/Devices/Cameras/Camera/Experiment/FileNameGeneration/BaseFileName")
             .ok_or("filename not found in XML footer")?
@@ -161,16 +312,84 @@ impl SpeData {
             center_wavelength,
             frame_count,
             exposure,
+            time_stamp_format,
+            time_stamps: Vec::new(),
             frame_size_bytes,
             frame_stride_bytes,
             wavelength_axis,
             frames: Vec::new(),
+            regions,
             filename,
             created,
         })
     }
 }
 
+/// Size and wavelength axis of a region of interest. The wavelength axis of
+/// the file either fits the region or covers the sensor, then the sensor
+/// mapping tells which (binned) pixels the region holds.
+fn region_from_xml(
+    block: &XMLTag,
+    wavelength: &[f64],
+    sensor_mapping: Option<&XMLTag>,
+) -> Result<SpeRegion, Box<dyn Error>> {
+    let parameter = |tag: &XMLTag, name: &str| -> Result<usize, Box<dyn Error>> {
+        Ok(tag
+            .parameters
+            .get(name)
+            .ok_or(format!("{} of region not found in XML footer", name))?
+            .parse::<usize>()?)
+    };
+    let width = parameter(block, "width")?;
+    if parameter(block, "height").unwrap_or(1) != 1 {
+        return Err("regions of interest must be binned to a single row".into());
+    }
+    if wavelength.len() == width {
+        return Ok(SpeRegion {
+            width,
+            wavelength_axis: wavelength.to_vec(),
+        });
+    }
+    let mapping = sensor_mapping.ok_or("wavelength axis does not fit the region of interest")?;
+    let x = parameter(mapping, "x")?;
+    let binning = parameter(mapping, "xBinning").unwrap_or(1).max(1);
+    let wavelength_axis = (0..width)
+        .map(|k| {
+            let start = x + k * binning;
+            // binned pixels see the mean wavelength of the sensor pixels
+            wavelength
+                .get(start..start + binning)
+                .map(|pixels| pixels.iter().sum::<f64>() / binning as f64)
+                .ok_or_else(|| "region of interest is outside of the wavelength axis".into())
+        })
+        .collect::<Result<Vec<f64>, Box<dyn Error>>>()?;
+    Ok(SpeRegion {
+        width,
+        wavelength_axis,
+    })
+}
+
+/// Byte offset and ticks per second of the exposure start time stamp in the
+/// metadata of each frame. The metadata items are stored in the order they
+/// are listed in the meta block, each `bitDepth` bits wide.
+fn exposure_started_format(meta_block: &XMLTag) -> Option<(u64, f64)> {
+    let mut offset = 0;
+    let mut first_time_stamp = None;
+    for item in meta_block.children.iter() {
+        let bit_depth = item.parameters.get("bitDepth")?.parse::<u64>().ok()?;
+        if item.name == "TimeStamp" && bit_depth == 64 {
+            let resolution = item.parameters.get("resolution")?.parse::<f64>().ok()?;
+            let format = (offset, resolution);
+            if item.parameters.get("event").map(|e| e.as_str()) == Some("ExposureStarted") {
+                return Some(format);
+            }
+            first_time_stamp = first_time_stamp.or(Some(format));
+        }
+        offset += bit_depth / 8;
+    }
+    first_time_stamp
+}
+
 fn convert_wavelength_string(raw: &str) -> Result<Vec<f64>, ()> {
     let mut result: Vec<f64> = Vec::new();
     for substr in raw.split(",") {
@@ -178,3 +397,76 @@ fn convert_wavelength_string(raw: &str) -> Result<Vec<f64>, ()> {
     }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::SpeData;
+    use crate::common::Dataset;
+
+    /// SPE file with two frames of two regions of interest, the first binned
+    /// by two pixels, the second holding the last three pixels of the sensor.
+    fn write_two_region_file(path: &std::path::Path) {
+        let counts: [u16; 12] = [1, 2, 3, 10, 20, 30, 4, 5, 6, 40, 50, 60];
+        let mut bytes = vec![0u8; 4100];
+        for count in counts {
+            bytes.extend(count.to_le_bytes());
+        }
+        let xml_offset = bytes.len() as u64;
+        bytes[678..686].copy_from_slice(&xml_offset.to_le_bytes());
+        let camera = "Experiment><Devices><Cameras><Camera>\
+            <ShutterTiming><ExposureTime>500</ExposureTime></ShutterTiming>\
+            <Experiment><FileNameGeneration><BaseFileName>rois</BaseFileName>\
+            </FileNameGeneration></Experiment></Camera></Cameras>\
+            <Spectrometers><Spectrometer><Grating><CenterWavelength>550</CenterWavelength>\
+            <Selected>600g/mm</Selected></Grating></Spectrometer></Spectrometers>\
+            </Devices></Experiment";
+        let xml = format!(
+            "<SpeFormat version=\"3.0\"><DataFormat>\
+            <DataBlock type=\"Frame\" count=\"2\" size=\"12\" stride=\"12\">\
+            <DataBlock type=\"Region\" width=\"3\" height=\"1\" size=\"6\" stride=\"6\"/>\
+            <DataBlock type=\"Region\" width=\"3\" height=\"1\" size=\"6\" stride=\"6\"/>\
+            </DataBlock></DataFormat>\
+            <Calibrations><WavelengthMapping><Wavelength>500,501,502,503,504,505</Wavelength>\
+            </WavelengthMapping>\
+            <SensorMapping id=\"1\" x=\"0\" y=\"0\" width=\"6\" height=\"10\" xBinning=\"2\" yBinning=\"10\"/>\
+            <SensorMapping id=\"2\" x=\"3\" y=\"20\" width=\"3\" height=\"10\" xBinning=\"1\" yBinning=\"10\"/>\
+            </Calibrations><DataHistories><DataHistory><Origin created=\"today\"><{}>\
+            </Origin></DataHistory></DataHistories></SpeFormat>",
+            camera
+        );
+        bytes.extend(xml.as_bytes());
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_regions() {
+        let path = std::env::temp_dir().join(format!("rois-{}.spe", std::process::id()));
+        write_two_region_file(&path);
+        let spe = SpeData::from_path(&path).unwrap();
+        assert_eq!(spe.get_region_count(), 2);
+        let (wavelength, frames) = spe.get_region(0).unwrap();
+        assert_eq!(wavelength, [500.5, 502.5, 504.5]);
+        assert_eq!(frames, vec![&[1, 2, 3], &[4, 5, 6]]);
+        let (wavelength, frames) = spe.get_region(1).unwrap();
+        assert_eq!(wavelength, [503.0, 504.0, 505.0]);
+        assert_eq!(frames[1], &[40, 50, 60]);
+        assert!(spe.get_region(2).is_none());
+        // the frames are read one at a time, the same as all at once
+        let (header, frames) = SpeData::open(&path).unwrap();
+        assert!(header.get_frames().is_empty());
+        assert_eq!(header.get_frame_count(), 2);
+        let frames: Vec<Vec<u16>> = frames.map(|frame| frame.unwrap().0).collect();
+        assert_eq!(frames, spe.get_frames());
+        // one frame of the dataset per frame and region
+        let dataset = Dataset::from_spe(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(dataset.data.shape(), &[3, 8]);
+        assert_eq!(dataset.data.column(3), ndarray::array![10.0, 20.0, 30.0]);
+        assert_eq!(dataset.data.column(6), ndarray::array![503.0, 504.0, 505.0]);
+        assert_eq!(
+            dataset.frame_metadata[1].label.as_deref(),
+            Some("frame 1 ROI 2")
+        );
+        assert!(dataset.previous_comments.contains("# regions = 2\n"));
+    }
+}
@@ -105,7 +105,7 @@ impl<'a> XMLTag {
             .map(|ch| (ch, self.name.clone()))
             .collect();
         while let Some((tag_ref, base_name)) = stack.pop() {
-            let key = base_name + "/" + &tag_ref.name;
+            let key = base_name + "/" + tag_ref.name.as_str();
             for ch in tag_ref.children.iter() {
                 stack.push((ch, key.clone()));
             }
@@ -0,0 +1,146 @@
+use crate::common::{AxisUnit, Dataset, FrameMetadata, Pipeline};
+use crate::spe_rs::SpeData;
+use crate::transformations::PipelineContext;
+use anyhow::{anyhow, Result};
+use ndarray::{Array1, Array2, Axis};
+use std::path::Path;
+
+/// Why the pipeline cannot run on streamed frames, None if it can: it must
+/// end in a step that turns every frame into a row of a table (integrate,
+/// kinetics) and all steps before must transform each frame on its own.
+pub fn streaming_problem(pipeline: &Pipeline) -> Option<String> {
+    // a single frame of two points to ask the last step for its row
+    let frame = Dataset {
+        data: Array2::zeros((2, 2)),
+        frame_metadata: vec![FrameMetadata::default()],
+        ..Default::default()
+    };
+    let Some((last, steps)) = pipeline.transformations.split_last() else {
+        return Some("the pipeline is empty".to_owned());
+    };
+    if last.frame_row(&frame, 0).is_none() {
+        return Some(format!(
+            "the pipeline must end in integrate, kinetics or peak-pick, not in\n{}",
+            last.config_to_string().unwrap_or_default()
+        ));
+    }
+    steps
+        .iter()
+        .find(|step| !step.is_frame_local())
+        .map(|step| {
+            format!(
+                "this step needs all frames at once:\n{}",
+                step.config_to_string().unwrap_or_default()
+            )
+        })
+}
+
+/// Run the pipeline on the frames of an SPE file one at a time, keeping only
+/// the current frame and the rows of the result in memory, e.g. to extract
+/// band areas vs. time from files larger than the memory. `metadata` is the
+/// metadata of the preprocessor the result starts with.
+pub fn stream_spe(
+    filepath: &Path,
+    pipeline: &mut Pipeline,
+    context: &PipelineContext,
+    metadata: String,
+) -> Result<Dataset> {
+    if let Some(problem) = streaming_problem(pipeline) {
+        return Err(anyhow!("cannot stream the frames, {}", problem));
+    }
+    let (spe, frames) =
+        SpeData::open(filepath).map_err(|e| anyhow!("Could not read SPE file: {e}"))?;
+    if spe.get_region_count() > 1 {
+        return Err(anyhow!(
+            "cannot stream the frames of SPE files with several regions of interest"
+        ));
+    }
+    let previous_comments = spe
+        .get_meta_data_string()
+        .map_err(|e| anyhow!("Could not read SPE file: {e}"))?;
+    let wavelength = Array1::from_vec(spe.get_wavelength().to_vec());
+    let frame_count = spe.get_frame_count().max(1) as f32;
+    let (last, steps) = pipeline.transformations.split_last_mut().unwrap();
+    let mut rows: Vec<Array1<f64>> = vec![];
+    let mut steps_metadata = None;
+    // each frame passes the steps on its own, the hashes of single frames
+    // would not identify the input of the run
+    let frame_context = PipelineContext {
+        skip_provenance: true,
+        ..context.clone()
+    };
+    for (i, frame) in frames.enumerate() {
+        context.progress.check()?;
+        let (counts, time) = frame.map_err(|e| anyhow!("Could not read SPE file: {e}"))?;
+        let counts = Array1::from_iter(counts.iter().map(|c| *c as f64));
+        let mut dataset = Dataset {
+            data: ndarray::stack(Axis(1), &[wavelength.view(), counts.view()])?,
+            metadata: String::new(),
+            previous_comments: String::new(),
+            frame_metadata: vec![FrameMetadata {
+                exposure: Some(spe.get_exposure()),
+                gain: None,
+                time,
+                label: None,
+                weight: None,
+            }],
+            x_unit: AxisUnit::Wavelength,
+            channels: Default::default(),
+            provenance_log: vec![],
+        };
+        for step in steps.iter_mut() {
+            step.apply(&mut dataset, &frame_context)?;
+        }
+        rows.push(last.frame_row(&dataset, i).unwrap()?);
+        // the steps record the same metadata for every frame
+        steps_metadata.get_or_insert(dataset.metadata);
+        context.progress.report(i as f32 / frame_count);
+    }
+    let views: Vec<_> = rows.iter().map(|row| row.view()).collect();
+    let data = if views.is_empty() {
+        Array2::zeros((0, 0))
+    } else {
+        ndarray::stack(Axis(0), &views)?
+    };
+    let mut result = Dataset {
+        data,
+        metadata: metadata + steps_metadata.unwrap_or_default().as_str(),
+        previous_comments,
+        frame_metadata: vec![],
+        x_unit: AxisUnit::Unknown,
+        channels: Default::default(),
+        provenance_log: vec![],
+    };
+    last.write_metadata_yaml(&mut result)?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::streaming_problem;
+    use crate::common::{Dataset, FrameMetadata, Pipeline};
+
+    #[test]
+    fn test_streaming_problem() {
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let pipeline = Pipeline::from_cli_args(vec![args("offset 1"), args("integrate 1,2")]);
+        assert_eq!(streaming_problem(&pipeline), None);
+        // the median needs all frames
+        let pipeline = Pipeline::from_cli_args(vec![args("median"), args("integrate 1,2")]);
+        assert!(streaming_problem(&pipeline).is_some());
+        let pipeline = Pipeline::from_cli_args(vec![args("offset 1")]);
+        assert!(streaming_problem(&pipeline).is_some());
+        // the rows of the streamed frames are the rows of the whole dataset
+        let mut pipeline = Pipeline::from_cli_args(vec![args("kinetics 21,41 --interval 2")]);
+        let mut dataset = Dataset::new_test_dummy();
+        let step = &pipeline.transformations[0];
+        let frame = Dataset {
+            data: dataset.data.slice(ndarray::s![.., 2..4]).to_owned(),
+            frame_metadata: vec![FrameMetadata::default()],
+            ..Default::default()
+        };
+        let row = step.frame_row(&frame, 1).unwrap().unwrap();
+        pipeline.transformations[0].transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.row(1), row);
+    }
+}
@@ -1,26 +1,260 @@
 pub mod align;
 pub mod append;
+pub mod arithmetic;
 pub mod average;
 pub mod baseline;
+pub mod bose_einstein;
 pub mod calibration;
+pub mod clip;
+pub mod coadd_regions;
+pub mod condition;
 pub mod count_conversion;
+pub mod deconvolve;
+pub mod derived;
 pub mod despike;
 pub mod draw_baseline;
+pub mod drop_nan;
 pub mod finning;
 pub mod integrate;
+pub mod kinetics;
+pub mod kmeans;
 pub mod mask_pixels;
+pub mod mcr;
+pub mod median;
 pub mod normalize;
 pub mod offset;
+pub mod offset_stack;
+pub mod peak_pick;
+pub mod plugin;
+pub mod reject_outliers;
+pub mod repeat;
 pub mod reshape;
+pub mod response_correction;
+pub mod scale;
+pub mod script;
 pub mod select;
 pub mod shift;
+pub mod similarity;
 pub mod subtract;
+pub mod time_axis;
+pub mod weight;
+pub mod x_shift;
 
-use crate::common::Dataset;
-use anyhow::Result;
+use crate::common::{Dataset, DatasetShape};
+use crate::config::UserConfig;
+use crate::metadata::{data_sha256, StepRecord};
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use ndarray::Array1;
+use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
 
+/// Handle shared between a running transformation and its caller, through
+/// which the transformation reports how far it got and learns whether it
+/// should stop early.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// fraction of the work done, stored as the bits of an `f32`
+    fraction: Arc<AtomicU32>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Progress {
+    /// Report the fraction (0 to 1) of the work done.
+    pub fn report(&self, fraction: f32) {
+        self.fraction
+            .store(fraction.clamp(0.0, 1.0).to_bits(), Ordering::Relaxed);
+    }
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.fraction.load(Ordering::Relaxed))
+    }
+    /// Ask the transformation to stop at the next opportunity.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+    /// Fail if the transformation was cancelled, to be called with `?`
+    /// inside long running loops.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(anyhow!("transformation was cancelled"));
+        }
+        Ok(())
+    }
+}
+
+/// Collects warnings of a pipeline run, which does not stop because of them.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    messages: Arc<Mutex<Vec<String>>>,
+}
+
+impl Diagnostics {
+    pub fn warn(&self, message: impl Into<String>) {
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.push(message.into());
+        }
+    }
+    /// Remove and return all collected warnings.
+    pub fn take(&self) -> Vec<String> {
+        self.messages
+            .lock()
+            .map(|mut messages| std::mem::take(&mut *messages))
+            .unwrap_or_default()
+    }
+}
+
+/// Properties of the spectrometer and detector the data was measured with,
+/// read from a YAML file, e.g.
+///
+/// ```yaml
+/// gain: 2.0
+/// read_noise: 4.5
+/// ```
+///
+/// The profiles in the `profiles` directory are built into the binary and
+/// can be used by name, so no files need to be copied to the instrument PCs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InstrumentProfile {
+    /// detector gain in electrons per count
+    #[serde(default)]
+    pub gain: Option<f64>,
+    /// detector read noise in electrons
+    #[serde(default)]
+    pub read_noise: Option<f64>,
+}
+
+/// Names and YAML of the built-in instrument profiles.
+const BUILTIN_PROFILES: [(&str, &str); 1] = [("ideal", include_str!("../profiles/ideal.yaml"))];
+
+impl InstrumentProfile {
+    /// Read the profile from a YAML file, or take the built-in profile of
+    /// that name if there is no such file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            if let Some((name, yaml)) = BUILTIN_PROFILES
+                .iter()
+                .find(|(name, _)| path.as_os_str() == *name)
+            {
+                return serde_yaml::from_str(yaml).with_context(|| {
+                    format!("Unable to parse built-in instrument profile {:?}", name)
+                });
+            }
+        }
+        let yaml = std::fs::read_to_string(path).with_context(|| {
+            let builtin: Vec<&str> = BUILTIN_PROFILES.iter().map(|(name, _)| *name).collect();
+            format!(
+                "Unable to read instrument profile {:?} (built-in profiles: {})",
+                path,
+                builtin.join(", ")
+            )
+        })?;
+        serde_yaml::from_str(&yaml)
+            .with_context(|| format!("Unable to parse instrument profile {:?}", path))
+    }
+}
+
+/// State shared by all transformations of a pipeline run.
+#[derive(Debug, Clone, Default)]
+pub struct PipelineContext {
+    /// file the input data was read from, if any
+    pub input_path: Option<PathBuf>,
+    pub instrument: InstrumentProfile,
+    /// seed of all random numbers drawn in the run, recorded in the metadata
+    /// with the preprocessor arguments
+    pub seed: u64,
+    /// progress of the running transformation, also used to cancel the run
+    pub progress: Progress,
+    pub diagnostics: Diagnostics,
+    /// only check the parameters of the pipeline instead of running it
+    pub dry_run: bool,
+    /// the result is not written to a file (e.g. a preview or a streamed
+    /// frame), so the steps are not added to the provenance log and the
+    /// hashes of their input are not computed
+    pub skip_provenance: bool,
+}
+
+impl PipelineContext {
+    /// Path of a file named by a transformation, relative paths are looked up
+    /// next to the input file first, so pipelines stored in the header of an
+    /// output file work from any working directory.
+    pub fn resolve_path(&self, path: &Path) -> PathBuf {
+        if path.is_relative() {
+            if let Some(dir) = self.input_path.as_ref().and_then(|p| p.parent()) {
+                let candidate = dir.join(path);
+                if candidate.exists() {
+                    return candidate;
+                }
+            }
+        }
+        path.to_owned()
+    }
+    /// Random number generator for a transformation (e.g. the initial means
+    /// of kmeans), the same seed gives the same numbers on every platform.
+    pub fn rng(&self) -> ChaCha8Rng {
+        ChaCha8Rng::seed_from_u64(self.seed)
+    }
+}
+
+/// A step of the pipeline. `transform` and `transform_with_context` call
+/// each other by default, so a transformation implements exactly one of
+/// them (implementing neither recurses forever).
 pub trait Transformer: std::fmt::Debug {
-    fn transform(&mut self, dataset: &mut Dataset) -> Result<()>;
+    /// Transform the dataset outside of a pipeline run, with the default
+    /// context, whose warnings are dropped.
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        self.transform_with_context(dataset, &PipelineContext::default())
+    }
+    /// Like `transform`, but with access to the state of the pipeline run,
+    /// e.g. to report progress and stop early if cancelled. Transformations
+    /// that finish quickly and draw no random numbers do not need to
+    /// implement this.
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        _context: &PipelineContext,
+    ) -> Result<()> {
+        self.transform(dataset)
+    }
+    /// Check the parameters against the shape of the dataset the
+    /// transformation would receive, without transforming anything. Returns
+    /// the problems found and updates `shape` to the shape of the result.
+    /// Transformations that keep the shape and take no frame numbers or
+    /// x-values do not need to implement this.
+    fn validate(&self, _shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        vec![]
+    }
+    /// Whether the step transforms every frame on its own, independent of
+    /// the other frames and of the position of the frame, so the frames of
+    /// a huge file can be streamed through it one at a time.
+    fn is_frame_local(&self) -> bool {
+        false
+    }
+    /// Whether the step refers to frames or pixels by their number (e.g. a
+    /// reference frame or masked pixels), so it would act on the wrong data
+    /// in a copy of the dataset holding only some of the frames or pixels.
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        false
+    }
+    /// Fill parameters that were not given on the command line with the
+    /// defaults of the config file, called after the arguments are parsed.
+    fn use_config_defaults(&mut self, _config: &UserConfig) {}
+    /// Row of the result for a single frame (`index` counting from 0), for
+    /// steps that turn every frame into one row of a table, e.g. of
+    /// integrals, so a streamed file ends in a small table. None if the step
+    /// needs all frames at once.
+    fn frame_row(&self, _frame: &Dataset, _index: usize) -> Option<Result<Array1<f64>>> {
+        None
+    }
     fn config_to_string(&self) -> Result<String>;
     fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
         let metadata = self.config_to_string()?;
@@ -28,9 +262,25 @@ pub trait Transformer: std::fmt::Debug {
         dataset.metadata += "---\n";
         Ok(())
     }
-    fn apply(&mut self, dataset: &mut Dataset) -> Result<()> {
-        self.transform(dataset)?;
+    fn apply(&mut self, dataset: &mut Dataset, context: &PipelineContext) -> Result<()> {
+        let started = Utc::now();
+        let input_sha256 = (!context.skip_provenance).then(|| data_sha256(dataset.data.view()));
+        self.transform_with_context(dataset, context)?;
+        // channels of a reshaped dataset can not be assigned to the rows and
+        // frames anymore
+        for name in dataset.drop_mismatched_channels() {
+            context.diagnostics.warn(format!(
+                "channel \"{}\" was dropped, the step changed the number of rows or frames",
+                name
+            ));
+        }
         self.write_metadata_yaml(dataset)?;
+        if let Some(input_sha256) = input_sha256 {
+            // the step documents and the records are added in step
+            let step = dataset.provenance_log.len() + 1;
+            let record = StepRecord::new(started, input_sha256, step);
+            dataset.provenance_log.push(record);
+        }
         Ok(())
     }
 }
@@ -1,12 +1,14 @@
-use crate::common::Dataset;
-use crate::transformations::Transformer;
-use crate::utils::linear_resample_array;
+use crate::common::{Dataset, DatasetShape, Pair};
+use crate::transformations::{PipelineContext, Transformer};
+use crate::utils::{linear_resample_array, Interpolation};
 use anyhow::{anyhow, Result};
 use argmin::core::{CostFunction, Executor};
 use argmin::solver::brent::BrentOpt;
+use argmin::solver::neldermead::NelderMead;
 use clap::Parser;
-use ndarray::{s, Array1, ArrayBase, Data, Ix1};
+use ndarray::{Array1, ArrayBase, Data, Ix1};
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
 
 #[derive(Debug, Parser, Serialize, Deserialize)]
 #[serde(tag = "transformation")]
@@ -18,38 +20,194 @@ pub struct AlignTransform {
         help = "Maximum absolut value of cost function, adapt only if alignment fails."
     )]
     pub cost_max_abs: f64,
+    #[clap(
+        long,
+        default_value = "linear",
+        help = "Interpolation of the shifted frames onto the x-axis of the reference frame: linear or pchip (monotone cubic, keeps sharp bands on coarse grids)."
+    )]
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    #[clap(
+        short,
+        long,
+        default_value_t = 1,
+        help = "Frame the other frames are aligned to."
+    )]
+    #[serde(default = "default_reference")]
+    pub reference: usize,
+    #[clap(
+        short,
+        long,
+        help = "Compare the frames only inside this x-range, separated by comma, e.g. around a sharp band, which makes the alignment of noisy frames more robust."
+    )]
+    #[serde(default)]
+    pub window: Option<Pair<f64>>,
+    #[clap(
+        short,
+        long,
+        help = "Also fit a linear stretch of the x-axis around the center of the window, up to this relative amount (e.g. 0.01), for frames that differ in dispersion as well as in position."
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stretch: Option<f64>,
+    #[clap(
+        short,
+        long,
+        help = "Shift of a frame given by hand as <frame>:<shift>, e.g. 3:-0.5, used instead of the optimized one for frames whose alignment fails."
+    )]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub manual_shifts: Vec<FrameShift>,
+    /// shift applied to each frame in the last run, shown in the GUI
+    #[serde(skip)]
+    #[clap(skip)]
+    pub shifts: Vec<f64>,
+}
+
+fn default_reference() -> usize {
+    1
+}
+
+/// Shift of the x-axis of a frame given by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FrameShift {
+    pub frame: usize,
+    pub shift: f64,
+}
+
+impl std::str::FromStr for FrameShift {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow!("invalid shift \"{}\", use <frame>:<shift>, e.g. 3:-0.5", s);
+        let (frame, shift) = s.split_once(':').ok_or_else(invalid)?;
+        Ok(FrameShift {
+            frame: frame.trim().parse().map_err(|_| invalid())?,
+            shift: shift.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl AlignTransform {
+    /// Indices of the points of the reference grid inside the window.
+    fn window_indices(&self, grid: &Array1<f64>) -> Result<Range<usize>> {
+        let Some(Pair { a, b }) = self.window else {
+            return Ok(0..grid.len());
+        };
+        let (left, right) = if a < b { (a, b) } else { (b, a) };
+        let inside: Vec<usize> = (0..grid.len())
+            .filter(|i| (left..=right).contains(&grid[*i]))
+            .collect();
+        match (inside.first(), inside.last()) {
+            (Some(first), Some(last)) if last > first => Ok(*first..last + 1),
+            _ => Err(anyhow!(
+                "alignment window {},{} holds less than two points of the reference frame",
+                a,
+                b
+            )),
+        }
+    }
+    /// Shift and stretch of the x-axis of a frame that matches it best with
+    /// the reference, the stretch is 0.0 if it is not fitted.
+    fn optimize<S, T>(&self, problem: OptAlignment<S, T>) -> Result<(f64, f64)>
+    where
+        S: Data<Elem = f64>,
+        T: Data<Elem = f64>,
+    {
+        let max_shift = f64::abs(self.cost_max_abs);
+        let Some(max_stretch) = self.stretch.map(f64::abs) else {
+            let solver = BrentOpt::new(-max_shift, max_shift);
+            let res = Executor::new(problem, solver)
+                .configure(|state| state.param(0.0))
+                .run()?;
+            return res.state().best_param.map(|dx| (dx, 0.0)).ok_or_else(|| {
+                anyhow!("frame alignment failed, optimization did not return optimized parameters")
+            });
+        };
+        // start from a simplex spanning half of the allowed shift and stretch
+        let solver = NelderMead::new(vec![
+            vec![0.0, 0.0],
+            vec![max_shift / 2.0, 0.0],
+            vec![0.0, max_stretch / 2.0],
+        ])
+        .with_sd_tolerance(1e-10)?;
+        let problem = OptStretchAlignment {
+            problem,
+            max_shift,
+            max_stretch,
+        };
+        let res = Executor::new(problem, solver)
+            .configure(|state| state.max_iters(500))
+            .run()?;
+        match res.state().best_param.as_deref() {
+            Some([dx, stretch]) => Ok((*dx, *stretch)),
+            _ => Err(anyhow!(
+                "frame alignment failed, optimization did not return optimized parameters"
+            )),
+        }
+    }
 }
 
 impl Transformer for AlignTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        true
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems: Vec<String> = shape.check_frame(self.reference).into_iter().collect();
+        if let Some(Pair { a, b }) = self.window {
+            problems.extend([shape.check_x(a), shape.check_x(b)].into_iter().flatten());
+        }
+        if self
+            .stretch
+            .is_some_and(|stretch| !(0.0..1.0).contains(&stretch.abs()))
+        {
+            problems.push("stretch must be smaller than 1".to_owned());
+        }
+        for FrameShift { frame, .. } in self.manual_shifts.iter() {
+            problems.extend(shape.check_frame(*frame));
+            if *frame == self.reference {
+                problems.push("the reference frame can not be shifted by hand".to_owned());
+            }
+        }
+        problems
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        dataset.verify_one_frame_in_bounds(self.reference)?;
         let nrows = dataset.data.nrows();
-        let ref_grid = dataset.data.slice(s![.., 0]).into_owned();
-        let ref_frame = dataset.data.slice(s![.., 1]).into_owned();
-        for i in (2..dataset.data.ncols()).step_by(2) {
-            // set all x-axes to values from reference frame (frame 1)
+        let ref_col = 2 * self.reference - 2;
+        let ref_grid = dataset.data.column(ref_col).into_owned();
+        let ref_frame = dataset.data.column(ref_col + 1).into_owned();
+        let window = self.window_indices(&ref_grid)?;
+        // the axis is stretched around the center of the window, the grid of
+        // the cost function starts at 1
+        let center = (window.start + window.end - 1) as f64 / 2.0 + 1.0;
+        let x_center = ref_grid[(window.start + window.end - 1) / 2];
+        self.shifts = vec![0.0; dataset.data.ncols() / 2];
+        for i in (0..dataset.data.ncols()).step_by(2) {
+            if i == ref_col {
+                continue;
+            }
+            // set all x-axes to values from reference frame
             for j in 0..nrows {
                 dataset.data[[j, i]] = ref_grid[j];
             }
             let mut frame = dataset.data.column_mut(i + 1);
-            let init_param = 0.0;
-            let problem = OptAlignment::new(&ref_frame, &frame)?;
-            let solver = BrentOpt::new(-f64::abs(self.cost_max_abs), f64::abs(self.cost_max_abs));
-            let res = Executor::new(problem, solver)
-                .configure(|state| state.param(init_param))
-                .run()?;
-            let dx = match res.state().best_param {
+            let manual = self
+                .manual_shifts
+                .iter()
+                .find(|manual| manual.frame == i / 2 + 1);
+            let (dx, stretch) = match manual {
+                Some(manual) => (manual.shift, 0.0),
                 None => {
-                    return Err(anyhow!(
-                        "frame alignment failed, optimization did not return optimized parameters"
-                    ))
+                    let problem = OptAlignment::new(&ref_frame, &frame, window.clone(), center)?;
+                    self.optimize(problem)?
                 }
-                Some(param) => param,
             };
-            let shifted_grid = &ref_grid + dx;
-            let aligned_frame = linear_resample_array(&shifted_grid, &frame, &ref_grid);
+            self.shifts[i / 2] = dx;
+            let shifted_grid = ref_grid.mapv(|x| x_center + (x - x_center) * (1.0 + stretch) + dx);
+            let aligned_frame = self
+                .interpolation
+                .resample(&shifted_grid, &frame, &ref_grid);
             for j in 0..nrows {
                 frame[j] = aligned_frame[j]
             }
@@ -63,12 +221,6 @@ impl Transformer for AlignTransform {
         dataset.metadata += "---\n";
         Ok(())
     }
-
-    fn apply(&mut self, dataset: &mut Dataset) -> Result<()> {
-        self.transform(dataset)?;
-        self.write_metadata_yaml(dataset)?;
-        Ok(())
-    }
 }
 
 struct OptAlignment<'a, S, T>
@@ -78,6 +230,10 @@ where
 {
     frame_a: &'a ArrayBase<S, Ix1>,
     frame_b: &'a ArrayBase<T, Ix1>,
+    /// indices of the points compared
+    window: Range<usize>,
+    /// index the axis is stretched around
+    center: f64,
 }
 
 impl<'a, S, T> OptAlignment<'a, S, T>
@@ -85,15 +241,42 @@ where
     S: Data<Elem = f64>,
     T: Data<Elem = f64>,
 {
-    fn new(frame_a: &'a ArrayBase<S, Ix1>, frame_b: &'a ArrayBase<T, Ix1>) -> Result<Self> {
+    fn new(
+        frame_a: &'a ArrayBase<S, Ix1>,
+        frame_b: &'a ArrayBase<T, Ix1>,
+        window: Range<usize>,
+        center: f64,
+    ) -> Result<Self> {
         if frame_a.len() == frame_b.len() {
-            Ok(Self { frame_a, frame_b })
+            Ok(Self {
+                frame_a,
+                frame_b,
+                window,
+                center,
+            })
         } else {
             Err(anyhow!(
                 "frames that shall be aligned must be of same length"
             ))
         }
     }
+
+    fn mismatch(&self, shift: f64, stretch: f64) -> f64 {
+        let grid: Array1<f64> = (1..self.frame_a.len()).map(|x| x as f64).collect();
+        let x_shifted = grid.mapv(|x| self.center + (x - self.center) * (1.0 + stretch) + shift);
+        let ys = linear_resample_array(&x_shifted, self.frame_b, &grid);
+        let mut sum = 0.0;
+        let compared = ys.iter().zip(self.frame_a).enumerate();
+        for (_, (y1, y0)) in compared.filter(|(i, _)| self.window.contains(i)) {
+            // this seems to work rather well, the cost function in the python implementation
+            // (square of difference) does not work here
+            let cst = -(y1 * y0).abs();
+            if !cst.is_nan() {
+                sum += cst;
+            }
+        }
+        sum
+    }
 }
 
 impl<'a, S, T> CostFunction for OptAlignment<'a, S, T>
@@ -105,19 +288,36 @@ where
     type Output = f64;
 
     fn cost(&self, param: &Self::Param) -> Result<Self::Output> {
-        let grid: Array1<f64> = (1..self.frame_a.len()).map(|x| x as f64).collect();
-        let x_shifted = &grid + *param;
-        let ys = linear_resample_array(&x_shifted, self.frame_b, &grid);
-        let mut sum = 0.0;
-        for (y1, y0) in ys.iter().zip(self.frame_a) {
-            // this seems to work rather well, the cost function in the python implementation
-            // (square of difference) does not work here
-            let cst = -(y1 * y0).abs();
-            if !cst.is_nan() {
-                sum += cst;
-            }
+        Ok(self.mismatch(*param, 0.0))
+    }
+}
+
+/// Alignment by shift and stretch, Nelder-Mead has no bounds, so parameters
+/// outside of them get an infinite cost.
+struct OptStretchAlignment<'a, S, T>
+where
+    S: Data<Elem = f64>,
+    T: Data<Elem = f64>,
+{
+    problem: OptAlignment<'a, S, T>,
+    max_shift: f64,
+    max_stretch: f64,
+}
+
+impl<'a, S, T> CostFunction for OptStretchAlignment<'a, S, T>
+where
+    S: Data<Elem = f64>,
+    T: Data<Elem = f64>,
+{
+    type Param = Vec<f64>; // x shift and stretch
+    type Output = f64;
+
+    fn cost(&self, param: &Self::Param) -> Result<Self::Output> {
+        let (shift, stretch) = (param[0], param[1]);
+        if shift.abs() > self.max_shift || stretch.abs() > self.max_stretch {
+            return Ok(f64::INFINITY);
         }
-        Ok(sum)
+        Ok(self.problem.mismatch(shift, stretch))
     }
 }
 
@@ -146,3 +346,89 @@ where
 //         Ok(param.forward_hessian(&|p| self.gradient(p).unwrap()))
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::AlignTransform;
+    use crate::common::{Dataset, Pair};
+    use crate::transformations::Transformer;
+    use crate::utils::Interpolation;
+    use ndarray::Array1;
+
+    #[test]
+    fn test_align_to_reference_in_window() {
+        let xs: Array1<f64> = (0..100).map(|x| x as f64).collect();
+        let band = |center: f64| xs.mapv(|x| (-(x - center).powi(2) / 8.0).exp());
+        // the band of the first frame is shifted, with junk outside the window
+        let mut first = band(53.0);
+        first.slice_mut(ndarray::s![..20]).fill(5.0);
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = ndarray::stack(
+            ndarray::Axis(1),
+            &[xs.view(), first.view(), xs.view(), band(50.0).view()],
+        )
+        .unwrap();
+        let mut trsf = AlignTransform {
+            cost_max_abs: 5.0,
+            interpolation: Interpolation::Linear,
+            reference: 2,
+            window: Some(Pair { a: 40.0, b: 60.0 }),
+            stretch: None,
+            manual_shifts: vec![],
+            shifts: vec![],
+        };
+        trsf.transform(&mut dataset).unwrap();
+        // the reference keeps its place, the band of the first frame moves
+        assert_eq!(dataset.data.column(3), band(50.0));
+        let peak = |dataset: &Dataset| {
+            // missing values where the shifted frame does not reach
+            let y = |i: usize| dataset.data[[i, 1]].max(0.0);
+            (20..100).max_by(|a, b| y(*a).total_cmp(&y(*b))).unwrap()
+        };
+        assert_eq!(peak(&dataset), 50);
+        assert_eq!(trsf.shifts[1], 0.0);
+        assert!((trsf.shifts[0] + 3.0).abs() < 0.1);
+        // a shift given by hand replaces the optimized one
+        trsf.manual_shifts = vec!["1:3".parse().unwrap()];
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(peak(&dataset), 53);
+        trsf.window = Some(Pair { a: 200.0, b: 300.0 });
+        assert!(trsf.transform(&mut dataset).is_err());
+    }
+
+    #[test]
+    fn test_align_with_stretch() {
+        let xs: Array1<f64> = (0..200).map(|x| x as f64).collect();
+        let bands = |stretch: f64| {
+            xs.mapv(|x| {
+                let x = 100.0 + (x - 100.0) / stretch;
+                (-(x - 60.0).powi(2) / 8.0).exp() + (-(x - 140.0).powi(2) / 8.0).exp()
+            })
+        };
+        // bands moved apart by a change of dispersion and shifted
+        let stretched = bands(1.05);
+        let moved = xs.mapv(|x| stretched[((x as usize) + 2).min(199)]);
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = ndarray::stack(
+            ndarray::Axis(1),
+            &[xs.view(), bands(1.0).view(), xs.view(), moved.view()],
+        )
+        .unwrap();
+        let mut trsf = AlignTransform {
+            cost_max_abs: 5.0,
+            interpolation: Interpolation::Linear,
+            reference: 1,
+            window: Some(Pair { a: 30.0, b: 170.0 }),
+            stretch: Some(0.1),
+            manual_shifts: vec![],
+            shifts: vec![],
+        };
+        trsf.transform(&mut dataset).unwrap();
+        let peak = |range: std::ops::Range<usize>| {
+            let y = |i: usize| dataset.data[[i, 3]].max(0.0);
+            range.max_by(|a, b| y(*a).total_cmp(&y(*b))).unwrap()
+        };
+        // both bands are back in place, which a shift alone can not do
+        assert_eq!((peak(30..100), peak(100..170)), (60, 140));
+    }
+}
@@ -1,9 +1,10 @@
-use super::Transformer;
-use crate::common::Dataset;
+use super::{PipelineContext, Transformer};
+use crate::common::{AxisUnit, CsvFormat, Dataset, DatasetShape, FrameMetadata};
+use crate::utils::Interpolation;
 use anyhow::anyhow;
 use anyhow::Result;
 use clap::Parser;
-use ndarray::Axis;
+use ndarray::{s, Array2, ArrayView2, Axis};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Parser, Serialize, Deserialize)]
@@ -26,31 +27,264 @@ pub struct AppendTransform {
         help = "if true, append data horizontally (as rows), e.g. to add scans"
     )]
     pub horizontal: bool,
+    #[clap(
+        short,
+        long,
+        default_value = "error",
+        help = "What to do if the appended frames have a different number of rows: \"error\", \"resample\" (interpolate them onto the x-axis of the first frame) or \"pad\" (fill the shorter frames with missing values)."
+    )]
+    #[serde(default)]
+    pub mismatch: AppendMismatch,
+    #[clap(
+        long,
+        default_value = "linear",
+        help = "Interpolation used with --mismatch resample: linear or pchip (monotone cubic, keeps sharp bands on coarse grids)."
+    )]
+    #[serde(default)]
+    pub interpolation: Interpolation,
+}
+
+/// Handling of appended frames whose number of rows differs from the dataset.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppendMismatch {
+    #[default]
+    Error,
+    Resample,
+    Pad,
+}
+
+impl std::str::FromStr for AppendMismatch {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(AppendMismatch::Error),
+            "resample" => Ok(AppendMismatch::Resample),
+            "pad" => Ok(AppendMismatch::Pad),
+            _ => Err(anyhow!(
+                "unknown mismatch handling \"{}\", use \"error\", \"resample\" or \"pad\"",
+                s
+            )),
+        }
+    }
+}
+
+/// Smallest and largest finite x-value of all frames.
+fn x_range(data: ArrayView2<f64>) -> Option<(f64, f64)> {
+    data.slice(s![.., ..;2])
+        .iter()
+        .filter(|x| x.is_finite())
+        .fold(None, |range, x| match range {
+            None => Some((*x, *x)),
+            Some((min, max)) => Some((x.min(min), x.max(max))),
+        })
+}
+
+/// Extend the frames by rows of missing values.
+fn pad_rows(data: ArrayView2<f64>, rows: usize) -> Array2<f64> {
+    let mut padded = Array2::from_elem((rows.max(data.nrows()), data.ncols()), f64::NAN);
+    padded.slice_mut(s![..data.nrows(), ..]).assign(&data);
+    padded
+}
+
+/// Interpolate the frames onto `grid`, missing values where they do not
+/// cover it.
+pub(crate) fn resample(
+    data: ArrayView2<f64>,
+    grid: ndarray::ArrayView1<f64>,
+    interpolation: Interpolation,
+) -> Array2<f64> {
+    let mut resampled = Array2::zeros((grid.len(), data.ncols()));
+    for frame in 0..data.ncols() / 2 {
+        let (mut xs, mut ys) = (data.column(2 * frame), data.column(2 * frame + 1));
+        // the interpolation walks along increasing x-values
+        if xs.first() > xs.last() {
+            xs.invert_axis(Axis(0));
+            ys.invert_axis(Axis(0));
+        }
+        resampled.column_mut(2 * frame).assign(&grid);
+        resampled
+            .column_mut(2 * frame + 1)
+            .assign(&interpolation.resample(&xs, &ys, &grid));
+    }
+    resampled
 }
 
 impl Transformer for AppendTransform {
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
-    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
-        let new_dataset = if self
-            .filepath
-            .as_ref()
-            .and_then(|fp| fp.extension())
-            .is_some_and(|ext| ext == "spe")
-        {
-            Dataset::from_spe(self.filepath.as_ref().unwrap())
-                .map_err(|e| anyhow!("Could not read SPE file: {e}"))?
-        } else {
-            Dataset::from_csv(&self.filepath, self.comment, self.delimiter)?
+    fn validate(&self, shape: &mut DatasetShape, context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        match &self.filepath {
+            None => problems.push("no file to append given".to_owned()),
+            Some(path) if !context.resolve_path(path).exists() => {
+                problems.push(format!("file {:?} to append does not exist", path))
+            }
+            _ => (),
+        }
+        // the shape of the appended data is only known after reading it
+        *shape = DatasetShape {
+            x_unit: shape.x_unit,
+            ..Default::default()
         };
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        let filepath = self.filepath.as_ref().map(|fp| context.resolve_path(fp));
+        let new_dataset =
+            Dataset::from_file(&filepath, &CsvFormat::new(self.comment, self.delimiter))?;
+        if dataset.x_unit != new_dataset.x_unit {
+            if dataset.x_unit == AxisUnit::Unknown {
+                dataset.x_unit = new_dataset.x_unit;
+            } else if new_dataset.x_unit != AxisUnit::Unknown {
+                return Err(anyhow!(
+                    "cannot append data with x-axis in {} to data with x-axis in {}",
+                    new_dataset.x_unit,
+                    dataset.x_unit
+                ));
+            }
+        }
         dataset.previous_comments += "\n";
         dataset.previous_comments += &new_dataset.previous_comments;
+        // an empty dataset has no x-range to compare, e.g. when frames are
+        // collected into a dataset that starts out empty
+        let either_empty = dataset.data.nrows() == 0 || new_dataset.data.nrows() == 0;
+        let overlapping = either_empty
+            || match (
+                x_range(dataset.data.view()),
+                x_range(new_dataset.data.view()),
+            ) {
+                (Some((min, max)), Some((new_min, new_max))) => new_min <= max && min <= new_max,
+                _ => false,
+            };
         dataset.data = if self.horizontal {
+            if dataset.data.ncols() != new_dataset.data.ncols() {
+                return Err(anyhow!(
+                    "cannot append {} frame(s) as rows to {} frame(s)",
+                    new_dataset.data.ncols() / 2,
+                    dataset.data.ncols() / 2
+                ));
+            }
             ndarray::concatenate(Axis(0), &[dataset.data.view(), new_dataset.data.view()])?
         } else {
-            ndarray::concatenate(Axis(1), &[dataset.data.view(), new_dataset.data.view()])?
+            let (rows, new_rows) = (dataset.data.nrows(), new_dataset.data.nrows());
+            let new_data = match self.mismatch {
+                // there is no x-axis to resample onto
+                AppendMismatch::Resample if either_empty => {
+                    dataset.data = pad_rows(dataset.data.view(), new_rows);
+                    pad_rows(new_dataset.data.view(), rows)
+                }
+                AppendMismatch::Resample => {
+                    if !overlapping {
+                        return Err(anyhow!(
+                            "cannot resample the appended frames, their x-range does not overlap the dataset"
+                        ));
+                    }
+                    resample(
+                        new_dataset.data.view(),
+                        dataset.data.column(0),
+                        self.interpolation,
+                    )
+                }
+                AppendMismatch::Pad => {
+                    dataset.data = pad_rows(dataset.data.view(), new_rows);
+                    pad_rows(new_dataset.data.view(), rows)
+                }
+                AppendMismatch::Error if rows != new_rows => {
+                    return Err(anyhow!(
+                        "appended frames have {} rows, the dataset {}, use --mismatch resample or pad",
+                        new_rows,
+                        rows
+                    ));
+                }
+                AppendMismatch::Error => new_dataset.data,
+            };
+            if !overlapping {
+                context
+                    .diagnostics
+                    .warn("x-range of the appended frames does not overlap the dataset");
+            }
+            // pad frame metadata, so it stays aligned with the frames
+            let n_frames = dataset.data.ncols() / 2;
+            dataset
+                .frame_metadata
+                .resize(n_frames, FrameMetadata::default());
+            let n_new_frames = new_data.ncols() / 2;
+            let mut new_frame_metadata = new_dataset.frame_metadata;
+            new_frame_metadata.resize(n_new_frames, FrameMetadata::default());
+            dataset.frame_metadata.extend(new_frame_metadata);
+            ndarray::concatenate(Axis(1), &[dataset.data.view(), new_data.view()])?
         };
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{pad_rows, resample, x_range, AppendMismatch, AppendTransform};
+    use crate::common::Dataset;
+    use crate::transformations::{PipelineContext, Transformer};
+    use crate::utils::Interpolation;
+    use ndarray::{array, Array2};
+
+    #[test]
+    fn test_mismatched_rows() {
+        // two frames, the second on a descending axis
+        let data = array![
+            [0.0, 1.0, 4.0, 8.0],
+            [2.0, 3.0, 2.0, 4.0],
+            [4.0, 5.0, 0.0, 0.0]
+        ];
+        assert_eq!(x_range(data.view()), Some((0.0, 4.0)));
+        let grid = array![1.0, 2.0, 3.0, 5.0];
+        let resampled = resample(data.view(), grid.view(), Interpolation::Linear);
+        assert_eq!(resampled.column(2), grid);
+        assert_eq!(
+            resampled.column(1).slice(ndarray::s![..3]),
+            array![2.0, 3.0, 4.0]
+        );
+        assert_eq!(
+            resampled.column(3).slice(ndarray::s![..3]),
+            array![2.0, 4.0, 6.0]
+        );
+        // outside of the appended x-range
+        assert!(resampled[[3, 1]].is_nan());
+        let padded = pad_rows(data.view(), 5);
+        assert_eq!(padded.dim(), (5, 4));
+        assert_eq!(padded.row(2), data.row(2));
+        assert!(padded.row(4).iter().all(|v| v.is_nan()));
+        assert_eq!(pad_rows(data.view(), 2), data);
+    }
+
+    #[test]
+    fn test_append_to_empty() {
+        let path = std::env::temp_dir().join(format!("append-{}.csv", std::process::id()));
+        std::fs::write(&path, "1,2\n2,4\n").unwrap();
+        let mut append = AppendTransform {
+            filepath: Some(path.clone()),
+            delimiter: ',',
+            comment: '#',
+            horizontal: false,
+            mismatch: AppendMismatch::Resample,
+            interpolation: Interpolation::default(),
+        };
+        let mut dataset = Dataset {
+            data: Array2::zeros((0, 2)),
+            ..Default::default()
+        };
+        let context = PipelineContext::default();
+        append
+            .transform_with_context(&mut dataset, &context)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // no warning about x-ranges that do not overlap
+        assert!(context.diagnostics.take().is_empty());
+        assert_eq!(dataset.data.dim(), (2, 4));
+        assert_eq!(dataset.data.column(3), array![2.0, 4.0]);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::transformations::{PipelineContext, Transformer};
+use crate::{
+    common::{Dataset, DatasetShape},
+    utils::Interpolation,
+};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::Zip;
+use serde::{Deserialize, Serialize};
+
+/// Combine frames with a reference frame point by point, e.g. to compute
+/// depolarization ratios from parallel and perpendicular scans. The
+/// reference frame is removed from the dataset.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct ArithmeticTransform {
+    #[clap(
+        help = "Operation between each frame and the reference: add, subtract, multiply, divide or ratio (like divide, but points where the reference is zero become missing values)."
+    )]
+    pub(crate) operation: Operation,
+    #[clap(help = "Column number of the reference frame")]
+    pub(crate) reference: usize,
+    #[clap(
+        short,
+        long,
+        help = "Frame(s) to combine with the reference (if none given, all other frames in dataset)"
+    )]
+    pub(crate) targets: Option<Vec<usize>>,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "If flag is set, combine frame intensities without interpolating on same grid first"
+    )]
+    pub(crate) direct: bool,
+    #[clap(
+        long,
+        default_value = "linear",
+        help = "Interpolation of the frames onto the x-axis of the reference: linear or pchip (monotone cubic, keeps sharp bands on coarse grids)."
+    )]
+    #[serde(default)]
+    pub(crate) interpolation: Interpolation,
+}
+
+/// Point by point operation between a frame and the reference frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Add,
+    #[default]
+    Subtract,
+    Multiply,
+    Divide,
+    /// divide, with missing values where the reference is zero
+    Ratio,
+}
+
+impl std::str::FromStr for Operation {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "add" => Ok(Operation::Add),
+            "subtract" => Ok(Operation::Subtract),
+            "multiply" => Ok(Operation::Multiply),
+            "divide" => Ok(Operation::Divide),
+            "ratio" => Ok(Operation::Ratio),
+            _ => Err(anyhow!(
+                "unknown operation \"{}\", use add, subtract, multiply, divide or ratio",
+                s
+            )),
+        }
+    }
+}
+
+impl Operation {
+    fn apply(self, y: f64, reference: f64) -> f64 {
+        match self {
+            Operation::Add => y + reference,
+            Operation::Subtract => y - reference,
+            Operation::Multiply => y * reference,
+            Operation::Divide => y / reference,
+            Operation::Ratio if reference == 0.0 => f64::NAN,
+            Operation::Ratio => y / reference,
+        }
+    }
+}
+
+impl ArithmeticTransform {
+    pub fn new(operation: Operation, reference: usize) -> Self {
+        Self {
+            operation,
+            reference,
+            targets: None,
+            direct: false,
+            interpolation: Interpolation::default(),
+        }
+    }
+}
+
+impl Transformer for ArithmeticTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        true
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems: Vec<String> = shape.check_frame(self.reference).into_iter().collect();
+        if let Some(targets) = &self.targets {
+            problems.extend(targets.iter().filter_map(|frame| shape.check_frame(*frame)));
+            if targets.contains(&self.reference) {
+                problems.push("the target frames must not contain the reference frame".to_owned());
+            }
+            shape.frames = Some(targets.len());
+        } else {
+            shape.frames = shape.frames.map(|frames| frames.saturating_sub(1));
+        }
+        problems
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let (mut targets, mut frame_metadata) = if let Some(targets) = &self.targets {
+            if targets.contains(&self.reference) {
+                return Err(anyhow!(
+                    "the target frames must not contain the reference frame"
+                ));
+            }
+            (
+                dataset.select_frames(targets, false)?,
+                dataset.select_frame_metadata(targets, false),
+            )
+        } else {
+            (
+                dataset.select_frames(&[self.reference], true)?,
+                dataset.select_frame_metadata(&[self.reference], true),
+            )
+        };
+        let reference = dataset.select_frames(&[self.reference], false)?;
+        let grid = reference.column(0);
+        let ref_ys = reference.column(1);
+        for n in (0..targets.ncols() - 1).step_by(2) {
+            let ys = if !self.direct {
+                self.interpolation
+                    .resample(&targets.column(n), &targets.column(n + 1), &grid)
+            } else {
+                // ignore spectral axes of the targets and combine intensity data directly
+                targets.column(n + 1).to_owned()
+            };
+            let result = Zip::from(&ys)
+                .and(&ref_ys)
+                .map_collect(|y, r| self.operation.apply(*y, *r));
+            targets.column_mut(n + 1).assign(&result);
+            if !self.direct {
+                targets.column_mut(n).assign(&grid);
+            }
+        }
+        if !matches!(self.operation, Operation::Add | Operation::Subtract) {
+            // the result is no longer in counts of the detector
+            for metadata in frame_metadata.iter_mut() {
+                metadata.exposure = None;
+                metadata.gain = None;
+            }
+        }
+        dataset.data = targets;
+        dataset.frame_metadata = frame_metadata;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArithmeticTransform, Operation};
+    use crate::{
+        common::{Dataset, FrameMetadata},
+        transformations::Transformer,
+    };
+    use ndarray::array;
+
+    #[test]
+    fn test_arithmetic() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![
+            [1.0, 2.0, 1.0, 4.0, 1.0, 1.0],
+            [2.0, 4.0, 2.0, 6.0, 2.0, 0.0]
+        ];
+        let exposed = FrameMetadata {
+            exposure: Some(1.0),
+            ..Default::default()
+        };
+        dataset.frame_metadata = vec![exposed; 3];
+        let mut ratio = dataset.clone();
+        let mut trsf = ArithmeticTransform::new(Operation::Divide, 2);
+        trsf.targets = Some(vec![1]);
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data, array![[1.0, 0.5], [2.0, 4.0 / 6.0]]);
+        assert_eq!(dataset.frame_metadata[0].exposure, None);
+        // missing values where the reference is zero
+        let mut trsf = ArithmeticTransform::new(Operation::Ratio, 3);
+        trsf.transform(&mut ratio).unwrap();
+        assert_eq!(ratio.data.row(0), array![1.0, 2.0, 1.0, 4.0]);
+        assert!(ratio.data[[1, 1]].is_nan() && ratio.data[[1, 3]].is_nan());
+    }
+}
@@ -1,23 +1,270 @@
-use crate::common::Dataset;
-use crate::transformations::Transformer;
-use anyhow::Result;
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{append::resample, PipelineContext, Transformer};
+use crate::utils::Interpolation;
+use anyhow::{anyhow, Result};
 use clap::Parser;
-use ndarray::{s, Axis};
+use ndarray::{s, Array1, Axis};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Parser, Serialize, Deserialize)]
+/// Weighted mean of all frames. Missing values (NaN) are skipped, a point
+/// missing in all frames stays missing.
+#[derive(Debug, Default, Parser, Serialize, Deserialize)]
 #[serde(tag = "transformation")]
-pub struct AverageTransform {}
+pub struct AverageTransform {
+    #[clap(
+        short,
+        long,
+        help = "Weights of the frames, either comma separated values (one per frame) or \"exposure\" to weight frames by their exposure time. Quality weights attached to the frames (weight, reject-outliers --weight) are applied on top."
+    )]
+    pub(crate) weights: Option<AverageWeights>,
+    #[clap(
+        short,
+        long,
+        help = "Interpolate frames whose x-axis differs from the first frame onto its x-axis before averaging, instead of failing. Points not covered by all frames become missing values, other missing values are skipped."
+    )]
+    #[serde(default)]
+    pub resample: bool,
+    #[clap(
+        long,
+        default_value = "linear",
+        help = "Interpolation used with --resample: linear or pchip (monotone cubic, keeps sharp bands on coarse grids)."
+    )]
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    #[serde(skip)]
+    #[clap(skip)]
+    pub gui_text_buffers: AverageIOBuffers,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct AverageIOBuffers {
+    pub weights: String,
+}
+
+/// Weights used to calculate a weighted average of frames.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AverageWeights {
+    /// weight each frame by its exposure time (read from the frame metadata)
+    Exposure,
+    /// explicit weight for each frame
+    Values(Vec<f64>),
+}
+
+impl std::str::FromStr for AverageWeights {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "exposure" {
+            return Ok(AverageWeights::Exposure);
+        }
+        let values = parse_weight_values(s).map_err(|_| {
+            anyhow!("could not parse weights, use \"exposure\" or \"<value>,<value>,...\"")
+        })?;
+        Ok(AverageWeights::Values(values))
+    }
+}
+
+/// Weights of the frames given as "<value>,<value>,...", on the command line
+/// and in the GUI alike.
+pub fn parse_weight_values(s: &str) -> Result<Vec<f64>> {
+    s.split(',')
+        .map(|w| w.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_| anyhow!("could not parse weights, use \"<value>,<value>,...\""))
+}
 
 impl Transformer for AverageTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        matches!(self.weights, Some(AverageWeights::Values(_)))
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if let (Some(AverageWeights::Values(weights)), Some(frames)) = (&self.weights, shape.frames)
+        {
+            if weights.len() != frames {
+                problems.push(format!(
+                    "got {} weights for {} frames",
+                    weights.len(),
+                    frames
+                ));
+            }
+        }
+        shape.frames = Some(1);
+        problems
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        // the y-values are averaged row by row, which only makes sense if
+        // the rows of all frames belong to the same x-values
+        let mut covered = vec![true; dataset.data.nrows()];
+        if dataset.shared_x_axis().is_none() {
+            if !self.resample {
+                return Err(anyhow!(
+                    "the frames do not share their x-axis, align them first or use --resample"
+                ));
+            }
+            // points outside the x-range of a frame are not averaged
+            for xs in dataset.data.columns().into_iter().step_by(2) {
+                let (min, max) = xs
+                    .iter()
+                    .filter(|x| x.is_finite())
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), x| {
+                        (min.min(*x), max.max(*x))
+                    });
+                for (covered, x) in covered.iter_mut().zip(dataset.data.column(0)) {
+                    *covered &= *x >= min && *x <= max;
+                }
+            }
+            dataset.data = resample(
+                dataset.data.view(),
+                dataset.data.column(0),
+                self.interpolation,
+            );
+        }
         let mask = s![.., 1..;2]; // every second column
-        let average_intensity = dataset.data.slice(mask).mean_axis(Axis(1)).unwrap();
+        let intensities = dataset.data.slice(mask);
+        let weights = match &self.weights {
+            None => None,
+            Some(AverageWeights::Values(values)) => Some(values.clone()),
+            Some(AverageWeights::Exposure) => Some(dataset.exposures().ok_or_else(|| {
+                anyhow!("exposure time is not known for all frames, cannot weight by exposure")
+            })?),
+        };
+        if let Some(weights) = &weights {
+            if weights.len() != intensities.ncols() {
+                return Err(anyhow!(
+                    "got {} weights for {} frames",
+                    weights.len(),
+                    intensities.ncols()
+                ));
+            }
+        }
+        // the quality weights of the frames apply on top of the given weights
+        let weights = match (weights, dataset.weights()) {
+            (Some(weights), Some(quality)) => Some(
+                weights
+                    .iter()
+                    .zip(quality)
+                    .map(|(weight, quality)| weight * quality)
+                    .collect(),
+            ),
+            (weights, quality) => weights.or(quality),
+        };
+        let weights = match weights {
+            None => Array1::ones(intensities.ncols()),
+            Some(weights) => {
+                let weights = Array1::from_vec(weights);
+                if weights.sum() == 0.0 {
+                    return Err(anyhow!("weights must not sum up to zero"));
+                }
+                weights
+            }
+        };
+        // missing values are skipped, a point missing in all frames stays
+        // missing
+        let average_intensity: Array1<f64> = intensities
+            .rows()
+            .into_iter()
+            .zip(covered)
+            .map(|(row, covered)| {
+                if !covered {
+                    return f64::NAN;
+                }
+                let (sum, weights_sum) = row
+                    .iter()
+                    .zip(weights.iter())
+                    .filter(|(y, _)| y.is_finite())
+                    .fold((0.0, 0.0), |(sum, weights_sum), (y, w)| {
+                        (sum + y * w, weights_sum + w)
+                    });
+                if weights_sum == 0.0 {
+                    f64::NAN
+                } else {
+                    sum / weights_sum
+                }
+            })
+            .collect();
+        let frame_metadata = dataset.combined_frame_metadata();
         let wavenumber_axis = dataset.data.slice(s![.., 0]);
         dataset.data = ndarray::stack(Axis(1), &[wavenumber_axis, average_intensity.view()])?;
+        dataset.frame_metadata = vec![frame_metadata];
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{AverageTransform, AverageWeights};
+    use crate::{common::Dataset, transformations::Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_weighted_average() {
+        let mut dataset = Dataset::new_test_dummy_shared_x();
+        let mut trsf = AverageTransform {
+            weights: Some("1, 0,0,1".parse::<AverageWeights>().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            trsf.weights,
+            Some(AverageWeights::Values(vec![1.0, 0.0, 0.0, 1.0]))
+        );
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(
+            dataset.data.column(1),
+            array![15., 25., 35., 45., 55., 65., 75., 85.]
+        );
+        let mut dataset = Dataset::new_test_dummy_shared_x();
+        trsf.weights = Some("exposure".parse::<AverageWeights>().unwrap());
+        assert!(trsf.transform(&mut dataset).is_err());
+        assert!("1 0 0 1".parse::<AverageWeights>().is_err());
+        // quality weights attached to the frames
+        let mut dataset = Dataset::new_test_dummy_shared_x();
+        for (meta, weight) in dataset.frame_metadata.iter_mut().zip([1.0, 0.0, 0.0, 1.0]) {
+            meta.weight = Some(weight);
+        }
+        trsf.weights = None;
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(
+            dataset.data.column(1),
+            array![15., 25., 35., 45., 55., 65., 75., 85.]
+        );
+    }
+
+    #[test]
+    fn test_average_shifted_axes() {
+        // second frame on an axis shifted by half a step
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![
+            [1.0, 2.0, 1.5, 4.0],
+            [2.0, 4.0, 2.5, 6.0],
+            [3.0, 6.0, 3.5, 8.0]
+        ];
+        let mut trsf = AverageTransform::default();
+        assert!(trsf.transform(&mut dataset.clone()).is_err());
+        trsf.resample = true;
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.column(0), array![1.0, 2.0, 3.0]);
+        assert!(dataset.data[[0, 1]].is_nan());
+        assert_eq!(
+            dataset.data.column(1).slice(ndarray::s![1..]),
+            array![4.5, 6.5]
+        );
+    }
+
+    #[test]
+    fn test_average_missing_values() {
+        let mut dataset = Dataset::new_test_dummy_shared_x();
+        dataset.data[[0, 1]] = f64::NAN;
+        for frame in 0..4 {
+            dataset.data[[1, 2 * frame + 1]] = f64::NAN;
+        }
+        AverageTransform::default().transform(&mut dataset).unwrap();
+        // the mean of the other frames, missing if missing in all frames
+        assert_eq!(dataset.data[[0, 1]], (14.0 + 16.0 + 18.0) / 3.0);
+        assert!(dataset.data[[1, 1]].is_nan());
+        assert_eq!(dataset.data[[2, 1]], 35.0);
+    }
+}
@@ -1,16 +1,41 @@
-use crate::common::{Dataset, Pair};
-use crate::transformations::Transformer;
-use anyhow::Result;
+use crate::common::{Dataset, DatasetShape, FrameMetadata, Pair};
+use crate::transformations::{PipelineContext, Transformer};
+use crate::utils::{lininterp, nearest_index};
+use anyhow::{anyhow, Result};
 use clap::Parser;
-use ndarray::{Array1, Array2, Axis};
+use ndarray::{Array1, Array2, ArrayView1, Axis};
 use serde::{Deserialize, Serialize};
-use splines::Key;
+use splines::{Key, Spline};
+use std::collections::BTreeMap;
 
+/// Subtract a spline baseline drawn through points. The points are shared by
+/// all frames, frames whose background differs get points of their own.
 #[derive(Debug, Parser, Serialize, Deserialize)]
 #[serde(tag = "transformation")]
 pub struct BaselineTransform {
     #[clap(short, long, help = "x,y points to draw spline baseline.")]
     pub(crate) points: Vec<Pair<f64>>,
+    #[clap(
+        short,
+        long,
+        help = "Point of the baseline of a single frame given as <frame>:<x>,<y>, e.g. 2:1550,30; frames with points of their own do not use the shared points."
+    )]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) frame_points: Vec<FramePoint>,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Interpolate the baseline of frames without points of their own between the closest frames with points (by frame number), instead of using the shared points."
+    )]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) interpolate: bool,
+    #[clap(
+        long,
+        help = "Move the points onto the data of each frame: their y-value becomes the median of the intensities within this many pixels around their x-value."
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) snap: Option<usize>,
     #[clap(
         short,
         long,
@@ -18,37 +43,208 @@ pub struct BaselineTransform {
         help = "If flag is set, add baseline to dataset instead of subtracting it."
     )]
     pub(crate) store: bool,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Keep the subtracted baseline in the \"baseline\" channel of the frames (ignored with --store)."
+    )]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) keep: bool,
+}
+
+/// Point of the baseline of a single frame.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FramePoint {
+    pub frame: usize,
+    pub x: f64,
+    pub y: f64,
+}
+
+impl std::str::FromStr for FramePoint {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow!(
+                "invalid point \"{}\", use <frame>:<x>,<y>, e.g. 2:1550,30",
+                s
+            )
+        };
+        let (frame, point) = s.split_once(':').ok_or_else(invalid)?;
+        let (x, y) = point.split_once(',').ok_or_else(invalid)?;
+        Ok(FramePoint {
+            frame: frame.trim().parse().map_err(|_| invalid())?,
+            x: x.trim().parse().map_err(|_| invalid())?,
+            y: y.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Spline through `points` (sorted by x), None for less than two points.
+fn spline_through(points: &[(f64, f64)]) -> Option<Spline<f64, f64>> {
+    let n_pts = points.len();
+    if n_pts < 2 {
+        return None;
+    }
+    let keys = points
+        .iter()
+        .enumerate()
+        .map(|(i, (x, y))| {
+            let interpolation = if i == 0 || i == n_pts - 2 {
+                splines::Interpolation::Linear
+            } else {
+                splines::Interpolation::CatmullRom
+            };
+            Key::new(*x, *y, interpolation)
+        })
+        .collect();
+    Some(Spline::from_vec(keys))
+}
+
+/// Median of the finite intensities within `half_width` pixels around the
+/// pixel closest to `x`, None if there are none.
+pub(crate) fn snapped_y(
+    xs: ArrayView1<f64>,
+    ys: ArrayView1<f64>,
+    x: f64,
+    half_width: usize,
+) -> Option<f64> {
+    let center = nearest_index(&xs, x)?;
+    let mut window: Vec<f64> = ys
+        .iter()
+        .skip(center.saturating_sub(half_width))
+        .take(2 * half_width + 1)
+        .copied()
+        .filter(|y| y.is_finite())
+        .collect();
+    if window.is_empty() {
+        return None;
+    }
+    window.sort_by(|a, b| a.total_cmp(b));
+    let n = window.len();
+    Some(0.5 * (window[(n - 1) / 2] + window[n / 2]))
+}
+
+impl BaselineTransform {
+    /// Spline through `points` for `frame` (1-based), snapped to the data of
+    /// the frame if asked for.
+    fn spline_of(
+        &self,
+        dataset: &Dataset,
+        frame: usize,
+        mut points: Vec<(f64, f64)>,
+    ) -> Option<Spline<f64, f64>> {
+        if let Some(half_width) = self.snap {
+            let xs = dataset.data.column(2 * frame - 2);
+            let ys = dataset.data.column(2 * frame - 1);
+            for (x, y) in points.iter_mut() {
+                *y = snapped_y(xs, ys, *x, half_width).unwrap_or(*y);
+            }
+        }
+        points.sort_by(|p1, p2| p1.0.total_cmp(&p2.0));
+        spline_through(&points)
+    }
+
+    /// Spline through the shared points for `frame` (1-based).
+    fn shared_spline(&self, dataset: &Dataset, frame: usize) -> Option<Spline<f64, f64>> {
+        let points = self.points.iter().map(|Pair { a, b }| (*a, *b)).collect();
+        self.spline_of(dataset, frame, points)
+    }
+
+    /// Spline of each frame (1-based) with points of its own.
+    fn frame_splines(&self, dataset: &Dataset) -> BTreeMap<usize, Spline<f64, f64>> {
+        let mut points: BTreeMap<usize, Vec<(f64, f64)>> = BTreeMap::new();
+        for FramePoint { frame, x, y } in self.frame_points.iter() {
+            points.entry(*frame).or_default().push((*x, *y));
+        }
+        points
+            .into_iter()
+            .filter_map(|(frame, pts)| {
+                self.spline_of(dataset, frame, pts)
+                    .map(|spline| (frame, spline))
+            })
+            .collect()
+    }
+
+    /// Baseline of each frame (one per column) at the x-values of `dataset`.
+    fn baseline(&self, dataset: &Dataset) -> Array2<f64> {
+        let shared: Vec<Option<Spline<f64, f64>>> = (1..=dataset.data.ncols() / 2)
+            .map(|frame| self.shared_spline(dataset, frame))
+            .collect();
+        let frame_splines = self.frame_splines(dataset);
+        let sample = |spline: &Spline<f64, f64>, x: f64| spline.sample(x).unwrap_or(0.0);
+        Array2::from_shape_fn(
+            (dataset.data.nrows(), dataset.data.ncols() / 2),
+            |(i, col)| {
+                let (frame, x) = (col + 1, dataset.data[[i, 2 * col]]);
+                if let Some(spline) = frame_splines.get(&frame) {
+                    return sample(spline, x);
+                }
+                if self.interpolate {
+                    let below = frame_splines.range(..frame).next_back();
+                    let above = frame_splines.range(frame..).next();
+                    match (below, above) {
+                        (Some((f0, s0)), Some((f1, s1))) => {
+                            return lininterp(
+                                frame as f64,
+                                *f0 as f64,
+                                *f1 as f64,
+                                sample(s0, x),
+                                sample(s1, x),
+                            )
+                        }
+                        (Some((_, closest)), None) | (None, Some((_, closest))) => {
+                            return sample(closest, x)
+                        }
+                        (None, None) => {}
+                    }
+                }
+                shared[col].as_ref().map_or(0.0, |spline| sample(spline, x))
+            },
+        )
+    }
 }
 
 impl Transformer for BaselineTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        !self.frame_points.is_empty()
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn is_frame_local(&self) -> bool {
+        // a stored baseline is a further frame, interpolated baselines depend
+        // on other frames
+        !self.store && (!self.interpolate || self.frame_points.is_empty())
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .frame_points
+            .iter()
+            .filter_map(|point| shape.check_frame(point.frame))
+            .collect();
+        problems.dedup();
+        if self.store && !self.frame_points.is_empty() {
+            problems.push("a stored baseline uses the shared points only".to_owned());
+        }
+        problems
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
-        if self.points.len() < 2 {
+        if self.points.len() < 2 && self.frame_points.is_empty() {
             return Ok(());
         }
-        let spline = {
-            let mut keys = vec![];
-            let n_pts = self.points.len();
-            for i in 0..n_pts {
-                if i == 0 || i == n_pts - 2 {
-                    keys.push(Key::new(
-                        self.points[i].a,
-                        self.points[i].b,
-                        splines::Interpolation::Linear,
-                    ));
-                } else {
-                    keys.push(Key::new(
-                        self.points[i].a,
-                        self.points[i].b,
-                        splines::Interpolation::CatmullRom,
-                    ));
-                }
-            }
-            splines::Spline::from_vec(keys)
-        };
+        if let Some(point) = self
+            .frame_points
+            .iter()
+            .find(|point| point.frame == 0 || point.frame > dataset.data.ncols() / 2)
+        {
+            return Err(anyhow!("frame {} is out of bounds", point.frame));
+        }
         if self.store {
+            // the stored baseline is on the x-axis of the first frame
+            let Some(spline) = self.shared_spline(dataset, 1) else {
+                return Ok(());
+            };
             // store baseline as a new frame
             let x_p: Array1<f64> = dataset.data.column(0).to_owned();
             let y_p: Array1<f64> = x_p
@@ -57,14 +253,112 @@ impl Transformer for BaselineTransform {
                 .collect();
             let baseline: Array2<f64> = ndarray::stack![Axis(1), x_p, y_p];
             dataset.data = ndarray::concatenate(Axis(1), &[dataset.data.view(), baseline.view()])?;
+            let n_frames = dataset.data.ncols() / 2;
+            dataset
+                .frame_metadata
+                .resize(n_frames, FrameMetadata::default());
         } else {
             // subtract baseline
-            for j in (0..dataset.data.ncols()).step_by(2) {
-                for i in 0..dataset.data.nrows() {
-                    dataset.data[[i, j + 1]] -= spline.sample(dataset.data[[i, j]]).unwrap_or(0.0);
-                }
+            let baseline = self.baseline(dataset);
+            for (mut ys, values) in dataset.iter_mut_frames().zip(baseline.columns()) {
+                ys -= &values;
+            }
+            if self.keep {
+                dataset.set_channel("baseline", baseline)?;
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BaselineTransform, FramePoint};
+    use crate::common::{Dataset, Pair};
+    use crate::transformations::Transformer;
+    use ndarray::Array2;
+
+    #[test]
+    fn test_keep_baseline() {
+        let mut dataset = Dataset::new_test_dummy();
+        let input = dataset.data.clone();
+        let mut trsf = BaselineTransform {
+            points: vec![Pair { a: 0.0, b: 1.0 }, Pair { a: 100.0, b: 1.0 }],
+            frame_points: vec![],
+            interpolate: false,
+            snap: None,
+            store: false,
+            keep: true,
+        };
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data[[0, 1]], input[[0, 1]] - 1.0);
+        let baseline = &dataset.channels["baseline"];
+        assert_eq!(baseline.dim(), (8, 4));
+        assert!(baseline.iter().all(|value| *value == 1.0));
+    }
+
+    #[test]
+    fn test_frame_baselines() {
+        let flat = |frame, y| [0.0, 100.0].map(|x| FramePoint { frame, x, y });
+        let mut dataset = Dataset::new_test_dummy();
+        let input = dataset.data.clone();
+        // baseline of each frame in `row`
+        let subtracted = |data: &Array2<f64>, row: usize| -> Vec<f64> {
+            (1..8)
+                .step_by(2)
+                .map(|col| input[[row, col]] - data[[row, col]])
+                .collect()
+        };
+        let mut trsf = BaselineTransform {
+            points: vec![Pair { a: 0.0, b: 1.0 }, Pair { a: 100.0, b: 1.0 }],
+            frame_points: [flat(2, 2.0), flat(4, 6.0)].concat(),
+            interpolate: false,
+            snap: None,
+            store: false,
+            keep: false,
+        };
+        trsf.transform(&mut dataset).unwrap();
+        // frames 1 and 3 use the shared points
+        assert_eq!(subtracted(&dataset.data, 0), vec![1.0, 2.0, 1.0, 6.0]);
+        trsf.interpolate = true;
+        let mut dataset = Dataset::new_test_dummy();
+        trsf.transform(&mut dataset).unwrap();
+        // frame 1 takes the closest frame, frame 3 is halfway between 2 and 4
+        assert_eq!(subtracted(&dataset.data, 3), vec![2.0, 2.0, 4.0, 6.0]);
+        assert_eq!(
+            "3:1550,30".parse::<FramePoint>().unwrap(),
+            FramePoint {
+                frame: 3,
+                x: 1550.0,
+                y: 30.0
+            }
+        );
+        trsf.frame_points.push(FramePoint {
+            frame: 5,
+            x: 0.0,
+            y: 0.0,
+        });
+        assert!(trsf.transform(&mut dataset).is_err());
+    }
+
+    #[test]
+    fn test_snap_baseline() {
+        let mut dataset = Dataset::new_test_dummy();
+        // a spike next to a point is ignored by the median
+        dataset.data[[2, 1]] = 1000.0;
+        let mut trsf = BaselineTransform {
+            points: vec![Pair { a: 21.0, b: 0.0 }, Pair { a: 71.0, b: 0.0 }],
+            frame_points: vec![],
+            interpolate: false,
+            snap: Some(1),
+            store: false,
+            keep: false,
+        };
+        trsf.transform(&mut dataset).unwrap();
+        // the first frame is a straight line through the snapped points
+        for row in [1, 3, 4, 5] {
+            assert!(dataset.data[[row, 1]].abs() < 1e-9);
+        }
+        assert!((dataset.data[[2, 1]] - (1000.0 - 32.0)).abs() < 1e-9);
+    }
+}
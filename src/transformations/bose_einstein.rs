@@ -0,0 +1,144 @@
+use crate::common::{AxisUnit, Dataset, DatasetShape};
+use crate::gui::TransformerGUI;
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Second radiation constant hc/k in cm K.
+const SECOND_RADIATION_CONSTANT: f64 = 1.438_776_877;
+
+/// Correct intensities for the thermal population of vibrational states,
+/// I_corr = I * |1 - exp(-hcν̃/kT)|, which matters for low-frequency Raman bands.
+/// The reduced intensity, I_red = I * ν̃ * (1 - exp(-hcν̃/kT)), additionally
+/// removes the frequency factor of the scattering, so band intensities of
+/// spectra taken at different temperatures can be compared directly.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct BoseEinsteinTransform {
+    #[clap(help = "Sample temperature in Kelvin.")]
+    pub(crate) temperature: f64,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Calculate the reduced Raman intensity, i.e. multiply by the Raman shift as well."
+    )]
+    #[serde(default)]
+    pub(crate) reduced: bool,
+    #[serde(skip)]
+    #[clap(skip)]
+    pub gui_text_buffers: BoseEinsteinIOBuffers,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct BoseEinsteinIOBuffers {
+    pub temperature: String,
+}
+
+impl Transformer for BoseEinsteinTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn is_frame_local(&self) -> bool {
+        true
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if self.temperature <= 0.0 {
+            problems.push(format!(
+                "temperature must be positive, got {} K",
+                self.temperature
+            ));
+        }
+        if shape.x_unit == AxisUnit::Wavelength {
+            problems.push(
+                "x-axis must be Raman shift in cm-1, calculate the Raman shift first".to_owned(),
+            );
+        }
+        problems
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        if self.temperature <= 0.0 {
+            return Err(anyhow!(
+                "temperature must be positive, got {} K",
+                self.temperature
+            ));
+        }
+        if dataset.x_unit == AxisUnit::Wavelength {
+            return Err(anyhow!(
+                "x-axis must be Raman shift in cm-1, calculate the Raman shift first"
+            ));
+        }
+        let num_cols = dataset.data.ncols();
+        for j in (1..num_cols).step_by(2) {
+            for i in 0..dataset.data.nrows() {
+                // j - 1 : wavenumber axes
+                let wavenumber = dataset.data[[i, j - 1]];
+                // the absolute value gives the correct factor for anti-Stokes bands, too
+                let mut factor = (1.0
+                    - (-SECOND_RADIATION_CONSTANT * wavenumber / self.temperature).exp())
+                .abs();
+                if self.reduced {
+                    factor *= wavenumber.abs();
+                }
+                dataset.data[[i, j]] *= factor;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for BoseEinsteinTransform {
+    fn default() -> Self {
+        let mut bet = BoseEinsteinTransform {
+            temperature: 293.15,
+            reduced: false,
+            gui_text_buffers: BoseEinsteinIOBuffers::default(),
+        };
+        bet.update_text_buffers();
+        bet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BoseEinsteinTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_bose_einstein_correction() {
+        let mut dataset = Dataset {
+            data: array![[-100.0, 1.0], [0.0, 1.0], [100.0, 1.0], [3000.0, 1.0]],
+            ..Default::default()
+        };
+        BoseEinsteinTransform {
+            temperature: 300.0,
+            ..Default::default()
+        }
+        .transform(&mut dataset)
+        .unwrap();
+        let x: f64 = 1.438_776_877 * 100.0 / 300.0;
+        assert!((dataset.data[[0, 1]] - (x.exp() - 1.0)).abs() < 1e-12);
+        assert_eq!(dataset.data[[1, 1]], 0.0);
+        assert!((dataset.data[[2, 1]] - (1.0 - (-x).exp())).abs() < 1e-12);
+        // high wavenumbers are hardly affected
+        assert!((dataset.data[[3, 1]] - 1.0).abs() < 1e-5);
+        // the reduced intensity removes the frequency factor as well
+        let mut dataset = Dataset {
+            data: array![[-100.0, 1.0], [0.0, 1.0], [100.0, 1.0]],
+            ..Default::default()
+        };
+        BoseEinsteinTransform {
+            temperature: 300.0,
+            reduced: true,
+            ..Default::default()
+        }
+        .transform(&mut dataset)
+        .unwrap();
+        assert!((dataset.data[[0, 1]] - 100.0 * (x.exp() - 1.0)).abs() < 1e-9);
+        assert_eq!(dataset.data[[1, 1]], 0.0);
+        assert!((dataset.data[[2, 1]] - 100.0 * (1.0 - (-x).exp())).abs() < 1e-9);
+    }
+}
@@ -1,6 +1,7 @@
-use crate::common::{Dataset, Pair};
-use crate::transformations::Transformer;
-use anyhow::Result;
+use crate::common::{Dataset, DatasetShape, Pair};
+use crate::transformations::{PipelineContext, Transformer};
+use crate::utils::polyfit;
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
@@ -9,25 +10,257 @@ use serde::{Deserialize, Serialize};
 pub struct CalibrationTransform {
     #[clap(short, long, help = "x,y reference data points for calibration.")]
     pub(crate) points: Vec<Pair<f64>>,
+    #[clap(
+        long,
+        help = "Number of a frame holding a lamp spectrum, used to calibrate automatically against reference lines."
+    )]
+    #[serde(default)]
+    pub(crate) lamp_frame: Option<usize>,
+    #[clap(
+        long,
+        help = "Built-in catalog of reference lines (\"neon\" or \"argon\") for the automatic calibration."
+    )]
+    #[serde(default)]
+    pub(crate) lamp: Option<Lamp>,
+    #[clap(
+        long,
+        help = "Additional reference line positions for the automatic calibration."
+    )]
+    #[serde(default)]
+    pub(crate) reference_lines: Vec<f64>,
+    #[clap(
+        long,
+        default_value("1"),
+        help = "Order of the calibration polynomial (1 to 4) fitted to the points or matched lines."
+    )]
+    #[serde(default = "default_order")]
+    pub(crate) order: usize,
+    #[clap(
+        long,
+        default_value("0.5"),
+        help = "Largest distance between a detected peak and a reference line to be matched."
+    )]
+    #[serde(default = "default_tolerance")]
+    pub(crate) tolerance: f64,
+    #[clap(
+        long,
+        default_value("0.05"),
+        help = "Smallest height of detected peaks, relative to the largest peak."
+    )]
+    #[serde(default = "default_min_height")]
+    pub(crate) min_height: f64,
+    /// residuals (reference - calibrated) of the last fit, reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) residuals: Vec<f64>,
+}
+
+fn default_order() -> usize {
+    1
+}
+
+fn default_tolerance() -> f64 {
+    0.5
+}
+
+fn default_min_height() -> f64 {
+    0.05
+}
+
+/// Calibration lamps with a built-in catalog of emission lines.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lamp {
+    Neon,
+    Argon,
+}
+
+impl Lamp {
+    /// wavelengths of strong emission lines in air, in nm
+    pub fn lines(&self) -> &'static [f64] {
+        match self {
+            Lamp::Neon => &[
+                540.056, 585.249, 588.190, 594.483, 597.553, 602.000, 607.434, 609.616, 614.306,
+                616.359, 621.728, 626.650, 630.479, 633.443, 638.299, 640.225, 650.653, 653.288,
+                659.895, 667.828, 671.704, 692.947, 703.241, 717.394, 724.517, 743.890,
+            ],
+            Lamp::Argon => &[
+                696.543, 706.722, 714.704, 727.294, 738.398, 750.387, 751.465, 763.511, 772.376,
+                794.818, 800.616, 801.479, 810.369, 811.531, 826.452, 840.821, 842.465, 852.144,
+                866.794, 912.297, 922.450,
+            ],
+        }
+    }
+}
+
+impl std::str::FromStr for Lamp {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "neon" => Ok(Lamp::Neon),
+            "argon" => Ok(Lamp::Argon),
+            _ => Err(anyhow!("unknown lamp \"{}\", use \"neon\" or \"argon\"", s)),
+        }
+    }
 }
 
 impl Transformer for CalibrationTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        self.lamp_frame.is_some()
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if !(1..=4).contains(&self.order) {
+            problems.push(format!(
+                "calibration polynomial order must be between 1 and 4, got {}",
+                self.order
+            ));
+        }
+        if let Some(problem) = self.lamp_frame.and_then(|frame| shape.check_frame(frame)) {
+            problems.push(problem);
+        }
+        // the calibrated x-axis is only known after fitting
+        shape.x_range = None;
+        problems
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
-        if let Some((slope, intercept)) = linregress(&self.points) {
-            // Iterate over all x-axes
-            for xs in dataset.data.axis_iter_mut(ndarray::Axis(1)).step_by(2) {
-                for x in xs {
-                    *x = *x * slope + intercept
-                }
+        if !(1..=4).contains(&self.order) {
+            return Err(anyhow!(
+                "calibration polynomial order must be between 1 and 4, got {}",
+                self.order
+            ));
+        }
+        self.residuals = vec![];
+        let calibration: Box<dyn Fn(f64) -> f64> = if let Some(frame) = self.lamp_frame {
+            let matches = self.match_lamp_lines(dataset, frame)?;
+            let (measured, reference): (Vec<f64>, Vec<f64>) =
+                matches.iter().map(|Pair { a, b }| (*a, *b)).unzip();
+            let poly = polyfit(&measured, &reference, self.order).map_err(|e| {
+                anyhow!(
+                    "automatic calibration matched {} lines: {}",
+                    matches.len(),
+                    e
+                )
+            })?;
+            self.residuals = matches.iter().map(|p| p.b - poly.eval(p.a)).collect();
+            Box::new(move |x| poly.eval(x))
+        } else if self.order > 1 {
+            let (measured, reference): (Vec<f64>, Vec<f64>) =
+                self.points.iter().map(|Pair { a, b }| (*a, *b)).unzip();
+            let poly = polyfit(&measured, &reference, self.order)?;
+            self.residuals = self.points.iter().map(|p| p.b - poly.eval(p.a)).collect();
+            Box::new(move |x| poly.eval(x))
+        } else if let Some((slope, intercept)) = linregress(&self.points) {
+            self.residuals = self
+                .points
+                .iter()
+                .map(|p| p.b - (p.a * slope + intercept))
+                .collect();
+            Box::new(move |x| x * slope + intercept)
+        } else {
+            return Ok(());
+        };
+        // Iterate over all x-axes
+        for xs in dataset.data.axis_iter_mut(ndarray::Axis(1)).step_by(2) {
+            for x in xs {
+                *x = calibration(*x)
+            }
+        }
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        // report quality of the fit, so calibrations can be judged later on
+        if !self.residuals.is_empty() {
+            let rms = (self.residuals.iter().map(|r| r.powi(2)).sum::<f64>()
+                / self.residuals.len() as f64)
+                .sqrt();
+            dataset.metadata += "residuals:\n";
+            for r in self.residuals.iter() {
+                dataset.metadata += &format!("- {}\n", r);
             }
+            dataset.metadata += &format!("rms_residual: {}\n", rms);
         }
+        dataset.metadata += "---\n";
         Ok(())
     }
 }
 
+impl CalibrationTransform {
+    /// Detect peaks in the lamp spectrum and pair each with the closest
+    /// reference line within tolerance, returns (measured, reference) pairs.
+    pub fn match_lamp_lines(&self, dataset: &Dataset, frame: usize) -> Result<Vec<Pair<f64>>> {
+        dataset.verify_one_frame_in_bounds(frame)?;
+        let mut reference_lines = self.reference_lines.clone();
+        if let Some(lamp) = self.lamp {
+            reference_lines.extend_from_slice(lamp.lines());
+        }
+        if reference_lines.is_empty() {
+            return Err(anyhow!(
+                "automatic calibration requires reference lines, use --lamp or --reference-lines"
+            ));
+        }
+        let xs = dataset.data.column((frame - 1) * 2);
+        let ys = dataset.data.column((frame - 1) * 2 + 1);
+        let peaks = find_peaks(&xs.to_vec(), &ys.to_vec(), self.min_height);
+        // every reference line is matched to the closest peak only
+        let mut matches: Vec<Pair<f64>> = vec![];
+        for line in reference_lines.iter() {
+            let closest = peaks
+                .iter()
+                .map(|peak| (peak, (peak - line).abs()))
+                .filter(|(_, distance)| *distance <= self.tolerance)
+                .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+            if let Some((peak, _)) = closest {
+                matches.push(Pair { a: *peak, b: *line });
+            }
+        }
+        // a peak matching several lines is ambiguous, drop it
+        let mut unique: Vec<Pair<f64>> = matches
+            .iter()
+            .filter(|p| matches.iter().filter(|q| q.a == p.a).count() == 1)
+            .copied()
+            .collect();
+        unique.sort_by(|p, q| p.a.total_cmp(&q.a));
+        Ok(unique)
+    }
+}
+
+/// Find local maxima higher than `min_height` times the highest value (above
+/// the median), their positions are refined by fitting a parabola through
+/// the maximum and its neighbors.
+fn find_peaks(xs: &[f64], ys: &[f64], min_height: f64) -> Vec<f64> {
+    let mut sorted: Vec<f64> = ys.iter().copied().filter(|y| y.is_finite()).collect();
+    if sorted.len() < 3 {
+        return vec![];
+    }
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+    let max = sorted[sorted.len() - 1];
+    let threshold = median + min_height * (max - median);
+    (1..ys.len() - 1)
+        .filter(|&i| ys[i] > threshold && ys[i] > ys[i - 1] && ys[i] >= ys[i + 1])
+        .map(|i| {
+            let (y0, y1, y2) = (ys[i - 1], ys[i], ys[i + 1]);
+            let denominator = y0 - 2.0 * y1 + y2;
+            // offset of the vertex in units of pixels
+            let offset = if denominator != 0.0 {
+                (0.5 * (y0 - y2) / denominator).clamp(-0.5, 0.5)
+            } else {
+                0.0
+            };
+            if offset >= 0.0 {
+                xs[i] + offset * (xs[i + 1] - xs[i])
+            } else {
+                xs[i] + offset * (xs[i] - xs[i - 1])
+            }
+        })
+        .collect()
+}
+
 fn linregress(pts: &[Pair<f64>]) -> Option<(f64, f64)> {
     // Zero reference points cannot be processed.
     if pts.len() == 0 {
@@ -60,3 +293,72 @@ fn linregress(pts: &[Pair<f64>]) -> Option<(f64, f64)> {
 
     Some((slope, intercept))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CalibrationTransform;
+    use crate::{
+        common::{Dataset, Pair},
+        transformations::{PipelineContext, Transformer},
+    };
+    use ndarray::{array, Array2};
+
+    #[test]
+    fn test_lamp_calibration() {
+        // lamp spectrum with gaussian lines, measured on an axis off by 1.2 nm
+        let lines = [585.249, 594.483, 614.306, 640.225];
+        let data = Array2::from_shape_fn((2000, 2), |(i, j)| {
+            let x_true = 580.0 + i as f64 * 0.05;
+            if j == 0 {
+                x_true - 1.2
+            } else {
+                lines
+                    .iter()
+                    .map(|l| 100.0 * (-(x_true - l).powi(2) / 0.02).exp())
+                    .sum::<f64>()
+            }
+        });
+        let mut dataset = Dataset {
+            data,
+            ..Default::default()
+        };
+        let mut trsf = CalibrationTransform {
+            lamp_frame: Some(1),
+            reference_lines: lines.to_vec(),
+            order: 1,
+            tolerance: 2.0,
+            min_height: 0.05,
+            ..Default::default()
+        };
+        trsf.transform(&mut dataset).unwrap();
+        assert!((dataset.data[[0, 0]] - 580.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_polynomial_calibration() {
+        let mut dataset = Dataset {
+            data: array![[1.0, 0.0], [2.0, 0.0], [3.0, 0.0]],
+            ..Default::default()
+        };
+        // points on y = x^2 + 1
+        let mut trsf = CalibrationTransform {
+            points: [0.0, 1.0, 2.0, 4.0]
+                .iter()
+                .map(|x| Pair {
+                    a: *x,
+                    b: x * x + 1.0,
+                })
+                .collect(),
+            order: 2,
+            ..Default::default()
+        };
+        trsf.apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        for (x, expected) in dataset.data.column(0).iter().zip([2.0, 5.0, 10.0]) {
+            assert!((x - expected).abs() < 1e-9);
+        }
+        assert!(dataset.metadata.contains("rms_residual: "));
+        trsf.order = 5;
+        assert!(trsf.transform(&mut dataset).is_err());
+    }
+}
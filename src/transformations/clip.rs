@@ -0,0 +1,118 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::Result;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Raise intensities below a floor to the floor, or make them missing
+/// values, e.g. the negative noise left by subtracting a dark frame. Other
+/// transformations keep negative intensities, so clipping is only done where
+/// it is asked for.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct ClipTransform {
+    #[clap(
+        short,
+        long,
+        default_value = "0",
+        help = "Intensities below this value are clipped."
+    )]
+    #[serde(default)]
+    pub(crate) floor: f64,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Replace clipped intensities by missing values instead of the floor."
+    )]
+    #[serde(default)]
+    pub(crate) nan: bool,
+    #[clap(short, long, help = "Clip only these frames.")]
+    pub(crate) target_frames: Option<Vec<usize>>,
+    #[serde(skip)]
+    #[clap(skip)]
+    pub gui_text_buffers: ClipIOBuffers,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct ClipIOBuffers {
+    pub floor: String,
+}
+
+impl Default for ClipTransform {
+    fn default() -> Self {
+        Self {
+            floor: 0.0,
+            nan: false,
+            target_frames: None,
+            gui_text_buffers: ClipIOBuffers {
+                floor: "0".to_owned(),
+            },
+        }
+    }
+}
+
+impl Transformer for ClipTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        self.target_frames.is_some()
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn is_frame_local(&self) -> bool {
+        self.target_frames.is_none()
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        self.target_frames
+            .iter()
+            .flatten()
+            .filter_map(|frame| shape.check_frame(*frame))
+            .collect()
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        if let Some(frames) = &self.target_frames {
+            dataset.verify_frames_in_bounds(frames)?;
+        }
+        let clipped = if self.nan { f64::NAN } else { self.floor };
+        for (col_no, mut vals) in dataset.iter_mut_frames().enumerate() {
+            if self
+                .target_frames
+                .as_ref()
+                .is_some_and(|frames| !frames.contains(&(col_no + 1)))
+            {
+                continue;
+            }
+            vals.mapv_inplace(|y| if y < self.floor { clipped } else { y });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClipTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_clip() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![[1.0, -2.0, 1.0, -4.0], [2.0, 4.0, 2.0, 0.5]];
+        let mut trsf = ClipTransform {
+            target_frames: Some(vec![1]),
+            ..Default::default()
+        };
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(
+            dataset.data,
+            array![[1.0, 0.0, 1.0, -4.0], [2.0, 4.0, 2.0, 0.5]]
+        );
+        trsf.target_frames = None;
+        trsf.floor = 1.0;
+        trsf.nan = true;
+        trsf.transform(&mut dataset).unwrap();
+        assert!(dataset.data.column(1)[0].is_nan() && dataset.data.column(3)[0].is_nan());
+        assert!(dataset.data.column(3)[1].is_nan());
+        assert_eq!(dataset.data[[1, 1]], 4.0);
+    }
+}
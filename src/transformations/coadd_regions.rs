@@ -0,0 +1,229 @@
+use super::{PipelineContext, Transformer};
+use crate::common::{value_from_comments, Dataset, DatasetShape};
+use crate::utils::linear_resample_array;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{Array1, Array2};
+use serde::{Deserialize, Serialize};
+
+/// Co-add the regions of interest of each exposure into a single spectrum,
+/// e.g. of several fibers imaged onto the same sensor. The regions of an
+/// exposure are consecutive frames, as read from an SPE file with several
+/// regions of interest. Each region is weighted, e.g. by the quantum
+/// efficiency of its part of the sensor, and interpolated onto the x-axis of
+/// the first region before adding it.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct CoaddRegionsTransform {
+    #[clap(
+        short,
+        long,
+        help = "Number of regions of interest of each exposure, taken from the comments of the input (\"regions = N\") if omitted."
+    )]
+    #[serde(default)]
+    pub(crate) regions: Option<usize>,
+    #[clap(
+        short,
+        long,
+        help = "Weight of each region of interest, all 1 if omitted."
+    )]
+    #[serde(default)]
+    pub(crate) weights: Option<Vec<f64>>,
+    #[clap(
+        short = 'm',
+        long,
+        parse(from_os_str),
+        help = "CSV file with one frame per region of interest holding the weight of each pixel (e.g. from a quantum efficiency or flat field map), interpolated onto the x-axis of the region."
+    )]
+    #[serde(default)]
+    pub(crate) weight_map: Option<std::path::PathBuf>,
+}
+
+impl CoaddRegionsTransform {
+    pub fn new(regions: Option<usize>) -> Self {
+        Self {
+            regions,
+            weights: None,
+            weight_map: None,
+        }
+    }
+
+    /// Number of regions of each exposure, given or read from the comments.
+    fn region_count(&self, dataset: &Dataset) -> Result<usize> {
+        let regions = match self.regions {
+            Some(regions) => regions,
+            None => value_from_comments(&dataset.previous_comments, "regions")
+                .map(|regions| regions as usize)
+                .ok_or_else(|| {
+                    anyhow!("number of regions of interest is unknown, use --regions")
+                })?,
+        };
+        if regions == 0 {
+            return Err(anyhow!("number of regions of interest must be positive"));
+        }
+        Ok(regions)
+    }
+}
+
+impl Transformer for CoaddRegionsTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        true
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if self.regions == Some(0) {
+            problems.push("number of regions of interest must be positive".to_owned());
+        }
+        if let (Some(weights), Some(regions)) = (&self.weights, self.regions) {
+            if weights.len() != regions {
+                problems.push(format!(
+                    "{} weights given for {} regions of interest",
+                    weights.len(),
+                    regions
+                ));
+            }
+        }
+        if let Some(path) = &self.weight_map {
+            if !context.resolve_path(path).exists() {
+                problems.push(format!("weight map {:?} does not exist", path));
+            }
+        }
+        match (shape.frames, self.regions) {
+            (Some(frames), Some(regions)) if regions > 0 => {
+                if !frames.is_multiple_of(regions) {
+                    problems.push(format!(
+                        "{} frames can not be split into exposures of {} regions of interest",
+                        frames, regions
+                    ));
+                }
+                shape.frames = Some(frames / regions);
+            }
+            _ => shape.frames = None,
+        }
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        let regions = self.region_count(dataset)?;
+        let n_frames = dataset.data.ncols() / 2;
+        if !n_frames.is_multiple_of(regions) {
+            return Err(anyhow!(
+                "{} frames can not be split into exposures of {} regions of interest",
+                n_frames,
+                regions
+            ));
+        }
+        let weights = match &self.weights {
+            Some(weights) if weights.len() != regions => {
+                return Err(anyhow!(
+                    "{} weights given for {} regions of interest",
+                    weights.len(),
+                    regions
+                ))
+            }
+            Some(weights) => weights.clone(),
+            None => vec![1.0; regions],
+        };
+        let weight_map = match &self.weight_map {
+            Some(path) => {
+                let map = Dataset::from_csv(&Some(context.resolve_path(path)), '#', ',')?;
+                if map.data.ncols() / 2 != regions {
+                    return Err(anyhow!(
+                        "weight map holds {} frames, one per region of interest ({}) is needed",
+                        map.data.ncols() / 2,
+                        regions
+                    ));
+                }
+                Some(map)
+            }
+            None => None,
+        };
+        let nrows = dataset.data.nrows();
+        let mut coadded = Array2::zeros((nrows, 2 * n_frames / regions));
+        for exposure in 0..n_frames / regions {
+            let first = exposure * regions;
+            let grid = dataset.data.column(2 * first);
+            let mut sum = Array1::<f64>::zeros(nrows);
+            for (r, weight) in weights.iter().enumerate() {
+                let xs = dataset.data.column(2 * (first + r));
+                let ys = dataset.data.column(2 * (first + r) + 1);
+                let mut weighted = ys.to_owned() * *weight;
+                if let Some(map) = &weight_map {
+                    weighted *= &linear_resample_array(
+                        &map.data.column(2 * r),
+                        &map.data.column(2 * r + 1),
+                        &xs,
+                    );
+                }
+                // regions usually share the columns of the sensor
+                if xs != grid {
+                    weighted = linear_resample_array(&xs, &weighted, &grid);
+                }
+                sum += &weighted;
+            }
+            coadded.column_mut(2 * exposure).assign(&grid);
+            coadded.column_mut(2 * exposure + 1).assign(&sum);
+        }
+        let frame_metadata = dataset
+            .frame_metadata
+            .iter()
+            .step_by(regions)
+            .cloned()
+            .map(|mut meta| {
+                meta.label = None;
+                meta
+            })
+            .collect();
+        dataset.data = coadded;
+        dataset.frame_metadata = frame_metadata;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CoaddRegionsTransform;
+    use crate::common::Dataset;
+    use crate::transformations::Transformer;
+    use ndarray::array;
+
+    #[test]
+    fn test_coadd_regions() {
+        // two exposures of two regions, the second region of the second
+        // exposure on a shifted axis
+        let mut dataset = Dataset {
+            data: array![
+                [1.0, 10.0, 1.0, 1.0, 1.0, 20.0, 1.5, 2.0],
+                [2.0, 20.0, 2.0, 2.0, 2.0, 40.0, 2.5, 4.0],
+                [3.0, 30.0, 3.0, 3.0, 3.0, 60.0, 3.5, 6.0]
+            ],
+            previous_comments: "# regions = 2\n".to_owned(),
+            ..Default::default()
+        };
+        let input = dataset.clone();
+        let mut coadd = CoaddRegionsTransform::new(None);
+        coadd.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.ncols(), 4);
+        assert_eq!(dataset.data.column(0), input.data.column(0));
+        assert_eq!(dataset.data.column(1), array![11.0, 22.0, 33.0]);
+        // interpolated onto the x-axis of the first region
+        assert_eq!(dataset.data[[1, 3]], 40.0 + 3.0);
+        assert!(dataset.data[[0, 3]].is_nan());
+        // weights of the regions
+        let mut dataset = input.clone();
+        coadd.weights = Some(vec![1.0, 0.5]);
+        coadd.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.column(1), array![10.5, 21.0, 31.5]);
+        coadd.weights = Some(vec![1.0]);
+        assert!(coadd.transform(&mut input.clone()).is_err());
+        // three regions do not fit four frames
+        let mut coadd = CoaddRegionsTransform::new(Some(3));
+        assert!(coadd.transform(&mut input.clone()).is_err());
+    }
+}
@@ -0,0 +1,351 @@
+use crate::common::{AxisUnit, Dataset, DatasetShape};
+use crate::gui::TransformerGUI;
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Run a step only if the dataset entering it fulfils a condition, e.g. to
+/// average frames only if there is more than one. In the YAML header, the
+/// condition is written as `when: ...` entry of the step.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+pub struct ConditionalTransform {
+    #[clap(
+        help = "Condition on the dataset, e.g. \"nframes > 1\" or \"xmax > 3500 and npoints >= 100\" (properties: nframes, npoints, xmin, xmax)."
+    )]
+    pub(crate) when: Condition,
+    /// the step that runs if the condition holds, the next command on the
+    /// command line
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) step: Option<Box<dyn TransformerGUI>>,
+    /// whether the step was skipped in the last run, reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) skipped: bool,
+    #[serde(skip)]
+    #[clap(skip)]
+    pub gui_text_buffer: String,
+}
+
+impl ConditionalTransform {
+    pub fn new(when: Condition, step: Box<dyn TransformerGUI>) -> Self {
+        Self {
+            gui_text_buffer: when.to_string(),
+            when,
+            step: Some(step),
+            skipped: false,
+        }
+    }
+    fn step(&self) -> Result<&dyn TransformerGUI> {
+        self.step
+            .as_deref()
+            .ok_or_else(|| anyhow!("no step follows the condition \"{}\"", self.when))
+    }
+}
+
+/// One or more comparisons of a property of the dataset with a number,
+/// joined by "and".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Condition {
+    expression: String,
+    comparisons: Vec<Comparison>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Comparison {
+    property: Property,
+    operator: Operator,
+    value: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Property {
+    Frames,
+    Points,
+    XMin,
+    XMax,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Less,
+    LessEqual,
+    Equal,
+    NotEqual,
+    GreaterEqual,
+    Greater,
+}
+
+impl Property {
+    fn value(self, shape: &DatasetShape) -> Option<f64> {
+        match self {
+            Property::Frames => shape.frames.map(|n| n as f64),
+            Property::Points => shape.rows.map(|n| n as f64),
+            Property::XMin => shape.x_range.map(|(min, _)| min),
+            Property::XMax => shape.x_range.map(|(_, max)| max),
+        }
+    }
+}
+
+impl Operator {
+    fn compare(self, a: f64, b: f64) -> bool {
+        match self {
+            Operator::Less => a < b,
+            Operator::LessEqual => a <= b,
+            Operator::Equal => a == b,
+            Operator::NotEqual => a != b,
+            Operator::GreaterEqual => a >= b,
+            Operator::Greater => a > b,
+        }
+    }
+}
+
+impl std::str::FromStr for Comparison {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // two character operators first, "<" is also found in "<="
+        const OPERATORS: [(&str, Operator); 6] = [
+            ("<=", Operator::LessEqual),
+            (">=", Operator::GreaterEqual),
+            ("==", Operator::Equal),
+            ("!=", Operator::NotEqual),
+            ("<", Operator::Less),
+            (">", Operator::Greater),
+        ];
+        let (position, symbol, operator) = OPERATORS
+            .iter()
+            .find_map(|(symbol, operator)| s.find(symbol).map(|pos| (pos, symbol, *operator)))
+            .ok_or_else(|| {
+                anyhow!(
+                    "no comparison in \"{}\", use one of <, <=, ==, !=, >=, >",
+                    s.trim()
+                )
+            })?;
+        let property = match s[..position].trim() {
+            "nframes" => Property::Frames,
+            "npoints" => Property::Points,
+            "xmin" => Property::XMin,
+            "xmax" => Property::XMax,
+            other => {
+                return Err(anyhow!(
+                    "unknown property \"{}\", use nframes, npoints, xmin or xmax",
+                    other
+                ))
+            }
+        };
+        let value = s[position + symbol.len()..].trim();
+        let value = value
+            .parse::<f64>()
+            .map_err(|_| anyhow!("could not parse \"{}\" as number", value))?;
+        Ok(Comparison {
+            property,
+            operator,
+            value,
+        })
+    }
+}
+
+impl std::str::FromStr for Condition {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = s.trim().to_owned();
+        let comparisons = expression
+            .split(" and ")
+            .map(|comparison| comparison.parse())
+            .collect::<Result<Vec<Comparison>>>()?;
+        Ok(Condition {
+            expression,
+            comparisons,
+        })
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = anyhow::Error;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> Self {
+        condition.expression
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expression)
+    }
+}
+
+impl Default for Condition {
+    fn default() -> Self {
+        "nframes > 1".parse().unwrap()
+    }
+}
+
+impl Condition {
+    /// Whether the dataset of the given shape fulfils the condition, None if
+    /// that depends on a property that is not known.
+    pub fn evaluate(&self, shape: &DatasetShape) -> Option<bool> {
+        let mut known = true;
+        for comparison in self.comparisons.iter() {
+            match comparison.property.value(shape) {
+                Some(value) if !comparison.operator.compare(value, comparison.value) => {
+                    return Some(false)
+                }
+                Some(_) => {}
+                None => known = false,
+            }
+        }
+        known.then_some(true)
+    }
+}
+
+impl Transformer for ConditionalTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        // conditions test e.g. the number of frames or points
+        true
+    }
+    fn config_to_string(&self) -> Result<String> {
+        let config = self.step()?.config_to_string()?;
+        let when = serde_yaml::to_string(&self.when).map_err(anyhow::Error::msg)?;
+        Ok(config + "when: " + when.as_str())
+    }
+    fn validate(&self, shape: &mut DatasetShape, context: &PipelineContext) -> Vec<String> {
+        let step = match self.step() {
+            Ok(step) => step,
+            Err(err) => return vec![err.to_string()],
+        };
+        match self.when.evaluate(shape) {
+            Some(true) => step.validate(shape, context),
+            Some(false) => vec![],
+            None => {
+                // the step may or may not run, whatever it changes is unknown
+                let mut changed = shape.clone();
+                let problems = step
+                    .validate(&mut changed, context)
+                    .into_iter()
+                    .map(|problem| format!("if {}: {}", self.when, problem))
+                    .collect();
+                if changed.rows != shape.rows {
+                    shape.rows = None;
+                }
+                if changed.frames != shape.frames {
+                    shape.frames = None;
+                }
+                if changed.x_range != shape.x_range {
+                    shape.x_range = None;
+                }
+                if changed.x_unit != shape.x_unit {
+                    shape.x_unit = AxisUnit::Unknown;
+                }
+                problems
+            }
+        }
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        let holds = self.when.evaluate(&dataset.shape()).ok_or_else(|| {
+            anyhow!(
+                "cannot evaluate condition \"{}\", the dataset holds no x-values",
+                self.when
+            )
+        })?;
+        self.skipped = !holds;
+        match self.step.as_mut() {
+            None => Err(anyhow!("no step follows the condition \"{}\"", self.when)),
+            Some(_) if !holds => Ok(()),
+            Some(step) => step.transform_with_context(dataset, context),
+        }
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        let when = serde_yaml::to_string(&self.when).map_err(anyhow::Error::msg)?;
+        if self.skipped {
+            dataset.metadata += &self.config_to_string()?;
+            dataset.metadata += "skipped: true\n---\n";
+            return Ok(());
+        }
+        // the step writes its own reports, the condition goes before the
+        // end of its segment
+        self.step()?.write_metadata_yaml(dataset)?;
+        if let Some(segment) = dataset.metadata.strip_suffix("---\n") {
+            dataset.metadata = segment.to_owned();
+        }
+        dataset.metadata += "when: ";
+        dataset.metadata += &when;
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Condition;
+    use crate::{
+        common::{Dataset, DatasetShape, Pipeline},
+        transformations::PipelineContext,
+    };
+
+    #[test]
+    fn test_condition() {
+        let shape = Dataset::new_test_dummy().shape();
+        let holds = |expression: &str, shape: &DatasetShape| {
+            expression.parse::<Condition>().unwrap().evaluate(shape)
+        };
+        assert_eq!(holds("nframes > 3", &shape), Some(true));
+        assert_eq!(holds("nframes>=5", &shape), Some(false));
+        assert_eq!(holds("npoints == 8 and xmax < 100", &shape), Some(true));
+        assert_eq!(holds("xmin != 11 and npoints == 8", &shape), Some(false));
+        let unknown = DatasetShape::default();
+        assert_eq!(holds("nframes > 3", &unknown), None);
+        assert!("nframes".parse::<Condition>().is_err());
+        assert!("width > 3".parse::<Condition>().is_err());
+        assert!("nframes > three".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn test_conditional_step() {
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let when = |condition: &str| vec!["when".to_owned(), condition.to_owned()];
+        let mut pipeline = Pipeline::from_cli_args(vec![
+            when("nframes > 1"),
+            args("average"),
+            when("nframes > 1"),
+            args("offset 10"),
+            args("offset 1"),
+        ]);
+        assert_eq!(pipeline.transformations.len(), 3);
+        let mut dataset = Dataset::new_test_dummy_shared_x();
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        // the frames are averaged, the first offset then skipped
+        assert_eq!(dataset.data.ncols(), 2);
+        assert_eq!(dataset.data[[0, 1]], 15.0 + 1.0);
+        assert!(dataset
+            .metadata
+            .contains("when: nframes > 1\nskipped: true\n"));
+        // the condition is read back from the YAML header
+        let parsed = Pipeline::from_yaml_header(&dataset.metadata).unwrap();
+        assert_eq!(parsed.transformations.len(), 3);
+        for (parsed, step) in parsed.transformations.iter().zip(&pipeline.transformations) {
+            assert_eq!(
+                parsed.config_to_string().unwrap(),
+                step.config_to_string().unwrap()
+            );
+        }
+        // an unknown number of frames leaves the number of frames unknown
+        let mut shape = DatasetShape::default();
+        assert!(pipeline.transformations[0]
+            .validate(&mut shape, &PipelineContext::default())
+            .is_empty());
+        assert_eq!(shape.frames, None);
+    }
+}
@@ -1,7 +1,8 @@
-use crate::common::Dataset;
+use crate::common::{Dataset, DatasetShape};
+use crate::config::UserConfig;
 use crate::gui::TransformerGUI;
-use crate::transformations::Transformer;
-use anyhow::Result;
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
@@ -10,12 +11,12 @@ use serde::{Deserialize, Serialize};
 pub struct CountConversionTransform {
     #[clap(help = "CCD exposure time in seconds.")]
     pub(crate) exposure: f64,
-    // default value from PyLoN calibration certificate
     #[clap(
         short,
         long,
-        help = "Count to photoelectron conversion factor.",
-        default_value_t = 1.42857
+        help = "Count to photoelectron conversion factor, 1.42857 unless set in the config file.",
+        default_value = "NaN",
+        hide_default_value = true
     )]
     pub(crate) conversion_factor: f64,
     #[serde(skip)]
@@ -33,20 +34,46 @@ impl Transformer for CountConversionTransform {
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn is_frame_local(&self) -> bool {
+        true
+    }
+    fn use_config_defaults(&mut self, config: &UserConfig) {
+        if self.conversion_factor.is_nan() {
+            self.conversion_factor = default_conversion_factor(config);
+        }
+    }
+    fn validate(&self, _shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        // negative counts (e.g. after subtracting a dark frame) stay negative,
+        // but a negative factor would flip the sign of all of them
+        let mut problems = vec![];
+        if self.exposure <= 0.0 {
+            problems.push("exposure time must be positive".to_owned());
+        }
+        if self.conversion_factor <= 0.0 {
+            problems.push("conversion factor must be positive".to_owned());
+        }
+        problems
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        if self.exposure <= 0.0 || self.conversion_factor <= 0.0 {
+            return Err(anyhow!(
+                "exposure time and conversion factor must be positive"
+            ));
+        }
         let num_rows = dataset.data.nrows();
         let num_cols = dataset.data.ncols();
         let mut prev_dx = 1.0;
         for j in (1..num_cols).step_by(2) {
             for i in 0..num_rows {
-                let dx;
-                if i == num_rows - 1 {
-                    dx = prev_dx;
-                } else {
-                    // j - 1 : wavelength axes
-                    dx = (dataset.data[[i + 1, j - 1]] - dataset.data[[i, j - 1]]).abs();
-                    prev_dx = dx;
-                }
+                // j - 1 : wavelength axes; the last pixel and pixels next to
+                // a missing x-value (e.g. padding of a shorter frame) take
+                // the width of the previous pixel, missing intensities stay
+                // missing
+                let dx = (i + 1 < num_rows)
+                    .then(|| (dataset.data[[i + 1, j - 1]] - dataset.data[[i, j - 1]]).abs())
+                    .filter(|dx| dx.is_finite())
+                    .unwrap_or(prev_dx);
+                prev_dx = dx;
                 dataset.data[[i, j]] /= dx * self.exposure * self.conversion_factor;
             }
         }
@@ -54,14 +81,51 @@ impl Transformer for CountConversionTransform {
     }
 }
 
+/// Conversion factor of the config file, otherwise the one of the PyLoN
+/// calibration certificate.
+fn default_conversion_factor(config: &UserConfig) -> f64 {
+    config.conversion_factor.unwrap_or(1.42857)
+}
+
 impl Default for CountConversionTransform {
     fn default() -> Self {
         let mut cct = CountConversionTransform {
             exposure: 300.0,
-            conversion_factor: 1.42857,
+            conversion_factor: default_conversion_factor(UserConfig::global()),
             gui_text_buffers: CountConversionIOBuffers::default(),
         };
         cct.update_text_buffers();
         cct
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CountConversionTransform;
+    use crate::common::Dataset;
+    use crate::transformations::Transformer;
+    use ndarray::array;
+
+    #[test]
+    fn test_missing_values() {
+        let mut dataset = Dataset::new_test_dummy();
+        // the first frame is one point shorter than the second
+        dataset.data = array![
+            [1.0, 2.0, 1.0, 2.0],
+            [3.0, 4.0, 3.0, f64::NAN],
+            [f64::NAN, f64::NAN, 5.0, 6.0]
+        ];
+        let mut trsf = CountConversionTransform {
+            exposure: 1.0,
+            conversion_factor: 1.0,
+            ..Default::default()
+        };
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(
+            dataset.data.column(1).slice(ndarray::s![..2]),
+            array![1.0, 2.0]
+        );
+        assert!(dataset.data[[2, 1]].is_nan() && dataset.data[[1, 3]].is_nan());
+        assert_eq!(dataset.data[[2, 3]], 3.0);
+    }
+}
@@ -0,0 +1,384 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Progress, Transformer};
+use crate::utils::fft;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{ArrayView1, ArrayViewMut1};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{LN_2, PI};
+
+/// Narrow bands by removing the broadening of the instrument (or of the
+/// bands themselves), e.g. to resolve overlapping bands before integrating
+/// them. The frames must be evenly spaced along the x-axis.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct DeconvolveTransform {
+    #[clap(help = "FWHM of the line shape that is removed, in units of the x-axis.")]
+    pub(crate) fwhm: f64,
+    #[clap(
+        short,
+        long,
+        default_value("richardson-lucy"),
+        help = "Deconvolution algorithm: \"richardson-lucy\" (iterative, keeps intensities positive) or \"fsd\" (Fourier self-deconvolution, the bands become gaussians narrower by the narrowing factor)."
+    )]
+    #[serde(default)]
+    pub(crate) method: DeconvolutionMethod,
+    #[clap(
+        short,
+        long,
+        default_value("gaussian"),
+        help = "Line shape that is removed: gaussian (e.g. the slit function of the spectrometer) or lorentzian (e.g. the natural shape of the bands)."
+    )]
+    #[serde(default)]
+    pub(crate) shape: LineShape,
+    #[clap(
+        short,
+        long,
+        default_value("20"),
+        help = "Number of iterations of the richardson-lucy method, more iterations narrow the bands further but amplify the noise."
+    )]
+    #[serde(default = "default_iterations")]
+    pub(crate) iterations: usize,
+    #[clap(
+        short,
+        long,
+        default_value("2"),
+        help = "Narrowing factor of the fsd method, the bands become gaussians with a FWHM of the given FWHM divided by this factor. Values above 3 mostly amplify the noise."
+    )]
+    #[serde(default = "default_narrowing")]
+    pub(crate) narrowing: f64,
+}
+
+fn default_iterations() -> usize {
+    20
+}
+
+fn default_narrowing() -> f64 {
+    2.0
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeconvolutionMethod {
+    /// iterative maximum likelihood deconvolution (Richardson 1972, Lucy 1974)
+    #[default]
+    RichardsonLucy,
+    /// divide the Fourier transform by the one of the line shape and
+    /// multiply it by the one of a narrower gaussian (Kauppinen 1981)
+    Fsd,
+}
+
+impl std::str::FromStr for DeconvolutionMethod {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "richardson-lucy" => Ok(DeconvolutionMethod::RichardsonLucy),
+            "fsd" => Ok(DeconvolutionMethod::Fsd),
+            _ => Err(anyhow!(
+                "unknown deconvolution method \"{}\", use \"richardson-lucy\" or \"fsd\"",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineShape {
+    #[default]
+    Gaussian,
+    Lorentzian,
+}
+
+impl std::str::FromStr for LineShape {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gaussian" => Ok(LineShape::Gaussian),
+            "lorentzian" => Ok(LineShape::Lorentzian),
+            _ => Err(anyhow!(
+                "unknown line shape \"{}\", use \"gaussian\" or \"lorentzian\"",
+                s
+            )),
+        }
+    }
+}
+
+impl LineShape {
+    /// Height of a band with maximum 1 and FWHM `width` at distance `x`
+    /// from its center.
+    fn value(self, width: f64, x: f64) -> f64 {
+        match self {
+            LineShape::Gaussian => (-4.0 * LN_2 * (x / width).powi(2)).exp(),
+            LineShape::Lorentzian => 1.0 / (1.0 + (2.0 * x / width).powi(2)),
+        }
+    }
+    /// Fourier transform of a band with area 1 and FWHM `width` (in points)
+    /// at `frequency` (in cycles per point).
+    fn transform(self, width: f64, frequency: f64) -> f64 {
+        match self {
+            LineShape::Gaussian => (-(PI * width * frequency).powi(2) / (4.0 * LN_2)).exp(),
+            LineShape::Lorentzian => (-PI * width * frequency.abs()).exp(),
+        }
+    }
+    /// Band with area 1 and FWHM `width` (in points), sampled on the points
+    /// around its center, at most `max_points` on each side.
+    fn kernel(self, width: f64, max_points: usize) -> Vec<f64> {
+        // the lorentzian has long tails
+        let reach = match self {
+            LineShape::Gaussian => 2.0 * width,
+            LineShape::Lorentzian => 10.0 * width,
+        };
+        let half = (reach.ceil() as usize).clamp(1, max_points.max(1));
+        let values: Vec<f64> = (0..=2 * half)
+            .map(|k| self.value(width, k as f64 - half as f64))
+            .collect();
+        let sum: f64 = values.iter().sum();
+        values.into_iter().map(|v| v / sum).collect()
+    }
+}
+
+/// Convolve the values with a kernel of odd length, the values beyond the
+/// ends are taken to be the end values.
+fn convolve(values: &[f64], kernel: &[f64]) -> Vec<f64> {
+    let n = values.len() as isize;
+    let half = (kernel.len() / 2) as isize;
+    (0..n)
+        .map(|i| {
+            kernel
+                .iter()
+                .enumerate()
+                .map(|(k, weight)| {
+                    weight * values[(i + k as isize - half).clamp(0, n - 1) as usize]
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Richardson-Lucy deconvolution, negative values are taken as zero.
+fn richardson_lucy(
+    values: &[f64],
+    kernel: &[f64],
+    iterations: usize,
+    progress: &Progress,
+) -> Result<Vec<f64>> {
+    let observed: Vec<f64> = values.iter().map(|y| y.max(0.0)).collect();
+    let mut estimate = observed.clone();
+    for _ in 0..iterations {
+        progress.check()?;
+        let blurred = convolve(&estimate, kernel);
+        let ratio: Vec<f64> = observed
+            .iter()
+            .zip(blurred)
+            .map(|(o, b)| if b > 0.0 { o / b } else { 0.0 })
+            .collect();
+        let correction = convolve(&ratio, kernel);
+        for (e, c) in estimate.iter_mut().zip(correction) {
+            *e *= c;
+        }
+    }
+    Ok(estimate)
+}
+
+/// Fourier self-deconvolution: remove the line shape of FWHM `width` (in
+/// points) and broaden the result by a gaussian of FWHM `width / narrowing`.
+/// Frequencies the narrowed bands do not reach are cut off, they only hold
+/// noise.
+fn self_deconvolve(values: &[f64], shape: LineShape, width: f64, narrowing: f64) -> Vec<f64> {
+    let n = values.len();
+    // remove the straight line through the end points, so the zero padding
+    // adds no steps
+    let (first, last) = (values[0], values[n - 1]);
+    let line = |i: usize| first + (last - first) * i as f64 / (n - 1).max(1) as f64;
+    let size = (2 * n).next_power_of_two();
+    let mut re = vec![0.0; size];
+    let mut im = vec![0.0; size];
+    for (i, value) in values.iter().enumerate() {
+        re[i] = value - line(i);
+    }
+    fft(&mut re, &mut im, false);
+    for k in 0..size {
+        let frequency = k.min(size - k) as f64 / size as f64;
+        let target = LineShape::Gaussian.transform(width / narrowing, frequency);
+        let factor = if target < 1e-3 {
+            0.0
+        } else {
+            target / shape.transform(width, frequency)
+        };
+        re[k] *= factor;
+        im[k] *= factor;
+    }
+    fft(&mut re, &mut im, true);
+    (0..n).map(|i| re[i] + line(i)).collect()
+}
+
+impl DeconvolveTransform {
+    pub fn new(fwhm: f64) -> Self {
+        Self {
+            fwhm,
+            method: DeconvolutionMethod::default(),
+            shape: LineShape::default(),
+            iterations: default_iterations(),
+            narrowing: default_narrowing(),
+        }
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        if !self.fwhm.is_finite() || self.fwhm <= 0.0 {
+            problems.push("the FWHM must be positive".to_owned());
+        }
+        if self.method == DeconvolutionMethod::Fsd
+            && (!self.narrowing.is_finite() || self.narrowing < 1.0)
+        {
+            problems.push("the narrowing factor must be at least 1".to_owned());
+        }
+        problems
+    }
+
+    /// Deconvolve the points of a frame that are not missing.
+    fn deconvolve_frame(
+        &self,
+        frame: usize,
+        xs: ArrayView1<f64>,
+        mut ys: ArrayViewMut1<f64>,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        let finite: Vec<usize> = (0..xs.len())
+            .filter(|i| xs[*i].is_finite() && ys[*i].is_finite())
+            .collect();
+        let (Some(&start), Some(&end)) = (finite.first(), finite.last()) else {
+            return Ok(());
+        };
+        if end - start + 1 != finite.len() {
+            return Err(anyhow!(
+                "frame {} has missing values between its first and last point, cannot deconvolve it",
+                frame
+            ));
+        }
+        if finite.len() < 3 {
+            return Ok(());
+        }
+        let step = (xs[end] - xs[start]) / (end - start) as f64;
+        if (start..end).any(|i| ((xs[i + 1] - xs[i]) - step).abs() > 1e-3 * step.abs()) {
+            return Err(anyhow!(
+                "the x-axis of frame {} is not evenly spaced, resample the frames before deconvolving them",
+                frame
+            ));
+        }
+        let width = self.fwhm / step.abs();
+        let values: Vec<f64> = (start..=end).map(|i| ys[i]).collect();
+        let deconvolved = match self.method {
+            DeconvolutionMethod::RichardsonLucy => {
+                if values.iter().any(|y| *y < 0.0) {
+                    context.diagnostics.warn(format!(
+                        "frame {} has negative intensities, Richardson-Lucy deconvolution takes them as zero",
+                        frame
+                    ));
+                }
+                let kernel = self.shape.kernel(width, values.len());
+                richardson_lucy(&values, &kernel, self.iterations, &context.progress)?
+            }
+            DeconvolutionMethod::Fsd => self_deconvolve(&values, self.shape, width, self.narrowing),
+        };
+        for (i, value) in (start..=end).zip(deconvolved) {
+            ys[i] = value;
+        }
+        Ok(())
+    }
+}
+
+impl Transformer for DeconvolveTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn is_frame_local(&self) -> bool {
+        true
+    }
+    fn validate(&self, _shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        self.problems()
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        if let Some(problem) = self.problems().into_iter().next() {
+            return Err(anyhow!(problem));
+        }
+        let nframes = dataset.data.ncols() / 2;
+        for frame in 0..nframes {
+            context.progress.report(frame as f32 / nframes as f32);
+            let (xs, ys) = dataset
+                .data
+                .multi_slice_mut((ndarray::s![.., 2 * frame], ndarray::s![.., 2 * frame + 1]));
+            self.deconvolve_frame(frame + 1, xs.view(), ys, context)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeconvolutionMethod, DeconvolveTransform, LineShape};
+    use crate::common::{AxisUnit, Dataset, FrameMetadata};
+    use crate::transformations::Transformer;
+    use ndarray::{Array1, Array2};
+
+    /// Dataset of one band of the given shape and FWHM at x = 100, sampled
+    /// every 0.5.
+    fn band(shape: LineShape, fwhm: f64) -> Dataset {
+        let xs: Array1<f64> = Array1::range(0.0, 200.0, 0.5);
+        let mut data = Array2::zeros((xs.len(), 2));
+        data.column_mut(0).assign(&xs);
+        data.column_mut(1)
+            .assign(&xs.mapv(|x| 10.0 * shape.value(fwhm, x - 100.0)));
+        Dataset {
+            data,
+            metadata: String::new(),
+            previous_comments: String::new(),
+            frame_metadata: vec![FrameMetadata::default()],
+            x_unit: AxisUnit::RamanShift,
+            channels: Default::default(),
+            provenance_log: vec![],
+        }
+    }
+
+    /// FWHM of the band in the frame, from the points above half maximum.
+    fn width(dataset: &Dataset) -> f64 {
+        let ys = dataset.data.column(1);
+        let max = ys.iter().fold(f64::MIN, |a, b| a.max(*b));
+        ys.iter().filter(|y| **y >= max / 2.0).count() as f64 * 0.5
+    }
+
+    #[test]
+    fn test_richardson_lucy() {
+        let mut dataset = band(LineShape::Gaussian, 8.0);
+        let mut trsf = DeconvolveTransform::new(6.0);
+        trsf.iterations = 200;
+        trsf.transform(&mut dataset).unwrap();
+        // sqrt(8² - 6²) ≈ 5.3 if fully deconvolved
+        let narrowed = width(&dataset);
+        assert!(narrowed < 7.0 && narrowed > 4.0, "{}", narrowed);
+        assert!(dataset.data.column(1).iter().all(|y| *y >= 0.0));
+    }
+
+    #[test]
+    fn test_fourier_self_deconvolution() {
+        let mut dataset = band(LineShape::Lorentzian, 10.0);
+        let area: f64 = dataset.data.column(1).sum();
+        let mut trsf = DeconvolveTransform::new(10.0);
+        trsf.method = DeconvolutionMethod::Fsd;
+        trsf.shape = LineShape::Lorentzian;
+        trsf.transform(&mut dataset).unwrap();
+        // a gaussian with half the width and the same area
+        assert!((width(&dataset) - 5.0).abs() <= 0.5, "{}", width(&dataset));
+        let deconvolved_area: f64 = dataset.data.column(1).sum();
+        assert!((deconvolved_area / area - 1.0).abs() < 0.05);
+        // an uneven x-axis is rejected
+        let mut dataset = band(LineShape::Lorentzian, 10.0);
+        dataset.data[[10, 0]] += 0.2;
+        assert!(trsf.transform(&mut dataset).is_err());
+    }
+}
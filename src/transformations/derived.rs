@@ -0,0 +1,161 @@
+use crate::common::{Dataset, Pair};
+use crate::metadata::document_yaml;
+use crate::transformations::script::number;
+use crate::utils::trapz;
+use anyhow::{anyhow, Result};
+use ndarray::ArrayView1;
+use regex::Regex;
+use rhai::{Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+
+/// Quantity computed from integrals of a frame, written as
+/// `name = expression`, e.g. `ratio_OD = I(2250..2280) / I(3350..3450)`.
+/// `I(a..b)` is the integral from a to b, the expression is evaluated as Rhai
+/// script and may use the quantities defined before it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DerivedQuantity {
+    pub name: String,
+    expression: String,
+    /// bounds of the integrals, in the order of their variables in the script
+    integrals: Vec<Pair<f64>>,
+    script: AST,
+}
+
+impl std::str::FromStr for DerivedQuantity {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, expression) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("no \"=\" in \"{}\", use \"<name> = <expression>\"", s))?;
+        let (name, expression) = (name.trim(), expression.trim());
+        if !Regex::new(r"^[A-Za-z_][A-Za-z0-9_]*$")?.is_match(name) {
+            return Err(anyhow!(
+                "\"{}\" is no valid name, use letters, digits and underscores",
+                name
+            ));
+        }
+        let number = r"\s*([-+]?[0-9]*\.?[0-9]+(?:[eE][-+]?[0-9]+)?)\s*";
+        let integral = Regex::new(&format!(r"I\({number}\.\.{number}\)"))?;
+        let mut integrals = vec![];
+        let script = integral.replace_all(expression, |caps: &regex::Captures| {
+            // the pattern only matches numbers
+            integrals.push(Pair {
+                a: caps[1].parse().unwrap(),
+                b: caps[2].parse().unwrap(),
+            });
+            format!("integral_{}_", integrals.len() - 1)
+        });
+        let script = Engine::new()
+            .compile_expression(script.as_ref())
+            .map_err(|e| anyhow!("could not compile \"{}\": {}", expression, e))?;
+        Ok(DerivedQuantity {
+            name: name.to_owned(),
+            expression: expression.to_owned(),
+            integrals,
+            script,
+        })
+    }
+}
+
+impl TryFrom<String> for DerivedQuantity {
+    type Error = anyhow::Error;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<DerivedQuantity> for String {
+    fn from(quantity: DerivedQuantity) -> Self {
+        quantity.to_string()
+    }
+}
+
+impl std::fmt::Display for DerivedQuantity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.name, self.expression)
+    }
+}
+
+impl DerivedQuantity {
+    /// Bounds of the integrals the quantity is computed from.
+    pub fn integrals(&self) -> &[Pair<f64>] {
+        &self.integrals
+    }
+}
+
+/// Values of the quantities for a frame, in the order of the quantities.
+pub fn derive(
+    quantities: &[DerivedQuantity],
+    xs: ArrayView1<f64>,
+    ys: ArrayView1<f64>,
+    local_baseline: bool,
+) -> Result<Vec<f64>> {
+    let engine = Engine::new();
+    let mut scope = Scope::new();
+    let mut values = vec![];
+    for quantity in quantities.iter() {
+        let mut local = scope.clone();
+        for (i, Pair { a, b }) in quantity.integrals.iter().enumerate() {
+            local.push(
+                format!("integral_{i}_"),
+                trapz(&xs, &ys, *a, *b, local_baseline)?,
+            );
+        }
+        let result = engine
+            .eval_ast_with_scope(&mut local, &quantity.script)
+            .map_err(|e| anyhow!("could not compute {}: {}", quantity, e))?;
+        let value =
+            number(&result).ok_or_else(|| anyhow!("{} is no number, but {}", quantity, result))?;
+        scope.push(quantity.name.clone(), value);
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Header document with the values of the quantities, taken from the last
+/// columns of a table of results with one row per frame, e.g.
+///
+/// ```yaml
+/// results: derived quantities
+/// frame: [1.0, 2.0]
+/// ratio_OD: [0.42, 0.45]
+/// ```
+pub fn results_yaml(quantities: &[DerivedQuantity], table: &Dataset) -> Result<String> {
+    let column = |n: usize| -> Value {
+        table
+            .data
+            .column(n)
+            .iter()
+            .map(|v| Value::from(*v))
+            .collect()
+    };
+    let first = (table.data.ncols() / 2).saturating_sub(quantities.len());
+    let mut document = Mapping::new();
+    document.insert("results".into(), "derived quantities".into());
+    document.insert("frame".into(), column(0));
+    for (i, quantity) in quantities.iter().enumerate() {
+        document.insert(quantity.name.as_str().into(), column(2 * (first + i) + 1));
+    }
+    document_yaml(&document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{derive, DerivedQuantity};
+    use ndarray::array;
+
+    #[test]
+    fn test_derive() {
+        let ratio: DerivedQuantity = "ratio = I(1..2) / I(2..4)".parse().unwrap();
+        assert_eq!(ratio.to_string(), "ratio = I(1..2) / I(2..4)");
+        let percent: DerivedQuantity = "percent=100 * ratio".parse().unwrap();
+        let xs = array![0.0, 1.0, 2.0, 3.0, 4.0];
+        let ys = array![1.0, 1.0, 1.0, 1.0, 1.0];
+        let values = derive(&[ratio, percent], xs.view(), ys.view(), false).unwrap();
+        assert_eq!(values, vec![0.5, 50.0]);
+        assert!("1ratio = I(0..1)".parse::<DerivedQuantity>().is_err());
+        assert!("ratio = I(0..1) /".parse::<DerivedQuantity>().is_err());
+    }
+}
@@ -1,8 +1,9 @@
-use super::Transformer;
-use crate::common::Dataset;
+use super::finning::finning;
+use super::{PipelineContext, Progress, Transformer};
+use crate::common::{Dataset, DatasetShape};
 use anyhow::{anyhow, Result};
 use clap::Parser;
-use ndarray::{Array2, ArrayBase, Axis, Data, Ix2};
+use ndarray::{s, Array2, ArrayBase, Axis, Data, Ix2};
 use noisy_float::types::N64;
 use serde::{Deserialize, Serialize};
 use std::{
@@ -13,17 +14,199 @@ use std::{
 #[derive(Debug, Parser, Serialize, Deserialize)]
 #[serde(tag = "transformation")]
 pub struct DespikeTransform {
-    #[clap(help = "siglim")]
+    #[clap(default_value("10"), help = "siglim")]
     pub siglim: f64,
-    #[clap(help = "sigfrac?")]
+    #[clap(default_value("10"), help = "sigfrac?")]
     pub flim: f64,
+    #[clap(
+        short,
+        long,
+        default_value("laplace"),
+        help = "Despike algorithm: \"laplace\" (van Dokkum), \"finning\" (median of all frames) or \"temporal\" (median of neighboring frames)."
+    )]
+    #[serde(default)]
+    pub method: DespikeMethod,
+    #[clap(
+        short,
+        long,
+        default_value("3.5"),
+        help = "Spike threshold of the finning (multiple of standard deviation) and temporal (multiple of robust standard deviation) methods."
+    )]
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    #[clap(
+        short,
+        long,
+        default_value("4"),
+        help = "Number of iterations of the laplace method, maximum number of spikes replaced per pixel by the finning method."
+    )]
+    #[serde(default = "default_iterations")]
+    pub iterations: usize,
+    #[clap(
+        short,
+        long,
+        help = "Detector gain in electrons per count used by the laplace method, taken from the instrument profile or the frame metadata (\"gain = <value>\" in the comments of the input) if omitted, 1 if unknown."
+    )]
+    #[serde(default)]
+    pub gain: Option<f64>,
+    #[clap(
+        short,
+        long,
+        help = "Detector read noise in electrons used by the laplace method, taken from the instrument profile if omitted, 6 if unknown."
+    )]
+    #[serde(default)]
+    pub readnoise: Option<f64>,
+    #[clap(
+        short,
+        long,
+        default_value("2"),
+        help = "Number of frames before and after a frame compared by the temporal method."
+    )]
+    #[serde(default = "default_window")]
+    pub window: usize,
+    /// positions replaced in the last run, reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub replaced: Vec<ReplacedPixel>,
+}
+
+fn default_threshold() -> f64 {
+    3.5
+}
+
+fn default_iterations() -> usize {
+    4
+}
+
+fn default_window() -> usize {
+    2
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DespikeMethod {
+    /// Laplacian edge detection across pixels and frames (van Dokkum 2001)
+    #[default]
+    Laplace,
+    /// compare each pixel with its median across all frames
+    Finning,
+    /// compare each pixel with its median across neighboring frames
+    Temporal,
+}
+
+impl std::str::FromStr for DespikeMethod {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "laplace" => Ok(DespikeMethod::Laplace),
+            "finning" => Ok(DespikeMethod::Finning),
+            "temporal" => Ok(DespikeMethod::Temporal),
+            _ => Err(anyhow!(
+                "unknown despike method \"{}\", use \"laplace\", \"finning\" or \"temporal\"",
+                s
+            )),
+        }
+    }
+}
+
+/// Position of a pixel replaced by a despike algorithm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplacedPixel {
+    /// frame number, counting from 1
+    pub frame: usize,
+    /// row index of the pixel, counting from 0
+    pub pixel: usize,
+    /// intensity before it was replaced
+    pub original: f64,
+}
+
+/// Number of replaced pixels listed in the metadata, a noisy dataset with a
+/// low threshold would otherwise blow up the header.
+const MAX_LISTED_PIXELS: usize = 1000;
+
+/// Number and YAML list of replaced pixels, to be written into the metadata
+/// of a despike step. Only the first `MAX_LISTED_PIXELS` are listed, a
+/// shortened list is marked with `replaced_truncated: true`.
+pub(crate) fn replaced_pixels_yaml(replaced: &[ReplacedPixel]) -> String {
+    let mut yaml = format!("replaced_count: {}\n", replaced.len());
+    if replaced.is_empty() {
+        yaml += "replaced: []\n";
+        return yaml;
+    }
+    yaml += "replaced:\n";
+    for ReplacedPixel {
+        frame,
+        pixel,
+        original,
+    } in replaced.iter().take(MAX_LISTED_PIXELS)
+    {
+        yaml += &format!(
+            "- frame: {}\n  pixel: {}\n  original: {}\n",
+            frame, pixel, original
+        );
+    }
+    if replaced.len() > MAX_LISTED_PIXELS {
+        yaml += "replaced_truncated: true\n";
+    }
+    yaml
 }
 
 impl Transformer for DespikeTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        // spikes are detected against the neighbouring pixels
+        true
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
-    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let min_frames = match self.method {
+            DespikeMethod::Laplace => 2,
+            DespikeMethod::Finning | DespikeMethod::Temporal => 3,
+        };
+        let mut problems = vec![];
+        if shape.frames.is_some_and(|frames| frames < min_frames) {
+            problems.push(format!(
+                "{:?} despiking needs at least {} frames, got {}",
+                self.method,
+                min_frames,
+                shape.frames.unwrap_or_default()
+            ));
+        }
+        if self.method == DespikeMethod::Laplace && shape.rows.is_some_and(|rows| rows < 2) {
+            problems.push("laplace despiking needs at least 2 rows".to_owned());
+        }
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        let progress = &context.progress;
+        self.replaced = match self.method {
+            DespikeMethod::Laplace => self.laplace_despike(dataset, context)?,
+            DespikeMethod::Finning => finning(dataset, self.threshold, self.iterations, progress)?,
+            DespikeMethod::Temporal => {
+                temporal_despike(dataset, self.threshold, self.window, progress)?
+            }
+        };
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        dataset.metadata += &replaced_pixels_yaml(&self.replaced);
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+impl DespikeTransform {
+    fn laplace_despike(
+        &self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<Vec<ReplacedPixel>> {
         let frames: Vec<_> = dataset
             .data
             .columns()
@@ -31,18 +214,213 @@ impl Transformer for DespikeTransform {
             .skip(1)
             .step_by(2)
             .collect();
-        let frames = ndarray::stack(Axis(1), &frames)?;
+        let mut frames = ndarray::stack(Axis(1), &frames)?;
+        let missing = fill_missing(&mut frames);
         let db = DespikeBuffer::new(frames)?;
-        let despiked_frames = despike(db, self.siglim, self.flim, 1.0, 6.0, 4);
+        let (gain, readnoise) = self.gain_and_readnoise(dataset, context)?;
+        let (despiked_frames, mask) = despike(
+            db,
+            self.siglim,
+            self.flim,
+            gain,
+            readnoise,
+            self.iterations,
+            &context.progress,
+        )?;
+        let replaced = mask
+            .indexed_iter()
+            .filter(|(index, is_spike)| **is_spike && !missing[*index])
+            .map(|((i, j), _)| ReplacedPixel {
+                frame: j + 1,
+                pixel: i,
+                original: dataset.data[[i, j * 2 + 1]],
+            })
+            .collect();
         for i in 0..despiked_frames.nrows() {
             for j in 0..despiked_frames.ncols() {
-                dataset.data[[i, j * 2 + 1]] = despiked_frames[[i, j]]
+                if !missing[[i, j]] {
+                    dataset.data[[i, j * 2 + 1]] = despiked_frames[[i, j]]
+                }
             }
         }
-        Ok(())
+        Ok(replaced)
+    }
+
+    /// Gain and read noise used by the laplace method: given values take
+    /// precedence over the instrument profile and the frame metadata.
+    pub(crate) fn gain_and_readnoise(
+        &self,
+        dataset: &Dataset,
+        context: &PipelineContext,
+    ) -> Result<(f64, f64)> {
+        let gain = self
+            .gain
+            .or(context.instrument.gain)
+            .or_else(|| dataset.shared_gain())
+            .unwrap_or(1.0);
+        let readnoise = self
+            .readnoise
+            .or(context.instrument.read_noise)
+            .unwrap_or(6.0);
+        if gain <= 0.0 || readnoise < 0.0 {
+            return Err(anyhow!(
+                "gain must be positive and read noise must not be negative"
+            ));
+        }
+        Ok((gain, readnoise))
+    }
+}
+
+/// Noise standard deviations above which a suggested threshold flags a spike.
+const SUGGESTED_SPIKE_SIGMAS: f64 = 5.0;
+
+/// Suggest a sigma limit for the laplace method from the median absolute
+/// deviation of the noise-normalized Laplacian of the dataset.
+pub(crate) fn suggest_siglim(dataset: &Dataset, gain: f64, readnoise: f64) -> Result<f64> {
+    let mut frames = dataset.data.slice(s![.., 1..;2]).to_owned();
+    fill_missing(&mut frames);
+    let mut db = DespikeBuffer::new(frames)?;
+    laplacian_to_noise(&mut db, gain, readnoise);
+    let (median, sigma) = robust_statistics(db.signal_to_noise_buffer.data.iter().copied())
+        .ok_or_else(|| anyhow!("dataset holds no finite intensities"))?;
+    Ok(median + SUGGESTED_SPIKE_SIGMAS * sigma)
+}
+
+/// Suggest a threshold for the finning or temporal method.
+///
+/// The noise is estimated from the median absolute deviation of the
+/// Laplacian across frames (second difference of each pixel), the threshold
+/// is expressed in units of the spread the method compares against: the
+/// standard deviation (finning) or the robust standard deviation (temporal)
+/// of each pixel across frames.
+pub(crate) fn suggest_threshold(dataset: &Dataset, method: DespikeMethod) -> Result<f64> {
+    let intensities = dataset.data.slice(s![.., 1..;2]);
+    if intensities.ncols() < 3 {
+        return Err(anyhow!(
+            "Not enough frames to estimate the noise, got {}, need at least 3.",
+            intensities.ncols()
+        ));
+    }
+    let laplacian = intensities.rows().into_iter().flat_map(|row| {
+        row.windows(3)
+            .into_iter()
+            .map(|w| w[0] - 2.0 * w[1] + w[2])
+            .collect::<Vec<_>>()
+    });
+    // the second difference of white noise has six times its variance
+    let (_, laplacian_sigma) = robust_statistics(laplacian)
+        .ok_or_else(|| anyhow!("dataset holds no finite intensities"))?;
+    let noise = laplacian_sigma / 6f64.sqrt();
+    let spreads = intensities.rows().into_iter().map(|row| match method {
+        DespikeMethod::Temporal => {
+            robust_statistics(row.iter().copied()).map_or(f64::NAN, |(_, s)| s)
+        }
+        _ => row.std(1.0),
+    });
+    let (spread, _) =
+        robust_statistics(spreads).ok_or_else(|| anyhow!("dataset holds no finite intensities"))?;
+    if noise <= 0.0 || spread <= 0.0 {
+        return Err(anyhow!(
+            "dataset holds no noise to estimate a threshold from"
+        ));
+    }
+    let threshold = SUGGESTED_SPIKE_SIGMAS * noise / spread;
+    match method {
+        // a spike inflates the standard deviation of its pixel, it lies at
+        // most sqrt(frames) standard deviations above the median
+        DespikeMethod::Finning => Ok(threshold.min(0.9 * (intensities.ncols() as f64).sqrt())),
+        _ => Ok(threshold),
     }
 }
 
+/// Median and robust standard deviation (scaled median absolute deviation)
+/// of the finite values, None if there are none.
+fn robust_statistics(values: impl Iterator<Item = f64>) -> Option<(f64, f64)> {
+    let values: Vec<f64> = values.filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    let median = median_of(values.clone());
+    let mad = median_of(values.iter().map(|v| (v - median).abs()).collect());
+    Some((median, 1.4826 * mad))
+}
+
+/// Replace pixels that exceed the median of the same pixel in the
+/// neighboring frames (`window` frames before and after) by more than
+/// `threshold` robust standard deviations, suited for time series where
+/// the spectrum changes slowly.
+fn temporal_despike(
+    dataset: &mut Dataset,
+    threshold: f64,
+    window: usize,
+    progress: &Progress,
+) -> Result<Vec<ReplacedPixel>> {
+    let n_frames = dataset.data.ncols() / 2;
+    if n_frames < 3 {
+        return Err(anyhow!(
+            "Not enough frames for temporal despiking, got {}, need at least 3.",
+            n_frames
+        ));
+    }
+    if window == 0 {
+        return Err(anyhow!("window of temporal despiking must be at least 1"));
+    }
+    let mut replaced = vec![];
+    let intensities = dataset.data.slice(s![.., 1..;2]).to_owned();
+    for ((pixel, frame), y) in intensities.indexed_iter() {
+        if frame == 0 {
+            progress.check()?;
+            progress.report(pixel as f32 / intensities.nrows() as f32);
+        }
+        let neighbors: Vec<f64> = (frame.saturating_sub(window)
+            ..=(frame + window).min(n_frames - 1))
+            .filter(|j| *j != frame)
+            .map(|j| intensities[[pixel, j]])
+            .filter(|y| y.is_finite())
+            .collect();
+        if neighbors.len() < 2 {
+            continue;
+        }
+        let median = median_of(neighbors.clone());
+        let mad = median_of(neighbors.iter().map(|y| (y - median).abs()).collect());
+        // scaled MAD estimates the standard deviation; shot noise is the
+        // lower limit if neighbors happen to be (nearly) identical
+        let sigma = (1.4826 * mad).max(median.abs().sqrt()).max(f64::EPSILON);
+        if y - median > threshold * sigma {
+            dataset.data[[pixel, 2 * frame + 1]] = median;
+            replaced.push(ReplacedPixel {
+                frame: frame + 1,
+                pixel,
+                original: *y,
+            });
+        }
+    }
+    Ok(replaced)
+}
+
+/// Replace missing values (NaN) by the median of the finite values of their
+/// frame (one frame per column), so the laplace method can filter around
+/// them, and return where they were.
+fn fill_missing(frames: &mut Array2<f64>) -> Array2<bool> {
+    let missing = frames.mapv(|y| !y.is_finite());
+    for mut frame in frames.columns_mut() {
+        let finite: Vec<f64> = frame.iter().copied().filter(|y| y.is_finite()).collect();
+        let fill = if finite.is_empty() {
+            0.0
+        } else {
+            median_of(finite)
+        };
+        frame.mapv_inplace(|y| if y.is_finite() { y } else { fill });
+    }
+    missing
+}
+
+fn median_of(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let n = values.len();
+    0.5 * (values[(n - 1) / 2] + values[n / 2])
+}
+
 struct DespikeBuffer {
     _original_data: Array2<f64>,
     input_data: MirroredArray2,
@@ -97,38 +475,13 @@ fn despike(
     gain: f64,
     readnoise: f64,
     iter: usize,
-) -> Array2<f64> {
-    for _ in 0..iter {
-        laplace_convolve(&mut db);
+    progress: &Progress,
+) -> Result<(Array2<f64>, Array2<bool>)> {
+    for n in 0..iter {
+        progress.check()?;
+        progress.report(n as f32 / iter as f32);
+        laplacian_to_noise(&mut db, gain, readnoise);
         let laplacian = &db.laplacian; // borrowing here to make sure not to accidentially mutate laplacian anymore
-
-        // calculate S' by repeatedly modifying data in signal_to_noise_buffer
-        median_filter(
-            &db.input_data,
-            &mut db.median_filtered_data,
-            &mut db.median_window_buffer,
-            5,
-        );
-        for i in 0..db.nrows {
-            for j in 0..db.ncols {
-                // equation 10 in van Dokkum 2001
-                let noise = 1.0 / gain
-                    * f64::sqrt(gain * db.median_filtered_data[[i, j]] + readnoise.powi(2));
-                db.signal_to_noise_buffer[[i, j]] = laplacian[[i, j]] / (2.0 * noise);
-            }
-        } // signal_to_noise_buffer now holds S, equation 11 in van Dokkum 2001
-        median_filter(
-            &db.signal_to_noise_buffer,
-            &mut db.median_filtered_data,
-            &mut db.median_window_buffer,
-            5,
-        ); // median_filtered_data now holds 5x5 median filtered S
-        for i in 0..db.nrows {
-            for j in 0..db.ncols {
-                db.signal_to_noise_buffer[[i, j]] =
-                    db.signal_to_noise_buffer[[i, j]] - db.median_filtered_data[[i, j]];
-            }
-        } // signal_to_noise_buffer now holds S', equation 13 in van Dokkum 2001
         let laplacian_to_noise = &db.signal_to_noise_buffer;
 
         // calculate fine structure image
@@ -170,7 +523,41 @@ fn despike(
             }
         }
     }
-    db.input_data.data
+    Ok((db.input_data.data, db.data_mask))
+}
+
+/// Laplacian of the data in `db` and its signal to noise ratio, leaves the
+/// Laplacian in db.laplacian and S' in db.signal_to_noise_buffer
+fn laplacian_to_noise(db: &mut DespikeBuffer, gain: f64, readnoise: f64) {
+    laplace_convolve(db);
+    let laplacian = &db.laplacian;
+
+    // calculate S' by repeatedly modifying data in signal_to_noise_buffer
+    median_filter(
+        &db.input_data,
+        &mut db.median_filtered_data,
+        &mut db.median_window_buffer,
+        5,
+    );
+    for i in 0..db.nrows {
+        for j in 0..db.ncols {
+            // equation 10 in van Dokkum 2001
+            let noise =
+                1.0 / gain * f64::sqrt(gain * db.median_filtered_data[[i, j]] + readnoise.powi(2));
+            db.signal_to_noise_buffer[[i, j]] = laplacian[[i, j]] / (2.0 * noise);
+        }
+    } // signal_to_noise_buffer now holds S, equation 11 in van Dokkum 2001
+    median_filter(
+        &db.signal_to_noise_buffer,
+        &mut db.median_filtered_data,
+        &mut db.median_window_buffer,
+        5,
+    ); // median_filtered_data now holds 5x5 median filtered S
+    for i in 0..db.nrows {
+        for j in 0..db.ncols {
+            db.signal_to_noise_buffer[[i, j]] -= db.median_filtered_data[[i, j]];
+        }
+    } // signal_to_noise_buffer now holds S', equation 13 in van Dokkum 2001
 }
 
 /// perform laplace transformation on data in db.copied_input_data
@@ -335,9 +722,103 @@ impl IndexMut<[usize; 2]> for MirroredArray2 {
 
 #[cfg(test)]
 mod tests {
-    use super::{median_filter, MirroredArray2};
-    use ndarray::array;
+    use super::{
+        median_filter, replaced_pixels_yaml, suggest_siglim, suggest_threshold, DespikeMethod,
+        DespikeTransform, MirroredArray2, ReplacedPixel, MAX_LISTED_PIXELS,
+    };
+    use crate::{
+        common::Dataset,
+        transformations::{PipelineContext, Transformer},
+    };
+    use clap::Parser;
+    use ndarray::{array, s, Array2};
     use noisy_float::types::N64;
+    use rand_chacha::rand_core::RngCore;
+    #[test]
+    fn test_temporal_despike() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data[[2, 5]] = 1000.0;
+        let mut transform = DespikeTransform::parse_from(["despike", "--method", "temporal"]);
+        assert_eq!(transform.method, DespikeMethod::Temporal);
+        transform
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        assert_eq!(
+            transform.replaced,
+            vec![ReplacedPixel {
+                frame: 3,
+                pixel: 2,
+                original: 1000.0
+            }]
+        );
+        assert!(dataset.data[[2, 5]] < 40.0);
+        assert!(dataset.metadata.contains("- frame: 3\n  pixel: 2\n"));
+        assert!(dataset.metadata.contains("replaced_count: 1\n"));
+        assert!(!dataset.metadata.contains("replaced_truncated"));
+        let many = vec![transform.replaced[0]; MAX_LISTED_PIXELS + 1];
+        let yaml = replaced_pixels_yaml(&many);
+        assert_eq!(yaml.matches("- frame:").count(), MAX_LISTED_PIXELS);
+        assert!(yaml.ends_with("replaced_truncated: true\n"));
+    }
+    #[test]
+    fn test_laplace_despike_missing_values() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data[[2, 3]] = f64::NAN;
+        let mut transform = DespikeTransform::parse_from(["despike"]);
+        transform.transform(&mut dataset).unwrap();
+        assert!(dataset.data[[2, 3]].is_nan());
+        assert!(!transform
+            .replaced
+            .iter()
+            .any(|p| p.frame == 2 && p.pixel == 2));
+        assert!(suggest_siglim(&dataset, 1.0, 6.0).is_ok());
+    }
+    #[test]
+    fn test_cancelled_despike() {
+        let mut dataset = Dataset::new_test_dummy();
+        let original = dataset.data.clone();
+        let context = PipelineContext::default();
+        context.progress.cancel();
+        let mut transform = DespikeTransform::parse_from(["despike"]);
+        assert!(transform
+            .transform_with_context(&mut dataset, &context)
+            .is_err());
+        assert_eq!(dataset.data, original);
+    }
+    #[test]
+    fn test_suggest_thresholds() {
+        // 12 frames of uniform noise around 100 counts with a single spike
+        let mut rng = PipelineContext::default().rng();
+        let mut dataset = Dataset {
+            data: Array2::from_shape_fn((40, 24), |(i, j)| {
+                if j % 2 == 0 {
+                    i as f64
+                } else {
+                    100.0 + 10.0 * (rng.next_u32() as f64 / u32::MAX as f64 - 0.5)
+                }
+            }),
+            ..Default::default()
+        };
+        dataset.data[[10, 11]] = 1000.0;
+        assert!(suggest_siglim(&dataset, 1.0, 6.0).unwrap() > 0.0);
+        let two_frames = Dataset {
+            data: dataset.data.slice(s![.., ..4]).to_owned(),
+            ..Default::default()
+        };
+        assert!(suggest_threshold(&two_frames, DespikeMethod::Finning).is_err());
+        for method in [DespikeMethod::Finning, DespikeMethod::Temporal] {
+            let mut despiked = dataset.clone();
+            let mut transform = DespikeTransform::parse_from(["despike"]);
+            transform.method = method;
+            transform.threshold = suggest_threshold(&dataset, method).unwrap();
+            transform.transform(&mut despiked).unwrap();
+            assert!(transform
+                .replaced
+                .iter()
+                .any(|p| p.frame == 6 && p.pixel == 10));
+            assert!(transform.replaced.len() < 5);
+        }
+    }
     #[test]
     fn test_median_filter() {
         let array2 = MirroredArray2::new(array![[1., 1., 1.], [1., 2., 1.], [1., 1., 1.]]);
@@ -1,5 +1,5 @@
-use crate::common::{Dataset, Pair};
-use crate::plot::{PlotTransform, SplineExtension};
+use crate::common::{Dataset, FrameMetadata, Pair};
+use crate::plot::{Colormap, PlotTransform, SplineExtension};
 use crate::transformations::Transformer;
 use anyhow::Result;
 use clap::Parser;
@@ -42,6 +42,9 @@ impl Transformer for DrawBaselineTransform {
             x_lim: None,
             y_lim: None,
             pixels: false,
+            stack: None,
+            heatmap: false,
+            colormap: Colormap::default(),
         };
         // the actual work is done by plot transform + spline drawing extension
         _ = plot_transform.transform(dataset);
@@ -56,6 +59,10 @@ impl Transformer for DrawBaselineTransform {
                 .collect();
             let baseline: Array2<f64> = ndarray::stack![Axis(1), x_p, y_p];
             dataset.data = ndarray::concatenate(Axis(1), &[dataset.data.view(), baseline.view()])?;
+            let n_frames = dataset.data.ncols() / 2;
+            dataset
+                .frame_metadata
+                .resize(n_frames, FrameMetadata::default());
         } else {
             // subtract baseline
             for j in (0..dataset.data.ncols()).step_by(2) {
@@ -0,0 +1,71 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::Axis;
+use serde::{Deserialize, Serialize};
+
+/// Remove rows in which all frames are missing (NaN), e.g. the pixels masked
+/// in every frame, before writing a file for tools that cannot handle
+/// missing values. Rows missing in only some frames are kept.
+#[derive(Debug, Default, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct DropNanTransform {}
+
+impl Transformer for DropNanTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        shape.rows = None;
+        vec![]
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let kept: Vec<usize> = dataset
+            .data
+            .axis_iter(Axis(0))
+            .enumerate()
+            .filter(|(_, row)| row.iter().skip(1).step_by(2).any(|y| !y.is_nan()))
+            .map(|(i, _)| i)
+            .collect();
+        if kept.is_empty() {
+            return Err(anyhow!("all intensities are missing"));
+        }
+        let rows = dataset.data.nrows();
+        dataset.data = dataset.data.select(Axis(0), &kept);
+        for values in dataset.channels.values_mut() {
+            if values.nrows() == rows {
+                *values = values.select(Axis(0), &kept);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DropNanTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_drop_nan() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![
+            [1.0, 1.0, 1.0, f64::NAN],
+            [2.0, f64::NAN, 2.0, f64::NAN],
+            [3.0, 3.0, 3.0, 3.0]
+        ];
+        dataset
+            .set_channel("baseline", array![[0.1, 0.2], [0.3, 0.4], [0.5, 0.6]])
+            .unwrap();
+        DropNanTransform {}.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.nrows(), 2);
+        assert_eq!(dataset.data.column(0).to_vec(), vec![1.0, 3.0]);
+        assert!(dataset.data[[0, 3]].is_nan());
+        assert_eq!(dataset.channels["baseline"], array![[0.1, 0.2], [0.5, 0.6]]);
+        dataset.data.column_mut(1).fill(f64::NAN);
+        dataset.data.column_mut(3).fill(f64::NAN);
+        assert!(DropNanTransform {}.transform(&mut dataset).is_err());
+    }
+}
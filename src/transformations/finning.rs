@@ -1,5 +1,6 @@
-use crate::common::Dataset;
-use crate::transformations::Transformer;
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::despike::{replaced_pixels_yaml, ReplacedPixel};
+use crate::transformations::{PipelineContext, Progress, Transformer};
 use anyhow::Result;
 use clap::Parser;
 use ndarray::{s, Array1, Axis};
@@ -20,58 +21,117 @@ pub struct FinningTransform {
         help = "Maximum number of iterations the finning algorithm runs."
     )]
     pub(crate) iterations: usize,
+    /// positions replaced in the last run, reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) replaced: Vec<ReplacedPixel>,
 }
 
 impl Transformer for FinningTransform {
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
-    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
-        // since we do not want to change order of columns in input dataset,
-        // we have to copy the data into a buffer to calculate the median
-        // for each pixel
-        let number_scans = dataset.data.ncols() / 2;
-        if number_scans < 3 {
-            let message = format!(
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        match shape.frames {
+            Some(frames) if frames < 3 => vec![format!(
                 "Not enough scans to perform finning, got {}, need at least 3.",
-                number_scans
-            );
-            return Err(anyhow::Error::msg(message));
+                frames
+            )],
+            _ => vec![],
         }
-        let mut intensities_buffer = Array1::<f64>::zeros(number_scans);
-        for mut row in dataset.data.slice_mut(s![.., 1..;2]).axis_iter_mut(Axis(0)) {
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        let progress = &context.progress;
+        self.replaced = finning(dataset, self.threshold, self.iterations, progress)?;
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        dataset.metadata += &replaced_pixels_yaml(&self.replaced);
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+/// Replace spikes in each pixel by the median of that pixel across all
+/// frames, returns the replaced positions. Missing values (NaN) are skipped
+/// and stay missing.
+pub(crate) fn finning(
+    dataset: &mut Dataset,
+    threshold: f64,
+    max_iterations: usize,
+    progress: &Progress,
+) -> Result<Vec<ReplacedPixel>> {
+    // since we do not want to change order of columns in input dataset,
+    // we have to copy the data into a buffer to calculate the median
+    // for each pixel
+    let number_scans = dataset.data.ncols() / 2;
+    if number_scans < 3 {
+        let message = format!(
+            "Not enough scans to perform finning, got {}, need at least 3.",
+            number_scans
+        );
+        return Err(anyhow::Error::msg(message));
+    }
+    let mut replaced = vec![];
+    let mut intensities_buffer = Array1::<f64>::zeros(number_scans);
+    let number_pixels = dataset.data.nrows();
+    for (pixel, mut row) in dataset
+        .data
+        .slice_mut(s![.., 1..;2])
+        .axis_iter_mut(Axis(0))
+        .enumerate()
+    {
+        progress.check()?;
+        progress.report(pixel as f32 / number_pixels as f32);
+        intensities_buffer.assign(&row);
+        let mut intensities_median =
+            match intensities_buffer.quantile_axis_skipnan_mut(Axis(0), n64(0.5), &Nearest) {
+                Ok(ms) => ms.into_scalar(),
+                Err(err) => return Err(anyhow::Error::from(err)),
+            };
+        let mut intensities_std = std_skipnan(&intensities_buffer);
+        // a pixel missing in all frames has no spike
+        let Ok(mut n) = row.argmax_skipnan() else {
+            continue;
+        };
+        let mut iterations: usize = 0;
+        while row[n] > intensities_median + threshold * intensities_std {
+            iterations += 1;
+            replaced.push(ReplacedPixel {
+                frame: n + 1,
+                pixel,
+                original: row[n],
+            });
+            row[n] = intensities_median;
             intensities_buffer.assign(&row);
-            let mut intensities_median =
+            intensities_median =
                 match intensities_buffer.quantile_axis_skipnan_mut(Axis(0), n64(0.5), &Nearest) {
                     Ok(ms) => ms.into_scalar(),
                     Err(err) => return Err(anyhow::Error::from(err)),
                 };
-            let mut intensities_std = intensities_buffer.std(1.0);
-            let mut n = match row.argmax() {
+            intensities_std = std_skipnan(&intensities_buffer);
+            n = match row.argmax_skipnan() {
                 Ok(index) => index,
                 Err(err) => return Err(anyhow::Error::from(err)),
             };
-            let mut iterations: usize = 0;
-            while row[n] > intensities_median + self.threshold * intensities_std {
-                iterations += 1;
-                row[n] = intensities_median;
-                intensities_buffer.assign(&row);
-                intensities_median =
-                    match intensities_buffer.quantile_axis_skipnan_mut(Axis(0), n64(0.5), &Nearest)
-                    {
-                        Ok(ms) => ms.into_scalar(),
-                        Err(err) => return Err(anyhow::Error::from(err)),
-                    };
-                intensities_std = intensities_buffer.std(1.0);
-                n = match row.argmax() {
-                    Ok(index) => index,
-                    Err(err) => return Err(anyhow::Error::from(err)),
-                };
-                if iterations > self.iterations {
-                    break;
-                }
+            if iterations > max_iterations {
+                break;
             }
         }
-        Ok(())
     }
+    Ok(replaced)
+}
+
+/// Sample standard deviation of the finite values, NaN for less than two.
+fn std_skipnan(values: &Array1<f64>) -> f64 {
+    let finite: Array1<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if finite.len() < 2 {
+        return f64::NAN;
+    }
+    finite.std(1.0)
 }
@@ -1,9 +1,12 @@
-use crate::common::{Dataset, Pair};
-use crate::transformations::Transformer;
-use crate::utils::trapz;
+use crate::common::{AxisUnit, Dataset, DatasetShape, Pair};
+use crate::transformations::{
+    derived::{derive, results_yaml, DerivedQuantity},
+    PipelineContext, Transformer,
+};
+use crate::utils::{trapz, trapz_uncertainty};
 use anyhow::Result;
 use clap::Parser;
-use ndarray::{Array2, Axis};
+use ndarray::{Array1, Array2, ArrayView1, Axis};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Parser, Serialize, Deserialize)]
@@ -18,15 +21,97 @@ pub struct IntegrateTransform {
         help = "Subtract local baseline (straight line from integration start- to end-point)."
     )]
     pub(crate) local_baseline: bool,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Follow each integral by its uncertainty, estimated from the noise next to the integration window."
+    )]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) uncertainty: bool,
+    #[clap(
+        short,
+        long,
+        help = "Quantities computed from integrals, e.g. \"ratio_OD = I(2250..2280) / I(3350..3450)\", added as columns of the results and written into the header."
+    )]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) derived: Vec<DerivedQuantity>,
+    #[serde(skip)]
+    #[clap(skip)]
+    pub gui_text_buffers: IntegrateIOBuffers,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct IntegrateIOBuffers {
+    /// derived quantities, one per line
+    pub derived: String,
+}
+
+impl IntegrateTransform {
+    /// Number of frames of the result, the integrals (and their
+    /// uncertainties) followed by the derived quantities.
+    fn result_frames(&self) -> usize {
+        let per_window = if self.uncertainty { 2 } else { 1 };
+        self.bounds.len() * per_window + self.derived.len()
+    }
+
+    /// Integrals of a frame in all windows followed by the derived
+    /// quantities, each preceded by `x`.
+    fn row(&self, xs: ArrayView1<f64>, ys: ArrayView1<f64>, x: f64) -> Result<Array1<f64>> {
+        let mut values = vec![];
+        for bd in self.bounds.iter() {
+            values.push(trapz(&xs, &ys, bd.a, bd.b, self.local_baseline)?);
+            if self.uncertainty {
+                values.push(trapz_uncertainty(
+                    &xs,
+                    &ys,
+                    bd.a,
+                    bd.b,
+                    self.local_baseline,
+                )?);
+            }
+        }
+        values.extend(derive(&self.derived, xs, ys, self.local_baseline)?);
+        Ok(values.into_iter().flat_map(|value| [x, value]).collect())
+    }
 }
 
 impl Transformer for IntegrateTransform {
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let problems = self
+            .bounds
+            .iter()
+            .chain(
+                self.derived
+                    .iter()
+                    .flat_map(|quantity| quantity.integrals()),
+            )
+            .flat_map(|Pair { a, b }| [shape.check_x(*a), shape.check_x(*b)])
+            .flatten()
+            .collect();
+        // the result holds one row per frame and one frame per window or
+        // derived quantity
+        *shape = DatasetShape {
+            rows: shape.frames,
+            frames: Some(self.result_frames()),
+            x_range: shape.frames.map(|frames| (1.0, frames as f64)),
+            x_unit: AxisUnit::Unknown,
+        };
+        problems
+    }
+    fn frame_row(&self, frame: &Dataset, index: usize) -> Option<Result<Array1<f64>>> {
+        Some(self.row(
+            frame.data.column(0),
+            frame.data.column(1),
+            (index + 1) as f64,
+        ))
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
         let mut integrals: Array2<f64> =
-            Array2::zeros((dataset.data.ncols() / 2, self.bounds.len() * 2));
+            Array2::zeros((dataset.data.ncols() / 2, self.result_frames() * 2));
         for (i, (xs, ys)) in dataset
             .data
             .axis_iter(Axis(1))
@@ -34,12 +119,65 @@ impl Transformer for IntegrateTransform {
             .zip(dataset.data.axis_iter(Axis(1)).skip(1).step_by(2))
             .enumerate()
         {
-            for (j, bd) in self.bounds.iter().enumerate() {
-                integrals[[i, j * 2]] = (i + 1) as f64;
-                integrals[[i, j * 2 + 1]] = trapz(&xs, &ys, bd.a, bd.b, self.local_baseline)?;
-            }
+            integrals
+                .row_mut(i)
+                .assign(&self.row(xs, ys, (i + 1) as f64)?);
         }
         dataset.data = integrals;
+        // the frames of the result are the integration windows
+        dataset.frame_metadata = vec![];
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        dataset.metadata += "---\n";
+        if !self.derived.is_empty() {
+            dataset.metadata += &results_yaml(&self.derived, dataset)?;
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{Dataset, Pipeline};
+    use crate::metadata::Header;
+    use crate::transformations::PipelineContext;
+
+    fn integrate(args: &[&str]) -> Dataset {
+        let args = std::iter::once("integrate").chain(args.iter().copied());
+        let mut pipeline = Pipeline::from_cli_args(vec![args.map(|s| s.to_owned()).collect()]);
+        let mut dataset = Dataset::new_test_dummy_shared_x();
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        dataset
+    }
+
+    #[test]
+    fn test_derived_quantities() {
+        let integrals = integrate(&["11,21", "21,41"]);
+        let derived = integrate(&["11,41", "-d", "ratio = I(11..21) / I(21..41)"]);
+        // one column for the window and one for the ratio
+        assert_eq!(derived.data.ncols(), 4);
+        let ratio = &integrals.data.column(1) / &integrals.data.column(3);
+        assert_eq!(derived.data.column(3), ratio);
+        assert!(derived
+            .metadata
+            .contains("derived:\n- ratio = I(11..21) / I(21..41)\n"));
+        assert!(derived.metadata.contains("results: derived quantities\n"));
+        assert_eq!(Header::parse(&derived.metadata).unwrap().steps.len(), 1);
+    }
+
+    #[test]
+    fn test_uncertainty() {
+        let integrals = integrate(&["21,41", "-d", "double = 2 * I(21..41)"]);
+        let uncertain = integrate(&["21,41", "-u", "-d", "double = 2 * I(21..41)"]);
+        // the uncertainty follows the integral, the derived quantities come last
+        assert_eq!(uncertain.data.ncols(), 6);
+        assert_eq!(uncertain.data.column(1), integrals.data.column(1));
+        assert!(uncertain.data.column(3).iter().all(|u| *u >= 0.0));
+        assert_eq!(uncertain.data.column(5), integrals.data.column(3));
+        assert!(uncertain.metadata.contains("uncertainty: true\n"));
+    }
+}
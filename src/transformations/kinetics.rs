@@ -0,0 +1,153 @@
+use crate::common::{AxisUnit, Dataset, DatasetShape, Pair};
+use crate::transformations::{PipelineContext, Transformer};
+use crate::utils::trapz;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{Array1, Array2, ArrayView1, Axis};
+use serde::{Deserialize, Serialize};
+
+/// Integrate frames in given interval(s) and pair the integrals with the
+/// time the frames were recorded, e.g. to follow a reaction.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct KineticsTransform {
+    #[clap(help = "Left and right integration bound, separated by comma.")]
+    pub(crate) bounds: Vec<Pair<f64>>,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Subtract local baseline (straight line from integration start- to end-point)."
+    )]
+    pub(crate) local_baseline: bool,
+    #[clap(
+        short,
+        long,
+        help = "Time between frames in seconds, overrides the time stamps of the frames (read from SPE files)."
+    )]
+    #[serde(default)]
+    pub(crate) interval: Option<f64>,
+}
+
+impl KineticsTransform {
+    /// Integrals of a frame in all windows, each preceded by `time`.
+    fn row(&self, xs: ArrayView1<f64>, ys: ArrayView1<f64>, time: f64) -> Result<Array1<f64>> {
+        let mut row = Array1::zeros(self.bounds.len() * 2);
+        for (j, bd) in self.bounds.iter().enumerate() {
+            row[j * 2] = time;
+            row[j * 2 + 1] = trapz(&xs, &ys, bd.a, bd.b, self.local_baseline)?;
+        }
+        Ok(row)
+    }
+}
+
+impl Transformer for KineticsTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .bounds
+            .iter()
+            .flat_map(|Pair { a, b }| [shape.check_x(*a), shape.check_x(*b)])
+            .flatten()
+            .collect();
+        if self.interval.is_some_and(|dt| dt <= 0.0) {
+            problems.push("interval between frames must be positive".to_owned());
+        }
+        // the result holds one row per frame and one frame per window, the
+        // range of the times is only known once the data is read
+        *shape = DatasetShape {
+            rows: shape.frames,
+            frames: Some(self.bounds.len()),
+            x_range: None,
+            x_unit: AxisUnit::Unknown,
+        };
+        problems
+    }
+    fn frame_row(&self, frame: &Dataset, index: usize) -> Option<Result<Array1<f64>>> {
+        let time = match self.interval {
+            Some(dt) if dt <= 0.0 => Err(anyhow!("interval between frames must be positive")),
+            Some(dt) => Ok(index as f64 * dt),
+            None => frame
+                .frame_metadata
+                .first()
+                .and_then(|meta| meta.time)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "frame {} carries no time stamp, give the time between frames as interval or add a time-axis step",
+                        index + 1
+                    )
+                }),
+        };
+        Some(time.and_then(|time| self.row(frame.data.column(0), frame.data.column(1), time)))
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let n_frames = dataset.data.ncols() / 2;
+        let times = match self.interval {
+            Some(dt) if dt <= 0.0 => {
+                return Err(anyhow!("interval between frames must be positive"))
+            }
+            Some(dt) => (0..n_frames).map(|i| i as f64 * dt).collect(),
+            None => dataset.times().ok_or_else(|| {
+                anyhow!("frames carry no time stamps, give the time between frames as interval or add a time-axis step")
+            })?,
+        };
+        let mut integrals: Array2<f64> = Array2::zeros((n_frames, self.bounds.len() * 2));
+        for (i, (xs, ys)) in dataset
+            .data
+            .axis_iter(Axis(1))
+            .step_by(2)
+            .zip(dataset.data.axis_iter(Axis(1)).skip(1).step_by(2))
+            .enumerate()
+        {
+            integrals.row_mut(i).assign(&self.row(xs, ys, times[i])?);
+        }
+        dataset.data = integrals;
+        // the frames of the result are the integration windows
+        dataset.frame_metadata = vec![];
+        dataset.x_unit = AxisUnit::Unknown;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KineticsTransform;
+    use crate::{
+        common::{Dataset, FrameMetadata, Pair},
+        transformations::Transformer,
+    };
+
+    #[test]
+    fn test_kinetics() {
+        let mut trsf = KineticsTransform {
+            bounds: vec![Pair { a: 21.0, b: 41.0 }],
+            local_baseline: false,
+            interval: None,
+        };
+        let mut dataset = Dataset::new_test_dummy();
+        // no time stamps and no interval
+        assert!(trsf.transform(&mut dataset).is_err());
+        dataset.frame_metadata = (0..4)
+            .map(|i| FrameMetadata {
+                time: Some(10.0 + i as f64),
+                ..Default::default()
+            })
+            .collect();
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(
+            dataset.data.column(0).to_vec(),
+            vec![10.0, 11.0, 12.0, 13.0]
+        );
+        // trapezoids over x = 21, 31, 41 of frame 1 (y = x + 1)
+        assert_eq!(
+            dataset.data[[0, 1]],
+            10.0 * (22.0 + 32.0) / 2.0 + 10.0 * (32.0 + 42.0) / 2.0
+        );
+        let mut dataset = Dataset::new_test_dummy();
+        trsf.interval = Some(0.5);
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.column(0).to_vec(), vec![0.0, 0.5, 1.0, 1.5]);
+    }
+}
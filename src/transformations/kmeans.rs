@@ -0,0 +1,355 @@
+use crate::common::{AxisUnit, Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{s, Array2, ArrayView1, Axis};
+use rand_chacha::rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Sort the frames into clusters of similar spectra by k-means, a quick look
+/// at heterogeneous samples. Frames of a map (one frame per position, row by
+/// row) are shown as a map colored by cluster in the GUI.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct KmeansTransform {
+    #[clap(help = "Number of clusters.")]
+    pub(crate) clusters: usize,
+    #[clap(
+        short,
+        long,
+        default_value("100"),
+        help = "Maximum number of iterations, stops earlier once no frame changes its cluster."
+    )]
+    #[serde(default = "default_max_iterations")]
+    pub(crate) max_iterations: usize,
+    #[clap(
+        short,
+        long,
+        default_value("means"),
+        help = "Result of the step: \"means\" (one frame per cluster, its mean spectrum) or \"assignments\" (cluster number vs. frame number)."
+    )]
+    #[serde(default)]
+    pub(crate) output: KmeansOutput,
+    #[clap(
+        long,
+        help = "Number of positions per row if the frames are a map, to show the clusters as a map."
+    )]
+    #[serde(default)]
+    pub(crate) map_width: Option<usize>,
+    /// cluster (counting from 1) of each frame in the last run, reported in
+    /// the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) assignments: Vec<usize>,
+    /// sum of squared distances of the frames to their cluster means
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) inertia: Option<f64>,
+}
+
+fn default_max_iterations() -> usize {
+    100
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KmeansOutput {
+    /// mean spectrum of each cluster, on the x-axis of the input
+    #[default]
+    Means,
+    /// cluster of each frame
+    Assignments,
+}
+
+impl std::str::FromStr for KmeansOutput {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "means" => Ok(KmeansOutput::Means),
+            "assignments" => Ok(KmeansOutput::Assignments),
+            _ => Err(anyhow!(
+                "unknown k-means output \"{}\", use \"means\" or \"assignments\"",
+                s
+            )),
+        }
+    }
+}
+
+impl KmeansTransform {
+    pub fn new(clusters: usize) -> Self {
+        Self {
+            clusters,
+            max_iterations: default_max_iterations(),
+            output: KmeansOutput::default(),
+            map_width: None,
+            assignments: vec![],
+            inertia: None,
+        }
+    }
+}
+
+fn squared_distance(a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+    a.iter().zip(b.iter()).map(|(a, b)| (a - b).powi(2)).sum()
+}
+
+/// Index of the closest mean and the squared distance to it.
+fn closest(point: ArrayView1<f64>, means: &Array2<f64>) -> (usize, f64) {
+    means
+        .axis_iter(Axis(0))
+        .map(|mean| squared_distance(point, mean))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, f64::INFINITY))
+}
+
+/// Choose the initial means among the points (one per row), each point
+/// with a probability proportional to its squared distance to the means
+/// chosen so far (k-means++).
+fn initial_means(points: &Array2<f64>, k: usize, rng: &mut impl RngCore) -> Array2<f64> {
+    let n = points.nrows();
+    let uniform = |rng: &mut dyn RngCore| (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    let mut chosen = vec![(rng.next_u64() % n as u64) as usize];
+    while chosen.len() < k {
+        let means = points.select(Axis(0), &chosen);
+        let distances: Vec<f64> = points
+            .axis_iter(Axis(0))
+            .map(|point| closest(point, &means).1)
+            .collect();
+        let total: f64 = distances.iter().sum();
+        let next = if total > 0.0 {
+            let mut target = uniform(rng) * total;
+            distances
+                .iter()
+                .position(|d| {
+                    target -= d;
+                    target < 0.0 && *d > 0.0
+                })
+                .unwrap_or(n - 1)
+        } else {
+            // all points coincide with the means, any unused one will do
+            (0..n).find(|i| !chosen.contains(i)).unwrap_or(0)
+        };
+        chosen.push(next);
+    }
+    points.select(Axis(0), &chosen)
+}
+
+/// Lloyd's algorithm, returns the means (one per row), the cluster of each
+/// point and the inertia.
+fn kmeans(
+    points: &Array2<f64>,
+    k: usize,
+    max_iterations: usize,
+    context: &PipelineContext,
+) -> Result<(Array2<f64>, Vec<usize>, f64)> {
+    let mut rng = context.rng();
+    let mut means = initial_means(points, k, &mut rng);
+    let mut assignments = vec![usize::MAX; points.nrows()];
+    for iteration in 0..max_iterations {
+        context.progress.check()?;
+        context
+            .progress
+            .report(iteration as f32 / max_iterations as f32);
+        let previous = assignments.clone();
+        let mut distances = vec![0.0; points.nrows()];
+        for (i, point) in points.axis_iter(Axis(0)).enumerate() {
+            (assignments[i], distances[i]) = closest(point, &means);
+        }
+        if assignments == previous {
+            break;
+        }
+        for (cluster, mut mean) in means.axis_iter_mut(Axis(0)).enumerate() {
+            let members: Vec<usize> = (0..points.nrows())
+                .filter(|i| assignments[*i] == cluster)
+                .collect();
+            match points.select(Axis(0), &members).mean_axis(Axis(0)) {
+                Some(new_mean) => mean.assign(&new_mean),
+                // restart an empty cluster from the point worst described
+                None => {
+                    let farthest = (0..points.nrows())
+                        .max_by(|i, j| distances[*i].total_cmp(&distances[*j]))
+                        .unwrap_or(0);
+                    distances[farthest] = 0.0;
+                    mean.assign(&points.row(farthest));
+                }
+            }
+        }
+    }
+    let inertia = points
+        .axis_iter(Axis(0))
+        .zip(assignments.iter())
+        .map(|(point, k)| squared_distance(point, means.row(*k)))
+        .sum();
+    Ok((means, assignments, inertia))
+}
+
+impl Transformer for KmeansTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if self.clusters == 0 {
+            problems.push("at least one cluster is required".to_owned());
+        }
+        if let Some(frames) = shape.frames {
+            if self.clusters > frames {
+                problems.push(format!(
+                    "cannot sort {} frame(s) into {} clusters",
+                    frames, self.clusters
+                ));
+            }
+        }
+        if self.map_width == Some(0) {
+            problems.push("map width must be at least 1".to_owned());
+        }
+        *shape = match self.output {
+            KmeansOutput::Means => DatasetShape {
+                frames: Some(self.clusters),
+                ..shape.clone()
+            },
+            KmeansOutput::Assignments => DatasetShape {
+                rows: shape.frames,
+                frames: Some(1),
+                x_range: shape.frames.map(|frames| (1.0, frames as f64)),
+                x_unit: AxisUnit::Unknown,
+            },
+        };
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        let nframes = dataset.data.ncols() / 2;
+        if self.clusters == 0 {
+            return Err(anyhow!("at least one cluster is required"));
+        }
+        if self.clusters > nframes {
+            return Err(anyhow!(
+                "cannot sort {} frame(s) into {} clusters",
+                nframes,
+                self.clusters
+            ));
+        }
+        let xs = dataset.data.column(0).to_owned();
+        let shared_axis = dataset
+            .data
+            .axis_iter(Axis(1))
+            .step_by(2)
+            .all(|column| column == xs);
+        if !shared_axis || dataset.data.iter().any(|v| !v.is_finite()) {
+            return Err(anyhow!(
+                "k-means requires frames on the same x-axis without missing values, crop or resample the frames first"
+            ));
+        }
+        // one row per frame
+        let points = dataset.data.slice(s![.., 1..;2]).t().to_owned();
+        let (means, assignments, inertia) =
+            kmeans(&points, self.clusters, self.max_iterations, context)?;
+        self.assignments = assignments.iter().map(|k| k + 1).collect();
+        self.inertia = Some(inertia);
+        match self.output {
+            KmeansOutput::Means => {
+                dataset.data = Array2::from_shape_fn((xs.len(), self.clusters * 2), |(i, j)| {
+                    if j % 2 == 0 {
+                        xs[i]
+                    } else {
+                        means[[j / 2, i]]
+                    }
+                });
+                // the frames of the result are the clusters
+                dataset.frame_metadata = vec![];
+            }
+            KmeansOutput::Assignments => {
+                dataset.data = Array2::from_shape_fn((nframes, 2), |(i, j)| {
+                    if j == 0 {
+                        (i + 1) as f64
+                    } else {
+                        self.assignments[i] as f64
+                    }
+                });
+                dataset.x_unit = AxisUnit::Unknown;
+                dataset.frame_metadata = vec![];
+            }
+        }
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        dataset.metadata += &format!("assignments: {:?}\n", self.assignments);
+        if let Some(inertia) = self.inertia {
+            dataset.metadata += &format!("inertia: {}\n", inertia);
+        }
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KmeansOutput, KmeansTransform};
+    use crate::common::{AxisUnit, Dataset, FrameMetadata};
+    use crate::transformations::{PipelineContext, Transformer};
+    use ndarray::Array2;
+
+    #[test]
+    fn test_kmeans() {
+        // two kinds of spectra, a band at 10 or at 30, with varying height
+        let xs: Vec<f64> = (0..40).map(|i| i as f64).collect();
+        let band = |center: f64, height: f64| -> Vec<f64> {
+            xs.iter()
+                .map(|x| height * (-(x - center).powi(2) / 8.0).exp())
+                .collect()
+        };
+        let frames = [
+            band(10.0, 1.0),
+            band(30.0, 1.0),
+            band(10.0, 1.2),
+            band(10.0, 0.9),
+            band(30.0, 1.1),
+        ];
+        let data = Array2::from_shape_fn((xs.len(), frames.len() * 2), |(i, j)| match j % 2 {
+            0 => xs[i],
+            _ => frames[j / 2][i],
+        });
+        let input = Dataset {
+            data,
+            metadata: String::new(),
+            previous_comments: String::new(),
+            frame_metadata: vec![FrameMetadata::default(); frames.len()],
+            x_unit: AxisUnit::RamanShift,
+            channels: Default::default(),
+            provenance_log: vec![],
+        };
+        for seed in 0..5 {
+            let context = PipelineContext {
+                seed,
+                ..Default::default()
+            };
+            let mut kmeans = KmeansTransform::new(2);
+            let mut dataset = input.clone();
+            kmeans
+                .transform_with_context(&mut dataset, &context)
+                .unwrap();
+            assert_eq!(dataset.data.dim(), (40, 4));
+            let first = kmeans.assignments[0];
+            let same: Vec<bool> = kmeans.assignments.iter().map(|k| *k == first).collect();
+            assert_eq!(same, vec![true, false, true, true, false], "seed {}", seed);
+            // the mean of the first cluster peaks at its band
+            let mean = dataset.data.column(2 * (first - 1) + 1);
+            assert!((mean[10] - (1.0 + 1.2 + 0.9) / 3.0).abs() < 1e-12);
+        }
+        let mut kmeans = KmeansTransform::new(2);
+        kmeans.output = KmeansOutput::Assignments;
+        let mut dataset = input.clone();
+        kmeans.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.dim(), (5, 2));
+        assert_eq!(dataset.data[[4, 0]], 5.0);
+        assert_eq!(dataset.data[[4, 1]], kmeans.assignments[4] as f64);
+        // more clusters than frames
+        let mut dataset = input.clone();
+        assert!(KmeansTransform::new(6).transform(&mut dataset).is_err());
+    }
+}
@@ -1,22 +1,108 @@
-use crate::common::{Dataset, Pair};
-use crate::transformations::Transformer;
-use anyhow::Result;
+use crate::common::{Dataset, DatasetShape, Pair};
+use crate::transformations::{PipelineContext, Transformer};
+use crate::utils::lininterp;
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
+/// Replace single pixels, e.g. hot pixels of the detector, or x-ranges,
+/// e.g. a band of the window material, by the mean of the other frames, by
+/// interpolation or by missing values. Pixels are given as frame,pixel pairs,
+/// x-ranges stay meaningful after cropping or reshaping.
 #[derive(Debug, Parser, Serialize, Deserialize)]
 #[serde(tag = "transformation")]
 pub struct MaskTransform {
     #[clap(help = "frame,pixel pairs of pixels that shall be masked")]
+    #[serde(default)]
     pub(crate) mask: Vec<Pair<usize>>,
+    #[clap(
+        short,
+        long,
+        help = "Left and right bound of x-ranges that shall be masked, separated by comma."
+    )]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) range: Vec<Pair<f64>>,
+    #[clap(
+        short,
+        long,
+        multiple_values(true),
+        help = "Frames in which the x-ranges are masked, all frames if omitted (give frame,pixel pairs before this option)."
+    )]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) frames: Option<Vec<usize>>,
+    #[clap(
+        short,
+        long,
+        default_value("cross-frame-mean"),
+        help = "Replacement of the masked pixels: \"cross-frame-mean\" (mean of the pixel in the frames where it is not masked), \"interpolate\" (linear between the neighbouring pixels of the same frame) or \"nan\" (missing values)."
+    )]
+    #[serde(default)]
+    pub(crate) method: MaskMethod,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaskMethod {
+    /// mean of the pixel in the other frames, fails if a pixel is masked in
+    /// all frames
+    #[default]
+    CrossFrameMean,
+    /// linear interpolation between the closest pixels of the same frame
+    /// that are not masked
+    Interpolate,
+    /// missing values
+    Nan,
+}
+
+impl std::str::FromStr for MaskMethod {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cross-frame-mean" => Ok(MaskMethod::CrossFrameMean),
+            "interpolate" => Ok(MaskMethod::Interpolate),
+            "nan" => Ok(MaskMethod::Nan),
+            _ => Err(anyhow!(
+                "unknown mask method \"{}\", use \"cross-frame-mean\", \"interpolate\" or \"nan\"",
+                s
+            )),
+        }
+    }
 }
 
 impl Transformer for MaskTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        true
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
-    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        for Pair { a, b } in self.mask.iter() {
+            if let Some(problem) = shape.check_frame(*a) {
+                problems.push(problem);
+            }
+            if *b == 0 || shape.rows.is_some_and(|rows| *b > rows) {
+                problems.push(format!("pixel number {} is out of bounds", b));
+            }
+        }
+        for Pair { a, b } in self.range.iter() {
+            problems.extend([shape.check_x(*a), shape.check_x(*b)].into_iter().flatten());
+        }
+        problems.extend(
+            self.frames
+                .iter()
+                .flatten()
+                .filter_map(|frame| shape.check_frame(*frame)),
+        );
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
         // re-organize the data structure holding the mask to simplify
         // the actual masking
         let mut mask: HashMap<usize, HashSet<usize>> = HashMap::new();
@@ -29,7 +115,9 @@ impl Transformer for MaskTransform {
             // that have to be manipulated, thus frame_idx = 2a - 1
             let (frame_idx, pixel_idx) = (2 * a - 1, b - 1);
             if frame_idx >= ncols || pixel_idx >= nrows {
-                eprintln!("frame,pixel = {a},{b} is out of bounds");
+                context
+                    .diagnostics
+                    .warn(format!("frame,pixel = {a},{b} is out of bounds"));
                 continue;
             }
             if !mask.contains_key(&pixel_idx) {
@@ -37,6 +125,22 @@ impl Transformer for MaskTransform {
             }
             mask.get_mut(&pixel_idx).map(|js| js.insert(frame_idx));
         }
+        self.mask_ranges(dataset, &mut mask, context);
+        match self.method {
+            MaskMethod::CrossFrameMean => {}
+            MaskMethod::Interpolate => {
+                interpolate_masked(dataset, &mask, context);
+                return Ok(());
+            }
+            MaskMethod::Nan => {
+                for (pixel_idx, frame_indices) in mask {
+                    for frame_idx in frame_indices {
+                        dataset.data[[pixel_idx, frame_idx]] = f64::NAN;
+                    }
+                }
+                return Ok(());
+            }
+        }
 
         // the re-organized mask is applied
         for (pixel_idx, frame_indices) in mask {
@@ -60,7 +164,10 @@ impl Transformer for MaskTransform {
                     n += 1;
                 }
                 if n == 0 {
-                    eprintln!("no data left for pixel {}, skipping", pixel_idx + 1);
+                    context.diagnostics.warn(format!(
+                        "no data left for pixel {}, skipping",
+                        pixel_idx + 1
+                    ));
                     continue;
                 } else {
                     sum / n as f64
@@ -73,3 +180,180 @@ impl Transformer for MaskTransform {
         Ok(())
     }
 }
+
+impl MaskTransform {
+    /// Add the pixels inside the x-ranges of the selected frames to `mask`
+    /// (pixel index -> indices of the y-columns).
+    fn mask_ranges(
+        &self,
+        dataset: &Dataset,
+        mask: &mut HashMap<usize, HashSet<usize>>,
+        context: &PipelineContext,
+    ) {
+        if self.range.is_empty() {
+            return;
+        }
+        let n_frames = dataset.data.ncols() / 2;
+        let frames: Vec<usize> = match &self.frames {
+            Some(frames) => frames
+                .iter()
+                .copied()
+                .filter(|frame| {
+                    let in_bounds = *frame >= 1 && *frame <= n_frames;
+                    if !in_bounds {
+                        context
+                            .diagnostics
+                            .warn(format!("frame {frame} is out of bounds"));
+                    }
+                    in_bounds
+                })
+                .collect(),
+            None => (1..=n_frames).collect(),
+        };
+        for Pair { a, b } in self.range.iter() {
+            let (left, right) = if a < b { (*a, *b) } else { (*b, *a) };
+            let mut found = false;
+            for frame in frames.iter() {
+                let frame_idx = 2 * frame - 1;
+                for (pixel_idx, x) in dataset.data.column(frame_idx - 1).iter().enumerate() {
+                    if *x >= left && *x <= right {
+                        mask.entry(pixel_idx).or_default().insert(frame_idx);
+                        found = true;
+                    }
+                }
+            }
+            if !found {
+                context
+                    .diagnostics
+                    .warn(format!("no data points between {left} and {right}"));
+            }
+        }
+    }
+}
+
+/// Replace the masked pixels (given as pixel index -> indices of the
+/// y-columns) by linear interpolation between the closest unmasked pixels of
+/// the same frame, pixels at the ends of a frame take the value of the
+/// closest unmasked pixel.
+fn interpolate_masked(
+    dataset: &mut Dataset,
+    mask: &HashMap<usize, HashSet<usize>>,
+    context: &PipelineContext,
+) {
+    let mut by_frame: HashMap<usize, HashSet<usize>> = HashMap::new();
+    for (pixel_idx, frame_indices) in mask.iter() {
+        for frame_idx in frame_indices.iter() {
+            by_frame.entry(*frame_idx).or_default().insert(*pixel_idx);
+        }
+    }
+    for (frame_idx, pixels) in by_frame {
+        let xs = dataset.data.column(frame_idx - 1).to_owned();
+        let mut ys = dataset.data.column_mut(frame_idx);
+        let usable = |i: &usize| !pixels.contains(i) && ys[*i].is_finite() && xs[*i].is_finite();
+        let kept: Vec<usize> = (0..ys.len()).filter(usable).collect();
+        if kept.is_empty() {
+            context.diagnostics.warn(format!(
+                "no data left in frame {}, skipping",
+                frame_idx / 2 + 1
+            ));
+            continue;
+        }
+        for pixel_idx in pixels.iter() {
+            // first kept pixel after the masked one
+            let next = kept.partition_point(|i| i < pixel_idx);
+            let value = match (
+                next.checked_sub(1).map(|k| kept[k]),
+                kept.get(next).copied(),
+            ) {
+                (Some(lower), Some(upper)) => {
+                    lininterp(xs[*pixel_idx], xs[lower], xs[upper], ys[lower], ys[upper])
+                }
+                (Some(closest), None) | (None, Some(closest)) => ys[closest],
+                (None, None) => unreachable!("kept pixels are not empty"),
+            };
+            ys[*pixel_idx] = value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MaskMethod, MaskTransform};
+    use crate::common::{Dataset, Pair};
+    use crate::transformations::Transformer;
+    use clap::Parser;
+
+    #[test]
+    fn test_mask_methods() {
+        // pixel 3 of all frames and pixel 1 of frame 1
+        let mask: Vec<Pair<usize>> = (1..=4)
+            .map(|frame| Pair { a: frame, b: 3 })
+            .chain([Pair { a: 1, b: 1 }])
+            .collect();
+        let mut trsf = MaskTransform {
+            mask,
+            range: vec![],
+            frames: None,
+            method: MaskMethod::Interpolate,
+        };
+        let mut dataset = Dataset::new_test_dummy();
+        let input = dataset.data.clone();
+        trsf.transform(&mut dataset).unwrap();
+        // the dummy is linear in x, so interpolation restores it
+        assert_eq!(dataset.data.column(3), input.column(3));
+        assert_eq!(dataset.data[[2, 1]], input[[2, 1]]);
+        // the first pixel takes the value of its closest neighbour
+        assert_eq!(dataset.data[[0, 1]], input[[1, 1]]);
+        trsf.method = MaskMethod::Nan;
+        let mut dataset = Dataset::new_test_dummy();
+        trsf.transform(&mut dataset).unwrap();
+        assert!(dataset
+            .data
+            .row(2)
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .all(|y| y.is_nan()));
+        assert_eq!(dataset.data[[3, 1]], input[[3, 1]]);
+        // no frame is left for the mean of pixel 3
+        trsf.method = MaskMethod::CrossFrameMean;
+        let mut dataset = Dataset::new_test_dummy();
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.row(2), input.row(2));
+    }
+
+    #[test]
+    fn test_mask_ranges() {
+        let parsed =
+            MaskTransform::parse_from(["mask", "--range", "1550,1560", "--frames", "2", "3"]);
+        assert!(parsed.mask.is_empty());
+        assert_eq!((parsed.range[0].a, parsed.range[0].b), (1550.0, 1560.0));
+        assert_eq!(parsed.frames, Some(vec![2, 3]));
+        let mut trsf = MaskTransform {
+            mask: vec![],
+            range: vec![Pair { a: 33.0, b: 31.0 }],
+            frames: Some(vec![2, 3]),
+            method: MaskMethod::Nan,
+        };
+        let mut dataset = Dataset::new_test_dummy();
+        let input = dataset.data.clone();
+        let masked: Vec<usize> = (0..input.nrows())
+            .filter(|i| (31.0..=33.0).contains(&input[[*i, 2]]))
+            .collect();
+        assert!(!masked.is_empty());
+        trsf.transform(&mut dataset).unwrap();
+        for i in 0..input.nrows() {
+            assert_eq!(dataset.data[[i, 3]].is_nan(), masked.contains(&i));
+            assert_eq!(dataset.data[[i, 1]], input[[i, 1]]);
+        }
+        // the pairs and ranges are combined
+        trsf.mask = vec![Pair { a: 1, b: 1 }];
+        trsf.frames = None;
+        let mut dataset = Dataset::new_test_dummy();
+        trsf.transform(&mut dataset).unwrap();
+        assert!(dataset.data[[0, 1]].is_nan());
+        // x = 31 in frame 1, the x-values of frame 3 are outside the range
+        assert!(dataset.data[[2, 1]].is_nan() && dataset.data[[2, 3]].is_nan());
+        assert_eq!(dataset.data[[2, 5]], input[[2, 5]]);
+    }
+}
@@ -0,0 +1,495 @@
+use crate::common::{AxisUnit, Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use crate::utils::solve_augmented;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{s, Array1, Array2, Axis};
+use serde::{Deserialize, Serialize};
+
+/// Multivariate curve resolution by alternating least squares (MCR-ALS):
+/// decompose the frames into a few pure-component spectra and their
+/// concentration profiles, both non-negative, so that every frame is the
+/// sum of the component spectra weighted by their concentrations. Frames
+/// with a quality weight (see the weight step) count less in the fit of the
+/// spectra.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct McrTransform {
+    #[clap(help = "Number of pure components.")]
+    pub(crate) components: usize,
+    #[clap(
+        short,
+        long,
+        default_value("100"),
+        help = "Maximum number of alternating least squares iterations."
+    )]
+    #[serde(default = "default_max_iterations")]
+    pub(crate) max_iterations: usize,
+    #[clap(
+        short,
+        long,
+        default_value("1e-6"),
+        help = "Stop once the lack of fit (in percent) changes by less than this from one iteration to the next."
+    )]
+    #[serde(default = "default_tolerance")]
+    pub(crate) tolerance: f64,
+    #[clap(
+        short,
+        long,
+        default_value("spectra"),
+        help = "Result of the step: \"spectra\" (one frame per pure-component spectrum) or \"concentrations\" (concentration profiles vs. frame number, one column per component)."
+    )]
+    #[serde(default)]
+    pub(crate) output: McrOutput,
+    /// iterations of the last run, reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) iterations: usize,
+    /// lack of fit of the last run (percent), reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) lack_of_fit: Option<f64>,
+}
+
+fn default_max_iterations() -> usize {
+    100
+}
+
+fn default_tolerance() -> f64 {
+    1e-6
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum McrOutput {
+    /// pure-component spectra, on the x-axis of the input
+    #[default]
+    Spectra,
+    /// concentration of each component in each frame
+    Concentrations,
+}
+
+impl std::str::FromStr for McrOutput {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "spectra" => Ok(McrOutput::Spectra),
+            "concentrations" => Ok(McrOutput::Concentrations),
+            _ => Err(anyhow!(
+                "unknown MCR output \"{}\", use \"spectra\" or \"concentrations\"",
+                s
+            )),
+        }
+    }
+}
+
+impl McrTransform {
+    pub fn new(components: usize) -> Self {
+        Self {
+            components,
+            max_iterations: default_max_iterations(),
+            tolerance: default_tolerance(),
+            output: McrOutput::default(),
+            iterations: 0,
+            lack_of_fit: None,
+        }
+    }
+}
+
+/// Result of the decomposition D ≈ C S of the frames D (one row per frame).
+struct Resolution {
+    /// concentrations, one row per frame and one column per component
+    concentrations: Array2<f64>,
+    /// spectra of the components with unit norm, one row per component
+    spectra: Array2<f64>,
+    iterations: usize,
+    /// root of the squared residuals relative to the squared data, in percent
+    lack_of_fit: f64,
+}
+
+/// Solve min |A x - b| subject to x >= 0, given the normal equations
+/// G = AᵀA and c = Aᵀb (Lawson-Hanson active set algorithm). The systems
+/// are small, one unknown per component.
+fn nnls(gram: &Array2<f64>, rhs: &Array1<f64>) -> Array1<f64> {
+    let n = rhs.len();
+    let scale = gram.diag().iter().fold(0.0_f64, |acc, g| acc.max(g.abs()));
+    let tolerance = 1e-12 * scale.max(f64::MIN_POSITIVE);
+    let mut x = Array1::zeros(n);
+    let mut passive = vec![false; n];
+    // solution of the unconstrained problem restricted to the passive set
+    let solve_passive = |passive: &[bool]| -> Option<Array1<f64>> {
+        let indices: Vec<usize> = (0..n).filter(|&i| passive[i]).collect();
+        let matrix = indices
+            .iter()
+            .map(|&i| {
+                let mut row: Vec<f64> = indices.iter().map(|&j| gram[[i, j]]).collect();
+                row.push(rhs[i]);
+                row
+            })
+            .collect();
+        let solution = solve_augmented(matrix)?;
+        let mut z = Array1::zeros(n);
+        for (&i, value) in indices.iter().zip(solution) {
+            z[i] = value;
+        }
+        Some(z)
+    };
+    for _ in 0..3 * n {
+        let gradient = rhs - &gram.dot(&x);
+        let next = (0..n)
+            .filter(|&i| !passive[i] && gradient[i] > tolerance)
+            .max_by(|&i, &j| gradient[i].total_cmp(&gradient[j]));
+        let Some(next) = next else {
+            break;
+        };
+        passive[next] = true;
+        loop {
+            let Some(z) = solve_passive(&passive) else {
+                // the new variable makes the system singular, leave it at 0
+                passive[next] = false;
+                break;
+            };
+            if (0..n).all(|i| !passive[i] || z[i] > 0.0) {
+                x = z;
+                break;
+            }
+            // step towards z until the first variable reaches zero
+            let alpha = (0..n)
+                .filter(|&i| passive[i] && z[i] <= 0.0)
+                .map(|i| x[i] / (x[i] - z[i]))
+                .fold(f64::INFINITY, f64::min);
+            x = &x + &((&z - &x) * alpha);
+            for i in 0..n {
+                if passive[i] && x[i] <= tolerance {
+                    passive[i] = false;
+                    x[i] = 0.0;
+                }
+            }
+            if !passive.iter().any(|&p| p) {
+                break;
+            }
+        }
+    }
+    x
+}
+
+/// Rows of the data that are most different from each other, as initial
+/// estimate of the spectra: the strongest row first, then each time the row
+/// least described by the rows chosen so far (Gram-Schmidt).
+fn purest_rows(data: &Array2<f64>, count: usize) -> Array2<f64> {
+    let mut basis: Vec<Array1<f64>> = vec![];
+    let mut chosen = Array2::zeros((count, data.ncols()));
+    for k in 0..count {
+        let residuals: Vec<Array1<f64>> = data
+            .rows()
+            .into_iter()
+            .map(|row| {
+                let mut residual = row.to_owned();
+                for b in basis.iter() {
+                    let projection = residual.dot(b);
+                    residual = residual - b * projection;
+                }
+                residual
+            })
+            .collect();
+        let (best, residual) = residuals
+            .into_iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.dot(a).total_cmp(&b.dot(b)))
+            .unwrap();
+        let norm = residual.dot(&residual).sqrt();
+        if norm > 0.0 {
+            basis.push(residual / norm);
+        }
+        chosen
+            .row_mut(k)
+            .assign(&data.row(best).mapv(|v| v.max(0.0)));
+    }
+    chosen
+}
+
+/// Scale each spectrum to unit norm, the concentrations take up the scale.
+fn normalize_rows(spectra: &mut Array2<f64>) {
+    for mut row in spectra.rows_mut() {
+        let norm = row.dot(&row).sqrt();
+        if norm > 0.0 {
+            row /= norm;
+        }
+    }
+}
+
+/// Concentrations for given spectra, one non-negative least squares fit per
+/// frame.
+fn fit_concentrations(data: &Array2<f64>, spectra: &Array2<f64>) -> Array2<f64> {
+    let gram = spectra.dot(&spectra.t());
+    let mut concentrations = Array2::zeros((data.nrows(), spectra.nrows()));
+    for (frame, mut row) in data.rows().into_iter().zip(concentrations.rows_mut()) {
+        row.assign(&nnls(&gram, &spectra.dot(&frame)));
+    }
+    concentrations
+}
+
+/// Spectra for given concentrations, one non-negative least squares fit per
+/// pixel, the squared residual of each frame counts with its weight.
+fn fit_spectra(
+    data: &Array2<f64>,
+    concentrations: &Array2<f64>,
+    weights: &Array1<f64>,
+) -> Array2<f64> {
+    let weighted = concentrations * &weights.view().insert_axis(Axis(1));
+    let gram = weighted.t().dot(concentrations);
+    let mut spectra = Array2::zeros((concentrations.ncols(), data.ncols()));
+    for (pixel, mut column) in data.columns().into_iter().zip(spectra.columns_mut()) {
+        column.assign(&nnls(&gram, &weighted.t().dot(&pixel)));
+    }
+    spectra
+}
+
+/// Lack of fit in percent, the squares of each frame count with its weight.
+fn lack_of_fit(
+    data: &Array2<f64>,
+    concentrations: &Array2<f64>,
+    spectra: &Array2<f64>,
+    weights: &Array1<f64>,
+) -> f64 {
+    let weights = weights.view().insert_axis(Axis(1));
+    let residuals = (data - &concentrations.dot(spectra)).mapv(|v| v * v) * weights;
+    let total = (data.mapv(|v| v * v) * weights).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    100.0 * (residuals.sum() / total).sqrt()
+}
+
+fn resolve(
+    data: &Array2<f64>,
+    weights: &Array1<f64>,
+    components: usize,
+    max_iterations: usize,
+    tolerance: f64,
+    context: &PipelineContext,
+) -> Result<Resolution> {
+    // down-weighted frames are less likely to be taken as initial spectra
+    let mut spectra = purest_rows(
+        &(data * &weights.mapv(f64::sqrt).insert_axis(Axis(1))),
+        components,
+    );
+    normalize_rows(&mut spectra);
+    let mut concentrations = fit_concentrations(data, &spectra);
+    let mut previous = lack_of_fit(data, &concentrations, &spectra, weights);
+    let mut iterations = 0;
+    while iterations < max_iterations {
+        context.progress.check()?;
+        spectra = fit_spectra(data, &concentrations, weights);
+        normalize_rows(&mut spectra);
+        concentrations = fit_concentrations(data, &spectra);
+        iterations += 1;
+        let current = lack_of_fit(data, &concentrations, &spectra, weights);
+        if (previous - current).abs() < tolerance {
+            previous = current;
+            break;
+        }
+        previous = current;
+    }
+    Ok(Resolution {
+        concentrations,
+        spectra,
+        iterations,
+        lack_of_fit: previous,
+    })
+}
+
+impl Transformer for McrTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if self.components == 0 {
+            problems.push("at least one component is required".to_owned());
+        }
+        if let Some(frames) = shape.frames {
+            if self.components > frames {
+                problems.push(format!(
+                    "cannot resolve {} components from {} frame(s)",
+                    self.components, frames
+                ));
+            }
+        }
+        *shape = match self.output {
+            McrOutput::Spectra => DatasetShape {
+                frames: Some(self.components),
+                ..shape.clone()
+            },
+            McrOutput::Concentrations => DatasetShape {
+                rows: shape.frames,
+                frames: Some(self.components),
+                x_range: shape.frames.map(|frames| (1.0, frames as f64)),
+                x_unit: AxisUnit::Unknown,
+            },
+        };
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        let nframes = dataset.data.ncols() / 2;
+        if self.components == 0 {
+            return Err(anyhow!("at least one component is required"));
+        }
+        if self.components > nframes {
+            return Err(anyhow!(
+                "cannot resolve {} components from {} frame(s)",
+                self.components,
+                nframes
+            ));
+        }
+        let xs = dataset.data.column(0).to_owned();
+        let shared_axis = dataset
+            .data
+            .axis_iter(Axis(1))
+            .step_by(2)
+            .all(|column| column == xs);
+        if !shared_axis || dataset.data.iter().any(|v| !v.is_finite()) {
+            return Err(anyhow!(
+                "MCR requires frames on the same x-axis without missing values, crop or resample the frames first"
+            ));
+        }
+        let weights = Array1::from_vec(dataset.weights().unwrap_or_else(|| vec![1.0; nframes]));
+        if weights.iter().any(|w| !w.is_finite() || *w < 0.0) || weights.sum() == 0.0 {
+            return Err(anyhow!(
+                "the weights of the frames must not be negative or all zero"
+            ));
+        }
+        // one row per frame
+        let data = dataset.data.slice(s![.., 1..;2]).t().to_owned();
+        let resolution = resolve(
+            &data,
+            &weights,
+            self.components,
+            self.max_iterations,
+            self.tolerance,
+            context,
+        )?;
+        self.iterations = resolution.iterations;
+        self.lack_of_fit = Some(resolution.lack_of_fit);
+        dataset.data = match self.output {
+            McrOutput::Spectra => {
+                Array2::from_shape_fn((xs.len(), self.components * 2), |(i, j)| {
+                    if j % 2 == 0 {
+                        xs[i]
+                    } else {
+                        resolution.spectra[[j / 2, i]]
+                    }
+                })
+            }
+            McrOutput::Concentrations => {
+                dataset.x_unit = AxisUnit::Unknown;
+                Array2::from_shape_fn((nframes, self.components * 2), |(i, j)| {
+                    if j % 2 == 0 {
+                        (i + 1) as f64
+                    } else {
+                        resolution.concentrations[[i, j / 2]]
+                    }
+                })
+            }
+        };
+        // the frames of the result are the components
+        dataset.frame_metadata = vec![];
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        dataset.metadata += &format!("iterations: {}\n", self.iterations);
+        if let Some(lack_of_fit) = self.lack_of_fit {
+            dataset.metadata += &format!("lack_of_fit: {}\n", lack_of_fit);
+        }
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nnls, McrOutput, McrTransform};
+    use crate::common::{AxisUnit, Dataset, FrameMetadata};
+    use crate::transformations::Transformer;
+    use ndarray::{array, Array2};
+
+    #[test]
+    fn test_nnls() {
+        // unconstrained solution (2, -1) is not allowed
+        let a = array![[1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        let b = array![2.0, -1.0, 1.0];
+        let x = nnls(&a.t().dot(&a), &a.t().dot(&b));
+        assert!((x[0] - 1.5).abs() < 1e-12 && x[1] == 0.0, "{}", x);
+        let b = array![2.0, 1.0, 3.0];
+        let x = nnls(&a.t().dot(&a), &a.t().dot(&b));
+        assert!((x[0] - 2.0).abs() < 1e-12 && (x[1] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_mcr() {
+        // two gaussian bands, mixed in changing proportion over 6 frames
+        let xs: Vec<f64> = (0..50).map(|i| i as f64).collect();
+        let band = |center: f64| -> Vec<f64> {
+            xs.iter()
+                .map(|x| (-(x - center).powi(2) / 20.0).exp())
+                .collect()
+        };
+        let pure = [band(15.0), band(35.0)];
+        let fractions = [0.0, 0.2, 0.4, 0.6, 0.8, 1.0];
+        let data = Array2::from_shape_fn((xs.len(), fractions.len() * 2), |(i, j)| {
+            let f = fractions[j / 2];
+            match j % 2 {
+                0 => xs[i],
+                _ => 100.0 * ((1.0 - f) * pure[0][i] + f * pure[1][i]),
+            }
+        });
+        let input = Dataset {
+            data,
+            metadata: String::new(),
+            previous_comments: String::new(),
+            frame_metadata: vec![FrameMetadata::default(); fractions.len()],
+            x_unit: AxisUnit::RamanShift,
+            channels: Default::default(),
+            provenance_log: vec![],
+        };
+        let mut mcr = McrTransform::new(2);
+        let mut dataset = input.clone();
+        mcr.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.dim(), (50, 4));
+        assert!(mcr.lack_of_fit.unwrap() < 1e-3, "{:?}", mcr.lack_of_fit);
+        // each resolved spectrum peaks at one of the bands
+        let mut peaks: Vec<usize> = (0..2)
+            .map(|k| {
+                let spectrum = dataset.data.column(2 * k + 1);
+                (0..50)
+                    .max_by(|&i, &j| spectrum[i].total_cmp(&spectrum[j]))
+                    .unwrap()
+            })
+            .collect();
+        peaks.sort();
+        assert_eq!(peaks, vec![15, 35]);
+        assert!(dataset.data.iter().all(|v| *v >= 0.0));
+        // concentration profiles, one row per frame
+        mcr.output = McrOutput::Concentrations;
+        let mut dataset = input.clone();
+        mcr.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.dim(), (6, 4));
+        assert_eq!(dataset.data.column(0), array![1., 2., 3., 4., 5., 6.]);
+        assert!(dataset.data.iter().all(|v| *v >= 0.0));
+        // more components than frames
+        let mut dataset = input.clone();
+        assert!(McrTransform::new(7).transform(&mut dataset).is_err());
+        // a distorted frame without weight does not spoil the spectra
+        let mut dataset = input.clone();
+        dataset.data.column_mut(5).mapv_inplace(|y| y + 50.0);
+        dataset.frame_metadata[2].weight = Some(0.0);
+        mcr.output = McrOutput::Spectra;
+        mcr.transform(&mut dataset).unwrap();
+        assert!(mcr.lack_of_fit.unwrap() < 1e-3, "{:?}", mcr.lack_of_fit);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{s, Axis};
+use ndarray_stats::interpolate::Midpoint;
+use ndarray_stats::QuantileExt;
+use noisy_float::prelude::n64;
+use serde::{Deserialize, Serialize};
+
+/// Collapse all frames into their pixel-wise median, a robust alternative to
+/// averaging if some frames contain artifacts.
+#[derive(Debug, Default, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct MedianTransform {}
+
+impl Transformer for MedianTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if shape.frames == Some(0) {
+            problems.push("dataset does not contain any frames".to_owned());
+        }
+        shape.frames = Some(1);
+        problems
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        if dataset.data.ncols() < 2 {
+            return Err(anyhow!("dataset does not contain any frames"));
+        }
+        let mut intensities = dataset.data.slice(s![.., 1..;2]).to_owned();
+        let median_intensity = intensities
+            .quantile_axis_skipnan_mut(Axis(1), n64(0.5), &Midpoint)
+            .map_err(anyhow::Error::from)?;
+        let frame_metadata = dataset.combined_frame_metadata();
+        let wavenumber_axis = dataset.data.slice(s![.., 0]);
+        dataset.data = ndarray::stack(Axis(1), &[wavenumber_axis, median_intensity.view()])?;
+        dataset.frame_metadata = vec![frame_metadata];
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MedianTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_median_transform() {
+        let mut dataset = Dataset::new_test_dummy();
+        // spike in the last frame must not show up in the median
+        dataset.data[[0, 7]] = 1000.0;
+        MedianTransform {}.transform(&mut dataset).unwrap();
+        assert_eq!(
+            dataset.data.column(1),
+            array![15., 25., 35., 45., 55., 65., 75., 85.]
+        );
+    }
+}
@@ -1,10 +1,16 @@
-use crate::common::{Dataset, Pair};
-use crate::transformations::Transformer;
+use crate::common::{Dataset, DatasetShape, Pair};
+use crate::transformations::{PipelineContext, Transformer};
 use crate::utils::{nearest_index, trapz};
 use anyhow::{anyhow, Result};
 use clap::Parser;
+use ndarray::Axis;
+use ndarray_stats::QuantileExt;
 use serde::{Deserialize, Serialize};
 
+/// Divide frames by their intensity at an x-value or their area between two
+/// x-values. Missing values (NaN) are skipped: the intensity is taken at the
+/// closest point that is not missing and the area bridges gaps (see `trapz`),
+/// missing values stay missing.
 #[derive(Debug, Parser, Serialize, Deserialize)]
 #[serde(tag = "transformation")]
 pub struct NormalizeTransform {
@@ -37,16 +43,70 @@ pub struct NormalizeIOBuffers {
     pub y_max: String,
 }
 
+impl NormalizeTransform {
+    /// Normalize at the mean x-value of the maxima of all frames.
+    pub fn at_mean_maximum(dataset: &Dataset) -> Self {
+        let iterx = dataset.data.axis_iter(Axis(1)).step_by(2);
+        let itery = dataset.data.axis_iter(Axis(1)).skip(1).step_by(2);
+        let x_max: f64 = iterx
+            .zip(itery)
+            .map(|(xs, ys)| {
+                let idx = ys.argmax_skipnan().unwrap_or(0);
+                xs[idx]
+            })
+            .sum::<f64>()
+            / dataset.data.ncols() as f64
+            * 2.0;
+        NormalizeTransform {
+            xi: x_max,
+            xj: None,
+            filter_range: None,
+            local_baseline: false,
+            target_frames: None,
+            gui_text_buffers: NormalizeIOBuffers::default(),
+        }
+    }
+}
+
 impl Transformer for NormalizeTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        self.target_frames.is_some()
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
-    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+    fn is_frame_local(&self) -> bool {
+        self.target_frames.is_none()
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems: Vec<String> = [Some(self.xi), self.xj]
+            .into_iter()
+            .flatten()
+            .filter_map(|x| shape.check_x(x))
+            .collect();
+        for frame in self.target_frames.iter().flatten() {
+            problems.extend(shape.check_frame(*frame));
+        }
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
         let frames_iter = dataset.iter_mut_selected_frames(&self.target_frames);
-        for (xs, mut ys) in frames_iter {
+        for ((xs, mut ys), frame) in frames_iter.zip(1..) {
             let norm = match self.xj {
-                // normalize to y-value closest to xi
+                // normalize to y-value closest to xi, the x-values of
+                // missing y-values are never the closest
                 None => {
+                    let xs = ndarray::Zip::from(&xs).and(&ys).map_collect(|x, y| {
+                        if y.is_finite() {
+                            *x
+                        } else {
+                            f64::NAN
+                        }
+                    });
                     match nearest_index(&xs, self.xi) {
                         // unwrap: index from nearest_index() should always be valid
                         Some(idx) => *ys.get(idx).unwrap(),
@@ -56,13 +116,27 @@ impl Transformer for NormalizeTransform {
                 // normalize to intergral between xi and xj
                 Some(xj) => trapz(&xs, &ys, self.xi, xj, self.local_baseline)?,
             };
+            // intensities may be negative after subtracting a background,
+            // but dividing by a negative norm would turn the frame upside down
+            if norm == 0.0 || !norm.is_finite() {
+                return Err(anyhow!(
+                    "cannot normalize frame {}, the norm is {}",
+                    frame,
+                    norm
+                ));
+            }
+            if norm < 0.0 {
+                context.diagnostics.warn(format!(
+                    "the norm of frame {} is negative ({}), normalized to its magnitude",
+                    frame, norm
+                ));
+            }
+            let norm = norm.abs();
             if let Some(Pair { a, b }) = self.filter_range {
-                
             } else {
                 for yi in ys.iter_mut() {
                     *yi /= norm;
                 }
-                
             }
         }
         Ok(())
@@ -70,4 +144,32 @@ impl Transformer for NormalizeTransform {
 }
 
 #[cfg(test)]
-mod test {}
+mod test {
+    use super::NormalizeTransform;
+    use crate::common::Dataset;
+    use crate::transformations::{PipelineContext, Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_normalize_negative_norm() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![[1.0, -2.0, 1.0, 3.0], [2.0, 4.0, 2.0, 6.0]];
+        let mut trsf = NormalizeTransform::at_mean_maximum(&dataset);
+        trsf.xi = 1.0;
+        let context = PipelineContext::default();
+        trsf.transform_with_context(&mut dataset, &context).unwrap();
+        // the frame keeps its orientation
+        assert_eq!(dataset.data.column(1), array![-1.0, 2.0]);
+        assert_eq!(dataset.data.column(3), array![1.0, 2.0]);
+        assert_eq!(context.diagnostics.take().len(), 1);
+        // a missing value at xi is skipped
+        let mut missing = Dataset::new_test_dummy();
+        missing.data = array![[1.0, f64::NAN, 1.0, 3.0], [2.0, 4.0, 2.0, 6.0]];
+        trsf.transform(&mut missing).unwrap();
+        assert_eq!(missing.data[[1, 1]], 1.0);
+        assert!(missing.data[[0, 1]].is_nan());
+        // a norm of zero would give infinite intensities
+        dataset.data[[0, 3]] = 0.0;
+        assert!(trsf.transform(&mut dataset).is_err());
+    }
+}
@@ -1,9 +1,9 @@
-use crate::common::Dataset;
-use crate::transformations::Transformer;
-use anyhow::Result;
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use ndarray::Array1;
-use ndarray_stats::Quantile1dExt;
+use ndarray_stats::{interpolate, Quantile1dExt};
 use noisy_float::types::N64;
 use serde::{Deserialize, Serialize};
 
@@ -21,21 +21,121 @@ pub struct OffsetTransform {
     pub(crate) percentile: bool,
     #[clap(short, long, help = "Apply offset to these frames.")]
     pub(crate) target_frames: Option<Vec<usize>>,
+    #[clap(
+        long,
+        default_value = "nearest",
+        help = "How the percentile is taken between two values: nearest, lower, higher, midpoint or linear."
+    )]
+    #[serde(default, skip_serializing_if = "QuantileMethod::is_default")]
+    pub(crate) quantile_method: QuantileMethod,
+    #[clap(
+        long,
+        default_value = "1",
+        help = "Frames with fewer valid (not missing) values are left as they are, with a warning, when subtracting a percentile."
+    )]
+    #[serde(
+        default = "default_min_points",
+        skip_serializing_if = "is_default_min_points"
+    )]
+    pub(crate) min_points: usize,
     #[serde(skip)]
     #[clap(skip)]
     pub gui_text_buffers: OffsetIOBuffers,
 }
 
+fn default_min_points() -> usize {
+    1
+}
+
+fn is_default_min_points(min_points: &usize) -> bool {
+    *min_points == default_min_points()
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct OffsetIOBuffers {
     pub value: String,
 }
 
+/// How a percentile falling between two values of a frame is taken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuantileMethod {
+    /// the value nearest to the percentile
+    #[default]
+    Nearest,
+    Lower,
+    Higher,
+    /// mean of the two values
+    Midpoint,
+    /// linear interpolation between the two values
+    Linear,
+}
+
+impl std::str::FromStr for QuantileMethod {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(QuantileMethod::Nearest),
+            "lower" => Ok(QuantileMethod::Lower),
+            "higher" => Ok(QuantileMethod::Higher),
+            "midpoint" => Ok(QuantileMethod::Midpoint),
+            "linear" => Ok(QuantileMethod::Linear),
+            _ => Err(anyhow!(
+                "unknown quantile method \"{}\", use nearest, lower, higher, midpoint or linear",
+                s
+            )),
+        }
+    }
+}
+
+impl QuantileMethod {
+    fn is_default(&self) -> bool {
+        *self == QuantileMethod::default()
+    }
+    /// The `q` quantile of the values, which must not be empty.
+    fn quantile(self, values: &mut Array1<N64>, q: f64) -> Result<f64> {
+        let q = N64::from_f64(q);
+        let quantile = match self {
+            QuantileMethod::Nearest => values.quantile_mut(q, &interpolate::Nearest),
+            QuantileMethod::Lower => values.quantile_mut(q, &interpolate::Lower),
+            QuantileMethod::Higher => values.quantile_mut(q, &interpolate::Higher),
+            QuantileMethod::Midpoint => values.quantile_mut(q, &interpolate::Midpoint),
+            QuantileMethod::Linear => values.quantile_mut(q, &interpolate::Linear),
+        }?;
+        Ok(f64::from(quantile))
+    }
+}
+
 impl Transformer for OffsetTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        self.target_frames.is_some()
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
-    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+    fn is_frame_local(&self) -> bool {
+        self.target_frames.is_none()
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .target_frames
+            .iter()
+            .flatten()
+            .filter_map(|frame| shape.check_frame(*frame))
+            .collect();
+        if self.percentile && !(0.0..=1.0).contains(&self.offset) {
+            problems.push("percentile must be between 0 and 1".to_owned());
+        }
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        if self.percentile && !(0.0..=1.0).contains(&self.offset) {
+            return Err(anyhow!("percentile must be between 0 and 1"));
+        }
         let target_frames = match &self.target_frames {
             None => (0..(dataset.data.ncols() / 2 + 1)).collect(),
             Some(frames) => {
@@ -49,17 +149,25 @@ impl Transformer for OffsetTransform {
             }
             let offset = match self.percentile {
                 true => {
-                    // we filter out nan values explicitly
+                    // we filter out nan and infinite values explicitly,
+                    // negative values (e.g. after a dark subtraction) count
                     let mut tmp: Array1<N64> = vals
                         .iter()
-                        .filter(|x| !x.is_nan())
+                        .filter(|x| x.is_finite())
                         .map(|x| N64::new(*x))
                         .collect();
-                    let quantile = tmp.quantile_mut(
-                        N64::from_f64(self.offset),
-                        &ndarray_stats::interpolate::Nearest,
-                    )?;
-                    f64::from(-quantile)
+                    // junk frames (e.g. all missing) are skipped, so they do
+                    // not stop a batch run
+                    if tmp.is_empty() || tmp.len() < self.min_points {
+                        context.diagnostics.warn(format!(
+                            "frame {} has {} valid values, fewer than {}, no offset subtracted",
+                            col_no + 1,
+                            tmp.len(),
+                            self.min_points.max(1)
+                        ));
+                        continue;
+                    }
+                    -self.quantile_method.quantile(&mut tmp, self.offset)?
                 }
                 false => self.offset,
             };
@@ -72,8 +180,8 @@ impl Transformer for OffsetTransform {
 #[cfg(test)]
 mod test {
     use super::OffsetTransform;
-    use crate::common::Dataset;
-    use crate::transformations::Transformer;
+    use crate::common::{AxisUnit, Dataset};
+    use crate::transformations::{PipelineContext, Transformer};
     use ndarray::array;
 
     #[test]
@@ -88,6 +196,8 @@ target_scans:
         let mut dataset = Dataset {
             metadata: "".to_string(),
             previous_comments: "".to_string(),
+            frame_metadata: vec![],
+            x_unit: AxisUnit::Unknown,
             data: array![
                 [11., 12., 13., 14., 11., 12., 13., 14.],
                 [21., 22., 23., 24., 21., 22., 23., 24.],
@@ -98,6 +208,8 @@ target_scans:
                 [71., 72., 73., 74., 71., 72., 73., 74.],
                 [81., 82., 83., 84., 81., 82., 83., 84.],
             ],
+            channels: Default::default(),
+            provenance_log: vec![],
         };
         let exprected_data = array![
             [11., 14., 13., 14., 11., 12., 13., 16.],
@@ -109,7 +221,46 @@ target_scans:
             [71., 74., 73., 74., 71., 72., 73., 76.],
             [81., 84., 83., 84., 81., 82., 83., 86.],
         ];
-        transform.apply(&mut dataset).unwrap();
+        transform
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
         assert_eq!(dataset.data, exprected_data)
     }
+
+    #[test]
+    fn test_percentile_junk_frames() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![
+            [1.0, f64::NAN, 1.0, 2.0],
+            [2.0, f64::NAN, 2.0, 4.0],
+            [3.0, f64::NAN, 3.0, 6.0]
+        ];
+        let mut transform: OffsetTransform =
+            serde_yaml::from_str("transformation: OffsetTransform\noffset: 0.25\npercentile: true\nquantile_method: linear")
+                .unwrap();
+        let context = PipelineContext::default();
+        transform
+            .transform_with_context(&mut dataset, &context)
+            .unwrap();
+        assert_eq!(context.diagnostics.take().len(), 1);
+        assert!(dataset.data[[0, 1]].is_nan());
+        assert_eq!(dataset.data.column(3), array![-1.0, 1.0, 3.0]);
+    }
+
+    #[test]
+    fn test_percentile_negative_values() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![
+            [1.0, -3.0],
+            [2.0, -1.0],
+            [3.0, f64::NEG_INFINITY],
+            [4.0, 2.0]
+        ];
+        let mut transform: OffsetTransform =
+            serde_yaml::from_str("transformation: OffsetTransform\noffset: 0.0\npercentile: true")
+                .unwrap();
+        transform.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data[[0, 1]], 0.0);
+        assert_eq!(dataset.data[[3, 1]], 5.0);
+    }
 }
@@ -0,0 +1,84 @@
+use crate::common::Dataset;
+use crate::plot::{stack_frames, StackOffset};
+use crate::transformations::Transformer;
+use anyhow::Result;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Shift each frame vertically by its index times the offset, the waterfall
+/// plot of the GUI baked into the data, e.g. for figures made with other
+/// programs.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct OffsetStackTransform {
+    #[clap(
+        default_value("auto"),
+        help = "Offset between successive frames, a number or \"auto\" (median intensity range of the frames)."
+    )]
+    pub(crate) offset: StackOffset,
+    /// offset used in the last run, reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) applied_offset: Option<f64>,
+}
+
+impl OffsetStackTransform {
+    pub fn new(offset: StackOffset) -> Self {
+        Self {
+            offset,
+            applied_offset: None,
+        }
+    }
+}
+
+impl Transformer for OffsetStackTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let offset = self.offset.resolve(dataset);
+        stack_frames(dataset, offset);
+        self.applied_offset = Some(offset);
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        if let Some(offset) = self.applied_offset {
+            dataset.metadata += &format!("applied_offset: {}\n", offset);
+        }
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OffsetStackTransform;
+    use crate::common::Dataset;
+    use crate::plot::StackOffset;
+    use crate::transformations::Transformer;
+    use ndarray::array;
+
+    #[test]
+    fn test_offset_stack() {
+        let mut dataset = Dataset::new_test_dummy();
+        let input = dataset.clone();
+        let mut stack = OffsetStackTransform::new(StackOffset::Fixed(100.0));
+        stack.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.column(1), input.data.column(1));
+        assert_eq!(
+            dataset.data.column(5),
+            &input.data.column(5) + array![200.0]
+        );
+        // x-axes are untouched
+        assert_eq!(dataset.data.column(4), input.data.column(4));
+        // every frame of the dummy spans 70 counts
+        let mut dataset = input.clone();
+        let mut stack = OffsetStackTransform::new(StackOffset::Auto);
+        stack.transform(&mut dataset).unwrap();
+        assert_eq!(stack.applied_offset, Some(70.0));
+        let yaml = stack.config_to_string().unwrap();
+        let parsed: OffsetStackTransform = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed.offset, StackOffset::Auto);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::common::{AxisUnit, Dataset, DatasetShape, Pair};
+use crate::transformations::{PipelineContext, Transformer};
+use crate::utils::{linear_resample_array, lininterp, trapz};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{array, Array1, Array2, ArrayView1, Axis};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Number of values reported for each window: position and intensity of the
+/// maximum, centroid and full width at half maximum.
+const PEAK_VALUES: usize = 4;
+
+/// Position and intensity of the maximum, centroid and FWHM of the band in
+/// each window, one row per frame, e.g. to follow the shift of a band over a
+/// series without fitting a peak shape.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct PeakPickTransform {
+    #[clap(help = "Left and right bound of the window around each band, separated by comma.")]
+    pub(crate) bounds: Vec<Pair<f64>>,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Subtract local baseline (straight line from window start- to end-point) before picking."
+    )]
+    #[serde(default)]
+    pub(crate) local_baseline: bool,
+}
+
+impl PeakPickTransform {
+    /// Values of a frame for all windows, each preceded by `x`.
+    fn row(&self, xs: ArrayView1<f64>, ys: ArrayView1<f64>, x: f64) -> Result<Array1<f64>> {
+        let mut values = vec![];
+        for bd in self.bounds.iter() {
+            values.extend(pick(xs, ys, bd.a, bd.b, self.local_baseline)?);
+        }
+        Ok(values.into_iter().flat_map(|value| [x, value]).collect())
+    }
+}
+
+/// Position and intensity of the maximum (refined by a parabola through the
+/// highest point and its neighbours), centroid and FWHM of the band between
+/// `left` and `right`. The FWHM is NaN if the band does not drop to half its
+/// maximum inside the window.
+fn pick(
+    xs: ArrayView1<f64>,
+    ys: ArrayView1<f64>,
+    left: f64,
+    right: f64,
+    local_baseline: bool,
+) -> Result<[f64; PEAK_VALUES]> {
+    let (left, right) = if left < right {
+        (left, right)
+    } else {
+        (right, left)
+    };
+    let ys = if local_baseline {
+        let ends = linear_resample_array(&xs, &ys, &array![left, right]);
+        if ends.iter().any(|y| y.is_nan()) {
+            return Err(anyhow!("Peak window out of bounds."));
+        }
+        &ys - &xs.mapv(|x| lininterp(x, left, right, ends[0], ends[1]))
+    } else {
+        ys.to_owned()
+    };
+    // the points between the bounds follow each other on the x-axis, missing
+    // values among them are skipped
+    let inside: Vec<usize> = (0..xs.len())
+        .filter(|i| xs[*i] >= left && xs[*i] <= right)
+        .collect();
+    let window: Range<usize> = match (inside.first(), inside.last()) {
+        (Some(first), Some(last)) => *first..last + 1,
+        _ => 0..0,
+    };
+    let in_window = |i: usize| window.contains(&i) && ys[i].is_finite();
+    let top = window
+        .clone()
+        .filter(|i| ys[*i].is_finite())
+        .max_by(|i, j| ys[*i].total_cmp(&ys[*j]))
+        .ok_or_else(|| anyhow!("no data points between {} and {}", left, right))?;
+    let (mut position, mut intensity) = (xs[top], ys[top]);
+    if top > 0 && in_window(top - 1) && in_window(top + 1) {
+        let (x0, x1, x2) = (xs[top - 1], xs[top], xs[top + 1]);
+        let (y0, y1, y2) = (ys[top - 1], ys[top], ys[top + 1]);
+        // vertex of the parabola through the three points
+        let d0 = (y1 - y0) / (x1 - x0);
+        let d1 = (y2 - y1) / (x2 - x1);
+        let curvature = (d1 - d0) / (x2 - x0);
+        if curvature < 0.0 {
+            let slope = d0 - curvature * (x0 + x1);
+            position = -slope / (2.0 * curvature);
+            intensity = y1 + (position - x1) * (d0 + curvature * (position - x0));
+        }
+    }
+    let area = trapz(&xs, &ys, left, right, false)?;
+    let centroid = trapz(&xs, &(&xs * &ys), left, right, false)? / area;
+    let half = intensity / 2.0;
+    // first crossing of the half maximum on each side of the maximum
+    let crossing = |indices: &mut dyn Iterator<Item = usize>| -> Option<f64> {
+        let mut previous = top;
+        for i in indices {
+            if !in_window(i) {
+                return None;
+            }
+            if ys[i] < half {
+                return Some(lininterp(half, ys[previous], ys[i], xs[previous], xs[i]));
+            }
+            previous = i;
+        }
+        None
+    };
+    let fwhm = match (
+        crossing(&mut (0..top).rev()),
+        crossing(&mut (top + 1..xs.len())),
+    ) {
+        (Some(low), Some(high)) => (high - low).abs(),
+        _ => f64::NAN,
+    };
+    Ok([position, intensity, centroid, fwhm])
+}
+
+impl Transformer for PeakPickTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let problems = self
+            .bounds
+            .iter()
+            .flat_map(|Pair { a, b }| [shape.check_x(*a), shape.check_x(*b)])
+            .flatten()
+            .collect();
+        // the result holds one row per frame and four frames per window
+        *shape = DatasetShape {
+            rows: shape.frames,
+            frames: Some(self.bounds.len() * PEAK_VALUES),
+            x_range: shape.frames.map(|frames| (1.0, frames as f64)),
+            x_unit: AxisUnit::Unknown,
+        };
+        problems
+    }
+    fn frame_row(&self, frame: &Dataset, index: usize) -> Option<Result<Array1<f64>>> {
+        Some(self.row(
+            frame.data.column(0),
+            frame.data.column(1),
+            (index + 1) as f64,
+        ))
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let mut peaks: Array2<f64> = Array2::zeros((
+            dataset.data.ncols() / 2,
+            self.bounds.len() * PEAK_VALUES * 2,
+        ));
+        for (i, (xs, ys)) in dataset
+            .data
+            .axis_iter(Axis(1))
+            .step_by(2)
+            .zip(dataset.data.axis_iter(Axis(1)).skip(1).step_by(2))
+            .enumerate()
+        {
+            peaks.row_mut(i).assign(&self.row(xs, ys, (i + 1) as f64)?);
+        }
+        dataset.data = peaks;
+        // the frames of the result are the values of the windows
+        dataset.frame_metadata = vec![];
+        dataset.x_unit = AxisUnit::Unknown;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pick, PeakPickTransform};
+    use crate::common::{Dataset, Pair};
+    use crate::transformations::Transformer;
+    use ndarray::Array1;
+
+    #[test]
+    fn test_pick() {
+        // gaussian band at 50.3 with a FWHM of 10 on a sloped baseline
+        let xs: Array1<f64> = Array1::range(0.0, 100.0, 0.5);
+        let sigma = 10.0 / (8.0 * 2.0_f64.ln()).sqrt();
+        let ys =
+            xs.mapv(|x| 5.0 * (-(x - 50.3_f64).powi(2) / (2.0 * sigma * sigma)).exp() + 0.01 * x);
+        let [position, intensity, centroid, fwhm] =
+            pick(xs.view(), ys.view(), 20.0, 80.0, true).unwrap();
+        assert!((position - 50.3).abs() < 0.05, "{}", position);
+        assert!((intensity - 5.0).abs() < 0.05, "{}", intensity);
+        assert!((centroid - 50.3).abs() < 0.05, "{}", centroid);
+        assert!((fwhm - 10.0).abs() < 0.1, "{}", fwhm);
+        // the band is cut off by a narrow window
+        let [_, _, _, fwhm] = pick(xs.view(), ys.view(), 48.0, 52.0, false).unwrap();
+        assert!(fwhm.is_nan());
+        assert!(pick(xs.view(), ys.view(), 120.0, 130.0, false).is_err());
+    }
+
+    #[test]
+    fn test_peak_pick() {
+        let mut dataset = Dataset::new_test_dummy();
+        let mut trsf = PeakPickTransform {
+            bounds: vec![Pair { a: 21.0, b: 51.0 }],
+            local_baseline: false,
+        };
+        trsf.transform(&mut dataset).unwrap();
+        // one row per frame, x = frame number followed by the four values
+        assert_eq!(dataset.data.dim(), (4, 8));
+        assert_eq!(dataset.data.column(0).to_vec(), vec![1.0, 2.0, 3.0, 4.0]);
+        // the intensities rise with x, the maximum is at the right bound
+        assert_eq!(dataset.data[[0, 1]], 51.0);
+    }
+}
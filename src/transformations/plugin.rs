@@ -0,0 +1,471 @@
+use crate::common::{Dataset, DatasetShape, FrameMetadata};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use libloading::{Library, Symbol};
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr, CString};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+/// Version of the interface between the program and the plugins, a plugin
+/// built against another version is not loaded.
+pub const PLUGIN_ABI_VERSION: u32 = 1;
+
+/// Frames exchanged with a plugin: `rows` x `cols` values in row-major
+/// order, the columns alternate between x and y of each frame like the
+/// columns of a dataset.
+#[repr(C)]
+pub struct PluginData {
+    pub data: *mut f64,
+    pub rows: usize,
+    pub cols: usize,
+}
+
+/// `u32 rustman_plugin_abi_version()`, the `PLUGIN_ABI_VERSION` the plugin
+/// was built against.
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+/// `const char *rustman_plugin_describe()`, the `PluginDescription` as
+/// YAML, owned by the plugin.
+type DescribeFn = unsafe extern "C" fn() -> *const c_char;
+/// `char *rustman_plugin_transform(const char *parameters, const PluginData
+/// *input, PluginData *output)`, transform the input with the parameters
+/// given as a YAML mapping. Returns null on success or an error message,
+/// output and message are owned by the plugin.
+type TransformFn =
+    unsafe extern "C" fn(*const c_char, *const PluginData, *mut PluginData) -> *mut c_char;
+/// `void rustman_plugin_free(PluginData *output, char *error)`, release
+/// what `rustman_plugin_transform` handed out, either may be null.
+type FreeFn = unsafe extern "C" fn(*mut PluginData, *mut c_char);
+
+/// What a plugin tells about itself, the parameters are used to render its
+/// form in the GUI and are checked before the plugin is called.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginDescription {
+    pub name: String,
+    #[serde(default)]
+    pub about: String,
+    #[serde(default)]
+    pub parameters: Vec<PluginParameterDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginParameterDescription {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: PluginParameterKind,
+    #[serde(default)]
+    pub default: Value,
+    #[serde(default)]
+    pub help: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PluginParameterKind {
+    Number,
+    Integer,
+    Boolean,
+    String,
+}
+
+impl PluginParameterKind {
+    fn accepts(&self, value: &Value) -> bool {
+        match self {
+            PluginParameterKind::Number => value.is_f64() || value.is_i64() || value.is_u64(),
+            PluginParameterKind::Integer => value.is_i64() || value.is_u64(),
+            PluginParameterKind::Boolean => value.is_bool(),
+            PluginParameterKind::String => value.is_string(),
+        }
+    }
+}
+
+/// Directory the plugins are looked up in: `RUSTMAN_PLUGINS` if set,
+/// otherwise the `plugins` directory next to the executable.
+pub fn plugin_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("RUSTMAN_PLUGINS") {
+        return Some(PathBuf::from(dir));
+    }
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.join("plugins"))
+}
+
+/// Plugins loaded so far by the path of their library. Each library is
+/// loaded once and stays loaded until the program ends, instead of again for
+/// every run of a step.
+static LOADED_PLUGINS: OnceLock<Mutex<HashMap<PathBuf, Arc<Plugin>>>> = OnceLock::new();
+
+/// A loaded plugin, the library stays open as long as this lives.
+struct Plugin {
+    library: Library,
+    description: PluginDescription,
+}
+
+impl Plugin {
+    fn load(path: &Path) -> Result<Self> {
+        // SAFETY: loading runs the initialisers of the library, plugins are
+        // only taken from the plugin directory the user put them into
+        let library = unsafe { Library::new(path) }
+            .with_context(|| format!("Unable to load plugin {:?}", path))?;
+        let description = unsafe {
+            let version: Symbol<AbiVersionFn> = library
+                .get(b"rustman_plugin_abi_version")
+                .with_context(|| format!("{:?} is not a rustman plugin", path))?;
+            if version() != PLUGIN_ABI_VERSION {
+                return Err(anyhow!(
+                    "plugin {:?} was built for interface version {}, this program uses {}",
+                    path,
+                    version(),
+                    PLUGIN_ABI_VERSION
+                ));
+            }
+            let describe: Symbol<DescribeFn> = library.get(b"rustman_plugin_describe")?;
+            let yaml = describe();
+            if yaml.is_null() {
+                return Err(anyhow!("plugin {:?} does not describe itself", path));
+            }
+            CStr::from_ptr(yaml).to_string_lossy().into_owned()
+        };
+        let description = serde_yaml::from_str(&description)
+            .with_context(|| format!("Unable to parse the description of plugin {:?}", path))?;
+        Ok(Plugin {
+            library,
+            description,
+        })
+    }
+
+    fn transform(&self, parameters: &Mapping, data: &Array2<f64>) -> Result<Array2<f64>> {
+        let parameters = CString::new(serde_yaml::to_string(parameters)?)?;
+        let mut input_values = data.as_standard_layout().to_owned();
+        let input = PluginData {
+            data: input_values.as_mut_ptr(),
+            rows: data.nrows(),
+            cols: data.ncols(),
+        };
+        let mut output = PluginData {
+            data: std::ptr::null_mut(),
+            rows: 0,
+            cols: 0,
+        };
+        // SAFETY: the plugin reads `rows` x `cols` values from the input and
+        // hands out as many as it says in the output, until they are freed
+        unsafe {
+            let transform: Symbol<TransformFn> = self.library.get(b"rustman_plugin_transform")?;
+            let free: Symbol<FreeFn> = self.library.get(b"rustman_plugin_free")?;
+            let error = transform(parameters.as_ptr(), &input, &mut output);
+            if !error.is_null() {
+                let message = CStr::from_ptr(error).to_string_lossy().into_owned();
+                free(&mut output, error);
+                return Err(anyhow!(
+                    "plugin {} failed: {}",
+                    self.description.name,
+                    message
+                ));
+            }
+            let values = if output.data.is_null() {
+                vec![]
+            } else {
+                std::slice::from_raw_parts(output.data, output.rows * output.cols).to_vec()
+            };
+            let shape = (output.rows, output.cols);
+            free(&mut output, std::ptr::null_mut());
+            Array2::from_shape_vec(shape, values).map_err(anyhow::Error::msg)
+        }
+    }
+}
+
+/// The plugin in the library at `path`, loaded at the first call. Libraries
+/// that fail to load are tried again at the next call, e.g. if the file was
+/// still being copied.
+fn load_cached(path: &Path) -> Result<Arc<Plugin>> {
+    let mut loaded = LOADED_PLUGINS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some(plugin) = loaded.get(path) {
+        return Ok(plugin.clone());
+    }
+    let plugin = Arc::new(Plugin::load(path)?);
+    loaded.insert(path.to_owned(), plugin.clone());
+    Ok(plugin)
+}
+
+/// Load all dynamic libraries found in `dir` as plugins.
+fn load_plugins(dir: &Path) -> Vec<Result<Arc<Plugin>>> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == std::env::consts::DLL_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| load_cached(path)).collect()
+}
+
+fn find_plugin(name: &str) -> Result<Arc<Plugin>> {
+    let dir = plugin_dir().ok_or_else(|| anyhow!("no plugin directory found"))?;
+    load_plugins(&dir)
+        .into_iter()
+        .flatten()
+        .find(|plugin| plugin.description.name == name)
+        .ok_or_else(|| anyhow!("no plugin named \"{}\" in {:?}", name, dir))
+}
+
+/// Descriptions of all plugins in the plugin directory.
+pub fn available_plugins() -> Vec<PluginDescription> {
+    plugin_dir()
+        .map(|dir| load_plugins(&dir))
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .map(|plugin| plugin.description.clone())
+        .collect()
+}
+
+/// Parameter of a plugin given on the command line as `name=value`, the
+/// value is read as YAML.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginParameter {
+    pub name: String,
+    pub value: Value,
+}
+
+impl std::str::FromStr for PluginParameter {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected parameter as <name>=<value>, got \"{}\"", s))?;
+        Ok(PluginParameter {
+            name: name.trim().to_owned(),
+            value: serde_yaml::from_str(value)?,
+        })
+    }
+}
+
+/// The parameters are written to the header as a mapping from their names to
+/// their values.
+mod parameter_mapping {
+    use super::PluginParameter;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde_yaml::{Mapping, Value};
+
+    pub fn serialize<S: Serializer>(
+        parameters: &[PluginParameter],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        parameters
+            .iter()
+            .map(|p| (Value::from(p.name.as_str()), p.value.clone()))
+            .collect::<Mapping>()
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<PluginParameter>, D::Error> {
+        Ok(Mapping::deserialize(deserializer)?
+            .into_iter()
+            .filter_map(|(name, value)| {
+                name.as_str().map(|name| PluginParameter {
+                    name: name.to_owned(),
+                    value,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Transformation provided by a plugin, a dynamic library in the plugin
+/// directory (see `plugin_dir`) exporting the functions described at
+/// `PluginData`.
+#[derive(Debug, Default, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct PluginTransform {
+    #[clap(help = "Name of the plugin, as it describes itself.")]
+    pub plugin: String,
+    #[clap(
+        short,
+        long = "param",
+        help = "Parameter of the plugin as <name>=<value>, may be given several times, parameters not given take their default."
+    )]
+    #[serde(default, with = "parameter_mapping")]
+    pub parameters: Vec<PluginParameter>,
+    #[serde(skip)]
+    #[clap(skip)]
+    pub gui_text_buffers: PluginIOBuffers,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct PluginIOBuffers {
+    /// plugins found in the plugin directory, looked up once
+    pub available: Option<Vec<PluginDescription>>,
+    /// text entered for the string and number parameters
+    pub values: Vec<String>,
+}
+
+impl PluginTransform {
+    /// The parameters with the defaults of the plugin filled in, problems if
+    /// parameters are unknown or of the wrong type.
+    fn complete_parameters(&self, description: &PluginDescription) -> (Mapping, Vec<String>) {
+        let mut problems = vec![];
+        for parameter in self.parameters.iter() {
+            if !description
+                .parameters
+                .iter()
+                .any(|p| p.name == parameter.name)
+            {
+                problems.push(format!(
+                    "plugin {} has no parameter \"{}\"",
+                    description.name, parameter.name
+                ));
+            }
+        }
+        let mut mapping = Mapping::new();
+        for expected in description.parameters.iter() {
+            let value = self
+                .parameters
+                .iter()
+                .find(|p| p.name == expected.name)
+                .map_or(&expected.default, |p| &p.value);
+            if !expected.kind.accepts(value) {
+                problems.push(format!(
+                    "parameter \"{}\" of plugin {} must be a {}, got {:?}",
+                    expected.name,
+                    description.name,
+                    format!("{:?}", expected.kind).to_lowercase(),
+                    value
+                ));
+            }
+            mapping.insert(expected.name.as_str().into(), value.clone());
+        }
+        (mapping, problems)
+    }
+}
+
+impl Transformer for PluginTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        // plugins get the whole data and may use any position
+        true
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        // a plugin may change the shape in any way
+        *shape = DatasetShape {
+            x_unit: shape.x_unit,
+            ..Default::default()
+        };
+        match find_plugin(&self.plugin) {
+            Ok(plugin) => self.complete_parameters(&plugin.description).1,
+            Err(err) => vec![err.to_string()],
+        }
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let plugin = find_plugin(&self.plugin)?;
+        let (parameters, problems) = self.complete_parameters(&plugin.description);
+        if let Some(problem) = problems.first() {
+            return Err(anyhow!("{}", problem));
+        }
+        let data = plugin.transform(&parameters, &dataset.data)?;
+        if data.ncols() % 2 != 0 {
+            return Err(anyhow!(
+                "plugin {} returned {} columns, expected pairs of x and y",
+                self.plugin,
+                data.ncols()
+            ));
+        }
+        // keep the frame metadata aligned with the frames
+        if data.ncols() != dataset.data.ncols() {
+            dataset
+                .frame_metadata
+                .resize(data.ncols() / 2, FrameMetadata::default());
+        }
+        dataset.data = data;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        available_plugins, find_plugin, PluginDescription, PluginParameter, PluginParameterKind,
+        PluginTransform,
+    };
+    use crate::common::Dataset;
+    use crate::transformations::Transformer;
+    use serde_yaml::{Mapping, Value};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_plugin_parameters() {
+        let description: PluginDescription = serde_yaml::from_str(
+            "name: tilt\nparameters:\n  - name: theta\n    type: number\n    default: 0.0\n  - name: frames\n    type: integer\n    default: 1\n",
+        )
+        .unwrap();
+        assert_eq!(description.parameters[1].kind, PluginParameterKind::Integer);
+        let mut trsf = PluginTransform {
+            plugin: "tilt".to_owned(),
+            parameters: vec!["theta=12.5".parse().unwrap()],
+            ..Default::default()
+        };
+        let (parameters, problems) = trsf.complete_parameters(&description);
+        assert!(problems.is_empty());
+        assert_eq!(parameters.get("theta"), Some(&Value::from(12.5)));
+        assert_eq!(parameters.get("frames"), Some(&Value::from(1)));
+        // the parameters round-trip through the header as a mapping
+        let yaml = serde_yaml::to_string(&trsf).unwrap();
+        assert!(yaml.contains("parameters:\n  theta: 12.5\n"));
+        let parsed: PluginTransform = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed.parameters, trsf.parameters);
+        trsf.parameters = vec!["theta=high".parse().unwrap(), "phi=1".parse().unwrap()];
+        assert_eq!(trsf.complete_parameters(&description).1.len(), 2);
+        assert!("theta".parse::<PluginParameter>().is_err());
+    }
+
+    #[test]
+    fn test_example_plugin() {
+        // examples/tilt_plugin.rs, built by cargo into the examples directory
+        // next to the directory of the test binary
+        let examples = std::env::current_exe()
+            .unwrap()
+            .parent()
+            .and_then(|deps| deps.parent())
+            .unwrap()
+            .join("examples");
+        std::env::set_var("RUSTMAN_PLUGINS", &examples);
+        let available = available_plugins();
+        assert!(
+            available.iter().any(|plugin| plugin.name == "tilt"),
+            "example plugin not found in {:?}, build it with cargo build --example tilt_plugin \
+             (cargo test builds it unless a filter is given)",
+            examples
+        );
+        // the library is loaded once
+        let plugin = find_plugin("tilt").unwrap();
+        assert!(Arc::ptr_eq(&plugin, &find_plugin("tilt").unwrap()));
+        let mut trsf = PluginTransform {
+            plugin: "tilt".to_owned(),
+            parameters: vec!["slope=2".parse().unwrap()],
+            ..Default::default()
+        };
+        let mut dataset = Dataset::new_test_dummy();
+        let expected = &dataset.data.column(1) + &(&dataset.data.column(0) * 2.0);
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.column(1), expected);
+        assert_eq!(dataset.data.dim(), Dataset::new_test_dummy().data.dim());
+        // errors of the plugin are passed on with its message
+        let error = plugin
+            .transform(&Mapping::new(), &dataset.data)
+            .unwrap_err();
+        assert!(error.to_string().contains("parameter slope is missing"));
+    }
+}
@@ -0,0 +1,223 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::similarity::SimilarityMeasure;
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{s, Array1, Axis};
+use ndarray_stats::interpolate::Midpoint;
+use ndarray_stats::QuantileExt;
+use noisy_float::prelude::n64;
+use serde::{Deserialize, Serialize};
+
+/// Remove frames that do not look like the others, for automated quality
+/// control in batch runs. The numbers of the removed frames are recorded in
+/// the metadata. With `--weight`, the frames are kept and outliers get a
+/// quality weight below 1 instead, the further they miss the criteria the
+/// lower.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct RejectOutliersTransform {
+    #[clap(
+        short,
+        long,
+        help = "Reject frames whose correlation with the pixel-wise median of all frames is below this value."
+    )]
+    #[serde(default)]
+    pub(crate) min_correlation: Option<f64>,
+    #[clap(
+        short,
+        long,
+        help = "Reject frames whose total counts deviate from the mean by more than this many standard deviations."
+    )]
+    #[serde(default)]
+    pub(crate) sigma: Option<f64>,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Keep all frames and down-weight the outliers instead (honored by average and mcr), e.g. a frame with correlation 0.8 gets weight 0.8 / min-correlation."
+    )]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub(crate) weight: bool,
+    /// frames (counting from 1) rejected in the last run, reported in the
+    /// metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) rejected: Vec<usize>,
+    /// quality weights of the frames in the last run with `--weight`,
+    /// reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) weights: Vec<f64>,
+}
+
+impl RejectOutliersTransform {
+    pub fn new(min_correlation: Option<f64>, sigma: Option<f64>) -> Self {
+        Self {
+            min_correlation,
+            sigma,
+            weight: false,
+            rejected: vec![],
+            weights: vec![],
+        }
+    }
+
+    /// Quality of each frame between 0 and 1: 1 if it meets all criteria,
+    /// otherwise the product of how far it gets towards each criterion it
+    /// fails.
+    fn quality(&self, dataset: &Dataset) -> Result<Vec<f64>> {
+        let mut intensities = dataset.data.slice(s![.., 1..;2]).to_owned();
+        let nframes = intensities.ncols();
+        let mut quality = vec![1.0; nframes];
+        if let Some(min_correlation) = self.min_correlation {
+            let median = intensities
+                .quantile_axis_skipnan_mut(Axis(1), n64(0.5), &Midpoint)
+                .map_err(anyhow::Error::from)?;
+            let intensities = dataset.data.slice(s![.., 1..;2]);
+            for (j, frame) in intensities.axis_iter(Axis(1)).enumerate() {
+                let correlation = SimilarityMeasure::Correlation.compare(frame, median.view());
+                // a NaN correlation (e.g. a constant frame) fails as well
+                if correlation.is_nan() {
+                    quality[j] = 0.0;
+                } else if correlation < min_correlation {
+                    quality[j] *= if min_correlation > 0.0 {
+                        (correlation / min_correlation).max(0.0)
+                    } else {
+                        0.0
+                    };
+                }
+            }
+        }
+        if let Some(sigma) = self.sigma {
+            let totals: Array1<f64> = dataset
+                .data
+                .slice(s![.., 1..;2])
+                .axis_iter(Axis(1))
+                .map(|frame| frame.iter().filter(|y| y.is_finite()).sum())
+                .collect();
+            if nframes > 1 {
+                let mean = totals.mean().unwrap_or_default();
+                let std = totals.std(1.0);
+                for (j, total) in totals.iter().enumerate() {
+                    let deviation = (total - mean).abs();
+                    if deviation > sigma * std {
+                        quality[j] *= sigma * std / deviation;
+                    }
+                }
+            }
+        }
+        Ok(quality)
+    }
+}
+
+impl Transformer for RejectOutliersTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if self.min_correlation.is_none() && self.sigma.is_none() {
+            problems.push("no criterion given, use --min-correlation and/or --sigma".to_owned());
+        }
+        if self.sigma.is_some_and(|sigma| sigma <= 0.0) {
+            problems.push("--sigma must be positive".to_owned());
+        }
+        // how many frames are left is only known after the run
+        if !self.weight {
+            shape.frames = None;
+        }
+        problems
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        if self.min_correlation.is_none() && self.sigma.is_none() {
+            return Err(anyhow!(
+                "no criterion given, use --min-correlation and/or --sigma"
+            ));
+        }
+        let quality = self.quality(dataset)?;
+        if self.weight {
+            let frames = dataset.data.ncols() / 2;
+            dataset.frame_metadata.resize(frames, Default::default());
+            for (meta, quality) in dataset.frame_metadata.iter_mut().zip(quality.iter()) {
+                meta.weight = Some(meta.weight.unwrap_or(1.0) * quality);
+            }
+            self.rejected = vec![];
+            self.weights = quality;
+            return Ok(());
+        }
+        let rejected: Vec<usize> = (1..=quality.len())
+            .filter(|n| quality[n - 1] < 1.0)
+            .collect();
+        if rejected.len() == dataset.data.ncols() / 2 {
+            return Err(anyhow!("all frames were rejected as outliers"));
+        }
+        let frame_metadata = dataset.select_frame_metadata(&rejected, true);
+        dataset.data = dataset.select_frames(&rejected, true)?;
+        dataset.frame_metadata = frame_metadata;
+        self.rejected = rejected;
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        if self.weight {
+            dataset.metadata += &format!("weights: {:?}\n", self.weights);
+        } else {
+            dataset.metadata += &format!("rejected: {:?}\n", self.rejected);
+        }
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RejectOutliersTransform;
+    use crate::common::Dataset;
+    use crate::transformations::Transformer;
+
+    #[test]
+    fn test_reject_outliers() {
+        let input = Dataset::new_test_dummy();
+        // a spike changes the shape of the third frame
+        let mut dataset = input.clone();
+        dataset.data[[2, 5]] = 1000.0;
+        let mut reject = RejectOutliersTransform::new(Some(0.9), None);
+        reject.transform(&mut dataset).unwrap();
+        assert_eq!(reject.rejected, vec![3]);
+        assert_eq!(dataset.data.ncols(), 6);
+        assert_eq!(dataset.data.column(5), input.data.column(7));
+        // a frame with much higher counts but the same shape
+        let mut dataset = input.clone();
+        dataset.data.column_mut(3).mapv_inplace(|y| 10.0 * y);
+        let mut reject = RejectOutliersTransform::new(Some(0.9), Some(1.0));
+        reject.transform(&mut dataset).unwrap();
+        assert_eq!(reject.rejected, vec![2]);
+        // nothing to reject
+        let mut dataset = input.clone();
+        let mut reject = RejectOutliersTransform::new(Some(0.9), Some(2.0));
+        reject.transform(&mut dataset).unwrap();
+        assert!(reject.rejected.is_empty());
+        assert_eq!(dataset.data, input.data);
+    }
+
+    #[test]
+    fn test_weight_outliers() {
+        let input = Dataset::new_test_dummy();
+        let mut dataset = input.clone();
+        dataset.data[[2, 5]] = 1000.0;
+        let mut reject = RejectOutliersTransform::new(Some(0.9), None);
+        reject.weight = true;
+        reject.transform(&mut dataset).unwrap();
+        // all frames are kept, only the third one is down-weighted
+        assert_eq!(dataset.data.ncols(), input.data.ncols());
+        assert!(reject.rejected.is_empty());
+        let weights = dataset.weights().unwrap();
+        assert_eq!(weights[0], 1.0);
+        assert!(weights[2] < 1.0, "{:?}", weights);
+        assert_eq!(weights, reject.weights);
+        // weights attached before are kept
+        dataset.frame_metadata[0].weight = Some(0.5);
+        reject.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.weights().unwrap()[0], 0.5);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::common::{yaml_mapping_to_transform, Dataset, DatasetShape};
+use crate::gui::TransformerGUI;
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::Zip;
+use serde::{Deserialize, Serialize};
+
+/// Apply a sub-pipeline several times, e.g. to alternate baseline
+/// subtraction and despiking until the result does not change anymore.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct RepeatTransform {
+    #[clap(help = "Maximum number of passes through the repeated steps.")]
+    pub(crate) times: usize,
+    #[clap(
+        short,
+        long,
+        help = "Stop early once a pass changes the intensities by less than this fraction (root mean square change relative to the root mean square intensity)."
+    )]
+    #[serde(default)]
+    pub(crate) tolerance: Option<f64>,
+    #[clap(
+        short,
+        long,
+        default_value("1"),
+        help = "Number of the commands following on the command line that are repeated."
+    )]
+    #[serde(skip)]
+    pub(crate) commands: usize,
+    /// the repeated steps, in the YAML header as a list of transformations
+    #[clap(skip)]
+    #[serde(with = "nested_steps")]
+    pub(crate) steps: Vec<Box<dyn TransformerGUI>>,
+    /// number of passes of the last run, reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) passes: usize,
+}
+
+/// (De)serialize the repeated steps with the YAML configuration each step
+/// writes into the metadata.
+mod nested_steps {
+    use super::{yaml_mapping_to_transform, TransformerGUI};
+    use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        steps: &[Box<dyn TransformerGUI>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let values = steps
+            .iter()
+            .map(|step| {
+                let config = step.config_to_string().map_err(S::Error::custom)?;
+                serde_yaml::from_str::<serde_yaml::Value>(&config).map_err(S::Error::custom)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        serializer.collect_seq(values)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Box<dyn TransformerGUI>>, D::Error> {
+        Vec::<serde_yaml::Value>::deserialize(deserializer)?
+            .iter()
+            .map(|value| match value.as_mapping() {
+                Some(config) => yaml_mapping_to_transform(config).map_err(D::Error::custom),
+                None => Err(D::Error::custom(format!(
+                    "a repeated step is no mapping: {:?}",
+                    value
+                ))),
+            })
+            .collect()
+    }
+}
+
+impl Transformer for RepeatTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        self.steps
+            .iter()
+            .any(|step| step.refers_to_frames_or_pixels())
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if self.times == 0 {
+            problems.push("steps must be repeated at least once".to_owned());
+        }
+        // a single pass is checked, later passes see the shape it leaves behind
+        for (i, step) in self.steps.iter().enumerate() {
+            for problem in step.validate(shape, context) {
+                problems.push(format!("repeated step {}: {}", i + 1, problem));
+            }
+        }
+        problems
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        if self.times == 0 {
+            return Err(anyhow!("steps must be repeated at least once"));
+        }
+        self.passes = 0;
+        while self.passes < self.times {
+            context.progress.check()?;
+            let previous = dataset.data.clone();
+            for step in self.steps.iter_mut() {
+                step.transform_with_context(dataset, context)?;
+            }
+            self.passes += 1;
+            if let (Some(tolerance), Some(change)) =
+                (self.tolerance, relative_change(&previous, dataset))
+            {
+                if change < tolerance {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        dataset.metadata += &format!("passes: {}\n", self.passes);
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+/// Root mean square change of the intensities relative to their root mean
+/// square, None if the shape of the data changed.
+fn relative_change(previous: &ndarray::Array2<f64>, dataset: &Dataset) -> Option<f64> {
+    if previous.dim() != dataset.data.dim() {
+        return None;
+    }
+    let mut squared_change = 0.0;
+    let mut squared_sum = 0.0;
+    Zip::indexed(previous)
+        .and(&dataset.data)
+        .for_each(|(_, j), old, new| {
+            if j % 2 == 1 && old.is_finite() && new.is_finite() {
+                squared_change += (new - old).powi(2);
+                squared_sum += old.powi(2);
+            }
+        });
+    if squared_sum == 0.0 {
+        return Some(squared_change.sqrt());
+    }
+    Some((squared_change / squared_sum).sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RepeatTransform;
+    use crate::{
+        common::{Dataset, Pipeline},
+        transformations::{PipelineContext, Transformer},
+    };
+
+    #[test]
+    fn test_repeat() {
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let mut pipeline = Pipeline::from_cli_args(vec![
+            args("repeat 3 --commands 1"),
+            args("offset 1"),
+            args("offset 10"),
+        ]);
+        assert_eq!(pipeline.transformations.len(), 2);
+        let mut dataset = Dataset::new_test_dummy();
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        assert_eq!(dataset.data[[0, 1]], 12.0 + 3.0 + 10.0);
+        assert!(dataset.metadata.contains("passes: 3\n"));
+        // the repeated steps are written as nested YAML and read back
+        let config = pipeline.transformations[0].config_to_string().unwrap();
+        let mut repeat: RepeatTransform = serde_yaml::from_str(&config).unwrap();
+        assert_eq!(repeat.steps.len(), 1);
+        assert_eq!(
+            repeat.steps[0].config_to_string().unwrap(),
+            "transformation: OffsetTransform\noffset: 1.0\npercentile: false\ntarget_frames: null\n"
+        );
+        // adding the same offset again never converges
+        repeat.tolerance = Some(1e-3);
+        repeat.times = 100;
+        let mut dataset = Dataset::new_test_dummy();
+        repeat.transform(&mut dataset).unwrap();
+        assert_eq!(repeat.passes, 100);
+        // a step that changes nothing converges after the first pass
+        let mut pipeline =
+            Pipeline::from_cli_args(vec![args("repeat 100 --tolerance 0.001"), args("offset 0")]);
+        let mut dataset = Dataset::new_test_dummy();
+        pipeline
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        assert!(dataset.metadata.contains("passes: 1\n"));
+        // the nested steps survive the round trip through the YAML header
+        let header = dataset.metadata.replace("---\n", "");
+        let parsed = Pipeline::from_yaml_header(&header).unwrap();
+        assert_eq!(parsed.transformations.len(), 1);
+        assert_eq!(
+            parsed.transformations[0].config_to_string().unwrap(),
+            pipeline.transformations[0].config_to_string().unwrap()
+        );
+        // the GUI previews on decimated data only if no nested step refers
+        // to frames or pixels by their number
+        assert!(!pipeline.transformations[0].refers_to_frames_or_pixels());
+        let pipeline = Pipeline::from_cli_args(vec![args("repeat 2"), args("select 1")]);
+        assert!(pipeline.transformations[0].refers_to_frames_or_pixels());
+    }
+}
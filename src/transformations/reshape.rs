@@ -1,5 +1,5 @@
-use crate::common::Dataset;
-use crate::transformations::Transformer;
+use crate::common::{Dataset, DatasetShape, FrameMetadata};
+use crate::transformations::{PipelineContext, Transformer};
 use anyhow::anyhow;
 use anyhow::Result;
 use clap::Parser;
@@ -15,9 +15,30 @@ pub struct ReshapeTransform {
 /// Reshape data into new form, e.g. to partition dataset where several
 /// scans were stored in a single pair of columns.
 impl Transformer for ReshapeTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        true
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if let (Some(rows), Some(frames)) = (shape.rows, shape.frames) {
+            let values = rows * frames * 2;
+            if self.rows == 0 || values % (2 * self.rows) != 0 {
+                problems.push(format!(
+                    "cannot reshape {} rows and {} frames into frames of {} rows",
+                    rows, frames, self.rows
+                ));
+            } else {
+                shape.frames = Some(values / (2 * self.rows));
+            }
+        } else {
+            shape.frames = None;
+        }
+        shape.rows = Some(self.rows);
+        problems
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
         let number_rows = dataset.data.nrows();
         let number_cols = dataset.data.ncols();
@@ -46,15 +67,29 @@ impl Transformer for ReshapeTransform {
                 }
             }
         }
+        // frame metadata can only be kept if it is the same for all frames,
+        // times differ between frames and belong to the frames before reshaping
+        let shared_metadata = FrameMetadata {
+            exposure: dataset.shared_exposure(),
+            gain: dataset.shared_gain(),
+            time: None,
+            label: None,
+            weight: None,
+        };
         dataset.data = data_reshaped;
+        dataset.frame_metadata = if shared_metadata == FrameMetadata::default() {
+            vec![]
+        } else {
+            vec![shared_metadata; number_cols_reshaped / 2]
+        };
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::common::Dataset;
-    use crate::transformations::{reshape::ReshapeTransform, Transformer};
+    use crate::common::{AxisUnit, Dataset};
+    use crate::transformations::{reshape::ReshapeTransform, PipelineContext, Transformer};
     use clap::Parser;
     use ndarray::array;
 
@@ -63,6 +98,8 @@ mod tests {
         let mut dataset = Dataset {
             metadata: "".to_string(),
             previous_comments: "".to_string(),
+            frame_metadata: vec![],
+            x_unit: AxisUnit::Unknown,
             data: array![
                 [11., 12., 13., 14.],
                 [21., 22., 23., 24.],
@@ -73,10 +110,14 @@ mod tests {
                 [71., 72., 73., 74.],
                 [81., 82., 83., 84.],
             ],
+            channels: Default::default(),
+            provenance_log: vec![],
         };
         // transform into same shape
         let mut transform = ReshapeTransform::parse_from(["reshape", "8"]);
-        transform.apply(&mut dataset).unwrap();
+        transform
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
         // reshape into same number of rows must not change dataset
         assert_eq!(
             dataset.data,
@@ -93,7 +134,9 @@ mod tests {
         );
         // transform into wider shape
         let mut transform = ReshapeTransform::parse_from(["reshape", "4"]);
-        transform.apply(&mut dataset).unwrap();
+        transform
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
         // reshape into same number of rows must not change dataset
         assert_eq!(
             dataset.data,
@@ -106,7 +149,9 @@ mod tests {
         );
         // transform into more narrow shape
         let mut transform = ReshapeTransform::parse_from(["reshape", "16"]);
-        transform.apply(&mut dataset).unwrap();
+        transform
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
         // reshape into same number of rows must not change dataset
         assert_eq!(
             dataset.data,
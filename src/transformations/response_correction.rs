@@ -0,0 +1,151 @@
+use super::{PipelineContext, Transformer};
+use crate::common::{Dataset, DatasetShape};
+use crate::utils::linear_resample_array;
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{s, Array1, Axis};
+use serde::{Deserialize, Serialize};
+
+/// Correct intensities for the spectral response of the instrument, using
+/// the spectrum of a calibrated (white-light) lamp.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct ResponseCorrectionTransform {
+    #[clap(
+        parse(from_os_str),
+        help = "CSV file holding the measured lamp spectrum (first frame is used)."
+    )]
+    pub filepath: Option<std::path::PathBuf>,
+    #[clap(
+        short,
+        long,
+        parse(from_os_str),
+        help = "CSV file holding the certified emission of the lamp, if omitted the measured spectrum is taken as the response curve."
+    )]
+    pub reference: Option<std::path::PathBuf>,
+    #[clap(
+        short,
+        long,
+        help = "the character starting a comment",
+        default_value = "#"
+    )]
+    pub comment: char,
+    #[clap(short, long, help = "the delimiting character", default_value = ",")]
+    pub delimiter: char,
+}
+
+impl Transformer for ResponseCorrectionTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, _shape: &mut DatasetShape, context: &PipelineContext) -> Vec<String> {
+        [&self.filepath, &self.reference]
+            .into_iter()
+            .flatten()
+            .filter(|path| !context.resolve_path(path).exists())
+            .map(|path| format!("file {:?} does not exist", path))
+            .collect()
+    }
+    fn transform_with_context(
+        &mut self,
+        dataset: &mut Dataset,
+        context: &PipelineContext,
+    ) -> Result<()> {
+        if self.filepath.is_none() {
+            return Err(anyhow!("no file with a lamp spectrum given"));
+        }
+        let (response_x, response_y) = self.response_curve(context)?;
+        let n_frames = dataset.data.ncols() / 2;
+        for i in 0..n_frames {
+            let response =
+                linear_resample_array(&response_x, &response_y, &dataset.data.column(2 * i));
+            dataset
+                .data
+                .column_mut(2 * i + 1)
+                .iter_mut()
+                .zip(response.iter())
+                .for_each(|(y, r)| {
+                    // outside the range of the response curve, no correction is possible
+                    *y = if *r > 0.0 { *y / r } else { f64::NAN };
+                });
+        }
+        Ok(())
+    }
+}
+
+impl ResponseCorrectionTransform {
+    /// Read the lamp spectrum (and the certified lamp emission, if given) and
+    /// return the response curve normalized to a maximum of 1.
+    fn response_curve(&self, context: &PipelineContext) -> Result<(Array1<f64>, Array1<f64>)> {
+        let (xs, mut response) = first_frame(&Dataset::from_csv(
+            &self.filepath.as_ref().map(|fp| context.resolve_path(fp)),
+            self.comment,
+            self.delimiter,
+        )?)?;
+        if let Some(reference_path) = &self.reference {
+            let (ref_x, ref_y) = first_frame(&Dataset::from_csv(
+                &Some(context.resolve_path(reference_path)),
+                self.comment,
+                self.delimiter,
+            )?)?;
+            let emission = linear_resample_array(&ref_x, &ref_y, &xs);
+            response = response / emission;
+        }
+        let max = response
+            .iter()
+            .copied()
+            .filter(|r| r.is_finite())
+            .fold(f64::NEG_INFINITY, f64::max);
+        if max <= 0.0 || !max.is_finite() {
+            return Err(anyhow!("response curve holds no positive values"));
+        }
+        Ok((xs, response / max))
+    }
+}
+
+/// x and y values of the first frame, sorted by x for resampling
+fn first_frame(dataset: &Dataset) -> Result<(Array1<f64>, Array1<f64>)> {
+    if dataset.data.ncols() < 2 {
+        return Err(anyhow!("lamp spectrum file holds no frame"));
+    }
+    let frame = dataset.data.slice(s![.., 0..2]);
+    let mut rows: Vec<_> = frame
+        .axis_iter(Axis(0))
+        .map(|row| (row[0], row[1]))
+        .filter(|(x, _)| x.is_finite())
+        .collect();
+    rows.sort_by(|(x1, _), (x2, _)| x1.total_cmp(x2));
+    let (xs, ys): (Vec<f64>, Vec<f64>) = rows.into_iter().unzip();
+    Ok((Array1::from_vec(xs), Array1::from_vec(ys)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResponseCorrectionTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_response_correction() {
+        let lamp_file = std::env::temp_dir().join("raman_test_response_lamp.csv");
+        std::fs::write(&lamp_file, "1,1\n3,2\n5,4\n").unwrap();
+        let mut dataset = Dataset {
+            data: array![[1.0, 10.0], [2.0, 10.0], [5.0, 10.0], [6.0, 10.0]],
+            ..Default::default()
+        };
+        let mut trsf = ResponseCorrectionTransform {
+            filepath: Some(lamp_file.clone()),
+            reference: None,
+            comment: '#',
+            delimiter: ',',
+        };
+        trsf.transform(&mut dataset).unwrap();
+        std::fs::remove_file(lamp_file).unwrap();
+        // response is normalized to 1 at x = 5
+        assert_eq!(dataset.data[[0, 1]], 40.0);
+        assert_eq!(dataset.data[[1, 1]], 10.0 / 0.375);
+        assert_eq!(dataset.data[[2, 1]], 10.0);
+        // x = 6 is outside the range of the lamp spectrum
+        assert!(dataset.data[[3, 1]].is_nan());
+    }
+}
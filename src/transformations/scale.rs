@@ -0,0 +1,105 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::Result;
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Multiply the intensities by a factor and add a constant, e.g. to compare
+/// spectra recorded at different laser powers.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct ScaleTransform {
+    #[clap(help = "Multiply intensities by this factor")]
+    pub(crate) factor: f64,
+    #[clap(
+        short,
+        long,
+        default_value = "0",
+        help = "Add this value after multiplying."
+    )]
+    #[serde(default)]
+    pub(crate) add: f64,
+    #[clap(short, long, help = "Apply scaling to these frames.")]
+    pub(crate) target_frames: Option<Vec<usize>>,
+    #[serde(skip)]
+    #[clap(skip)]
+    pub gui_text_buffers: ScaleIOBuffers,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct ScaleIOBuffers {
+    pub factor: String,
+    pub add: String,
+}
+
+impl ScaleTransform {
+    pub fn new(factor: f64) -> Self {
+        Self {
+            factor,
+            add: 0.0,
+            target_frames: None,
+            gui_text_buffers: ScaleIOBuffers {
+                factor: factor.to_string(),
+                add: "0".to_owned(),
+            },
+        }
+    }
+}
+
+impl Transformer for ScaleTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        self.target_frames.is_some()
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn is_frame_local(&self) -> bool {
+        self.target_frames.is_none()
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        self.target_frames
+            .iter()
+            .flatten()
+            .filter_map(|frame| shape.check_frame(*frame))
+            .collect()
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        if let Some(frames) = &self.target_frames {
+            dataset.verify_frames_in_bounds(frames)?;
+        }
+        for (col_no, mut vals) in dataset.iter_mut_frames().enumerate() {
+            if self
+                .target_frames
+                .as_ref()
+                .is_some_and(|frames| !frames.contains(&(col_no + 1)))
+            {
+                continue;
+            }
+            vals.mapv_inplace(|y| self.factor * y + self.add);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScaleTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_scale() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![[1.0, 2.0, 1.0, 4.0], [2.0, 4.0, 2.0, 6.0]];
+        let mut trsf = ScaleTransform::new(2.0);
+        trsf.add = 1.0;
+        trsf.target_frames = Some(vec![2]);
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(
+            dataset.data,
+            array![[1.0, 2.0, 1.0, 9.0], [2.0, 4.0, 2.0, 13.0]]
+        );
+        trsf.target_frames = Some(vec![3]);
+        assert!(trsf.transform(&mut dataset).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::common::{Dataset, DatasetShape, FrameMetadata};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use rhai::{Dynamic, Engine, Scope, AST};
+use serde::{Deserialize, Serialize};
+
+/// Evaluate a small Rhai script for every point of every frame, for quick
+/// one-off corrections without writing a transformation in Rust.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct ScriptTransform {
+    #[clap(
+        help = "Rhai script evaluated for every point, e.g. \"y / cos(x * PI() / 180)\" or \"if x < 200 { y = 0 }\". The new value is the result of the script if it is a number, otherwise the value of y after the script ran. Variables: x, y, i (point index), frame (frame number), nframes, npoints, exposure, gain, time (() if unknown)."
+    )]
+    pub(crate) script: String,
+    #[clap(short, long, help = "Apply the script to these frames.")]
+    pub(crate) target_frames: Option<Vec<usize>>,
+}
+
+impl ScriptTransform {
+    pub fn new(script: &str) -> Self {
+        Self {
+            script: script.to_owned(),
+            target_frames: None,
+        }
+    }
+    /// Compile the script, the error message points to the problem.
+    fn compile(&self, engine: &Engine) -> Result<AST> {
+        engine
+            .compile(&self.script)
+            .map_err(|e| anyhow!("could not compile script: {}", e))
+    }
+    /// Why the script does not compile, None if it does.
+    pub fn syntax_error(&self) -> Option<String> {
+        self.compile(&Engine::new()).err().map(|e| e.to_string())
+    }
+}
+
+/// Value of an optional frame property in the script, `()` if unknown.
+fn optional(value: Option<f64>) -> Dynamic {
+    value.map_or(Dynamic::UNIT, Dynamic::from_float)
+}
+
+/// Read a number from the script, integers are accepted as well.
+pub(crate) fn number(value: &Dynamic) -> Option<f64> {
+    value
+        .as_float()
+        .ok()
+        .or_else(|| value.as_int().ok().map(|i| i as f64))
+}
+
+impl Transformer for ScriptTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        // the script may use the index of the point and the number of the frame
+        true
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .target_frames
+            .iter()
+            .flatten()
+            .filter_map(|frame| shape.check_frame(*frame))
+            .collect();
+        problems.extend(self.syntax_error());
+        problems
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let engine = Engine::new();
+        let ast = self.compile(&engine)?;
+        let nframes = dataset.data.ncols() / 2;
+        let npoints = dataset.data.nrows();
+        if let Some(frames) = &self.target_frames {
+            dataset.verify_frames_in_bounds(frames)?;
+        }
+        let mut scope = Scope::new();
+        for frame in 1..=nframes {
+            if self
+                .target_frames
+                .as_ref()
+                .is_some_and(|frames| !frames.contains(&frame))
+            {
+                continue;
+            }
+            let metadata = dataset
+                .frame_metadata
+                .get(frame - 1)
+                .cloned()
+                .unwrap_or_else(FrameMetadata::default);
+            for i in 0..npoints {
+                let x = dataset.data[[i, 2 * frame - 2]];
+                let y = dataset.data[[i, 2 * frame - 1]];
+                scope.clear();
+                scope
+                    .push("x", x)
+                    .push("y", y)
+                    .push("i", i as rhai::INT)
+                    .push("frame", frame as rhai::INT)
+                    .push("nframes", nframes as rhai::INT)
+                    .push("npoints", npoints as rhai::INT)
+                    .push_dynamic("exposure", optional(metadata.exposure))
+                    .push_dynamic("gain", optional(metadata.gain))
+                    .push_dynamic("time", optional(metadata.time));
+                let result = engine
+                    .eval_ast_with_scope::<Dynamic>(&mut scope, &ast)
+                    .map_err(|e| anyhow!("script failed in frame {frame} at x = {x}: {e}"))?;
+                let value = match number(&result) {
+                    Some(value) => value,
+                    None => scope.get("y").and_then(number).ok_or_else(|| {
+                        anyhow!("script did not leave a number in y in frame {frame} at x = {x}")
+                    })?,
+                };
+                dataset.data[[i, 2 * frame - 1]] = value;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScriptTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+    use ndarray::array;
+
+    #[test]
+    fn test_script() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![[1.0, 2.0, 1.0, 4.0], [2.0, 4.0, 2.0, 6.0]];
+        // expression and assignment
+        ScriptTransform::new("y * x + frame")
+            .transform(&mut dataset)
+            .unwrap();
+        assert_eq!(dataset.data.column(1), array![3.0, 9.0]);
+        assert_eq!(dataset.data.column(3), array![6.0, 14.0]);
+        let mut trsf = ScriptTransform::new("if i == 0 { y = 0 }");
+        trsf.target_frames = Some(vec![2]);
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.column(1), array![3.0, 9.0]);
+        assert_eq!(dataset.data.column(3), array![0.0, 14.0]);
+        // unknown metadata is not a number
+        assert!(ScriptTransform::new("y * exposure")
+            .transform(&mut dataset)
+            .is_err());
+        assert!(ScriptTransform::new("y *").transform(&mut dataset).is_err());
+    }
+}
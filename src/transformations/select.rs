@@ -1,5 +1,5 @@
-use crate::common::Dataset;
-use crate::transformations::Transformer;
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
 use anyhow::Result;
 use clap::Parser;
 use serde::{Deserialize, Serialize};
@@ -19,11 +19,29 @@ pub struct SelectTransform {
 }
 
 impl Transformer for SelectTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        true
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let problems: Vec<String> = self
+            .frames
+            .iter()
+            .filter_map(|frame| shape.check_frame(*frame))
+            .collect();
+        shape.frames = shape.frames.map(|frames| {
+            (1..=frames)
+                .filter(|n| self.invert ^ self.frames.contains(n))
+                .count()
+        });
+        problems
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let frame_metadata = dataset.select_frame_metadata(&self.frames, self.invert);
         dataset.data = dataset.select_frames(&self.frames, self.invert)?;
+        dataset.frame_metadata = frame_metadata;
         Ok(())
     }
 }
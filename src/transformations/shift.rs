@@ -1,6 +1,7 @@
-use crate::common::Dataset;
-use crate::transformations::Transformer;
-use anyhow::Result;
+use crate::common::{AxisUnit, Dataset, DatasetShape};
+use crate::config::UserConfig;
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
 use clap::Parser;
 use ndarray::s;
 use serde::{Deserialize, Serialize};
@@ -8,7 +9,11 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Default, Parser, Serialize, Deserialize)]
 #[serde(tag = "transformation")]
 pub struct RamanShiftTransform {
-    #[clap(help = "Laser wavelength in nm.")]
+    #[clap(
+        help = "Laser wavelength in nm, taken from the config file if omitted.",
+        default_value = "NaN",
+        hide_default_value = true
+    )]
     pub wavelength: f64,
     #[clap(
         short,
@@ -23,11 +28,27 @@ pub struct RamanShiftTransform {
         help = "Optional corrective offset added to calculated wavenumbers."
     )]
     pub correction: Option<f64>,
+    #[clap(
+        short,
+        long,
+        help = "Convert Raman shift in cm⁻¹ back to wavelength in nm instead."
+    )]
+    #[serde(default)]
+    pub inverse: bool,
+    #[clap(
+        long,
+        help = "Remove all data points closer than this to 0 cm⁻¹ after the shift, so the residual Rayleigh line does not dominate plots and integrals."
+    )]
+    #[serde(default)]
+    pub rayleigh_cutoff: Option<f64>,
     #[serde(skip)]
     #[clap(skip)]
     pub gui_text_buffers: RamanShiftIOBuffers,
 }
 
+const MISSING_WAVELENGTH: &str =
+    "laser wavelength must be positive, give it or set it with \"config set laser-wavelength\"";
+
 #[derive(Default, Debug, Clone)]
 pub struct RamanShiftIOBuffers {
     pub wavelength: String,
@@ -39,15 +60,192 @@ impl Transformer for RamanShiftTransform {
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn is_frame_local(&self) -> bool {
+        true
+    }
+    /// A wavelength that is missing in the config file as well stays NaN,
+    /// so it is reported when the pipeline is checked.
+    fn use_config_defaults(&mut self, config: &UserConfig) {
+        if self.wavelength.is_nan() {
+            self.wavelength = config.laser_wavelength.unwrap_or(f64::NAN);
+        }
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let (from_unit, to_unit) = self.units();
+        let mut problems = vec![];
+        if self.wavelength.is_nan() || self.wavelength <= 0.0 {
+            problems.push(MISSING_WAVELENGTH.to_owned());
+        }
+        if shape.x_unit == to_unit {
+            problems.push(format!(
+                "x-axis is already in {}, expected it to be in {}",
+                to_unit, from_unit
+            ));
+        }
+        shape.x_range = shape.x_range.map(|(min, max)| {
+            let (a, b) = (self.shift(min), self.shift(max));
+            (a.min(b), a.max(b))
+        });
+        shape.x_unit = to_unit;
+        if let Some(cutoff) = self.rayleigh_cutoff {
+            if self.inverse {
+                problems.push("Rayleigh cutoff requires shifting to wavenumbers".to_owned());
+            }
+            if cutoff < 0.0 {
+                problems.push("Rayleigh cutoff must not be negative".to_owned());
+            }
+            shape.rows = None;
+        }
+        problems
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
-        let correction = self.correction.unwrap_or(0.0);
-        dataset
-            .data
-            .slice_mut(s![.., 0..;2])
-            // this parallel inplace map is perhaps an overkill ... but why not
-            .par_map_inplace(|x| {
-                *x = (1e7_f64 / self.wavelength - 1e7_f64 / *x) / self.refractive_index + correction
-            });
+        let (from_unit, to_unit) = self.units();
+        if self.wavelength.is_nan() || self.wavelength <= 0.0 {
+            return Err(anyhow!(MISSING_WAVELENGTH));
+        }
+        if dataset.x_unit == to_unit {
+            return Err(anyhow!(
+                "x-axis is already in {}, expected it to be in {}",
+                to_unit,
+                from_unit
+            ));
+        }
+        let mut x_axes = dataset.data.slice_mut(s![.., 0..;2]);
+        // this parallel inplace map is perhaps an overkill ... but why not
+        x_axes.par_map_inplace(|x| *x = self.shift(*x));
+        dataset.x_unit = to_unit;
+        if let Some(cutoff) = self.rayleigh_cutoff {
+            if self.inverse {
+                return Err(anyhow!("Rayleigh cutoff requires shifting to wavenumbers"));
+            }
+            dataset.remove_x_range(-cutoff.abs(), cutoff.abs())?;
+        }
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        // record the unit, so reading the output again knows about the axis
+        dataset.metadata += &format!("x_unit: {}\n", dataset.x_unit);
+        dataset.metadata += "---\n";
         Ok(())
     }
 }
+
+impl RamanShiftTransform {
+    /// units of the x-axis before and after the transformation
+    fn units(&self) -> (AxisUnit, AxisUnit) {
+        if self.inverse {
+            (AxisUnit::RamanShift, AxisUnit::Wavelength)
+        } else {
+            (AxisUnit::Wavelength, AxisUnit::RamanShift)
+        }
+    }
+    fn shift(&self, x: f64) -> f64 {
+        let correction = self.correction.unwrap_or(0.0);
+        if self.inverse {
+            1e7_f64 / (1e7_f64 / self.wavelength - (x - correction) * self.refractive_index)
+        } else {
+            (1e7_f64 / self.wavelength - 1e7_f64 / x) / self.refractive_index + correction
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RamanShiftTransform;
+    use crate::{
+        common::{AxisUnit, Dataset},
+        config::UserConfig,
+        transformations::{PipelineContext, Transformer},
+    };
+    use clap::Parser;
+    use ndarray::array;
+
+    #[test]
+    fn test_shift_roundtrip() {
+        let mut dataset = Dataset {
+            data: array![[532.1, 1.0], [550.0, 2.0], [600.0, 3.0]],
+            x_unit: AxisUnit::Wavelength,
+            ..Default::default()
+        };
+        let mut shift = RamanShiftTransform {
+            wavelength: 532.1,
+            refractive_index: 1.000264,
+            correction: Some(1.5),
+            ..Default::default()
+        };
+        shift
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        assert_eq!(dataset.x_unit, AxisUnit::RamanShift);
+        assert!((dataset.data[[0, 0]] - 1.5).abs() < 1e-9);
+        // shifting twice is an error
+        assert!(shift
+            .apply(&mut dataset, &PipelineContext::default())
+            .is_err());
+        shift.inverse = true;
+        shift
+            .apply(&mut dataset, &PipelineContext::default())
+            .unwrap();
+        assert_eq!(dataset.x_unit, AxisUnit::Wavelength);
+        for (x, x_orig) in dataset.data.column(0).iter().zip([532.1, 550.0, 600.0]) {
+            assert!((x - x_orig).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_missing_wavelength() {
+        // without wavelength on the command line or in the config file
+        let mut shift = RamanShiftTransform {
+            wavelength: f64::NAN,
+            refractive_index: 1.0,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::new_test_dummy();
+        let problems = shift.validate(&mut dataset.shape(), &PipelineContext::default());
+        assert!(problems[0].contains("laser wavelength"));
+        assert!(shift.transform(&mut dataset).is_err());
+    }
+
+    #[test]
+    fn test_config_wavelength() {
+        // the config file only fills in a wavelength that was not given
+        let mut shift = RamanShiftTransform::try_parse_from(["shift"]).unwrap();
+        shift.use_config_defaults(&UserConfig::default());
+        assert!(shift.wavelength.is_nan());
+        let config = UserConfig {
+            laser_wavelength: Some(785.0),
+            ..Default::default()
+        };
+        shift.use_config_defaults(&config);
+        assert_eq!(shift.wavelength, 785.0);
+        let mut shift = RamanShiftTransform::try_parse_from(["shift", "532.1"]).unwrap();
+        shift.use_config_defaults(&config);
+        assert_eq!(shift.wavelength, 532.1);
+    }
+
+    #[test]
+    fn test_rayleigh_cutoff() {
+        let mut dataset = Dataset {
+            data: array![
+                [532.0, 100.0, 532.1, 100.0],
+                [532.5, 50.0, 540.0, 2.0],
+                [550.0, 2.0, 550.0, 3.0]
+            ],
+            x_unit: AxisUnit::Wavelength,
+            ..Default::default()
+        };
+        let mut shift = RamanShiftTransform {
+            wavelength: 532.1,
+            refractive_index: 1.0,
+            rayleigh_cutoff: Some(50.0),
+            ..Default::default()
+        };
+        shift.transform(&mut dataset).unwrap();
+        // the first frame keeps one point, the second two
+        assert_eq!(dataset.data.nrows(), 2);
+        assert_eq!(dataset.data.column(1).to_vec()[0], 2.0);
+        assert!(dataset.data[[1, 1]].is_nan());
+        assert_eq!(dataset.data.column(3).to_vec(), vec![2.0, 3.0]);
+    }
+}
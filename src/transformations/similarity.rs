@@ -0,0 +1,330 @@
+use crate::common::{AxisUnit, Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use ndarray::{Array2, ArrayView1};
+use serde::{Deserialize, Serialize};
+
+/// Compare all frames pairwise, e.g. to spot outlier scans in a set of
+/// replicates before averaging them. Frame j of the result holds the
+/// similarity of frame j to each frame of the input, plotted against the
+/// frame number, so the result is the N×N similarity matrix. Given a
+/// threshold, the frames are also grouped into clusters of mutually
+/// consistent scans, which are the candidates for averaging.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct SimilarityTransform {
+    #[clap(
+        short,
+        long,
+        default_value("correlation"),
+        help = "Similarity measure: \"correlation\" (Pearson), \"cosine\", \"hqi\" (hit quality index, the squared cosine) or \"angle\" (spectral angle in degrees, lower is more similar)."
+    )]
+    #[serde(default)]
+    pub(crate) measure: SimilarityMeasure,
+    #[clap(
+        short,
+        long,
+        help = "Group the frames into clusters in which every pair is at least this similar (at most this angle apart for \"angle\"), reported in the metadata."
+    )]
+    #[serde(default)]
+    pub(crate) cluster: Option<f64>,
+    /// mean similarity of each frame to all other frames, reported in the
+    /// metadata, the outliers have the lowest values (largest angles)
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) mean_similarity: Vec<f64>,
+    /// clusters of frames (counting from 1) of the last run, the largest
+    /// first, reported in the metadata
+    #[serde(skip)]
+    #[clap(skip)]
+    pub(crate) clusters: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SimilarityMeasure {
+    /// Pearson correlation coefficient, insensitive to offsets and scaling
+    #[default]
+    Correlation,
+    /// cosine of the angle between the frames, insensitive to scaling
+    Cosine,
+    /// hit quality index as used in spectral library search
+    Hqi,
+    /// angle between the frames in degrees (spectral angle mapper), zero
+    /// for identical frames
+    Angle,
+}
+
+impl std::str::FromStr for SimilarityMeasure {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "correlation" => Ok(SimilarityMeasure::Correlation),
+            "cosine" => Ok(SimilarityMeasure::Cosine),
+            "hqi" => Ok(SimilarityMeasure::Hqi),
+            "angle" => Ok(SimilarityMeasure::Angle),
+            _ => Err(anyhow!(
+                "unknown similarity measure \"{}\", use \"correlation\", \"cosine\", \"hqi\" or \"angle\"",
+                s
+            )),
+        }
+    }
+}
+
+impl SimilarityMeasure {
+    /// Similarity of two frames, using the points where both have values.
+    pub fn compare(&self, a: ArrayView1<f64>, b: ArrayView1<f64>) -> f64 {
+        let (a, b): (Vec<f64>, Vec<f64>) = a
+            .iter()
+            .zip(b.iter())
+            .filter(|(a, b)| a.is_finite() && b.is_finite())
+            .unzip();
+        let cosine = |a: &[f64], b: &[f64]| {
+            let dot = |u: &[f64], v: &[f64]| u.iter().zip(v).map(|(u, v)| u * v).sum::<f64>();
+            dot(a, b) / (dot(a, a) * dot(b, b)).sqrt()
+        };
+        match self {
+            SimilarityMeasure::Correlation => {
+                let centered = |values: &[f64]| {
+                    let mean = values.iter().sum::<f64>() / values.len() as f64;
+                    values.iter().map(|v| v - mean).collect::<Vec<f64>>()
+                };
+                cosine(&centered(&a), &centered(&b))
+            }
+            SimilarityMeasure::Cosine => cosine(&a, &b),
+            SimilarityMeasure::Hqi => cosine(&a, &b).powi(2),
+            // rounding may push the cosine of identical frames above one
+            SimilarityMeasure::Angle => cosine(&a, &b).min(1.0).acos().to_degrees(),
+        }
+    }
+
+    /// Value of a frame compared to itself.
+    pub fn identical(&self) -> f64 {
+        match self {
+            SimilarityMeasure::Angle => 0.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Value that grows with the similarity, for all measures.
+    pub fn score(&self, value: f64) -> f64 {
+        match self {
+            SimilarityMeasure::Angle => -value,
+            _ => value,
+        }
+    }
+}
+
+/// Complete-linkage clustering of the frames: clusters are merged, most
+/// similar first, as long as every pair of frames in the merged cluster
+/// scores at least `threshold`.
+fn clusters(scores: &Array2<f64>, threshold: f64) -> Vec<Vec<usize>> {
+    let mut clusters: Vec<Vec<usize>> = (0..scores.nrows()).map(|i| vec![i]).collect();
+    let linkage = |a: &[usize], b: &[usize]| {
+        a.iter()
+            .flat_map(|i| b.iter().map(move |j| scores[[*i, *j]]))
+            // a NaN score (e.g. a constant frame) never links
+            .fold(f64::INFINITY, |min, score| {
+                if score.is_nan() {
+                    f64::NEG_INFINITY
+                } else {
+                    min.min(score)
+                }
+            })
+    };
+    loop {
+        let mut best: Option<(usize, usize, f64)> = None;
+        for i in 0..clusters.len() {
+            for j in i + 1..clusters.len() {
+                let score = linkage(&clusters[i], &clusters[j]);
+                if score >= threshold && best.is_none_or(|(_, _, best)| score > best) {
+                    best = Some((i, j, score));
+                }
+            }
+        }
+        let Some((i, j, _)) = best else { break };
+        let merged = clusters.remove(j);
+        clusters[i].extend(merged);
+        clusters[i].sort();
+    }
+    clusters.sort_by(|a, b| b.len().cmp(&a.len()).then(a.cmp(b)));
+    clusters
+}
+
+impl SimilarityTransform {
+    pub fn new(measure: SimilarityMeasure) -> Self {
+        Self {
+            measure,
+            cluster: None,
+            mean_similarity: vec![],
+            clusters: vec![],
+        }
+    }
+}
+
+impl Transformer for SimilarityTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if shape.frames == Some(0) {
+            problems.push("dataset does not contain any frames".to_owned());
+        }
+        *shape = DatasetShape {
+            rows: shape.frames,
+            frames: shape.frames,
+            x_range: shape.frames.map(|frames| (1.0, frames as f64)),
+            x_unit: AxisUnit::Unknown,
+        };
+        problems
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let nframes = dataset.data.ncols() / 2;
+        if nframes == 0 {
+            return Err(anyhow!("dataset does not contain any frames"));
+        }
+        let xs = dataset.data.column(0);
+        let shared_axis = (0..nframes).all(|j| {
+            dataset
+                .data
+                .column(2 * j)
+                .iter()
+                .zip(xs.iter())
+                .all(|(x, x0)| x == x0 || (x.is_nan() && x0.is_nan()))
+        });
+        if !shared_axis {
+            return Err(anyhow!(
+                "frames can only be compared on the same x-axis, resample the frames first"
+            ));
+        }
+        let frame = |j: usize| dataset.data.column(2 * j + 1);
+        let mut matrix = Array2::from_elem((nframes, nframes), self.measure.identical());
+        for i in 0..nframes {
+            for j in 0..i {
+                let similarity = self.measure.compare(frame(i), frame(j));
+                matrix[[i, j]] = similarity;
+                matrix[[j, i]] = similarity;
+            }
+        }
+        self.mean_similarity = (0..nframes)
+            .map(|j| {
+                if nframes < 2 {
+                    return f64::NAN;
+                }
+                (matrix.column(j).sum() - matrix[[j, j]]) / (nframes - 1) as f64
+            })
+            .collect();
+        self.clusters = match self.cluster {
+            Some(threshold) => clusters(
+                &matrix.mapv(|value| self.measure.score(value)),
+                self.measure.score(threshold),
+            )
+            .into_iter()
+            .map(|cluster| cluster.into_iter().map(|i| i + 1).collect())
+            .collect(),
+            None => vec![],
+        };
+        dataset.data = Array2::from_shape_fn((nframes, nframes * 2), |(i, j)| {
+            if j % 2 == 0 {
+                (i + 1) as f64
+            } else {
+                matrix[[i, j / 2]]
+            }
+        });
+        dataset.x_unit = AxisUnit::Unknown;
+        Ok(())
+    }
+    fn write_metadata_yaml(&self, dataset: &mut Dataset) -> Result<()> {
+        dataset.metadata += &self.config_to_string()?;
+        dataset.metadata += &format!("mean_similarity: {:?}\n", self.mean_similarity);
+        if self.cluster.is_some() {
+            dataset.metadata += &format!("clusters: {:?}\n", self.clusters);
+        }
+        dataset.metadata += "---\n";
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clusters, SimilarityMeasure, SimilarityTransform};
+    use crate::common::{AxisUnit, Dataset, FrameMetadata};
+    use crate::transformations::Transformer;
+    use ndarray::{array, Array2};
+
+    #[test]
+    fn test_similarity() {
+        // three replicates, the last one with an extra band
+        let frames = [
+            [0.0, 1.0, 4.0, 1.0, 0.0, 0.0],
+            [0.0, 2.0, 8.0, 2.0, 0.0, 0.0],
+            [0.0, 1.0, 4.0, 1.0, 0.0, 9.0],
+        ];
+        let data = Array2::from_shape_fn((6, 6), |(i, j)| match j % 2 {
+            0 => i as f64,
+            _ => frames[j / 2][i],
+        });
+        let input = Dataset {
+            data,
+            metadata: String::new(),
+            previous_comments: String::new(),
+            frame_metadata: vec![FrameMetadata::default(); 3],
+            x_unit: AxisUnit::RamanShift,
+            channels: Default::default(),
+            provenance_log: vec![],
+        };
+        let mut similarity = SimilarityTransform::new(SimilarityMeasure::Cosine);
+        let mut dataset = input.clone();
+        similarity.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.dim(), (3, 6));
+        assert_eq!(dataset.data.column(0), array![1., 2., 3.]);
+        // scaled frames are identical, the matrix is symmetric
+        assert!((dataset.data[[1, 1]] - 1.0).abs() < 1e-12);
+        assert_eq!(dataset.data[[2, 1]], dataset.data[[0, 5]]);
+        assert!(dataset.data[[2, 1]] < 0.9);
+        // the outlier is least similar to the others
+        let outlier = (0..3)
+            .min_by(|&i, &j| {
+                similarity.mean_similarity[i].total_cmp(&similarity.mean_similarity[j])
+            })
+            .unwrap();
+        assert_eq!(outlier, 2);
+        // the hit quality index is the squared cosine
+        let cosine = dataset.data[[2, 1]];
+        let mut dataset = input.clone();
+        SimilarityTransform::new(SimilarityMeasure::Hqi)
+            .transform(&mut dataset)
+            .unwrap();
+        assert!((dataset.data[[2, 1]] - cosine.powi(2)).abs() < 1e-12);
+        // the spectral angle is zero for scaled frames
+        let mut similarity = SimilarityTransform::new(SimilarityMeasure::Angle);
+        similarity.cluster = Some(5.0);
+        let mut dataset = input.clone();
+        similarity.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data[[0, 1]], 0.0);
+        assert!(dataset.data[[1, 1]].abs() < 1e-6);
+        assert!((dataset.data[[2, 1]] - cosine.acos().to_degrees()).abs() < 1e-9);
+        assert_eq!(similarity.clusters, vec![vec![1, 2], vec![3]]);
+        // frames on different x-axes cannot be compared
+        let mut dataset = Dataset::new_test_dummy();
+        assert!(SimilarityTransform::new(SimilarityMeasure::Correlation)
+            .transform(&mut dataset)
+            .is_err());
+    }
+
+    #[test]
+    fn test_clusters() {
+        // frames 1 and 3 are both similar to frame 2, but not to each other
+        let scores = array![
+            [1.0, 0.95, 0.5, 0.2],
+            [0.95, 1.0, 0.95, 0.2],
+            [0.5, 0.95, 1.0, 0.2],
+            [0.2, 0.2, 0.2, 1.0],
+        ];
+        assert_eq!(clusters(&scores, 0.9), vec![vec![0, 1], vec![2], vec![3]]);
+        assert_eq!(clusters(&scores, 0.4), vec![vec![0, 1, 2], vec![3]]);
+        assert_eq!(clusters(&scores, 0.0), vec![vec![0, 1, 2, 3]]);
+    }
+}
@@ -1,9 +1,17 @@
-use crate::transformations::Transformer;
-use crate::{common::Dataset, utils::linear_resample_array};
-use anyhow::{anyhow, Result};
+use crate::transformations::{
+    arithmetic::{ArithmeticTransform, Operation},
+    PipelineContext, Transformer,
+};
+use crate::{
+    common::{Dataset, DatasetShape},
+    utils::Interpolation,
+};
+use anyhow::Result;
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 
+/// Subtract a frame from the others, the arithmetic step with the subtract
+/// operation under its own command.
 #[derive(Debug, Parser, Serialize, Deserialize)]
 #[serde(tag = "transformation")]
 pub struct SubtractTransform {
@@ -22,40 +30,39 @@ pub struct SubtractTransform {
         help = "If flag is set, subtract frame intensities without interpolating on same grid first"
     )]
     pub(crate) direct: bool,
+    #[clap(
+        long,
+        default_value = "linear",
+        help = "Interpolation of the minuends onto the x-axis of the subtrahend: linear or pchip (monotone cubic, keeps sharp bands on coarse grids)."
+    )]
+    #[serde(default)]
+    pub(crate) interpolation: Interpolation,
+}
+
+impl SubtractTransform {
+    /// The same subtraction as a general arithmetic step.
+    fn arithmetic(&self) -> ArithmeticTransform {
+        ArithmeticTransform {
+            operation: Operation::Subtract,
+            reference: self.subtrahend,
+            targets: self.minuends.clone(),
+            direct: self.direct,
+            interpolation: self.interpolation,
+        }
+    }
 }
 
 impl Transformer for SubtractTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        true
+    }
     fn config_to_string(&self) -> Result<String> {
         serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
     }
+    fn validate(&self, shape: &mut DatasetShape, context: &PipelineContext) -> Vec<String> {
+        self.arithmetic().validate(shape, context)
+    }
     fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
-        let mut minuends = if let Some(minuends) = &self.minuends {
-            if minuends.contains(&self.subtrahend) {
-                return Err(anyhow!(
-                    "the minuend frames must not contain the subtrahend frame"
-                ));
-            }
-            dataset.select_frames(minuends, false)?
-        } else {
-            dataset.select_frames(&[self.subtrahend], true)?
-        };
-        let subtrahend = dataset.select_frames(&[self.subtrahend], false)?;
-        let grid = subtrahend.column(0);
-        let sub_ys = subtrahend.column(1);
-        for n in (0..minuends.ncols() - 1).step_by(2) {
-            let ys = if !self.direct {
-                linear_resample_array(&minuends.column(n), &minuends.column(n + 1), &grid)
-            } else {
-                // ignore spectral axes of minuends and subtract intensity data directly
-                minuends.column(n + 1).to_owned()
-            };
-            let difference = ys - &sub_ys;
-            minuends.column_mut(n + 1).assign(&difference);
-            if !self.direct {
-                minuends.column_mut(n).assign(&grid);
-            }
-        }
-        dataset.data = minuends;
-        Ok(())
+        self.arithmetic().transform(dataset)
     }
 }
@@ -0,0 +1,97 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Give frames without time stamps the time their exposure started, assuming
+/// the frames were recorded one after another, e.g. to follow a reaction in
+/// files of older software that wrote no time stamps.
+#[derive(Debug, Default, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct TimeAxisTransform {
+    #[clap(
+        short,
+        long,
+        default_value = "0",
+        help = "Time in seconds between the end of an exposure and the start of the next (readout of the detector)."
+    )]
+    #[serde(default)]
+    pub(crate) dead_time: f64,
+    #[clap(
+        short,
+        long,
+        help = "Exposure time of the frames in seconds, used for frames whose exposure time is not known."
+    )]
+    #[serde(default)]
+    pub(crate) exposure: Option<f64>,
+    #[clap(
+        short,
+        long,
+        action,
+        help = "Replace the time stamps of frames that have one as well."
+    )]
+    #[serde(default)]
+    pub(crate) overwrite: bool,
+}
+
+impl Transformer for TimeAxisTransform {
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, _shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems = vec![];
+        if self.dead_time < 0.0 {
+            problems.push("dead time must not be negative".to_owned());
+        }
+        if self.exposure.is_some_and(|exposure| exposure <= 0.0) {
+            problems.push("exposure time must be positive".to_owned());
+        }
+        problems
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let frames = dataset.data.ncols() / 2;
+        dataset.frame_metadata.resize(frames, Default::default());
+        // the exposure of a frame starts when the previous one is read out
+        let mut start = 0.0;
+        for (i, metadata) in dataset.frame_metadata.iter_mut().enumerate() {
+            let exposure = metadata.exposure.or(self.exposure).ok_or_else(|| {
+                anyhow!(
+                    "exposure time of frame {} is not known, give it as exposure",
+                    i + 1
+                )
+            })?;
+            if self.overwrite || metadata.time.is_none() {
+                metadata.time = Some(start);
+            }
+            start += exposure + self.dead_time;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TimeAxisTransform;
+    use crate::{
+        common::{Dataset, FrameMetadata},
+        transformations::Transformer,
+    };
+
+    #[test]
+    fn test_time_axis() {
+        let mut dataset = Dataset::new_test_dummy();
+        let mut trsf = TimeAxisTransform {
+            dead_time: 0.5,
+            ..Default::default()
+        };
+        dataset.frame_metadata = vec![FrameMetadata::default(); 4];
+        assert!(trsf.transform(&mut dataset).is_err());
+        dataset.frame_metadata[0].exposure = Some(2.0);
+        dataset.frame_metadata[2].time = Some(10.0);
+        trsf.exposure = Some(1.0);
+        trsf.transform(&mut dataset).unwrap();
+        let times: Vec<_> = dataset.frame_metadata.iter().map(|m| m.time).collect();
+        assert_eq!(times, vec![Some(0.0), Some(2.5), Some(10.0), Some(5.5)]);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Attach a quality weight to each frame, e.g. to count slightly degraded
+/// frames less instead of removing them. The weights travel with the frames
+/// and are honored by average and mcr.
+#[derive(Debug, Default, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+pub struct WeightTransform {
+    #[clap(help = "Weight of each frame between 0 and 1, one per frame.")]
+    pub(crate) weights: Vec<f64>,
+    #[serde(skip)]
+    #[clap(skip)]
+    pub gui_text_buffers: WeightIOBuffers,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct WeightIOBuffers {
+    pub weights: String,
+}
+
+impl WeightTransform {
+    fn problems(&self, frames: Option<usize>) -> Vec<String> {
+        let mut problems = vec![];
+        if self
+            .weights
+            .iter()
+            .any(|w| !w.is_finite() || *w < 0.0 || *w > 1.0)
+        {
+            problems.push("weights must be between 0 and 1".to_owned());
+        }
+        if let Some(frames) = frames.filter(|frames| *frames != self.weights.len()) {
+            problems.push(format!(
+                "got {} weights for {} frames",
+                self.weights.len(),
+                frames
+            ));
+        }
+        problems
+    }
+}
+
+impl Transformer for WeightTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        true
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        self.problems(shape.frames)
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let frames = dataset.data.ncols() / 2;
+        if let Some(problem) = self.problems(Some(frames)).into_iter().next() {
+            return Err(anyhow!(problem));
+        }
+        dataset.frame_metadata.resize(frames, Default::default());
+        for (meta, weight) in dataset.frame_metadata.iter_mut().zip(self.weights.iter()) {
+            meta.weight = Some(*weight);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeightTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+
+    #[test]
+    fn test_weight() {
+        let mut dataset = Dataset::new_test_dummy();
+        let mut trsf = WeightTransform {
+            weights: vec![1.0, 0.5, 1.0],
+            ..Default::default()
+        };
+        assert!(trsf.transform(&mut dataset).is_err());
+        trsf.weights = vec![1.0, 0.5, 1.0, 2.0];
+        assert!(trsf.transform(&mut dataset).is_err());
+        trsf.weights = vec![1.0, 0.5, 1.0, 0.0];
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.weights(), Some(vec![1.0, 0.5, 1.0, 0.0]));
+    }
+}
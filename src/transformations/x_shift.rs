@@ -0,0 +1,115 @@
+use crate::common::{Dataset, DatasetShape};
+use crate::transformations::{PipelineContext, Transformer};
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Shift the x-axis of frames by known amounts, e.g. taken from a
+/// calibration standard, where AlignTransform would estimate the shifts.
+#[derive(Debug, Parser, Serialize, Deserialize)]
+#[serde(tag = "transformation")]
+#[clap(allow_negative_numbers = true)]
+pub struct XShiftTransform {
+    #[clap(help = "Shift added to the x-values, either one for all frames or one per frame.")]
+    pub(crate) shifts: Vec<f64>,
+    #[clap(
+        short,
+        long,
+        help = "Shift these frames (the shifts are given in the order of these frames)."
+    )]
+    pub(crate) target_frames: Option<Vec<usize>>,
+}
+
+impl XShiftTransform {
+    /// Number of frames shifted, `frames` being the number of frames in the
+    /// dataset.
+    fn shifted_frames(&self, frames: usize) -> usize {
+        self.target_frames
+            .as_ref()
+            .map_or(frames, |targets| targets.len())
+    }
+    fn check_shifts(&self, frames: usize) -> Option<String> {
+        let shifted = self.shifted_frames(frames);
+        match self.shifts.len() {
+            0 => Some("no shift given".to_owned()),
+            1 => None,
+            n if n != shifted => Some(format!("got {} shifts for {} frames", n, shifted)),
+            _ => None,
+        }
+    }
+}
+
+impl Transformer for XShiftTransform {
+    fn refers_to_frames_or_pixels(&self) -> bool {
+        self.target_frames.is_some() || self.shifts.len() > 1
+    }
+    fn config_to_string(&self) -> Result<String> {
+        serde_yaml::to_string(&self).map_err(anyhow::Error::msg)
+    }
+    fn is_frame_local(&self) -> bool {
+        self.target_frames.is_none() && self.shifts.len() == 1
+    }
+    fn validate(&self, shape: &mut DatasetShape, _context: &PipelineContext) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .target_frames
+            .iter()
+            .flatten()
+            .filter_map(|frame| shape.check_frame(*frame))
+            .collect();
+        if self.target_frames.is_some() || shape.frames.is_some() {
+            problems.extend(self.check_shifts(shape.frames.unwrap_or_default()));
+        }
+        if let (Some((min, max)), [shift]) = (shape.x_range, self.shifts.as_slice()) {
+            shape.x_range = Some((min + shift, max + shift));
+        } else {
+            shape.x_range = None;
+        }
+        problems
+    }
+    fn transform(&mut self, dataset: &mut Dataset) -> Result<()> {
+        let frames = dataset.data.ncols() / 2;
+        if let Some(problem) = self.check_shifts(frames) {
+            return Err(anyhow!(problem));
+        }
+        let targets: Vec<usize> = match &self.target_frames {
+            Some(targets) => {
+                dataset.verify_frames_in_bounds(targets)?;
+                targets.clone()
+            }
+            None => (1..=frames).collect(),
+        };
+        for (i, frame) in targets.iter().enumerate() {
+            let shift = self.shifts.get(i).unwrap_or(&self.shifts[0]);
+            let mut xs = dataset.data.column_mut(2 * frame - 2);
+            xs += *shift;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::XShiftTransform;
+    use crate::{common::Dataset, transformations::Transformer};
+    use clap::Parser;
+    use ndarray::array;
+
+    #[test]
+    fn test_x_shift() {
+        let mut dataset = Dataset::new_test_dummy();
+        dataset.data = array![[1.0, 2.0, 1.0, 4.0], [2.0, 4.0, 2.0, 6.0]];
+        let mut trsf = XShiftTransform::try_parse_from(["x-shift", "-0.5", "1.5"]).unwrap();
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(
+            dataset.data,
+            array![[0.5, 2.0, 2.5, 4.0], [1.5, 4.0, 3.5, 6.0]]
+        );
+        let mut trsf = XShiftTransform::try_parse_from(["x-shift", "-1", "-t", "2"]).unwrap();
+        trsf.transform(&mut dataset).unwrap();
+        assert_eq!(dataset.data.column(2), array![1.5, 2.5]);
+        // one shift per target frame
+        trsf.target_frames = Some(vec![1]);
+        trsf.shifts = vec![1.0, 2.0];
+        assert!(trsf.transform(&mut dataset).is_err());
+    }
+}
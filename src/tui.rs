@@ -0,0 +1,717 @@
+use std::io::{stdout, Stdout};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use clap::Parser;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{
+    Axis, Block, Borders, Chart, Dataset as ChartDataset, GraphType, List, ListItem, ListState,
+    Paragraph, Wrap,
+};
+use ratatui::{Frame, Terminal};
+use serde_yaml::{Mapping, Value};
+
+use crate::cli::{split_commands, CommandLine, Preprocessor};
+use crate::common::{yaml_segment_to_transform, Dataset, Pipeline};
+use crate::gui::{make_output_filepath, TransformerGUI};
+use crate::registry::transformer_by_name;
+
+/// Most frames drawn at once when all frames are shown.
+const MAX_PLOTTED_FRAMES: usize = 20;
+
+const FRAME_COLORS: [Color; 6] = [
+    Color::Yellow,
+    Color::Cyan,
+    Color::Magenta,
+    Color::Green,
+    Color::LightRed,
+    Color::LightBlue,
+];
+
+const BROWSE_HELP: &str = "a add  e edit  d delete  J/K move  ←/→ frame  i input/result  m marker  c check  s save  q quit";
+
+/// Run the pipeline editor in the terminal, for computers without display
+/// (e.g. over SSH on the measurement PC). The pipeline is re-run after every
+/// change and its result plotted with braille characters.
+pub fn tui_loop(mut preprocessor: Preprocessor) -> Result<()> {
+    let pipeline = preprocessor.get_pipeline();
+    let input = preprocessor.get_input_data()?;
+    let output_file_path = match &preprocessor.args.filepath {
+        Some(path) => make_output_filepath(path),
+        None => PathBuf::from("processed.csv"),
+    };
+    let mut app = TuiApp {
+        preprocessor,
+        pipeline,
+        input,
+        output: None,
+        output_file_path,
+        selected: 0,
+        frame: None,
+        show_input: false,
+        marker: Marker::Braille,
+        mode: Mode::Browse,
+        line: LineInput::default(),
+        messages: vec![],
+        quit: false,
+    };
+    app.run_pipeline();
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)?;
+    let result = Terminal::new(CrosstermBackend::new(stdout()))
+        .map_err(anyhow::Error::from)
+        .and_then(|mut terminal| app.event_loop(&mut terminal));
+    // restore the terminal also if the event loop failed
+    disable_raw_mode()?;
+    execute!(stdout(), LeaveAlternateScreen)?;
+    result
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Browse,
+    /// a new step is entered as on the command line, e.g. "offset 10", and
+    /// inserted after the selected one
+    Add,
+    /// the selected step is edited as YAML in flow style
+    Edit,
+    /// path of the file the result is saved to
+    Save,
+}
+
+/// Single line text input with a cursor.
+#[derive(Default)]
+struct LineInput {
+    text: Vec<char>,
+    cursor: usize,
+}
+
+impl LineInput {
+    fn set(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+    }
+    fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+    fn handle_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char(c) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            KeyCode::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Delete if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Right => self.cursor = (self.cursor + 1).min(self.text.len()),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            _ => {}
+        }
+    }
+}
+
+struct Message {
+    text: String,
+    error: bool,
+}
+
+struct TuiApp {
+    preprocessor: Preprocessor,
+    pipeline: Pipeline,
+    input: Dataset,
+    /// result of the last successful run, None if the pipeline failed
+    output: Option<Dataset>,
+    output_file_path: PathBuf,
+    selected: usize,
+    /// frame shown in the plot, all frames if None
+    frame: Option<usize>,
+    show_input: bool,
+    marker: Marker,
+    mode: Mode,
+    line: LineInput,
+    messages: Vec<Message>,
+    quit: bool,
+}
+
+impl TuiApp {
+    fn event_loop(&mut self, terminal: &mut Terminal<CrosstermBackend<Stdout>>) -> Result<()> {
+        while !self.quit {
+            terminal.draw(|frame| self.render(frame))?;
+            if let Event::Key(key) = event::read()? {
+                // some terminals also report the release of a key
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn info(&mut self, text: impl Into<String>) {
+        self.messages.push(Message {
+            text: text.into(),
+            error: false,
+        });
+    }
+
+    fn error(&mut self, text: impl Into<String>) {
+        self.messages.push(Message {
+            text: text.into(),
+            error: true,
+        });
+    }
+
+    fn run_pipeline(&mut self) {
+        self.messages.clear();
+        let context = match self.preprocessor.get_context() {
+            Ok(context) => context,
+            Err(err) => return self.error(format!("{:#}", err)),
+        };
+        let mut dataset = self.input.clone();
+        let result = self.pipeline.apply(&mut dataset, &context);
+        for warning in context.diagnostics.take() {
+            self.error(warning);
+        }
+        match result {
+            Ok(()) => {
+                self.info(format!(
+                    "{} step(s) applied, {} frame(s) in result",
+                    self.pipeline.transformations.len(),
+                    dataset.data.ncols() / 2
+                ));
+                self.output = Some(dataset);
+            }
+            Err(err) => {
+                self.error(format!("{:#}", err));
+                self.output = None;
+            }
+        }
+    }
+
+    fn check_pipeline(&mut self) {
+        self.messages.clear();
+        let context = match self.preprocessor.get_context() {
+            Ok(context) => context,
+            Err(err) => return self.error(format!("{:#}", err)),
+        };
+        let problems = self.pipeline.validate(&self.input, &context);
+        if problems.is_empty() {
+            self.info("No problems found in pipeline.");
+        }
+        for problem in problems {
+            self.error(problem);
+        }
+    }
+
+    /// Dataset shown in the plot, the input if the pipeline failed.
+    fn plotted_dataset(&self) -> &Dataset {
+        match &self.output {
+            Some(output) if !self.show_input => output,
+            _ => &self.input,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        if self.mode != Mode::Browse {
+            match key.code {
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Enter => self.submit_line(),
+                _ => self.line.handle_key(key),
+            }
+            return;
+        }
+        let nsteps = self.pipeline.transformations.len();
+        let shift = key.modifiers.contains(KeyModifiers::SHIFT);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('K') | KeyCode::Up if shift && self.selected > 0 => {
+                self.pipeline
+                    .transformations
+                    .swap(self.selected, self.selected - 1);
+                self.selected -= 1;
+                self.run_pipeline();
+            }
+            KeyCode::Char('J') | KeyCode::Down if shift && self.selected + 1 < nsteps => {
+                self.pipeline
+                    .transformations
+                    .swap(self.selected, self.selected + 1);
+                self.selected += 1;
+                self.run_pipeline();
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.selected = (self.selected + 1).min(nsteps.saturating_sub(1))
+            }
+            KeyCode::Char('a') => {
+                self.line.set("");
+                self.mode = Mode::Add;
+            }
+            KeyCode::Char('e') | KeyCode::Enter if self.selected < nsteps => {
+                match step_to_line(self.pipeline.transformations[self.selected].as_ref()) {
+                    Ok(line) => {
+                        self.line.set(&line);
+                        self.mode = Mode::Edit;
+                    }
+                    Err(err) => self.error(format!("{:#}", err)),
+                }
+            }
+            KeyCode::Char('d') | KeyCode::Delete if self.selected < nsteps => {
+                self.pipeline.transformations.remove(self.selected);
+                self.selected = self.selected.min(nsteps.saturating_sub(2));
+                self.run_pipeline();
+            }
+            KeyCode::Left => {
+                self.frame = match self.frame {
+                    None => (self.plotted_dataset().data.ncols() / 2).checked_sub(1),
+                    Some(0) => None,
+                    Some(frame) => Some(frame - 1),
+                }
+            }
+            KeyCode::Right => {
+                let nframes = self.plotted_dataset().data.ncols() / 2;
+                self.frame = match self.frame {
+                    None if nframes > 0 => Some(0),
+                    Some(frame) if frame + 1 < nframes => Some(frame + 1),
+                    _ => None,
+                }
+            }
+            KeyCode::Char('i') => self.show_input = !self.show_input,
+            KeyCode::Char('m') => {
+                self.marker = match self.marker {
+                    Marker::Braille => Marker::HalfBlock,
+                    Marker::HalfBlock => Marker::Dot,
+                    _ => Marker::Braille,
+                }
+            }
+            KeyCode::Char('c') => self.check_pipeline(),
+            KeyCode::Char('r') => self.run_pipeline(),
+            KeyCode::Char('s') => {
+                self.line.set(&self.output_file_path.to_string_lossy());
+                self.mode = Mode::Save;
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply the text entered in the line input, the input stays open if it
+    /// cannot be used.
+    fn submit_line(&mut self) {
+        let text = self.line.text();
+        let result = match self.mode {
+            Mode::Browse => return,
+            Mode::Add => steps_from_command_line(&text).map(|steps| {
+                let position = (self.selected + 1).min(self.pipeline.transformations.len());
+                self.selected = position;
+                self.pipeline
+                    .transformations
+                    .splice(position..position, steps);
+                self.run_pipeline();
+            }),
+            Mode::Edit => step_from_line(&text).map(|step| {
+                self.pipeline.transformations[self.selected] = step;
+                self.run_pipeline();
+            }),
+            Mode::Save => self.save_output(PathBuf::from(text.trim())),
+        };
+        match result {
+            Ok(()) => self.mode = Mode::Browse,
+            Err(err) => self.error(format!("{:#}", err)),
+        }
+    }
+
+    fn save_output(&mut self, path: PathBuf) -> Result<()> {
+        let output = self
+            .output
+            .as_ref()
+            .ok_or_else(|| anyhow!("nothing to save, the pipeline failed"))?;
+        output.write_file(&path, self.preprocessor.args.float_format)?;
+        self.info(format!("saved result to {}", path.display()));
+        self.output_file_path = path;
+        Ok(())
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let [main, messages, bottom] = Layout::vertical([
+            Constraint::Min(8),
+            Constraint::Length(6),
+            Constraint::Length(1),
+        ])
+        .areas(frame.size());
+        let [steps, plot] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+        self.render_steps(frame, steps);
+        self.render_plot(frame, plot);
+        self.render_messages(frame, messages);
+        self.render_bottom_line(frame, bottom);
+    }
+
+    fn render_steps(&self, frame: &mut Frame, area: Rect) {
+        let items: Vec<ListItem> = self
+            .pipeline
+            .transformations
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let (title, parameters) = match step_summary(step.as_ref()) {
+                    Ok(summary) => summary,
+                    Err(err) => ("?".to_owned(), err.to_string()),
+                };
+                ListItem::new(vec![
+                    Line::from(Span::styled(
+                        format!("{} {}", i + 1, title),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    )),
+                    Line::from(Span::styled(
+                        format!("  {}", parameters),
+                        Style::default().fg(Color::DarkGray),
+                    )),
+                ])
+            })
+            .collect();
+        let title = match &self.preprocessor.args.filepath {
+            Some(path) => format!(" Pipeline: {} ", path.display()),
+            None => " Pipeline ".to_owned(),
+        };
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::default().bg(Color::DarkGray))
+            .highlight_symbol("> ");
+        let mut state = ListState::default();
+        if !self.pipeline.transformations.is_empty() {
+            state.select(Some(self.selected));
+        }
+        frame.render_stateful_widget(list, area, &mut state);
+    }
+
+    fn render_plot(&self, frame: &mut Frame, area: Rect) {
+        let dataset = self.plotted_dataset();
+        let nframes = dataset.data.ncols() / 2;
+        let frames: Vec<usize> = match self.frame {
+            Some(frame) if frame < nframes => vec![frame],
+            _ => (0..nframes.min(MAX_PLOTTED_FRAMES)).collect(),
+        };
+        let points: Vec<Vec<(f64, f64)>> = frames
+            .iter()
+            .map(|&frame| frame_points(dataset, frame))
+            .collect();
+        let name = if self.output.is_some() && !self.show_input {
+            "Result"
+        } else {
+            "Input"
+        };
+        let shown = match self.frame {
+            Some(frame) if frame < nframes => format!("frame {} of {}", frame + 1, nframes),
+            _ if nframes > MAX_PLOTTED_FRAMES => {
+                format!("frames 1-{} of {}", MAX_PLOTTED_FRAMES, nframes)
+            }
+            _ => format!("{} frame(s)", nframes),
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {} ({}) ", name, shown));
+        let Some((x_bounds, y_bounds)) = bounds(&points) else {
+            frame.render_widget(Paragraph::new("no data to plot").block(block), area);
+            return;
+        };
+        let datasets = points
+            .iter()
+            .zip(frames.iter())
+            .map(|(points, frame)| {
+                ChartDataset::default()
+                    .marker(self.marker)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(FRAME_COLORS[frame % FRAME_COLORS.len()]))
+                    .data(points)
+            })
+            .collect();
+        let axis_labels = |[min, max]: [f64; 2]| {
+            [min, (min + max) / 2.0, max]
+                .iter()
+                .map(|v| Span::raw(format_tick(*v)))
+                .collect::<Vec<_>>()
+        };
+        let chart = Chart::new(datasets)
+            .block(block)
+            .x_axis(
+                Axis::default()
+                    .title(dataset.x_unit.symbol())
+                    .bounds(x_bounds)
+                    .labels(axis_labels(x_bounds)),
+            )
+            .y_axis(
+                Axis::default()
+                    .bounds(y_bounds)
+                    .labels(axis_labels(y_bounds)),
+            );
+        frame.render_widget(chart, area);
+    }
+
+    fn render_messages(&self, frame: &mut Frame, area: Rect) {
+        let lines: Vec<Line> = self
+            .messages
+            .iter()
+            .map(|message| {
+                let style = if message.error {
+                    Style::default().fg(Color::Red)
+                } else {
+                    Style::default()
+                };
+                Line::from(Span::styled(message.text.clone(), style))
+            })
+            .collect();
+        let paragraph = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(" Messages "))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(paragraph, area);
+    }
+
+    fn render_bottom_line(&self, frame: &mut Frame, area: Rect) {
+        let prompt = match self.mode {
+            Mode::Browse => {
+                let help = Span::styled(BROWSE_HELP, Style::default().fg(Color::DarkGray));
+                frame.render_widget(Paragraph::new(help), area);
+                return;
+            }
+            Mode::Add => "add: ",
+            Mode::Edit => "edit: ",
+            Mode::Save => "save to: ",
+        };
+        // scroll the text so the cursor stays visible
+        let width = (area.width as usize).saturating_sub(prompt.len() + 1);
+        let offset = self.line.cursor.saturating_sub(width);
+        let visible: String = self.line.text.iter().skip(offset).take(width).collect();
+        let line = Line::from(vec![
+            Span::styled(prompt, Style::default().add_modifier(Modifier::BOLD)),
+            Span::raw(visible),
+        ]);
+        frame.render_widget(Paragraph::new(line), area);
+        frame.set_cursor(
+            area.x + (prompt.len() + self.line.cursor - offset) as u16,
+            area.y,
+        );
+    }
+}
+
+/// Points of a frame that can be drawn, NaN values (e.g. masked pixels or
+/// padding of shorter frames) are left out.
+fn frame_points(dataset: &Dataset, frame: usize) -> Vec<(f64, f64)> {
+    dataset
+        .data
+        .column(2 * frame)
+        .iter()
+        .zip(dataset.data.column(2 * frame + 1))
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .map(|(x, y)| (*x, *y))
+        .collect()
+}
+
+/// Ranges of the x- and y-values of all points, None if there are none.
+fn bounds(points: &[Vec<(f64, f64)>]) -> Option<([f64; 2], [f64; 2])> {
+    let mut all = points.iter().flatten();
+    let &(x, y) = all.next()?;
+    let ([mut xmin, mut xmax], [mut ymin, mut ymax]) = ([x, x], [y, y]);
+    for &(x, y) in all {
+        xmin = xmin.min(x);
+        xmax = xmax.max(x);
+        ymin = ymin.min(y);
+        ymax = ymax.max(y);
+    }
+    if ymin == ymax {
+        ymin -= 0.5;
+        ymax += 0.5;
+    }
+    Some(([xmin, xmax], [ymin, ymax]))
+}
+
+fn format_tick(value: f64) -> String {
+    if value != 0.0 && (value.abs() >= 1e5 || value.abs() < 1e-2) {
+        format!("{:.2e}", value)
+    } else if value.abs() < 10.0 {
+        format!("{:.2}", value)
+    } else {
+        format!("{:.0}", value)
+    }
+}
+
+/// Split a command line at whitespace, text in single or double quotes is
+/// kept together, e.g. the condition in `when "nframes > 1" average`.
+fn split_command_line(line: &str) -> Result<Vec<String>> {
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => arg.get_or_insert_with(String::new).push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(anyhow!("missing closing {} in command line", q));
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+/// Steps given as on the command line, e.g. "offset 10" or
+/// "when 'nframes > 1' average".
+fn steps_from_command_line(line: &str) -> Result<Vec<Box<dyn TransformerGUI>>> {
+    let mut commands = split_commands(split_command_line(line)?);
+    let leading = commands.remove(0);
+    if let Some(arg) = leading.first() {
+        return Err(anyhow!("unknown command \"{}\"", arg));
+    }
+    for command in commands.iter() {
+        let checked = match command.first().and_then(|name| transformer_by_name(name)) {
+            Some(transformer) => (transformer.parse)(command.clone()).map(|_| ()),
+            None => CommandLine::try_parse_from(command).map(|_| ()),
+        };
+        checked.map_err(|err| anyhow!("{}", err.to_string().trim_end()))?;
+    }
+    let steps = Pipeline::from_cli_args(commands).transformations;
+    if steps.is_empty() {
+        return Err(anyhow!("\"{}\" adds no step to the pipeline", line.trim()));
+    }
+    Ok(steps)
+}
+
+/// Configuration of a step as YAML in flow style, to edit it in a single line.
+fn step_to_line(step: &dyn TransformerGUI) -> Result<String> {
+    let config: Value = serde_yaml::from_str(&step.config_to_string()?)?;
+    Ok(flow_yaml(&config))
+}
+
+/// Read a step back from its configuration in flow style.
+fn step_from_line(line: &str) -> Result<Box<dyn TransformerGUI>> {
+    let config: Value = serde_yaml::from_str(line)?;
+    if !config.is_mapping() {
+        return Err(anyhow!("a step is given as {{transformation: ..., ...}}"));
+    }
+    yaml_segment_to_transform(&serde_yaml::to_string(&config)?)
+}
+
+/// Name of the step and its parameters on a single line, for the list of
+/// steps.
+fn step_summary(step: &dyn TransformerGUI) -> Result<(String, String)> {
+    let config: Mapping = serde_yaml::from_str(&step.config_to_string()?)?;
+    let name = config
+        .get("transformation")
+        .and_then(|name| name.as_str())
+        .unwrap_or("?");
+    let mut title = name.strip_suffix("Transform").unwrap_or(name).to_owned();
+    if let Some(when) = config.get("when").and_then(|when| when.as_str()) {
+        title += &format!(" when {}", when);
+    }
+    let parameters = config
+        .iter()
+        .filter_map(|(key, value)| {
+            let key = key.as_str()?;
+            match value.as_sequence() {
+                _ if ["transformation", "when"].contains(&key) || value.is_null() => None,
+                Some(steps) if key == "steps" => Some(format!("{} nested step(s)", steps.len())),
+                _ => Some(format!("{}: {}", key, flow_yaml(value))),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok((title, parameters))
+}
+
+/// A YAML value on a single line, in flow style. Strings are quoted unless
+/// they are a single word that YAML reads as a string.
+fn flow_yaml(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => {
+            let plain = s.chars().next().is_some_and(|c| c.is_alphabetic())
+                && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+                && !["true", "false", "null"].contains(&s.to_lowercase().as_str());
+            if plain {
+                s.to_owned()
+            } else {
+                format!("{:?}", s)
+            }
+        }
+        Value::Sequence(values) => format!(
+            "[{}]",
+            values.iter().map(flow_yaml).collect::<Vec<_>>().join(", ")
+        ),
+        Value::Mapping(mapping) => format!(
+            "{{{}}}",
+            mapping
+                .iter()
+                .map(|(k, v)| format!("{}: {}", flow_yaml(k), flow_yaml(v)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::Tagged(tagged) => format!("{} {}", tagged.tag, flow_yaml(&tagged.value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_command_line, step_from_line, step_to_line, steps_from_command_line};
+    use crate::common::Pipeline;
+
+    #[test]
+    fn test_edit_steps_in_line() {
+        assert_eq!(
+            split_command_line("when 'nframes > 1'  offset \"1\"").unwrap(),
+            vec!["when", "nframes > 1", "offset", "1"]
+        );
+        assert!(split_command_line("when \"nframes > 1").is_err());
+        let steps = steps_from_command_line("when \"nframes > 1\" average").unwrap();
+        assert_eq!(steps.len(), 1);
+        assert!(steps[0].condition().is_some());
+        assert!(steps_from_command_line("offset").is_err());
+        assert!(steps_from_command_line("shrink 3").is_err());
+        assert!(steps_from_command_line("gui").is_err());
+        // every step survives the round trip through a single line
+        let args = |line: &str| line.split(' ').map(|s| s.to_owned()).collect::<Vec<_>>();
+        let pipeline = Pipeline::from_cli_args(vec![
+            args("offset 1"),
+            vec!["when".to_owned(), "xmax < 3500".to_owned()],
+            args("select 1 2"),
+            args("repeat 2 --commands 2"),
+            args("average"),
+            args("offset 2"),
+        ]);
+        for step in pipeline.transformations.iter() {
+            let line = step_to_line(step.as_ref()).unwrap();
+            assert!(!line.contains('\n'));
+            let parsed = step_from_line(&line).unwrap();
+            assert_eq!(
+                parsed.config_to_string().unwrap(),
+                step.config_to_string().unwrap()
+            );
+        }
+        assert_eq!(
+            step_to_line(pipeline.transformations[0].as_ref()).unwrap(),
+            "{transformation: OffsetTransform, offset: 1.0, percentile: false, target_frames: null}"
+        );
+        assert!(step_from_line("[1, 2]").is_err());
+    }
+}
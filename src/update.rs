@@ -0,0 +1,66 @@
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Latest release of the project, as published by GitHub.
+const RELEASE_FEED: &str = "https://api.github.com/repos/nluetts/rustman-cli-tools/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+}
+
+/// Ask the release feed for the latest version and tell if it is newer than
+/// the running one. Nothing is downloaded or installed.
+pub fn check_update() -> Result<String> {
+    let current = env!("CARGO_PKG_VERSION");
+    let release: Release = ureq::get(RELEASE_FEED)
+        .timeout(Duration::from_secs(10))
+        .set(
+            "User-Agent",
+            concat!("raman-cli-tools/", env!("CARGO_PKG_VERSION")),
+        )
+        .call()
+        .context("Unable to query the release feed")?
+        .into_json()
+        .context("Unable to read the release feed")?;
+    let latest = release.tag_name.trim_start_matches('v');
+    if is_newer(latest, current)? {
+        Ok(format!(
+            "Version {} is available (running {}), download it from {}",
+            latest, current, release.html_url
+        ))
+    } else {
+        Ok(format!("Version {} is up to date.", current))
+    }
+}
+
+/// Compare dotted version numbers, e.g. "0.10.0" is newer than "0.9.2".
+fn is_newer(version: &str, than: &str) -> Result<bool> {
+    let numbers = |version: &str| -> Result<Vec<u64>> {
+        version
+            .split('.')
+            .map(|n| {
+                n.parse()
+                    .map_err(|_| anyhow!("unexpected version number \"{}\"", version))
+            })
+            .collect()
+    };
+    Ok(numbers(version)? > numbers(than)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_newer;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("0.2.0", "0.1.0").unwrap());
+        assert!(is_newer("0.10.0", "0.9.2").unwrap());
+        assert!(is_newer("1.0.1", "1.0").unwrap());
+        assert!(!is_newer("0.1.0", "0.1.0").unwrap());
+        assert!(!is_newer("0.1.0", "0.2.0").unwrap());
+        assert!(is_newer("nightly", "0.1.0").is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::{anyhow, Result};
 use ndarray::{array, Array1, ArrayBase, Data, Ix1};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering::Greater;
+use std::str::FromStr;
 
 /// Calculate area of single trapezoid.
 fn singletrapz(x0: f64, x1: f64, y0: f64, y1: f64) -> f64 {
@@ -18,7 +20,8 @@ pub fn lininterp(x: f64, x0: f64, x1: f64, y0: f64, y1: f64) -> f64 {
 /// If `left` and `right` do not fall on the `x`-grid, additional data points will be interpolated linearly.
 /// (i.e. the width of the first and last trapezoid will be somewhat smaller).
 /// If `left` and/or `right` falls outside the `x`-range, the integration window will be cropped
-/// to the available range.
+/// to the available range. Points with missing values (NaN) are skipped, the trapezoid bridges
+/// the gap.
 pub fn trapz<'a, S, T>(
     x: &'a ArrayBase<S, Ix1>,
     y: &'a ArrayBase<T, Ix1>,
@@ -30,6 +33,9 @@ where
     S: Data<Elem = f64>,
     T: Data<Elem = f64>,
 {
+    if let Some((x, y)) = finite_points(x, y) {
+        return trapz(&x, &y, left, right, local_baseline);
+    }
     let (mut left, right) = if left < right {
         (left, right)
     } else {
@@ -108,6 +114,139 @@ where
     Ok(area)
 }
 
+/// Number of points on each side of an integration window used to estimate
+/// the noise of the integral.
+const NOISE_POINTS: usize = 10;
+
+/// Standard uncertainty of `trapz(x, y, left, right, local_baseline)` from
+/// the noise of `y`, NaN if there are too few points next to the window.
+///
+/// The noise is the standard deviation of the residuals of a straight line
+/// (the local baseline) fitted to the points on both sides of the window.
+/// The integral is a weighted sum of the `y`-values, so its uncertainty is
+/// the noise times the root of the sum of the squared weights.
+pub fn trapz_uncertainty<'a, S, T>(
+    x: &'a ArrayBase<S, Ix1>,
+    y: &'a ArrayBase<T, Ix1>,
+    left: f64,
+    right: f64,
+    local_baseline: bool,
+) -> Result<f64>
+where
+    S: Data<Elem = f64>,
+    T: Data<Elem = f64>,
+{
+    if let Some((x, y)) = finite_points(x, y) {
+        return trapz_uncertainty(&x, &y, left, right, local_baseline);
+    }
+    let (left, right) = if left < right {
+        (left, right)
+    } else {
+        (right, left)
+    };
+    let below = (0..x.len()).filter(|i| x[*i] < left);
+    let above = (0..x.len()).filter(|i| x[*i] > right);
+    let below: Vec<usize> = below.collect();
+    let flanks: Vec<usize> = below[below.len().saturating_sub(NOISE_POINTS)..]
+        .iter()
+        .copied()
+        .chain(above.take(NOISE_POINTS))
+        .collect();
+    if flanks.len() < 3 {
+        return Ok(f64::NAN);
+    }
+    // least squares line through the flanks
+    let n = flanks.len() as f64;
+    let mean_x = flanks.iter().map(|i| x[*i]).sum::<f64>() / n;
+    let mean_y = flanks.iter().map(|i| y[*i]).sum::<f64>() / n;
+    let sxx: f64 = flanks.iter().map(|i| (x[*i] - mean_x).powi(2)).sum();
+    let sxy: f64 = flanks
+        .iter()
+        .map(|i| (x[*i] - mean_x) * (y[*i] - mean_y))
+        .sum();
+    let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+    let squared_residuals: f64 = flanks
+        .iter()
+        .map(|i| (y[*i] - mean_y - slope * (x[*i] - mean_x)).powi(2))
+        .sum();
+    let noise = (squared_residuals / (n - 2.0)).sqrt();
+    // weight of each point in the integral, only points in the window and
+    // their neighbours contribute
+    let first = (0..x.len()).find(|i| x[*i] >= left).unwrap_or(x.len());
+    let last = (0..x.len()).rev().find(|i| x[*i] <= right).unwrap_or(0);
+    let mut unit = Array1::zeros(y.len());
+    let mut squared_weights = 0.0;
+    for i in first.saturating_sub(1)..(last + 2).min(x.len()) {
+        unit[i] = 1.0;
+        squared_weights += trapz(x, &unit, left, right, local_baseline)?.powi(2);
+        unit[i] = 0.0;
+    }
+    Ok(noise * squared_weights.sqrt())
+}
+
+/// The points of `x` and `y` where both are finite, None if all are (or the
+/// lengths differ), so missing values can be skipped by calling a function
+/// again with the returned points.
+fn finite_points<S, T>(
+    x: &ArrayBase<S, Ix1>,
+    y: &ArrayBase<T, Ix1>,
+) -> Option<(Array1<f64>, Array1<f64>)>
+where
+    S: Data<Elem = f64>,
+    T: Data<Elem = f64>,
+{
+    let finite = |(x, y): &(&f64, &f64)| x.is_finite() && y.is_finite();
+    if x.len() != y.len() || x.iter().zip(y.iter()).all(|point| finite(&point)) {
+        return None;
+    }
+    let (xs, ys): (Vec<f64>, Vec<f64>) = x.iter().zip(y.iter()).filter(finite).unzip();
+    Some((Array1::from(xs), Array1::from(ys)))
+}
+
+/// In-place radix-2 fast Fourier transform of the complex values `re` +
+/// i `im`, whose length must be a power of two. The inverse transform
+/// includes the factor 1/n, so it undoes the forward transform.
+pub fn fft(re: &mut [f64], im: &mut [f64], inverse: bool) {
+    let n = re.len();
+    assert!(n.is_power_of_two() && im.len() == n);
+    // bit reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut len = 2;
+    while len <= n {
+        let angle = sign * 2.0 * std::f64::consts::PI / len as f64;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let (tr, ti) = (re[b] * cos - im[b] * sin, re[b] * sin + im[b] * cos);
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+    if inverse {
+        for value in re.iter_mut().chain(im.iter_mut()) {
+            *value /= n as f64;
+        }
+    }
+}
+
 /// Linearly interpolate x, y datapoints on grid where grid and xs overlap.
 ///
 /// Returns NAN in range where xs and grid do not overlap
@@ -149,6 +288,136 @@ where
     Array1::from_vec(yp)
 }
 
+/// Interpolation between the points of a frame when it is resampled on
+/// another grid.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    /// straight lines between the points
+    #[default]
+    Linear,
+    /// monotone cubic (PCHIP), keeps sharp bands on coarse grids without
+    /// overshooting
+    Pchip,
+}
+
+impl FromStr for Interpolation {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(Interpolation::Linear),
+            "pchip" => Ok(Interpolation::Pchip),
+            _ => Err(anyhow!(
+                "unknown interpolation \"{}\", use linear or pchip",
+                s
+            )),
+        }
+    }
+}
+
+impl Interpolation {
+    /// Interpolate x, y datapoints on grid, NAN where grid and xs do not
+    /// overlap.
+    pub fn resample<S, T, V>(
+        &self,
+        xs: &ArrayBase<S, Ix1>,
+        ys: &ArrayBase<T, Ix1>,
+        grid: &ArrayBase<V, Ix1>,
+    ) -> Array1<f64>
+    where
+        S: Data<Elem = f64>,
+        T: Data<Elem = f64>,
+        V: Data<Elem = f64>,
+    {
+        match self {
+            Interpolation::Linear => linear_resample_array(xs, ys, grid),
+            Interpolation::Pchip => pchip_resample_array(xs, ys, grid),
+        }
+    }
+}
+
+/// Slopes of the monotone cubic interpolant at the points (Fritsch-Carlson),
+/// zero at local extrema so the curve does not overshoot.
+fn pchip_slopes(xs: &[f64], ys: &[f64]) -> Vec<f64> {
+    let n = xs.len();
+    let h: Vec<f64> = xs.windows(2).map(|x| x[1] - x[0]).collect();
+    let delta: Vec<f64> = (0..n - 1).map(|k| (ys[k + 1] - ys[k]) / h[k]).collect();
+    if n == 2 {
+        return vec![delta[0]; 2];
+    }
+    let mut slopes = vec![0.0; n];
+    for k in 1..n - 1 {
+        if delta[k - 1] * delta[k] > 0.0 {
+            // weighted harmonic mean of the neighbouring secants
+            let w1 = 2.0 * h[k] + h[k - 1];
+            let w2 = h[k] + 2.0 * h[k - 1];
+            slopes[k] = (w1 + w2) / (w1 / delta[k - 1] + w2 / delta[k]);
+        }
+    }
+    // one-sided three-point estimate at the ends, limited to stay monotone
+    let end_slope = |h0: f64, h1: f64, d0: f64, d1: f64| {
+        let slope = ((2.0 * h0 + h1) * d0 - h0 * d1) / (h0 + h1);
+        if slope.signum() != d0.signum() {
+            0.0
+        } else if d0.signum() != d1.signum() && slope.abs() > 3.0 * d0.abs() {
+            3.0 * d0
+        } else {
+            slope
+        }
+    };
+    slopes[0] = end_slope(h[0], h[1], delta[0], delta[1]);
+    slopes[n - 1] = end_slope(h[n - 2], h[n - 3], delta[n - 2], delta[n - 3]);
+    slopes
+}
+
+/// Interpolate x, y datapoints on grid with a monotone cubic (PCHIP), where
+/// grid and xs overlap. `xs` must be increasing.
+///
+/// Returns NAN in range where xs and grid do not overlap
+pub fn pchip_resample_array<S, T, V>(
+    xs: &ArrayBase<S, Ix1>,
+    ys: &ArrayBase<T, Ix1>,
+    grid: &ArrayBase<V, Ix1>,
+) -> Array1<f64>
+where
+    S: Data<Elem = f64>,
+    T: Data<Elem = f64>,
+    V: Data<Elem = f64>,
+{
+    let xs: Vec<f64> = xs.iter().copied().collect();
+    let ys: Vec<f64> = ys.iter().copied().collect();
+    if xs.len() < 2 {
+        return grid
+            .iter()
+            .map(|xi| match xs.first() {
+                Some(x0) if xi == x0 => ys[0],
+                _ => f64::NAN,
+            })
+            .collect();
+    }
+    let slopes = pchip_slopes(&xs, &ys);
+    let last = xs.len() - 1;
+    grid.iter()
+        .map(|xi| {
+            if *xi == xs[last] {
+                return ys[last];
+            }
+            if !(*xi >= xs[0] && *xi < xs[last]) {
+                return f64::NAN;
+            }
+            let k = xs.partition_point(|x| x <= xi) - 1;
+            let h = xs[k + 1] - xs[k];
+            let t = (xi - xs[k]) / h;
+            // cubic Hermite basis
+            let h00 = (1.0 + 2.0 * t) * (1.0 - t).powi(2);
+            let h10 = t * (1.0 - t).powi(2);
+            let h01 = t * t * (3.0 - 2.0 * t);
+            let h11 = t * t * (t - 1.0);
+            h00 * ys[k] + h10 * h * slopes[k] + h01 * ys[k + 1] + h11 * h * slopes[k + 1]
+        })
+        .collect()
+}
+
 /// get the index of element in `x` which is closest to `xi`
 pub fn nearest_index<'a, T>(x: &'a ArrayBase<T, Ix1>, xi: f64) -> Option<usize>
 where
@@ -169,13 +438,119 @@ where
     }
 }
 
+/// Polynomial fitted by least squares, see [`polyfit`].
+///
+/// x-values are centered and scaled before fitting to keep the normal
+/// equations well conditioned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polynomial {
+    /// coefficients of the scaled x, lowest order first
+    pub coefficients: Vec<f64>,
+    pub x_center: f64,
+    pub x_scale: f64,
+}
+
+impl Polynomial {
+    pub fn eval(&self, x: f64) -> f64 {
+        let x = (x - self.x_center) / self.x_scale;
+        // Horner's method
+        self.coefficients
+            .iter()
+            .rev()
+            .fold(0.0, |acc, coeff| acc * x + coeff)
+    }
+}
+
+/// Fit a polynomial of order `order` to the data points `xs`, `ys` by least squares.
+pub fn polyfit(xs: &[f64], ys: &[f64], order: usize) -> Result<Polynomial> {
+    let n_coeffs = order + 1;
+    if xs.len() != ys.len() {
+        return Err(anyhow!(
+            "got {} x-values but {} y-values",
+            xs.len(),
+            ys.len()
+        ));
+    }
+    if xs.len() < n_coeffs {
+        return Err(anyhow!(
+            "fitting a polynomial of order {} requires at least {} points, got {}",
+            order,
+            n_coeffs,
+            xs.len()
+        ));
+    }
+    let x_center = xs.iter().sum::<f64>() / xs.len() as f64;
+    let x_scale = xs.iter().map(|x| (x - x_center).abs()).fold(0.0, f64::max);
+    let x_scale = if x_scale > 0.0 { x_scale } else { 1.0 };
+    // set up normal equations A c = b as augmented matrix
+    let mut matrix = vec![vec![0.0; n_coeffs + 1]; n_coeffs];
+    for (x, y) in xs.iter().zip(ys) {
+        let x = (x - x_center) / x_scale;
+        let powers: Vec<f64> = (0..n_coeffs).map(|k| x.powi(k as i32)).collect();
+        for (row, p_row) in matrix.iter_mut().zip(powers.iter()) {
+            for (col, p_col) in powers.iter().enumerate() {
+                row[col] += p_row * p_col;
+            }
+            row[n_coeffs] += p_row * y;
+        }
+    }
+    let coefficients = solve_augmented(matrix).ok_or_else(|| {
+        anyhow!(
+            "cannot fit polynomial of order {}, x-values are not distinct enough",
+            order
+        )
+    })?;
+    Ok(Polynomial {
+        coefficients,
+        x_center,
+        x_scale,
+    })
+}
+
+/// Solve the linear equations given as augmented matrix (coefficients
+/// followed by the right hand side in each row) by Gaussian elimination with
+/// partial pivoting, None if the matrix is (nearly) singular.
+pub fn solve_augmented(mut matrix: Vec<Vec<f64>>) -> Option<Vec<f64>> {
+    let n = matrix.len();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|i, j| {
+                matrix[*i][col]
+                    .abs()
+                    .partial_cmp(&matrix[*j][col].abs())
+                    .unwrap_or(Greater)
+            })
+            .unwrap();
+        if matrix[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        matrix.swap(col, pivot);
+        let (upper, lower) = matrix.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for row in lower.iter_mut() {
+            let factor = row[col] / pivot_row[col];
+            for (r, p) in row.iter_mut().zip(pivot_row.iter()).skip(col) {
+                *r -= factor * p;
+            }
+        }
+    }
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (matrix[row][n] - sum) / matrix[row][row];
+    }
+    Some(solution)
+}
+
 #[cfg(test)]
 //  (f = x->  exp(3x), F = x->        1/3*exp(3x)),
 //  (f = x->  1.2^(x), F = x->   1.2^(x)/log(1.2)),
 //  (f = x->   sin(x), F = x->            -cos(x)),
 //  (f = x-> 1/(2x+3), F = x-> 1/2*log(abs(2x+3)))
 mod tests {
-    use super::{linear_resample_array, trapz};
+    use super::{
+        fft, linear_resample_array, pchip_resample_array, polyfit, trapz, trapz_uncertainty,
+    };
     use ndarray::{self, Array1};
 
     #[test]
@@ -187,10 +562,103 @@ mod tests {
         assert_eq!(area, area_analytic);
     }
     #[test]
+    fn test_polyfit() {
+        let xs = [500.0, 520.0, 550.0, 580.0, 610.0];
+        let ys: Vec<f64> = xs.iter().map(|x| 0.5 + 1.01 * x - 2e-5 * x * x).collect();
+        let poly = polyfit(&xs, &ys, 2).unwrap();
+        for (x, y) in xs.iter().zip(ys.iter()) {
+            assert!((poly.eval(*x) - y).abs() < 1e-9);
+        }
+        assert!(polyfit(&xs[..2], &ys[..2], 2).is_err());
+    }
+    #[test]
+    fn test_fft() {
+        // a delta transforms into a constant
+        let mut re = vec![0.0; 8];
+        let mut im = vec![0.0; 8];
+        re[0] = 1.0;
+        fft(&mut re, &mut im, false);
+        assert!(re.iter().all(|v| (v - 1.0).abs() < 1e-12));
+        assert!(im.iter().all(|v| v.abs() < 1e-12));
+        // the inverse transform restores the values
+        let values: Vec<f64> = (0..16)
+            .map(|i| (i as f64 * 0.7).sin() + 0.1 * i as f64)
+            .collect();
+        let mut re = values.clone();
+        let mut im = vec![0.0; 16];
+        fft(&mut re, &mut im, false);
+        fft(&mut re, &mut im, true);
+        for (v, r) in values.iter().zip(re.iter()) {
+            assert!((v - r).abs() < 1e-12);
+        }
+    }
+    #[test]
+    fn test_trapz_uncertainty() {
+        // noise of ±1 on a flat line
+        let x: Array1<f64> = Array1::range(0.0, 101.0, 1.0);
+        let y = x.mapv(|xi| {
+            if (xi as usize).is_multiple_of(2) {
+                1.0
+            } else {
+                -1.0
+            }
+        });
+        let uncertainty = trapz_uncertainty(&x, &y, 40.0, 60.0, false).unwrap();
+        // 20 points next to the window, 19 points inside with weight 1 and
+        // two end points with weight 0.5
+        let expected = (20.0 / 18.0_f64).sqrt() * 19.5_f64.sqrt();
+        assert!((uncertainty - expected).abs() < 1e-9, "{}", uncertainty);
+        // the local baseline adds the uncertainty of its end points
+        assert!(trapz_uncertainty(&x, &y, 40.0, 60.0, true).unwrap() > uncertainty);
+        // no points next to the window
+        assert!(trapz_uncertainty(&x, &y, 0.0, 100.0, false)
+            .unwrap()
+            .is_nan());
+    }
+    #[test]
+    fn test_trapz_missing_values() {
+        let x: Array1<f64> = Array1::range(0.0, 11.0, 1.0);
+        let mut y = x.clone();
+        y[5] = f64::NAN;
+        // the gap is bridged, which is exact for a straight line
+        let area = trapz(&x, &y, 2.0, 8.0, false).unwrap();
+        assert!((area - 30.0).abs() < 1e-12, "{}", area);
+        assert!(trapz_uncertainty(&x, &y, 2.0, 8.0, false)
+            .unwrap()
+            .is_finite());
+    }
+    #[test]
     fn test_linear_resample() {
         let xs = ndarray::array![1., 2., 3., 4., 5.];
         let ys = ndarray::array![1., 2., 3., 4., 5.];
         let grid = ndarray::array![1.5, 2.5, 2.0, 5.0]; // TODO: 5.0 should also be interpolated
         let res = linear_resample_array(&xs, &ys, &grid);
     }
+    #[test]
+    fn test_pchip_resample() {
+        // a sharp band on a coarse grid
+        let xs = ndarray::array![0., 1., 2., 3., 4., 5.];
+        let ys = ndarray::array![0., 0., 1., 10., 1., 0.];
+        let grid: Array1<f64> = Array1::range(-0.5, 5.5, 0.25);
+        let res = pchip_resample_array(&xs, &ys, &grid);
+        // passes through the points, missing values outside of them
+        for (xi, yi) in grid.iter().zip(res.iter()) {
+            if let Some(k) = xs.iter().position(|x| x == xi) {
+                assert!((yi - ys[k]).abs() < 1e-12);
+            }
+            assert_eq!(yi.is_nan(), *xi < 0.0 || *xi > 5.0);
+        }
+        // no overshoot: flat where the data is flat, nothing above the band
+        assert!(res
+            .iter()
+            .filter(|y| !y.is_nan())
+            .all(|y| *y >= 0.0 && *y <= 10.0));
+        assert_eq!(res[4], 0.0);
+        // but not the straight lines of linear interpolation
+        let linear = linear_resample_array(&xs, &ys, &grid);
+        assert!(res[13] > linear[13]);
+        // a straight line stays straight
+        let res = pchip_resample_array(&xs, &(&xs * 2.0), &grid);
+        assert!((res[9] - 2.0 * grid[9]).abs() < 1e-12);
+    }
 }